@@ -0,0 +1,66 @@
+//! Export throughput on large graphs
+//!
+//! Run with `cargo bench --bench export`, optionally adding
+//! `--features parallel` to compare the multi-threaded path.
+
+use std::io;
+use std::time::Instant;
+
+use futures::executor::block_on;
+use serde_json::json;
+use zflow::graph::graph::Graph;
+use zflow::graph::types::{GraphEdge, GraphLeaf};
+
+fn build(nodes: usize, fan_out: usize) -> Graph<'static> {
+    let mut graph = Graph::new("bench", true);
+    for i in 0..nodes {
+        graph.add_node(
+            &format!("n{}", i),
+            "core/Repeat",
+            json!({"x": i, "y": i * 2, "label": format!("Node {}", i)})
+                .as_object()
+                .cloned(),
+        );
+    }
+    // Edges are pushed directly, add_edge's duplicate checks would
+    // dominate the setup time at this size
+    for i in 0..nodes {
+        for j in 1..=fan_out {
+            graph.raw_parts().edges.push(GraphEdge {
+                from: GraphLeaf {
+                    port: "out".to_owned(),
+                    node_id: format!("n{}", i),
+                    index: None,
+                },
+                to: GraphLeaf {
+                    port: "in".to_owned(),
+                    node_id: format!("n{}", (i + j) % nodes),
+                    index: Some(j),
+                },
+                metadata: json!({"route": j}).as_object().cloned(),
+                key: None,
+            });
+        }
+    }
+    graph
+}
+
+fn main() {
+    let start = Instant::now();
+    let graph = build(20_000, 5);
+    println!(
+        "built {} nodes / {} edges in {:?}",
+        graph.nodes().len(),
+        graph.edges().len(),
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    let json = block_on(graph.to_json());
+    serde_json::to_writer(io::sink(), &json).unwrap();
+    println!("to_json + serialize: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    graph.to_writer(io::sink()).unwrap();
+    println!("to_writer:           {:?}", start.elapsed());
+}
@@ -0,0 +1,126 @@
+//! Canonical example graphs
+//!
+//! Small, well-known flows built through the public graph API, for use
+//! in documentation, tests and benchmarks. Each example comes with a
+//! sample input for its inports and the output its outports are
+//! expected to produce once run.
+
+use serde_json::{json, Value};
+
+use crate::graph::graph::Graph;
+
+pub struct ExampleGraph<'a> {
+    pub graph: Graph<'a>,
+    pub description: &'static str,
+    /// Packets to send, keyed by inport
+    pub sample_input: Value,
+    /// Packets expected in return, keyed by outport
+    pub expected_output: Value,
+}
+
+/// Read a text, split it into words and count each word
+pub fn word_count<'a>() -> ExampleGraph<'a> {
+    let mut graph = Graph::new("WordCount", true);
+    graph
+        .add_node("Split", "strings/SplitWords", None)
+        .add_node("Normalize", "strings/LowerCase", None)
+        .add_node("Count", "objects/CountBy", None)
+        .add_edge("Split", "out", "Normalize", "in", None)
+        .add_edge("Normalize", "out", "Count", "in", None)
+        .add_inport("text", "Split", "in", None)
+        .add_outport("counts", "Count", "out", None);
+    ExampleGraph {
+        graph,
+        description: "Counts how often each word occurs in a text, ignoring case",
+        sample_input: json!({"text": "The cat saw the dog"}),
+        expected_output: json!({"counts": {"the": 2, "cat": 1, "saw": 1, "dog": 1}}),
+    }
+}
+
+/// Fetch a document over HTTP, transform it and store the result
+pub fn fetch_transform_store<'a>() -> ExampleGraph<'a> {
+    let mut graph = Graph::new("FetchTransformStore", true);
+    graph
+        .add_node("Fetch", "http/Get", None)
+        .add_node("Parse", "json/Parse", None)
+        .add_node("Pick", "objects/GetProperty", None)
+        .add_node("Store", "kv/Put", None)
+        .add_node("Errors", "core/Output", None)
+        .add_edge("Fetch", "out", "Parse", "in", None)
+        .add_edge("Parse", "out", "Pick", "in", None)
+        .add_edge("Pick", "out", "Store", "value", None)
+        .add_edge("Fetch", "error", "Errors", "in", None)
+        .add_edge("Parse", "error", "Errors", "in", None)
+        .add_initial(json!("title"), "Pick", "key", None)
+        .add_initial(json!("latest"), "Store", "key", None)
+        .add_inport("url", "Fetch", "url", None)
+        .add_outport("stored", "Store", "out", None);
+    ExampleGraph {
+        graph,
+        description: "Downloads a JSON document, picks its title and stores it under a fixed key; \
+                      fetch and parse errors go to a shared error output",
+        sample_input: json!({"url": "https://example.com/post.json"}),
+        expected_output: json!({"stored": {"key": "latest"}}),
+    }
+}
+
+/// Split work across parallel workers and merge their results
+pub fn fan_out_fan_in<'a>(workers: usize) -> ExampleGraph<'a> {
+    let mut graph = Graph::new("FanOutFanIn", true);
+    graph
+        .add_node("Split", "flow/RoundRobin", None)
+        .add_node("Merge", "flow/Gather", None)
+        .add_inport("items", "Split", "in", None)
+        .add_outport("results", "Merge", "out", None);
+    for i in 0..workers {
+        let worker = format!("Worker{}", i);
+        graph
+            .add_node(&worker, "math/Square", None)
+            .add_edge_index("Split", "out", Some(i), &worker, "in", None, None)
+            .add_edge_index(&worker, "out", None, "Merge", "in", Some(i), None);
+    }
+    ExampleGraph {
+        graph,
+        description: "Distributes numbers round-robin over the workers, squares them in \
+                      parallel and gathers the results in input order",
+        sample_input: json!({"items": [1, 2, 3, 4]}),
+        expected_output: json!({"results": [1, 4, 9, 16]}),
+    }
+}
+
+/// Every example, with the fan-out example using four workers
+pub fn all<'a>() -> Vec<ExampleGraph<'a>> {
+    vec![word_count(), fetch_transform_store(), fan_out_fan_in(4)]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::examples_graphs;
+    use beady::scenario;
+
+    #[scenario]
+    #[test]
+    fn fbp_example_graphs() {
+        'given_the_example_graphs: {
+            let examples = examples_graphs::all();
+            'then_each_should_be_connected_and_documented: {
+                for example in examples.iter() {
+                    let graph = &example.graph;
+                    assert_eq!(graph.connected_components().len(), 1, "{}", graph.name());
+                    assert!(!example.description.is_empty());
+                    for name in example.sample_input.as_object().unwrap().keys() {
+                        assert!(graph.inports().contains_key(name));
+                    }
+                    for name in example.expected_output.as_object().unwrap().keys() {
+                        assert!(graph.outports().contains_key(name));
+                    }
+                }
+            }
+            'then_the_fan_out_should_scale_with_the_workers: {
+                let example = examples_graphs::fan_out_fan_in(8);
+                assert_eq!(example.graph.nodes().len(), 10);
+                assert_eq!(example.graph.edges().len(), 16);
+            }
+        }
+    }
+}
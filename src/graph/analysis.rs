@@ -0,0 +1,1113 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::graph::Graph;
+use super::registry::{port_matches, ComponentRegistry, PortSpec};
+use super::types::{GraphExportedPort, GraphLeaf};
+
+/// Channel that would be created for an edge
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChannelPlan {
+    pub from: GraphLeaf,
+    pub to: GraphLeaf,
+    /// Buffer size declared in the edge's `capacity` metadata
+    pub capacity: Option<u64>,
+}
+
+/// How an inport fed by several edges interleaves their packets
+///
+/// Declared per inport in the node's `fanIn` metadata, e.g.
+/// `{"fanIn": {"in": "priority"}}`. Inports without a declaration take
+/// packets in arrival order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FanInOrder {
+    /// Whichever packet arrives first is delivered first
+    #[default]
+    Arrival,
+    /// One packet from each edge in turn, skipping edges with nothing queued
+    RoundRobin,
+    /// Packets from the edge with the highest `priority` metadata first
+    Priority,
+}
+
+/// Edges merging into one inport, and how they're served
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MergePlan {
+    pub node: String,
+    pub port: String,
+    pub order: FanInOrder,
+    /// Upstream ports, in the order they're served: by descending
+    /// priority for `Priority`, in edge order otherwise
+    pub sources: Vec<GraphLeaf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PlanWarning {
+    /// Nodes forming a cycle, none of which is marked with `delay` metadata
+    CycleWithoutDelay(Vec<String>),
+    /// Inport fed by several edges, none of which declares a capacity
+    UnboundedFanIn {
+        node: String,
+        port: String,
+        edges: usize,
+    },
+}
+
+/// Execution plan preview
+///
+/// Describes how a graph would be brought up without running it,
+/// similar to `EXPLAIN` for SQL queries.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ExecutionPlan {
+    /// Order in which nodes would be instantiated, upstream first
+    pub order: Vec<String>,
+    /// Nodes without incoming edges
+    pub sources: Vec<String>,
+    /// Nodes without outgoing edges
+    pub sinks: Vec<String>,
+    pub channels: Vec<ChannelPlan>,
+    /// Inports fed by several edges
+    pub merges: Vec<MergePlan>,
+    /// Nodes marked with `lazy` metadata, to be instantiated only when
+    /// their first packet arrives or is demanded, in instantiation order
+    pub lazy: Vec<String>,
+    /// Sets of nodes connected to each other, which can be scheduled independently
+    pub groups: Vec<Vec<String>>,
+    pub warnings: Vec<PlanWarning>,
+}
+
+/// Port that is declared by a component but can never carry data in this graph
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PortIssue {
+    /// Inport without edges, IIPs or a graph export feeding it
+    UnreachableInport { node: String, port: String },
+    /// Outport whose packets are not consumed by any edge or graph export
+    UnconsumedOutport { node: String, port: String },
+}
+
+/// Port connected fewer or more times than it allows
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ConstraintViolation {
+    /// Port below its minimum, e.g. a required inport left unconnected
+    TooFewConnections {
+        node: String,
+        port: String,
+        min: usize,
+        actual: usize,
+    },
+    /// Port above its maximum, e.g. fan-in into a single-connection port
+    TooManyConnections {
+        node: String,
+        port: String,
+        max: usize,
+        actual: usize,
+    },
+}
+
+/// Node that a runtime will not be able to instantiate as declared
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DeploymentIssue {
+    /// Component not provided by the runtime
+    MissingComponent { node: String, component: String },
+    /// Port used by the graph but not declared by the node's component
+    MissingInport { node: String, port: String },
+    MissingOutport { node: String, port: String },
+}
+
+/// Edges running between the same pair of nodes, drawn and analyzed as one
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EdgeBundle {
+    pub from: String,
+    pub to: String,
+    /// Distinct `(outport, inport)` pairs, with addressable indexes folded in
+    pub ports: Vec<(String, String)>,
+    /// Number of edges in the bundle
+    pub count: usize,
+}
+
+impl<'a> Graph<'a> {
+    /// Preview how the graph will execute
+    /// ```no_run
+    /// let plan = my_graph.plan();
+    /// for warning in plan.warnings { println!("{:?}", warning); }
+    /// ```
+    pub fn plan(&self) -> ExecutionPlan {
+        let ids = self
+            .nodes
+            .iter()
+            .map(|node| node.id.clone())
+            .collect::<Vec<String>>();
+        let successors = self.successors();
+
+        let sources = ids
+            .iter()
+            .filter(|id| !self.edges.iter().any(|edge| &edge.to.node_id == *id))
+            .cloned()
+            .collect::<Vec<String>>();
+        let sinks = ids
+            .iter()
+            .filter(|id| !self.edges.iter().any(|edge| &edge.from.node_id == *id))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        // Kahn's algorithm; nodes stuck in cycles are appended in graph order
+        let mut in_degree = ids
+            .iter()
+            .map(|id| (id.clone(), 0))
+            .collect::<HashMap<String, usize>>();
+        successors.values().flatten().for_each(|to| {
+            if let Some(d) = in_degree.get_mut(to) {
+                *d += 1;
+            }
+        });
+        let mut order = Vec::new();
+        let mut ready = ids
+            .iter()
+            .filter(|id| in_degree[*id] == 0)
+            .cloned()
+            .collect::<Vec<String>>();
+        while !ready.is_empty() {
+            let id = ready.remove(0);
+            if let Some(next) = successors.get(&id) {
+                for to in next {
+                    if let Some(d) = in_degree.get_mut(to) {
+                        *d -= 1;
+                        if *d == 0 {
+                            ready.push(to.clone());
+                        }
+                    }
+                }
+            }
+            order.push(id);
+        }
+        ids.iter().for_each(|id| {
+            if !order.contains(id) {
+                order.push(id.clone());
+            }
+        });
+
+        let lazy = order
+            .iter()
+            .filter(|id| {
+                self.get_node(id)
+                    .and_then(|node| node.metadata.as_ref())
+                    .and_then(|m| m.get("lazy"))
+                    .and_then(|l| l.as_bool())
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect::<Vec<String>>();
+
+        let channels = self
+            .edges
+            .iter()
+            .map(|edge| ChannelPlan {
+                from: edge.from.clone(),
+                to: edge.to.clone(),
+                capacity: edge
+                    .metadata
+                    .as_ref()
+                    .and_then(|m| m.get("capacity"))
+                    .and_then(|c| c.as_u64()),
+            })
+            .collect::<Vec<ChannelPlan>>();
+
+        let mut warnings = Vec::new();
+        for cycle in self.cycles() {
+            let delayed = cycle.iter().any(|id| {
+                self.get_node(id)
+                    .and_then(|node| node.metadata.as_ref())
+                    .and_then(|m| m.get("delay"))
+                    .map(|d| d.as_bool().unwrap_or(true))
+                    .unwrap_or(false)
+            });
+            if !delayed {
+                warnings.push(PlanWarning::CycleWithoutDelay(cycle));
+            }
+        }
+        let mut fan_in: Vec<(String, String)> = Vec::new();
+        for channel in channels.iter() {
+            let key = (channel.to.node_id.clone(), channel.to.port.clone());
+            if !fan_in.contains(&key) {
+                fan_in.push(key);
+            }
+        }
+        let mut merges = Vec::new();
+        for (node, port) in fan_in {
+            let feeding = channels
+                .iter()
+                .filter(|c| c.to.node_id == node && c.to.port == port)
+                .collect::<Vec<&ChannelPlan>>();
+            if feeding.len() > 1 {
+                let order = self.fan_in_order(&node, &port);
+                let mut sources = feeding
+                    .iter()
+                    .map(|c| c.from.clone())
+                    .collect::<Vec<GraphLeaf>>();
+                if order == FanInOrder::Priority {
+                    sources.sort_by_key(|from| -self.edge_priority(from, &node, &port));
+                }
+                merges.push(MergePlan {
+                    node: node.clone(),
+                    port: port.clone(),
+                    order,
+                    sources,
+                });
+            }
+            if feeding.len() > 1 && feeding.iter().all(|c| c.capacity.is_none()) {
+                warnings.push(PlanWarning::UnboundedFanIn {
+                    node,
+                    port,
+                    edges: feeding.len(),
+                });
+            }
+        }
+
+        ExecutionPlan {
+            order,
+            sources,
+            sinks,
+            channels,
+            merges,
+            lazy,
+            groups: self.connected_components(),
+            warnings,
+        }
+    }
+
+    /// Fan-in order declared for an inport
+    pub fn fan_in_order(&self, node: &str, port: &str) -> FanInOrder {
+        self.get_node(node)
+            .and_then(|node| node.metadata.as_ref())
+            .and_then(|m| m.get("fanIn"))
+            .and_then(|f| f.get(port))
+            .and_then(|order| serde_json::from_value(order.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Declare how an inport fed by several edges orders their packets
+    pub fn set_fan_in_order(&mut self, node: &str, port: &str, order: FanInOrder) -> &mut Self {
+        let mut fan_in = self
+            .get_node(node)
+            .and_then(|node| node.metadata.as_ref())
+            .and_then(|m| m.get("fanIn"))
+            .and_then(|f| f.as_object())
+            .cloned()
+            .unwrap_or_default();
+        fan_in.insert(port.to_owned(), json!(order));
+        let mut metadata = Map::new();
+        metadata.insert("fanIn".to_owned(), Value::Object(fan_in));
+        self.set_node_metadata(node, metadata)
+    }
+
+    /// `priority` metadata of the edge between two ports, `0` if not set
+    fn edge_priority(&self, from: &GraphLeaf, node: &str, port: &str) -> i64 {
+        self.edges
+            .iter()
+            .find(|edge| {
+                edge.from.node_id == from.node_id
+                    && edge.from.port == from.port
+                    && edge.from.index == from.index
+                    && edge.to.node_id == node
+                    && edge.to.port == port
+            })
+            .and_then(|edge| edge.metadata.as_ref())
+            .and_then(|m| m.get("priority"))
+            .and_then(|p| p.as_i64())
+            .unwrap_or(0)
+    }
+
+    /// Cross-reference component port declarations with the graph
+    ///
+    /// Reports inports that can never receive data and outports whose data
+    /// is never consumed. Edges leaving an outport the upstream component
+    /// does not declare are not counted as feeding anything. Nodes whose
+    /// component is not in the registry are skipped, as are dynamic port
+    /// families, whose ports only exist once connected.
+    pub fn port_issues(&self, registry: &ComponentRegistry) -> Vec<PortIssue> {
+        let mut issues = Vec::new();
+        let declares_outport = |node_id: &str, port: &str| -> bool {
+            self.get_node(node_id)
+                .and_then(|node| registry.get(&node.component))
+                .map(|spec| {
+                    spec.out_ports
+                        .iter()
+                        .any(|p| port_matches(&self.get_port_name(&p.id), port))
+                })
+                .unwrap_or(true)
+        };
+        for node in self.nodes.iter() {
+            let spec = if let Some(spec) = registry.get(&node.component) {
+                spec
+            } else {
+                continue;
+            };
+            for inport in spec.in_ports.iter().filter(|p| !p.is_dynamic()) {
+                let port = self.get_port_name(&inport.id);
+                let fed = self.edges.iter().any(|edge| {
+                    edge.to.node_id == node.id
+                        && edge.to.port == port
+                        && declares_outport(&edge.from.node_id, &edge.from.port)
+                }) || self.initializers.iter().any(|iip| {
+                    iip.to
+                        .as_ref()
+                        .map(|to| to.node_id == node.id && to.port == port)
+                        .unwrap_or(false)
+                }) || self
+                    .inports
+                    .values()
+                    .any(|exported| exported.process == node.id && exported.port == port);
+                if !fed {
+                    issues.push(PortIssue::UnreachableInport {
+                        node: node.id.clone(),
+                        port,
+                    });
+                }
+            }
+            for outport in spec.out_ports.iter().filter(|p| !p.is_dynamic()) {
+                let port = self.get_port_name(&outport.id);
+                let consumed = self
+                    .edges
+                    .iter()
+                    .any(|edge| edge.from.node_id == node.id && edge.from.port == port)
+                    || self
+                        .outports
+                        .values()
+                        .any(|exported| exported.process == node.id && exported.port == port);
+                if !consumed {
+                    issues.push(PortIssue::UnconsumedOutport {
+                        node: node.id.clone(),
+                        port,
+                    });
+                }
+            }
+        }
+        issues
+    }
+
+    /// Check connection counts against the bounds ports declare
+    ///
+    /// Bounds come from the component's port declaration, and can be
+    /// overridden per graph with `minConnections`/`maxConnections` in the
+    /// metadata of an exported port. Edges, IIPs and graph exports all
+    /// count as connections. Nodes whose component is not in the registry
+    /// are skipped, and the bounds of a dynamic port family apply to each
+    /// of its ports in use.
+    pub fn check_connection_constraints(
+        &self,
+        registry: &ComponentRegistry,
+    ) -> Vec<ConstraintViolation> {
+        let exported_bounds = |exported: &GraphExportedPort, (min, max): (usize, Option<usize>)| {
+            let meta = exported.metadata.as_ref();
+            let get = |key: &str| {
+                meta.and_then(|m| m.get(key))
+                    .and_then(|v| v.as_u64())
+                    .map(|v| v as usize)
+            };
+            (get("minConnections").unwrap_or(min), get("maxConnections").or(max))
+        };
+        let check = |node: &str, port: &str, (min, max): (usize, Option<usize>), actual: usize| {
+            if actual < min {
+                Some(ConstraintViolation::TooFewConnections {
+                    node: node.to_owned(),
+                    port: port.to_owned(),
+                    min,
+                    actual,
+                })
+            } else {
+                match max {
+                    Some(max) if actual > max => Some(ConstraintViolation::TooManyConnections {
+                        node: node.to_owned(),
+                        port: port.to_owned(),
+                        max,
+                        actual,
+                    }),
+                    _ => None,
+                }
+            }
+        };
+
+        let mut violations = Vec::new();
+        for node in self.nodes.iter() {
+            let spec = if let Some(spec) = registry.get(&node.component) {
+                spec
+            } else {
+                continue;
+            };
+            let used_inports = self
+                .edges
+                .iter()
+                .map(|edge| &edge.to)
+                .chain(self.initializers.iter().filter_map(|iip| iip.to.as_ref()))
+                .filter(|to| to.node_id == node.id)
+                .map(|to| to.port.clone())
+                .chain(
+                    self.inports
+                        .values()
+                        .filter(|exported| exported.process == node.id)
+                        .map(|exported| exported.port.clone()),
+                )
+                .collect::<BTreeSet<String>>();
+            let used_outports = self
+                .edges
+                .iter()
+                .filter(|edge| edge.from.node_id == node.id)
+                .map(|edge| edge.from.port.clone())
+                .chain(
+                    self.outports
+                        .values()
+                        .filter(|exported| exported.process == node.id)
+                        .map(|exported| exported.port.clone()),
+                )
+                .collect::<BTreeSet<String>>();
+            // Ports a declaration stands for: itself, or the ports in use
+            // from its family that aren't declared on their own
+            let instances = |declared: &[PortSpec], port: &PortSpec, used: &BTreeSet<String>| {
+                let id = self.get_port_name(&port.id);
+                if !port.is_dynamic() {
+                    return vec![id];
+                }
+                used.iter()
+                    .filter(|name| port_matches(&id, name))
+                    .filter(|name| {
+                        !declared
+                            .iter()
+                            .any(|p| !p.is_dynamic() && self.get_port_name(&p.id) == **name)
+                    })
+                    .cloned()
+                    .collect::<Vec<String>>()
+            };
+            for inport in spec.in_ports.iter() {
+                for port in instances(&spec.in_ports, inport, &used_inports) {
+                    let mut bounds = inport.connection_bounds();
+                    let mut actual = self
+                        .edges
+                        .iter()
+                        .filter(|edge| edge.to.node_id == node.id && edge.to.port == port)
+                        .count()
+                        + self
+                            .initializers
+                            .iter()
+                            .filter(|iip| {
+                                iip.to
+                                    .as_ref()
+                                    .map(|to| to.node_id == node.id && to.port == port)
+                                    .unwrap_or(false)
+                            })
+                            .count();
+                    for exported in self.inports.values() {
+                        if exported.process == node.id && exported.port == port {
+                            bounds = exported_bounds(exported, bounds);
+                            actual += 1;
+                        }
+                    }
+                    violations.extend(check(&node.id, &port, bounds, actual));
+                }
+            }
+            for outport in spec.out_ports.iter() {
+                for port in instances(&spec.out_ports, outport, &used_outports) {
+                    let mut bounds = outport.connection_bounds();
+                    let mut actual = self
+                        .edges
+                        .iter()
+                        .filter(|edge| edge.from.node_id == node.id && edge.from.port == port)
+                        .count();
+                    for exported in self.outports.values() {
+                        if exported.process == node.id && exported.port == port {
+                            bounds = exported_bounds(exported, bounds);
+                            actual += 1;
+                        }
+                    }
+                    violations.extend(check(&node.id, &port, bounds, actual));
+                }
+            }
+        }
+        violations
+    }
+
+    /// Check the graph against the components a runtime provides
+    ///
+    /// The registry is typically loaded from the runtime's `component:list`
+    /// replies. Reports nodes whose component is missing, and ports used by
+    /// edges, IIPs or graph exports that their component does not declare.
+    /// Each missing port is reported once per node.
+    /// ```no_run
+    /// let issues = my_graph.verify_against(&runtime_components);
+    /// ```
+    pub fn verify_against(&self, registry: &ComponentRegistry) -> Vec<DeploymentIssue> {
+        let mut issues = Vec::new();
+        for node in self.nodes.iter() {
+            let spec = if let Some(spec) = registry.get(&node.component) {
+                spec
+            } else {
+                issues.push(DeploymentIssue::MissingComponent {
+                    node: node.id.clone(),
+                    component: node.component.clone(),
+                });
+                continue;
+            };
+            let mut inports = self
+                .edges
+                .iter()
+                .map(|edge| &edge.to)
+                .chain(self.initializers.iter().filter_map(|iip| iip.to.as_ref()))
+                .filter(|to| to.node_id == node.id)
+                .map(|to| to.port.as_str())
+                .chain(
+                    self.inports
+                        .values()
+                        .filter(|exported| exported.process == node.id)
+                        .map(|exported| exported.port.as_str()),
+                )
+                .collect::<Vec<&str>>();
+            let mut outports = self
+                .edges
+                .iter()
+                .filter(|edge| edge.from.node_id == node.id)
+                .map(|edge| edge.from.port.as_str())
+                .chain(
+                    self.outports
+                        .values()
+                        .filter(|exported| exported.process == node.id)
+                        .map(|exported| exported.port.as_str()),
+                )
+                .collect::<Vec<&str>>();
+            inports.sort_unstable();
+            inports.dedup();
+            outports.sort_unstable();
+            outports.dedup();
+            for port in inports {
+                if !spec
+                    .in_ports
+                    .iter()
+                    .any(|p| port_matches(&self.get_port_name(&p.id), port))
+                {
+                    issues.push(DeploymentIssue::MissingInport {
+                        node: node.id.clone(),
+                        port: port.to_owned(),
+                    });
+                }
+            }
+            for port in outports {
+                if !spec
+                    .out_ports
+                    .iter()
+                    .any(|p| port_matches(&self.get_port_name(&p.id), port))
+                {
+                    issues.push(DeploymentIssue::MissingOutport {
+                        node: node.id.clone(),
+                        port: port.to_owned(),
+                    });
+                }
+            }
+        }
+        issues
+    }
+
+    /// Compressed view of the edges, bundling those between the same nodes
+    ///
+    /// Bundles are listed in the order of their first edge.
+    pub fn edge_bundles(&self) -> Vec<EdgeBundle> {
+        let mut bundles: Vec<EdgeBundle> = Vec::new();
+        for edge in self.edges.iter() {
+            let ports = (edge.from.port.clone(), edge.to.port.clone());
+            match bundles
+                .iter_mut()
+                .find(|b| b.from == edge.from.node_id && b.to == edge.to.node_id)
+            {
+                Some(bundle) => {
+                    if !bundle.ports.contains(&ports) {
+                        bundle.ports.push(ports);
+                    }
+                    bundle.count += 1;
+                }
+                None => bundles.push(EdgeBundle {
+                    from: edge.from.node_id.clone(),
+                    to: edge.to.node_id.clone(),
+                    ports: vec![ports],
+                    count: 1,
+                }),
+            }
+        }
+        bundles
+    }
+
+    /// Downstream neighbours of every node, in edge order
+    pub(crate) fn successors(&self) -> HashMap<String, Vec<String>> {
+        let mut successors: HashMap<String, Vec<String>> = HashMap::new();
+        self.edges.iter().for_each(|edge| {
+            let next = successors.entry(edge.from.node_id.clone()).or_default();
+            if !next.contains(&edge.to.node_id) {
+                next.push(edge.to.node_id.clone());
+            }
+        });
+        successors
+    }
+
+    /// Sets of nodes reachable from each other ignoring edge direction
+    pub fn connected_components(&self) -> Vec<Vec<String>> {
+        let mut neighbours: HashMap<&str, Vec<&str>> = HashMap::new();
+        self.edges.iter().for_each(|edge| {
+            neighbours
+                .entry(edge.from.node_id.as_str())
+                .or_default()
+                .push(edge.to.node_id.as_str());
+            neighbours
+                .entry(edge.to.node_id.as_str())
+                .or_default()
+                .push(edge.from.node_id.as_str());
+        });
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut components = Vec::new();
+        for node in self.nodes.iter() {
+            if seen.contains(node.id.as_str()) {
+                continue;
+            }
+            let mut component = Vec::new();
+            let mut stack = vec![node.id.as_str()];
+            seen.insert(node.id.as_str());
+            while let Some(id) = stack.pop() {
+                component.push(id.to_owned());
+                if let Some(next) = neighbours.get(id) {
+                    for n in next {
+                        if self.get_node(n).is_some() && seen.insert(n) {
+                            stack.push(n);
+                        }
+                    }
+                }
+            }
+            components.push(component);
+        }
+        components
+    }
+
+    /// Cycles in the graph, as strongly connected sets of nodes
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let successors = self.successors();
+        let mut index = 0;
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mut lowlink: HashMap<String, usize> = HashMap::new();
+        let mut stack: Vec<String> = Vec::new();
+        let mut cycles = Vec::new();
+
+        fn strong_connect(
+            id: &str,
+            successors: &HashMap<String, Vec<String>>,
+            index: &mut usize,
+            indices: &mut HashMap<String, usize>,
+            lowlink: &mut HashMap<String, usize>,
+            stack: &mut Vec<String>,
+            cycles: &mut Vec<Vec<String>>,
+        ) {
+            indices.insert(id.to_owned(), *index);
+            lowlink.insert(id.to_owned(), *index);
+            *index += 1;
+            stack.push(id.to_owned());
+
+            for next in successors.get(id).cloned().unwrap_or_default() {
+                if !indices.contains_key(&next) {
+                    strong_connect(&next, successors, index, indices, lowlink, stack, cycles);
+                    let low = lowlink[id].min(lowlink[&next]);
+                    lowlink.insert(id.to_owned(), low);
+                } else if stack.contains(&next) {
+                    let low = lowlink[id].min(indices[&next]);
+                    lowlink.insert(id.to_owned(), low);
+                }
+            }
+
+            if lowlink[id] == indices[id] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    let done = member == id;
+                    component.push(member);
+                    if done {
+                        break;
+                    }
+                }
+                let self_loop = successors
+                    .get(id)
+                    .map(|next| next.iter().any(|n| n == id))
+                    .unwrap_or(false);
+                if component.len() > 1 || self_loop {
+                    component.reverse();
+                    cycles.push(component);
+                }
+            }
+        }
+
+        for node in self.nodes.iter() {
+            if !indices.contains_key(&node.id) {
+                strong_connect(
+                    &node.id,
+                    &successors,
+                    &mut index,
+                    &mut indices,
+                    &mut lowlink,
+                    &mut stack,
+                    &mut cycles,
+                );
+            }
+        }
+        cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::analysis::{
+        ConstraintViolation, DeploymentIssue, EdgeBundle, FanInOrder, PlanWarning, PortIssue,
+    };
+    use crate::graph::graph::Graph;
+    use crate::graph::registry::ComponentRegistry;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_analysis() {
+        'given_a_graph_with_a_feedback_loop: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Parse", "Parse", None)
+                .add_node("Retry", "Retry", None)
+                .add_node("Write", "WriteFile", None)
+                .add_node("Log", "Log", None)
+                .add_edge("Read", "out", "Parse", "in", None)
+                .add_edge("Parse", "error", "Retry", "in", None)
+                .add_edge("Retry", "out", "Parse", "in", None)
+                .add_edge("Parse", "out", "Write", "in", None);
+            'when_planning: {
+                let plan = g.plan();
+                'then_it_should_detect_sources_and_sinks: {
+                    assert_eq!(plan.sources, vec!["Read".to_owned(), "Log".to_owned()]);
+                    assert_eq!(plan.sinks, vec!["Write".to_owned(), "Log".to_owned()]);
+                }
+                'then_it_should_order_upstream_first: {
+                    assert_eq!(plan.order.len(), 5);
+                    assert_eq!(plan.order[0], "Read");
+                }
+                'then_it_should_find_scheduling_groups: {
+                    assert_eq!(plan.groups.len(), 2);
+                }
+                'then_it_should_warn_about_the_cycle_and_fan_in: {
+                    assert!(plan.warnings.contains(&PlanWarning::CycleWithoutDelay(vec![
+                        "Parse".to_owned(),
+                        "Retry".to_owned()
+                    ])));
+                    assert!(plan.warnings.contains(&PlanWarning::UnboundedFanIn {
+                        node: "Parse".to_owned(),
+                        port: "in".to_owned(),
+                        edges: 2
+                    }));
+                }
+            }
+            'when_the_cycle_has_a_delay: {
+                g.set_node_metadata("Retry", json!({"delay": true}).as_object().unwrap().clone());
+                'then_it_should_not_warn_about_it: {
+                    let plan = g.plan();
+                    assert_eq!(plan.warnings.len(), 1);
+                }
+            }
+            'when_a_branch_is_lazy: {
+                g.set_node_metadata("Retry", json!({"lazy": true}).as_object().unwrap().clone())
+                    .set_node_metadata("Log", json!({"lazy": false}).as_object().unwrap().clone());
+                'then_the_plan_should_show_it: {
+                    assert_eq!(g.plan().lazy, vec!["Retry".to_owned()]);
+                }
+            }
+        }
+        'given_a_graph_with_parallel_edges: {
+            let mut g = Graph::new("", true);
+            g.add_node("Split", "Split", None)
+                .add_node("Join", "Join", None)
+                .add_node("Log", "Output", None)
+                .add_edge_index("Split", "out", Some(0), "Join", "in", Some(0), None)
+                .add_edge_index("Split", "out", Some(1), "Join", "in", Some(1), None)
+                .add_edge("Split", "error", "Join", "error", None)
+                .add_edge("Join", "out", "Log", "in", None);
+            'when_bundling_edges: {
+                let bundles = g.edge_bundles();
+                'then_edges_between_the_same_nodes_should_be_merged: {
+                    assert_eq!(
+                        bundles,
+                        vec![
+                            EdgeBundle {
+                                from: "Split".to_owned(),
+                                to: "Join".to_owned(),
+                                ports: vec![
+                                    ("out".to_owned(), "in".to_owned()),
+                                    ("error".to_owned(), "error".to_owned())
+                                ],
+                                count: 3,
+                            },
+                            EdgeBundle {
+                                from: "Join".to_owned(),
+                                to: "Log".to_owned(),
+                                ports: vec![("out".to_owned(), "in".to_owned())],
+                                count: 1,
+                            },
+                        ]
+                    );
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_port_analysis() {
+        'given_a_component_registry: {
+            let registry = ComponentRegistry::from_json_string(
+                r#"[
+                    {"name": "ReadFile", "inPorts": [{"id": "source"}], "outPorts": [{"id": "out"}, {"id": "error"}]},
+                    {"name": "WriteFile", "inPorts": [{"id": "in"}, {"id": "encoding"}], "outPorts": []}
+                ]"#,
+            )
+            .unwrap();
+            'when_checking_a_graph: {
+                let mut g = Graph::new("", true);
+                g.add_node("Read", "ReadFile", None)
+                    .add_node("Write", "WriteFile", None)
+                    .add_node("Other", "Unknown", None)
+                    .add_edge("Read", "out", "Write", "in", None)
+                    .add_edge("Read", "missing", "Write", "encoding", None)
+                    .add_initial(json!("a.txt"), "Read", "source", None);
+                let issues = g.port_issues(&registry);
+                'then_it_should_report_unreachable_and_unconsumed_ports: {
+                    assert_eq!(
+                        issues,
+                        vec![
+                            PortIssue::UnconsumedOutport {
+                                node: "Read".to_owned(),
+                                port: "error".to_owned()
+                            },
+                            PortIssue::UnreachableInport {
+                                node: "Write".to_owned(),
+                                port: "encoding".to_owned()
+                            },
+                        ]
+                    );
+                }
+            }
+        }
+        'given_ports_with_connection_bounds: {
+            let registry = ComponentRegistry::from_json_string(
+                r#"[
+                    {"name": "Merge", "inPorts": [{"id": "in", "required": true, "maxConnections": 1}], "outPorts": [{"id": "out"}]},
+                    {"name": "Output", "inPorts": [{"id": "in", "required": true}], "outPorts": []}
+                ]"#,
+            )
+            .unwrap();
+            'when_a_single_connection_port_has_fan_in: {
+                let mut g = Graph::new("", true);
+                g.add_node("A", "Source", None)
+                    .add_node("B", "Source", None)
+                    .add_node("Merge", "Merge", None)
+                    .add_node("Log", "Output", None)
+                    .add_edge("A", "out", "Merge", "in", None)
+                    .add_edge("B", "out", "Merge", "in", None);
+                let violations = g.check_connection_constraints(&registry);
+                'then_both_violations_should_be_reported: {
+                    assert_eq!(
+                        violations,
+                        vec![
+                            ConstraintViolation::TooManyConnections {
+                                node: "Merge".to_owned(),
+                                port: "in".to_owned(),
+                                max: 1,
+                                actual: 2
+                            },
+                            ConstraintViolation::TooFewConnections {
+                                node: "Log".to_owned(),
+                                port: "in".to_owned(),
+                                min: 1,
+                                actual: 0
+                            },
+                        ]
+                    );
+                }
+                'and_when_an_exported_port_relaxes_the_bound: {
+                    g.add_inport(
+                        "extra",
+                        "Merge",
+                        "in",
+                        json!({"maxConnections": 3}).as_object().cloned(),
+                    )
+                    .add_inport("log", "Log", "in", None);
+                    'then_the_graph_should_satisfy_its_constraints: {
+                        assert!(g.check_connection_constraints(&registry).is_empty());
+                    }
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_deployment_verification() {
+        'given_the_components_of_a_runtime: {
+            let registry = ComponentRegistry::from_json_string(
+                r#"[
+                    {"name": "ReadFile", "inPorts": [{"id": "source"}], "outPorts": [{"id": "out"}]},
+                    {"name": "Output", "inPorts": [{"id": "in"}], "outPorts": []}
+                ]"#,
+            )
+            .unwrap();
+            'when_verifying_a_graph_built_for_another_runtime: {
+                let mut g = Graph::new("", true);
+                g.add_node("Read", "ReadFile", None)
+                    .add_node("Log", "Output", None)
+                    .add_node("Parse", "ParseJson", None)
+                    .add_edge("Read", "out", "Log", "in", None)
+                    .add_edge("Read", "error", "Log", "in", None)
+                    .add_edge("Read", "error", "Parse", "in", None)
+                    .add_initial(json!("a.txt"), "Read", "path", None)
+                    .add_outport("done", "Log", "done", None);
+                let issues = g.verify_against(&registry);
+                'then_missing_components_and_ports_should_be_reported: {
+                    assert_eq!(
+                        issues,
+                        vec![
+                            DeploymentIssue::MissingInport {
+                                node: "Read".to_owned(),
+                                port: "path".to_owned()
+                            },
+                            DeploymentIssue::MissingOutport {
+                                node: "Read".to_owned(),
+                                port: "error".to_owned()
+                            },
+                            DeploymentIssue::MissingOutport {
+                                node: "Log".to_owned(),
+                                port: "done".to_owned()
+                            },
+                            DeploymentIssue::MissingComponent {
+                                node: "Parse".to_owned(),
+                                component: "ParseJson".to_owned()
+                            },
+                        ]
+                    );
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_dynamic_ports() {
+        'given_a_router_with_a_dynamic_port_family: {
+            let registry = ComponentRegistry::from_json_string(
+                r#"[
+                    {"name": "Router", "inPorts": [{"id": "in"}], "outPorts": [
+                        {"id": "out_<route>", "maxConnections": 1},
+                        {"id": "missed"}
+                    ]},
+                    {"name": "Output", "inPorts": [{"id": "in"}], "outPorts": []}
+                ]"#,
+            )
+            .unwrap();
+            let spec = registry.get("Router").unwrap();
+            'when_looking_up_ports: {
+                'then_family_members_should_resolve_to_the_pattern: {
+                    assert_eq!(spec.get_outport("out_users").unwrap().id, "out_<route>");
+                    assert_eq!(spec.get_outport("missed").unwrap().id, "missed");
+                    assert!(spec.get_outport("out_").is_none());
+                    assert!(spec.get_outport("users").is_none());
+                }
+            }
+            'when_connecting_family_members: {
+                let mut g = Graph::new("", true);
+                g.add_node("Route", "Router", None)
+                    .add_node("A", "Output", None)
+                    .add_node("B", "Output", None)
+                    .add_edge("Route", "out_a", "A", "in", None)
+                    .add_edge("Route", "out_b", "A", "in", None)
+                    .add_edge("Route", "out_b", "B", "in", None)
+                    .add_edge("Route", "rest", "B", "in", None)
+                    .add_edge("Route", "missed", "B", "in", None)
+                    .add_inport("in", "Route", "in", None);
+                'then_they_should_be_accepted: {
+                    assert_eq!(
+                        g.verify_against(&registry),
+                        vec![DeploymentIssue::MissingOutport {
+                            node: "Route".to_owned(),
+                            port: "rest".to_owned()
+                        }]
+                    );
+                    assert!(g.port_issues(&registry).is_empty());
+                }
+                'then_bounds_should_apply_to_each_member: {
+                    assert_eq!(
+                        g.check_connection_constraints(&registry),
+                        vec![ConstraintViolation::TooManyConnections {
+                            node: "Route".to_owned(),
+                            port: "out_b".to_owned(),
+                            max: 1,
+                            actual: 2
+                        }]
+                    );
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_fan_in_order() {
+        'given_an_inport_fed_by_several_edges: {
+            let mut g = Graph::new("", true);
+            g.add_node("Fast", "Source", None)
+                .add_node("Slow", "Source", None)
+                .add_node("Urgent", "Source", None)
+                .add_node("Merge", "Merge", None)
+                .add_edge("Fast", "out", "Merge", "in", None)
+                .add_edge(
+                    "Slow",
+                    "out",
+                    "Merge",
+                    "in",
+                    json!({"priority": 1}).as_object().cloned(),
+                )
+                .add_edge(
+                    "Urgent",
+                    "out",
+                    "Merge",
+                    "in",
+                    json!({"priority": 5}).as_object().cloned(),
+                );
+            'when_no_order_is_declared: {
+                let plan = g.plan();
+                'then_packets_should_be_taken_in_arrival_order: {
+                    assert_eq!(plan.merges.len(), 1);
+                    assert_eq!(plan.merges[0].order, FanInOrder::Arrival);
+                    assert_eq!(plan.merges[0].sources[0].node_id, "Fast");
+                }
+            }
+            'when_ordering_by_priority: {
+                g.set_fan_in_order("Merge", "in", FanInOrder::Priority);
+                let plan = g.plan();
+                'then_sources_should_be_served_by_priority: {
+                    let sources = plan.merges[0]
+                        .sources
+                        .iter()
+                        .map(|from| from.node_id.as_str())
+                        .collect::<Vec<&str>>();
+                    assert_eq!(sources, vec!["Urgent", "Slow", "Fast"]);
+                }
+                'then_the_order_should_be_stored_in_metadata: {
+                    let meta = g.get_node("Merge").unwrap().metadata.clone().unwrap();
+                    assert_eq!(meta["fanIn"], json!({"in": "priority"}));
+                }
+            }
+            'when_switching_to_round_robin: {
+                g.set_fan_in_order("Merge", "in", FanInOrder::RoundRobin);
+                'then_the_declaration_should_be_replaced: {
+                    assert_eq!(g.fan_in_order("Merge", "in"), FanInOrder::RoundRobin);
+                    assert_eq!(g.fan_in_order("Merge", "other"), FanInOrder::Arrival);
+                }
+            }
+        }
+    }
+}
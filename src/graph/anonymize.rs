@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::types::{GraphEdge, GraphExportedPort, GraphGroup, GraphIIP, GraphLeaf, GraphNode};
+
+/// Options for [`Graph::anonymize`]
+#[derive(Clone, Debug, Default)]
+pub struct AnonymizeOptions {
+    /// Replace component names with a hash of the name
+    pub hash_components: bool,
+}
+
+/// Hands out numbered placeholders, giving equal inputs equal placeholders
+struct Placeholders {
+    prefix: &'static str,
+    assigned: HashMap<String, String>,
+}
+
+impl Placeholders {
+    fn new(prefix: &'static str) -> Self {
+        Self {
+            prefix,
+            assigned: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, value: &str) -> String {
+        let next = self.assigned.len() + 1;
+        let prefix = self.prefix;
+        self.assigned
+            .entry(value.to_owned())
+            .or_insert_with(|| format!("{}{}", prefix, next))
+            .clone()
+    }
+}
+
+/// FNV-1a, stable across platforms and releases unlike `DefaultHasher`
+fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Replace strings (object keys included) and, in payloads, numbers
+fn scrub(value: &Value, strings: &mut Placeholders, numbers: bool) -> Value {
+    match value {
+        Value::String(s) => Value::from(strings.get(s)),
+        Value::Number(_) if numbers => Value::from(0),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| scrub(item, strings, numbers))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (strings.get(k), scrub(v, strings, numbers)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Replace the string values of metadata, keeping its keys and numbers so
+/// that layout and routing information survive
+fn scrub_metadata(
+    metadata: &Option<Map<String, Value>>,
+    strings: &mut Placeholders,
+) -> Option<Map<String, Value>> {
+    metadata.as_ref().map(|metadata| {
+        metadata
+            .iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    Value::Object(_) | Value::Array(_) | Value::String(_) => {
+                        scrub(v, strings, false)
+                    }
+                    other => other.clone(),
+                };
+                (k.clone(), v)
+            })
+            .collect()
+    })
+}
+
+impl<'a> Graph<'a> {
+    /// Copy of the graph that is safe to attach to bug reports
+    ///
+    /// Node IDs become `n1`, `n2`, ... in node order, group names `g1`,
+    /// `g2`, ... and metadata and property strings `s1`, `s2`, ...; the same
+    /// string always gets the same placeholder. IIP payloads keep their
+    /// shape, with strings replaced and numbers zeroed. Topology, port
+    /// names and component names are kept, unless `hash_components` is set.
+    /// Exported ports are visited in name order, so the result only depends
+    /// on the graph and anonymizing it twice gives the same output.
+    /// ```no_run
+    /// let safe = my_graph.anonymize(AnonymizeOptions::default());
+    /// ```
+    pub fn anonymize(&self, options: AnonymizeOptions) -> Graph<'a> {
+        let mut nodes = Placeholders::new("n");
+        let mut groups = Placeholders::new("g");
+        let mut strings = Placeholders::new("s");
+
+        let component = |name: &str| {
+            if options.hash_components {
+                format!("c{:016x}", fnv1a(name))
+            } else {
+                name.to_owned()
+            }
+        };
+        let leaf = |leaf: &GraphLeaf, nodes: &mut Placeholders| GraphLeaf {
+            port: leaf.port.clone(),
+            node_id: nodes.get(&leaf.node_id),
+            index: leaf.index,
+        };
+        let exported =
+            |port: &GraphExportedPort, nodes: &mut Placeholders, strings: &mut Placeholders| {
+                GraphExportedPort {
+                    process: nodes.get(&port.process),
+                    port: port.port.clone(),
+                    metadata: scrub_metadata(&port.metadata, strings),
+                }
+            };
+
+        let mut graph = Graph::new("graph", self.case_sensitive);
+        graph.invalidate_tags();
+        for node in self.nodes.iter() {
+            let id = nodes.get(&node.id);
+            graph.nodes.push(GraphNode {
+                uid: id.clone(),
+                id,
+                component: component(&node.component),
+                metadata: scrub_metadata(&node.metadata, &mut strings),
+            });
+        }
+        for edge in self.edges.iter() {
+            graph.edges.push(GraphEdge {
+                from: leaf(&edge.from, &mut nodes),
+                to: leaf(&edge.to, &mut nodes),
+                metadata: scrub_metadata(&edge.metadata, &mut strings),
+                key: edge.key.as_ref().map(|key| strings.get(key)),
+            });
+        }
+        for iip in self.initializers.iter() {
+            let mut from = iip.from.clone();
+            if let Some(from) = from.as_mut() {
+                from.data = scrub(&from.data, &mut strings, true);
+            }
+            graph.initializers.push(GraphIIP {
+                from,
+                to: iip.to.as_ref().map(|to| leaf(to, &mut nodes)),
+                metadata: scrub_metadata(&iip.metadata, &mut strings),
+            });
+        }
+        for group in self.groups.iter() {
+            graph.groups.push(GraphGroup {
+                name: groups.get(&group.name),
+                nodes: group.nodes.iter().map(|id| nodes.get(id)).collect(),
+                metadata: scrub_metadata(&group.metadata, &mut strings),
+            });
+        }
+        let mut inports = self.inports.iter().collect::<Vec<_>>();
+        inports.sort_by_key(|(name, _)| name.as_str());
+        for (name, port) in inports {
+            graph
+                .inports
+                .insert(name.clone(), exported(port, &mut nodes, &mut strings));
+        }
+        let mut outports = self.outports.iter().collect::<Vec<_>>();
+        outports.sort_by_key(|(name, _)| name.as_str());
+        for (name, port) in outports {
+            graph
+                .outports
+                .insert(name.clone(), exported(port, &mut nodes, &mut strings));
+        }
+        graph.properties =
+            scrub_metadata(&Some(self.properties.clone()), &mut strings).unwrap_or_default();
+        graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::anonymize::AnonymizeOptions;
+    use crate::graph::graph::Graph;
+    use beady::scenario;
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_anonymize() {
+        'given_a_proprietary_graph: {
+            let mut g = Graph::new("payroll", true);
+            let label = json!({"x": 10, "label": "salaries"});
+            let route = json!({"route": 3});
+            let query = json!({"table": "salaries", "limit": 500});
+            g.add_node("Read", "acme/ReadDb", label.as_object().cloned())
+                .add_node("Scale", "core/Multiply", None)
+                .add_edge("Read", "out", "Scale", "in", route.as_object().cloned())
+                .add_initial(query, "Read", "query", None)
+                .add_inport("db", "Read", "query", None)
+                .add_group("secret", vec!["Read".to_owned()], None);
+            'when_anonymizing_it: {
+                let safe = g.anonymize(AnonymizeOptions::default());
+                'then_identifiers_should_be_replaced: {
+                    assert_eq!(safe.nodes[0].id, "n1");
+                    assert_eq!(safe.nodes[1].id, "n2");
+                    assert_eq!(safe.edges[0].from.node_id, "n1");
+                    assert_eq!(safe.inports.get("db").unwrap().process, "n1");
+                    assert_eq!(safe.groups[0].name, "g1");
+                    assert_eq!(safe.groups[0].nodes, vec!["n1"]);
+                }
+                'then_metadata_and_payloads_should_be_scrubbed: {
+                    let meta = safe.nodes[0].metadata.as_ref().unwrap();
+                    assert_eq!(meta.get("x"), Some(&json!(10)));
+                    assert_eq!(meta.get("label"), Some(&json!("s1")));
+                    assert_eq!(
+                        safe.edges[0].metadata.as_ref().unwrap().get("route"),
+                        Some(&json!(3))
+                    );
+                    let data = &safe.initializers[0].from.as_ref().unwrap().data;
+                    assert_eq!(data, &json!({"s2": 0, "s3": "s1"}));
+                }
+                'then_components_should_be_kept: {
+                    assert_eq!(safe.nodes[0].component, "acme/ReadDb");
+                }
+                'then_it_should_be_deterministic: {
+                    let again = g.anonymize(AnonymizeOptions::default());
+                    assert_eq!(
+                        json!(block_on(again.to_json())),
+                        json!(block_on(safe.to_json()))
+                    );
+                }
+            }
+            'when_hashing_components: {
+                let safe = g.anonymize(AnonymizeOptions {
+                    hash_components: true,
+                });
+                'then_component_names_should_be_hidden: {
+                    assert!(safe.nodes[0].component.starts_with('c'));
+                    assert_ne!(safe.nodes[0].component, safe.nodes[1].component);
+                    assert!(!safe.nodes[0].component.contains("acme"));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::journal::TransactionEntry;
+
+/// A committed transaction, as delivered to audit sinks
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub graph: String,
+    pub revision: usize,
+    /// Seconds since the Unix epoch at which the transaction was committed
+    pub timestamp: u64,
+    /// Metadata the transaction was started with, e.g. the author
+    pub metadata: Option<Map<String, Value>>,
+    pub entries: Vec<TransactionEntry>,
+}
+
+impl AuditRecord {
+    pub fn new(graph: &str, revision: usize, entries: Vec<TransactionEntry>) -> Self {
+        let metadata = entries
+            .iter()
+            .find(|entry| entry.cmd.as_deref() == Some("start_transaction"))
+            .and_then(|entry| entry.args.as_ref())
+            .and_then(|args| args.get("metadata"))
+            .and_then(|meta| meta.as_object())
+            .cloned();
+        Self {
+            graph: graph.to_owned(),
+            revision,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            metadata,
+            entries,
+        }
+    }
+}
+
+/// Receives every transaction committed to a journaled graph
+///
+/// Register sinks with `Graph::add_audit_sink` to keep an audit trail of
+/// who changed a graph and when.
+pub trait AuditSink {
+    fn record(&self, record: &AuditRecord) -> Result<(), io::Error>;
+}
+
+/// Writes each record as a JSON line to stdout
+pub struct StdoutSink;
+
+impl AuditSink for StdoutSink {
+    fn record(&self, record: &AuditRecord) -> Result<(), io::Error> {
+        let line = serde_json::to_string(record)?;
+        let mut out = io::stdout().lock();
+        writeln!(out, "{}", line)
+    }
+}
+
+/// Appends each record as a JSON line to a file
+pub struct FileSink {
+    pub path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+        }
+    }
+}
+
+impl AuditSink for FileSink {
+    fn record(&self, record: &AuditRecord) -> Result<(), io::Error> {
+        let line = serde_json::to_string(record)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+}
+
+/// POSTs each record as JSON to a plain `http://` endpoint
+///
+/// Records are queued and delivered one by one from a background thread,
+/// so a slow or unreachable endpoint never blocks the graph committing
+/// them. Connecting, sending and reading the response each time out after
+/// `HTTP_SINK_TIMEOUT`. Failed deliveries are logged, and records arriving
+/// while `HTTP_SINK_QUEUE` records are still waiting are dropped with an
+/// error.
+pub struct HttpSink {
+    pub url: String,
+    queue: SyncSender<AuditRecord>,
+}
+
+/// How long `HttpSink` waits for each step of a delivery
+pub const HTTP_SINK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many records `HttpSink` holds while the endpoint is slow
+pub const HTTP_SINK_QUEUE: usize = 1024;
+
+impl HttpSink {
+    pub fn new(url: &str) -> Self {
+        let (queue, records) = sync_channel::<AuditRecord>(HTTP_SINK_QUEUE);
+        let endpoint = url.to_owned();
+        thread::spawn(move || {
+            for record in records {
+                if let Err(err) = post_record(&endpoint, &record, HTTP_SINK_TIMEOUT) {
+                    log::error!("Can't deliver audit record {}: {}", record.revision, err);
+                }
+            }
+        });
+        Self {
+            url: url.to_owned(),
+            queue,
+        }
+    }
+}
+
+impl AuditSink for HttpSink {
+    fn record(&self, record: &AuditRecord) -> Result<(), io::Error> {
+        self.queue
+            .try_send(record.clone())
+            .map_err(|err| match err {
+                TrySendError::Full(_) => io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!("Audit queue for {} is full", self.url),
+                ),
+                TrySendError::Disconnected(_) => io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    format!("Audit delivery to {} has stopped", self.url),
+                ),
+            })
+    }
+}
+
+/// POST a record to an `http://` URL, waiting at most `timeout` per step
+fn post_record(url: &str, record: &AuditRecord, timeout: Duration) -> Result<(), io::Error> {
+    let rest = url.strip_prefix("http://").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unsupported audit endpoint {}", url),
+        )
+    })?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_owned()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let body = serde_json::to_string(record)?;
+    let mut stream = connect(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        body.len(),
+        body
+    )?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .unwrap_or(0);
+    if !(200..300).contains(&status) {
+        return Err(io::Error::other(format!(
+            "Audit endpoint responded with status {}",
+            status
+        )));
+    }
+    Ok(())
+}
+
+/// Connect to the first reachable address `address` resolves to
+fn connect(address: &str, timeout: Duration) -> Result<TcpStream, io::Error> {
+    let mut last = io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} did not resolve to any address", address),
+    );
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last = err,
+        }
+    }
+    Err(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use crate::graph::audit::{post_record, AuditRecord, AuditSink, FileSink, HttpSink};
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use beady::scenario;
+    use serde_json::json;
+
+    struct MemorySink {
+        records: Arc<Mutex<Vec<AuditRecord>>>,
+    }
+
+    impl AuditSink for MemorySink {
+        fn record(&self, record: &AuditRecord) -> Result<(), io::Error> {
+            self.records.lock().unwrap().push(record.clone());
+            Ok(())
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_audit() {
+        'given_a_journaled_graph_with_audit_sinks: {
+            let records = Arc::new(Mutex::new(Vec::new()));
+            let path =
+                std::env::temp_dir().join(format!("zflow-audit-{}.jsonl", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let mut g = Graph::new("audited", true);
+            g.add_audit_sink(MemorySink {
+                records: records.clone(),
+            })
+            .add_audit_sink(FileSink::new(path.to_str().unwrap()))
+            .init_journal(None);
+            'when_committing_transactions: {
+                g.add_node("Foo", "Bar", None);
+                g.start_transaction("rename", json!({"author": "alice"}).as_object().cloned())
+                    .rename_node("Foo", "Baz")
+                    .end_transaction("rename", None);
+                'then_every_sink_should_receive_them: {
+                    let records = records.lock().unwrap();
+                    assert_eq!(records.len(), 3);
+                    assert_eq!(records[2].graph, "audited");
+                    assert_eq!(records[2].revision, 2);
+                    assert_eq!(
+                        records[2].metadata.as_ref().unwrap().get("author"),
+                        Some(&json!("alice"))
+                    );
+                    let written = std::fs::read_to_string(&path).unwrap();
+                    assert_eq!(written.lines().count(), 3);
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_http_audit() {
+        'given_an_endpoint_that_never_answers: {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/audit", listener.local_addr().unwrap());
+            let record = AuditRecord::new("audited", 1, Vec::new());
+            'when_recording_through_an_http_sink: {
+                let started = Instant::now();
+                let recorded = HttpSink::new(&url).record(&record);
+                'then_it_should_return_without_waiting: {
+                    assert!(recorded.is_ok());
+                    assert!(started.elapsed() < Duration::from_secs(1));
+                }
+            }
+            'when_posting_a_record: {
+                let posted = post_record(&url, &record, Duration::from_millis(100));
+                'then_it_should_time_out: {
+                    let err = posted.unwrap_err();
+                    assert!(matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::executor::block_on;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::graph::Graph;
+use super::journal::{Journal, JournalStore, TransactionEntry};
+
+/// What `IncrementalSaver::save` wrote
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SaveKind {
+    /// Nothing changed since the last save
+    Unchanged,
+    /// The whole graph was rewritten
+    Snapshot,
+    /// The given number of transactions were appended to the journal segment
+    Segment(usize),
+}
+
+/// Snapshot a journal segment extends
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Base {
+    /// Unique per snapshot, so segments of an older snapshot can be told apart
+    id: String,
+    /// Journal revision the snapshot was taken at
+    revision: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Segment {
+    base: String,
+    rev: usize,
+    entries: Vec<TransactionEntry>,
+}
+
+/// The part of a snapshot file that isn't graph JSON
+#[derive(Deserialize)]
+struct SnapshotHeader {
+    autosave: Option<Base>,
+}
+
+/// Key of the snapshot file's `Base` next to the graph JSON
+const BASE_KEY: &str = "autosave";
+
+/// Autosave for journaled graphs that avoids rewriting the whole file
+///
+/// After a full snapshot of the graph, later saves only append the new
+/// journal transactions to a `<path>.journal` file. A fresh snapshot is
+/// written every `snapshot_every` saves, when the graph moved back in its
+/// history, or when revisions after the last save were replaced by new
+/// edits after an undo.
+///
+/// Snapshots record the revision they were taken at and journal segments
+/// the snapshot they extend, so `load` ignores segments left behind if
+/// saving a snapshot is interrupted before the journal is cleared.
+/// ```no_run
+/// let mut saver = IncrementalSaver::new("flow.json", 50);
+/// my_graph.init_journal(None);
+/// // after each edit
+/// saver.save(&mut my_graph)?;
+/// // later
+/// let restored = IncrementalSaver::load("flow.json")?;
+/// ```
+pub struct IncrementalSaver {
+    pub path: PathBuf,
+    pub snapshot_every: usize,
+    saved_revision: Option<i32>,
+    /// `Graph::history_rewrites` at the last save
+    saved_rewrites: usize,
+    base: Option<Base>,
+    segments: usize,
+}
+
+impl IncrementalSaver {
+    pub fn new(path: &str, snapshot_every: usize) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            snapshot_every,
+            saved_revision: None,
+            saved_rewrites: 0,
+            base: None,
+            segments: 0,
+        }
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".journal");
+        PathBuf::from(path)
+    }
+
+    pub fn save(&mut self, graph: &mut Graph) -> Result<SaveKind, io::Error> {
+        let current = graph.current_revision;
+        let (saved, base) = match (self.saved_revision, &self.base) {
+            (Some(saved), Some(base))
+                if saved <= current
+                    && graph.history_rewrites == self.saved_rewrites
+                    && self.segments < self.snapshot_every =>
+            {
+                (saved, base.id.clone())
+            }
+            _ => {
+                self.snapshot(graph)?;
+                return Ok(SaveKind::Snapshot);
+            }
+        };
+        if saved == current {
+            return Ok(SaveKind::Unchanged);
+        }
+
+        let mut file = BufWriter::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.journal_path())?,
+        );
+        let mut appended = 0;
+        for rev in (saved + 1)..=current {
+            if let Some(entries) = graph.fetch_transaction(rev as usize) {
+                let segment = Segment {
+                    base: base.clone(),
+                    rev: rev as usize,
+                    entries: entries.clone(),
+                };
+                writeln!(file, "{}", serde_json::to_string(&segment)?)?;
+                appended += 1;
+            }
+        }
+        file.flush()?;
+        self.saved_revision = Some(current);
+        self.segments += 1;
+        Ok(SaveKind::Segment(appended))
+    }
+
+    /// Rewrite the whole graph and start a new journal segment
+    pub fn snapshot(&mut self, graph: &Graph) -> Result<(), io::Error> {
+        let written = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or_default();
+        let base = Base {
+            id: format!("{}-{}", graph.current_revision, written),
+            revision: graph.current_revision,
+        };
+        let mut json = Vec::new();
+        graph.to_writer(&mut json)?;
+        let mut json = serde_json::from_slice::<Value>(&json)?;
+        if let Some(json) = json.as_object_mut() {
+            json.insert(BASE_KEY.to_owned(), serde_json::to_value(&base)?);
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        {
+            let mut writer = BufWriter::new(File::create(&tmp)?);
+            serde_json::to_writer(&mut writer, &json)?;
+            writer.flush()?;
+        }
+        fs::rename(&tmp, &self.path)?;
+        File::create(self.journal_path())?;
+        self.saved_revision = Some(graph.current_revision);
+        self.saved_rewrites = graph.history_rewrites;
+        self.base = Some(base);
+        self.segments = 0;
+        Ok(())
+    }
+
+    /// Load a graph from its last snapshot and journal segment
+    ///
+    /// Segments belonging to another snapshot, or at or below the
+    /// snapshot's revision, are skipped.
+    pub fn load<'a>(path: &str) -> Result<Graph<'a>, io::Error> {
+        let saver = Self::new(path, 0);
+        let source = fs::read_to_string(&saver.path)?;
+        let mut graph = block_on(Graph::from_json_string(&source, None))?;
+        let header = serde_json::from_str::<SnapshotHeader>(&source)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let Some(base) = header.autosave else {
+            return Ok(graph);
+        };
+        let journal = match File::open(saver.journal_path()) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(graph),
+            Err(e) => return Err(e),
+        };
+        for line in BufReader::new(journal).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let segment = serde_json::from_str::<Segment>(&line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            if segment.base != base.id || segment.rev as i32 <= base.revision {
+                continue;
+            }
+            for entry in segment.entries {
+                graph.execute_entry(entry);
+            }
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::autosave::{IncrementalSaver, SaveKind};
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_incremental_save() {
+        'given_a_journaled_graph_with_an_incremental_saver: {
+            let path = std::env::temp_dir().join(format!(
+                "zflow-autosave-{}-{:?}.json",
+                std::process::id(),
+                std::thread::current().id()
+            ));
+            let path = path.to_str().unwrap().to_owned();
+            let mut saver = IncrementalSaver::new(&path, 2);
+            let mut g = Graph::new("autosaved", true);
+            g.add_node("Read", "ReadFile", None);
+            g.init_journal(None);
+            assert_eq!(saver.save(&mut g).unwrap(), SaveKind::Snapshot);
+            'when_saving_small_edits: {
+                g.add_node("Log", "Output", json!({"x": 1}).as_object().cloned())
+                    .add_edge("Read", "out", "Log", "in", None);
+                let first = saver.save(&mut g).unwrap();
+                let unchanged = saver.save(&mut g).unwrap();
+                'then_only_journal_segments_should_be_written: {
+                    assert_eq!(first, SaveKind::Segment(2));
+                    assert_eq!(unchanged, SaveKind::Unchanged);
+                    let restored = IncrementalSaver::load(&path).unwrap();
+                    assert_eq!(restored.nodes.len(), 2);
+                    assert_eq!(restored.edges.len(), 1);
+                    assert_eq!(
+                        restored
+                            .get_node("Log")
+                            .unwrap()
+                            .metadata
+                            .as_ref()
+                            .unwrap()
+                            .get("x"),
+                        Some(&json!(1))
+                    );
+                }
+                'and_then_after_enough_segments: {
+                    g.remove_node("Read");
+                    saver.save(&mut g).unwrap();
+                    g.rename_node("Log", "Print");
+                    'then_a_new_snapshot_should_be_written: {
+                        assert_eq!(saver.save(&mut g).unwrap(), SaveKind::Snapshot);
+                        let journal = std::fs::read_to_string(format!("{}.journal", path)).unwrap();
+                        assert!(journal.is_empty());
+                        let restored = IncrementalSaver::load(&path).unwrap();
+                        assert_eq!(restored.nodes.len(), 1);
+                        assert!(restored.get_node("Print").is_some());
+                    }
+                }
+                'and_then_after_replacing_saved_revisions: {
+                    saver.save(&mut g).unwrap();
+                    g.undo().undo();
+                    g.add_node("Other", "Output", None)
+                        .add_node("More", "Output", None)
+                        .add_node("Most", "Output", None);
+                    'then_a_new_snapshot_should_be_written: {
+                        assert_eq!(saver.save(&mut g).unwrap(), SaveKind::Snapshot);
+                        let restored = IncrementalSaver::load(&path).unwrap();
+                        assert_eq!(restored.nodes.len(), 4);
+                        assert!(restored.get_node("Log").is_none());
+                        assert!(restored.get_node("Most").is_some());
+                    }
+                }
+                'and_then_when_a_snapshot_leaves_old_segments_behind: {
+                    let journal = std::fs::read(format!("{}.journal", path)).unwrap();
+                    g.add_node("Late", "Output", None);
+                    saver.snapshot(&g).unwrap();
+                    std::fs::write(format!("{}.journal", path), journal).unwrap();
+                    'then_loading_should_skip_them: {
+                        let restored = IncrementalSaver::load(&path).unwrap();
+                        assert_eq!(restored.nodes.len(), 3);
+                        assert_eq!(restored.edges.len(), 1);
+                    }
+                }
+                'and_then_after_an_undo: {
+                    g.undo();
+                    'then_a_new_snapshot_should_be_written: {
+                        assert_eq!(saver.save(&mut g).unwrap(), SaveKind::Snapshot);
+                        assert_eq!(IncrementalSaver::load(&path).unwrap().edges.len(), 0);
+                    }
+                }
+            }
+            let _ = std::fs::remove_file(&path);
+            let _ = std::fs::remove_file(format!("{}.journal", path));
+        }
+    }
+}
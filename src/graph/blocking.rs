@@ -0,0 +1,86 @@
+//! Synchronous versions of the graph's async APIs
+//!
+//! The async graph methods don't wait on I/O readiness, so they can be
+//! driven by a plain executor. These wrappers do that, letting CLI tools
+//! and other non-async applications load and save graphs without setting
+//! up a runtime:
+//! ```no_run
+//! let mut my_graph = blocking::load_file("flow.json", None)?;
+//! my_graph.add_node("Log", "core/Output", None);
+//! blocking::save(&my_graph, "flow.json")?;
+//! ```
+use std::io;
+
+use futures::executor::block_on;
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::lenient::LoadIssue;
+use super::types::GraphJson;
+
+pub fn to_json(graph: &Graph) -> GraphJson {
+    block_on(graph.to_json())
+}
+
+pub fn from_json<'a>(json: GraphJson, metadata: Option<Map<String, Value>>) -> Graph<'a> {
+    block_on(Graph::from_json(json, metadata))
+}
+
+pub fn from_json_string<'a>(
+    source: &str,
+    metadata: Option<Map<String, Value>>,
+) -> Result<Graph<'a>, io::Error> {
+    block_on(Graph::from_json_string(source, metadata))
+}
+
+pub fn load_file<'a>(
+    path: &str,
+    metadata: Option<Map<String, Value>>,
+) -> Result<Graph<'a>, io::Error> {
+    block_on(Graph::load_file(path, metadata))
+}
+
+pub fn load_lenient<'a>(path: &str) -> Result<(Graph<'a>, Vec<LoadIssue>), io::Error> {
+    block_on(Graph::load_lenient(path))
+}
+
+pub fn save(graph: &Graph, path: &str) -> Result<(), io::Error> {
+    block_on(graph.save(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::blocking;
+    use crate::graph::graph::Graph;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_blocking_api() {
+        'given_a_graph: {
+            let mut g = Graph::new("sync", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Log", "Output", None)
+                .add_edge("Read", "out", "Log", "in", None)
+                .add_initial(json!("a.txt"), "Read", "source", None);
+            'when_saving_and_loading_it_without_a_runtime: {
+                let path = std::env::temp_dir().join(format!(
+                    "zflow-blocking-{}-{:?}.json",
+                    std::process::id(),
+                    std::thread::current().id()
+                ));
+                let path = path.to_str().unwrap().to_owned();
+                blocking::save(&g, &path).unwrap();
+                let loaded = blocking::load_file(&path, None).unwrap();
+                std::fs::remove_file(&path).unwrap();
+                'then_it_should_round_trip: {
+                    assert_eq!(
+                        json!(blocking::to_json(&loaded)),
+                        json!(blocking::to_json(&g))
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::journal::TransactionEntry;
+
+/// Graph size after a revision
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SizeSample {
+    pub revision: usize,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+/// Summary of how a graph changed over its journaled history
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChurnReport {
+    /// Node IDs with the number of entries changing them, most changed first
+    pub node_changes: Vec<(String, usize)>,
+    /// Edges as `node.port -> node.port`, most changed first
+    pub edge_changes: Vec<(String, usize)>,
+    /// Transactions per `author` transaction metadata, most active first
+    pub transactions_by_author: Vec<(String, usize)>,
+    pub size_over_time: Vec<SizeSample>,
+}
+
+/// Occurrences per key, with the order keys were first seen in
+#[derive(Default)]
+struct Tally(HashMap<String, (usize, usize)>);
+
+impl Tally {
+    fn bump(&mut self, key: String) {
+        let seen = self.0.len();
+        self.0.entry(key).or_insert((0, seen)).0 += 1;
+    }
+
+    /// Keys with their counts, most frequent first, ties in first-seen order
+    fn into_sorted(self) -> Vec<(String, usize)> {
+        let mut counts = self.0.into_iter().collect::<Vec<_>>();
+        counts.sort_by_key(|(_, (count, seen))| (Reverse(*count), *seen));
+        counts
+            .into_iter()
+            .map(|(key, (count, _))| (key, count))
+            .collect()
+    }
+}
+
+fn edge_key(args: &Value) -> Option<String> {
+    let leaf = |key: &str| {
+        let leaf = args.get(key)?;
+        Some(format!(
+            "{}.{}",
+            leaf.get("node_id")?.as_str()?,
+            leaf.get("port")?.as_str()?
+        ))
+    };
+    Some(format!("{} -> {}", leaf("from")?, leaf("to")?))
+}
+
+impl ChurnReport {
+    /// Build the report from journal transactions, in revision order
+    pub fn from_transactions(transactions: &[Vec<TransactionEntry>]) -> Self {
+        let mut report = ChurnReport::default();
+        let (mut node_changes, mut edge_changes, mut authors) =
+            (Tally::default(), Tally::default(), Tally::default());
+        let (mut nodes, mut edges) = (0usize, 0usize);
+        for (revision, entries) in transactions.iter().enumerate() {
+            for entry in entries.iter() {
+                let cmd = entry.cmd.as_deref().unwrap_or_default();
+                match cmd {
+                    "start_transaction" => {
+                        let author = entry
+                            .args
+                            .as_ref()
+                            .and_then(|a| a.get("metadata"))
+                            .and_then(|m| m.get("author"))
+                            .and_then(|a| a.as_str())
+                            .unwrap_or("unknown");
+                        authors.bump(author.to_owned());
+                    }
+                    "add_node" => nodes += 1,
+                    "remove_node" => nodes = nodes.saturating_sub(1),
+                    "add_edge" => edges += 1,
+                    "remove_edge" => edges = edges.saturating_sub(1),
+                    _ => {}
+                }
+                match cmd {
+                    "add_node" | "remove_node" | "rename_node" | "change_node"
+                    | "change_component" => {
+                        for node in entry.nodes() {
+                            node_changes.bump(node);
+                        }
+                    }
+                    "add_edge" | "remove_edge" | "change_edge" => {
+                        if let Some(key) = entry.args.as_ref().and_then(edge_key) {
+                            edge_changes.bump(key);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            report.size_over_time.push(SizeSample {
+                revision,
+                nodes,
+                edges,
+            });
+        }
+        report.node_changes = node_changes.into_sorted();
+        report.edge_changes = edge_changes.into_sorted();
+        report.transactions_by_author = authors.into_sorted();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::churn::SizeSample;
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_churn_report() {
+        'given_a_graph_edited_by_several_authors: {
+            let mut g = Graph::new("", true);
+            g.init_journal(None);
+            g.start_transaction("build", json!({"author": "alice"}).as_object().cloned())
+                .add_node("Read", "ReadFile", None)
+                .add_node("Log", "Output", None)
+                .add_edge("Read", "out", "Log", "in", None)
+                .end_transaction("build", None);
+            g.start_transaction("tweak", json!({"author": "bob"}).as_object().cloned())
+                .set_node_metadata("Log", json!({"x": 1}).as_object().cloned().unwrap())
+                .set_node_metadata("Log", json!({"x": 2}).as_object().cloned().unwrap())
+                .end_transaction("tweak", None);
+            g.start_transaction("tweak", json!({"author": "bob"}).as_object().cloned())
+                .remove_node("Read")
+                .end_transaction("tweak", None);
+            'when_computing_the_churn_report: {
+                let report = g.churn_report();
+                'then_it_should_summarize_the_history: {
+                    assert!(report.node_changes.contains(&("Log".to_owned(), 3)));
+                    assert_eq!(
+                        report.edge_changes,
+                        vec![("Read.out -> Log.in".to_owned(), 3)]
+                    );
+                    assert_eq!(report.transactions_by_author[0], ("bob".to_owned(), 2));
+                    assert!(report
+                        .transactions_by_author
+                        .contains(&("alice".to_owned(), 1)));
+                    assert_eq!(
+                        report.size_over_time.last(),
+                        Some(&SizeSample {
+                            revision: 3,
+                            nodes: 1,
+                            edges: 0
+                        })
+                    );
+                }
+            }
+        }
+    }
+}
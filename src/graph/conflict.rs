@@ -0,0 +1,347 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::journal::{Journal, JournalStore, TransactionEntry};
+
+/// Why a remote journal entry can't be applied cleanly
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ConflictKind {
+    /// Entry refers to a node that no longer exists locally
+    MissingNode(String),
+    /// Entry adds a node whose ID is already taken locally
+    DuplicateNode(String),
+    /// Node was changed both locally and remotely since the base revision
+    ConcurrentEdit(String),
+    /// Entry is missing arguments it needs to be replayed
+    Malformed,
+    /// Mutation policy refused the entry
+    Denied(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Conflict {
+    /// Position of the entry in the remote transaction
+    pub index: usize,
+    pub cmd: String,
+    pub kind: ConflictKind,
+}
+
+/// How `Graph::apply_remote_transaction` handles conflicting entries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// Apply nothing if any entry conflicts
+    Abort,
+    /// Apply the entries that don't conflict, keeping local changes
+    KeepLocal,
+    /// Apply concurrent edits and replace duplicate nodes; entries
+    /// referring to missing nodes are still skipped
+    KeepRemote,
+}
+
+/// Outcome of checking a remote transaction against the local graph
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConflictReport {
+    pub base_revision: usize,
+    pub conflicts: Vec<Conflict>,
+    /// Indexes of the remote entries that were applied
+    pub applied: Vec<usize>,
+}
+
+impl ConflictReport {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Check a transaction recorded against an older revision of this graph
+    ///
+    /// Local changes are the journaled transactions after `base_revision`.
+    pub fn detect_conflicts(
+        &mut self,
+        base_revision: usize,
+        remote: &[TransactionEntry],
+    ) -> ConflictReport {
+        let mut local_nodes: Vec<String> = Vec::new();
+        let current = self.current_revision.max(0) as usize;
+        for rev in (base_revision + 1)..=current {
+            if let Some(entries) = self.fetch_transaction(rev) {
+                for entry in entries.iter() {
+                    local_nodes.extend(entry.nodes());
+                }
+            }
+        }
+
+        // Nodes added or removed earlier in the remote transaction
+        let mut added: Vec<String> = Vec::new();
+        let mut removed: Vec<String> = Vec::new();
+        let mut conflicts = Vec::new();
+        for (index, entry) in remote.iter().enumerate() {
+            let cmd = entry.cmd.clone().unwrap_or_default();
+            let mut conflict = |kind| {
+                conflicts.push(Conflict {
+                    index,
+                    cmd: cmd.clone(),
+                    kind,
+                })
+            };
+            let exists = |id: &String, graph: &Graph| {
+                (graph.get_node(id).is_some() && !removed.contains(id)) || added.contains(id)
+            };
+            if !entry.is_well_formed() {
+                conflict(ConflictKind::Malformed);
+                continue;
+            }
+            let nodes = entry.nodes();
+            if cmd == "add_node" {
+                for id in nodes.iter() {
+                    if exists(id, self) {
+                        conflict(ConflictKind::DuplicateNode(id.clone()));
+                    }
+                }
+                added.extend(nodes);
+                continue;
+            }
+            for id in nodes.iter() {
+                if !exists(id, self) {
+                    conflict(ConflictKind::MissingNode(id.clone()));
+                } else if local_nodes.contains(id) && !added.contains(id) {
+                    conflict(ConflictKind::ConcurrentEdit(id.clone()));
+                }
+            }
+            if cmd == "remove_node" || cmd == "rename_node" {
+                removed.extend(nodes);
+            }
+            if cmd == "rename_node" {
+                let args = entry.args.as_ref().and_then(|a| a.as_object());
+                if let Some(new_id) = args.and_then(|a| a.get("new_id")?.as_str()) {
+                    removed.retain(|id| id != new_id);
+                    added.push(new_id.to_owned());
+                }
+            }
+        }
+
+        ConflictReport {
+            base_revision,
+            conflicts,
+            applied: vec![],
+        }
+    }
+
+    /// Apply a transaction recorded against an older revision
+    ///
+    /// Entries are replayed in one local transaction. With
+    /// `ConflictResolution::Abort` a conflicting transaction is returned
+    /// as an error and the graph is left untouched. Malformed entries are
+    /// always skipped, and if the mutation policy refuses any entry that
+    /// would be applied, nothing is.
+    /// ```no_run
+    /// match my_graph.apply_remote_transaction(4, &entries, ConflictResolution::Abort, None) {
+    ///     Ok(report) => println!("applied {} entries", report.applied.len()),
+    ///     Err(report) => println!("{} conflicts", report.conflicts.len()),
+    /// }
+    /// ```
+    pub fn apply_remote_transaction(
+        &mut self,
+        base_revision: usize,
+        remote: &[TransactionEntry],
+        resolution: ConflictResolution,
+        metadata: Option<Map<String, Value>>,
+    ) -> Result<ConflictReport, ConflictReport> {
+        let mut report = self.detect_conflicts(base_revision, remote);
+        if resolution == ConflictResolution::Abort && !report.is_clean() {
+            return Err(report);
+        }
+
+        // Work out what gets applied, then check all of it with the policy
+        // before touching the graph
+        let mut plan = Vec::new();
+        let mut mutations = Vec::new();
+        for (index, entry) in remote.iter().enumerate() {
+            let conflicts = report
+                .conflicts
+                .iter()
+                .filter(|c| c.index == index)
+                .collect::<Vec<_>>();
+            let skip = conflicts.iter().any(|c| match c.kind {
+                ConflictKind::MissingNode(_) | ConflictKind::Malformed => true,
+                _ => resolution == ConflictResolution::KeepLocal,
+            });
+            if skip {
+                continue;
+            }
+            let replaced = conflicts
+                .iter()
+                .filter_map(|c| match &c.kind {
+                    ConflictKind::DuplicateNode(id) => Some(id.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for id in replaced.iter() {
+                mutations.extend(self.remove_node_mutations(id));
+            }
+            mutations.extend(entry.mutation(false));
+            if let Err(err) = self.check_mutations(mutations.clone()) {
+                report.conflicts.push(Conflict {
+                    index,
+                    cmd: entry.cmd.clone().unwrap_or_default(),
+                    kind: ConflictKind::Denied(err.to_string()),
+                });
+                let _ = self.permit_all(mutations);
+                return Err(report);
+            }
+            plan.push((index, entry, replaced));
+        }
+
+        self.start_transaction("apply_remote", metadata.clone());
+        for (index, entry, replaced) in plan {
+            for id in replaced.iter() {
+                self.remove_node(id);
+            }
+            self.execute_entry(entry.clone());
+            report.applied.push(index);
+        }
+        self.end_transaction("apply_remote", metadata);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::conflict::{ConflictKind, ConflictResolution};
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::{Journal, JournalStore, TransactionEntry};
+    use beady::scenario;
+    use serde_json::json;
+
+    fn remote_transaction(build: impl FnOnce(&mut Graph)) -> Vec<TransactionEntry> {
+        let mut remote = Graph::new("", true);
+        remote
+            .add_node("Read", "ReadFile", None)
+            .add_node("Write", "WriteFile", None);
+        remote.init_journal(None);
+        remote.start_transaction("remote", None);
+        build(&mut remote);
+        remote.end_transaction("remote", None);
+        remote.fetch_transaction(1).unwrap().clone()
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_conflicts() {
+        'given_a_graph_edited_locally_since_revision_0: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Write", "WriteFile", None);
+            g.init_journal(None);
+            g.remove_node("Write");
+            'when_a_remote_edit_touches_the_removed_node: {
+                let remote = remote_transaction(|r| {
+                    r.add_node("Log", "Output", None)
+                        .add_edge("Read", "out", "Write", "in", None)
+                        .add_edge("Read", "out", "Log", "in", None);
+                });
+                'then_aborting_should_report_the_conflict: {
+                    let report = g
+                        .apply_remote_transaction(0, &remote, ConflictResolution::Abort, None)
+                        .unwrap_err();
+                    assert_eq!(report.conflicts.len(), 1);
+                    assert_eq!(
+                        report.conflicts[0].kind,
+                        ConflictKind::MissingNode("Write".to_owned())
+                    );
+                    assert!(g.get_node("Log").is_none());
+                }
+                'then_keeping_local_changes_should_apply_the_rest: {
+                    let report = g
+                        .apply_remote_transaction(0, &remote, ConflictResolution::KeepLocal, None)
+                        .unwrap();
+                    assert!(g.get_node("Log").is_some());
+                    assert_eq!(g.edges.len(), 1);
+                    assert_eq!(report.applied.len(), remote.len() - 1);
+                }
+            }
+            'when_a_remote_edit_adds_a_duplicate_node: {
+                g.add_node("Log", "Output", None);
+                let remote = remote_transaction(|r| {
+                    r.add_node("Log", "Console", None);
+                });
+                let report = g.detect_conflicts(0, &remote);
+                'then_it_should_be_reported: {
+                    assert!(report
+                        .conflicts
+                        .iter()
+                        .any(|c| c.kind == ConflictKind::DuplicateNode("Log".to_owned())));
+                }
+                'then_keeping_remote_changes_should_replace_it: {
+                    g.apply_remote_transaction(0, &remote, ConflictResolution::KeepRemote, None)
+                        .unwrap();
+                    assert_eq!(g.get_node("Log").unwrap().component, "Console");
+                }
+            }
+            'when_a_remote_edit_renames_a_node_and_connects_it: {
+                let remote = remote_transaction(|r| {
+                    r.rename_node("Read", "Source")
+                        .add_node("Log", "Output", None)
+                        .add_edge("Source", "out", "Log", "in", None);
+                });
+                let report = g
+                    .apply_remote_transaction(0, &remote, ConflictResolution::KeepRemote, None)
+                    .unwrap();
+                'then_the_edit_should_see_the_new_name: {
+                    assert!(report.is_clean());
+                    assert_eq!(report.applied.len(), remote.len());
+                    assert!(g.get_node("Source").is_some());
+                    assert_eq!(g.edges.len(), 1);
+                }
+            }
+            'when_a_remote_entry_is_malformed: {
+                let mut remote = remote_transaction(|r| {
+                    r.add_node("Log", "Output", None);
+                });
+                remote.insert(
+                    1,
+                    TransactionEntry {
+                        cmd: Some("add_node".to_owned()),
+                        args: Some(json!({ "id": "Broken" })),
+                        rev: None,
+                        old: None,
+                        new: None,
+                    },
+                );
+                'then_it_should_be_reported: {
+                    let report = g.detect_conflicts(0, &remote);
+                    assert_eq!(report.conflicts.len(), 1);
+                    assert_eq!(report.conflicts[0].index, 1);
+                    assert_eq!(report.conflicts[0].kind, ConflictKind::Malformed);
+                }
+                'then_it_should_be_skipped_without_panicking: {
+                    let report = g
+                        .apply_remote_transaction(0, &remote, ConflictResolution::KeepRemote, None)
+                        .unwrap();
+                    assert!(!report.applied.contains(&1));
+                    assert!(g.get_node("Log").is_some());
+                    assert!(g.get_node("Broken").is_none());
+                }
+            }
+            'when_the_policy_refuses_a_remote_entry: {
+                g.freeze();
+                let remote = remote_transaction(|r| {
+                    r.add_node("Log", "Output", None);
+                });
+                let result =
+                    g.apply_remote_transaction(0, &remote, ConflictResolution::KeepRemote, None);
+                'then_nothing_should_be_applied: {
+                    let report = result.unwrap_err();
+                    assert!(report.applied.is_empty());
+                    assert!(report
+                        .conflicts
+                        .iter()
+                        .any(|c| matches!(c.kind, ConflictKind::Denied(_))));
+                    assert!(g.get_node("Log").is_none());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::graph::Graph;
+use super::types::{GraphExportedPort, GraphLeaf};
+
+/// One difference between two versions of a graph
+///
+/// Edges and IIPs are identified by their endpoints, written as
+/// `Node.port` or `Node.port[index]`, so reordering them is not a change.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "camelCase")]
+pub enum GraphChange {
+    AddedNode {
+        id: String,
+        component: String,
+    },
+    RemovedNode {
+        id: String,
+        component: String,
+    },
+    ChangedComponent {
+        id: String,
+        from: String,
+        to: String,
+    },
+    /// Node metadata changed, listing the keys that differ
+    ChangedNodeMetadata {
+        id: String,
+        keys: Vec<String>,
+    },
+    AddedEdge {
+        from: String,
+        to: String,
+    },
+    RemovedEdge {
+        from: String,
+        to: String,
+    },
+    AddedInitial {
+        to: String,
+        data: Value,
+    },
+    RemovedInitial {
+        to: String,
+        data: Value,
+    },
+    AddedInport {
+        name: String,
+        target: String,
+    },
+    RemovedInport {
+        name: String,
+        target: String,
+    },
+    /// Exported port now leads to another node or port
+    ChangedInport {
+        name: String,
+        from: String,
+        to: String,
+    },
+    AddedOutport {
+        name: String,
+        target: String,
+    },
+    RemovedOutport {
+        name: String,
+        target: String,
+    },
+    ChangedOutport {
+        name: String,
+        from: String,
+        to: String,
+    },
+    AddedGroup {
+        name: String,
+    },
+    RemovedGroup {
+        name: String,
+    },
+    /// Group with a different set of member nodes
+    ChangedGroup {
+        name: String,
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+    /// Graph properties changed, listing the keys that differ
+    ChangedProperties {
+        keys: Vec<String>,
+    },
+}
+
+impl fmt::Display for GraphChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphChange::AddedNode { id, component } => {
+                write!(f, "+ node {} ({})", id, component)
+            }
+            GraphChange::RemovedNode { id, component } => {
+                write!(f, "- node {} ({})", id, component)
+            }
+            GraphChange::ChangedComponent { id, from, to } => {
+                write!(f, "~ node {}: component {} -> {}", id, from, to)
+            }
+            GraphChange::ChangedNodeMetadata { id, keys } => {
+                write!(f, "~ node {}: metadata {}", id, keys.join(", "))
+            }
+            GraphChange::AddedEdge { from, to } => write!(f, "+ edge {} -> {}", from, to),
+            GraphChange::RemovedEdge { from, to } => write!(f, "- edge {} -> {}", from, to),
+            GraphChange::AddedInitial { to, data } => write!(f, "+ iip {} -> {}", data, to),
+            GraphChange::RemovedInitial { to, data } => write!(f, "- iip {} -> {}", data, to),
+            GraphChange::AddedInport { name, target } => {
+                write!(f, "+ inport {} -> {}", name, target)
+            }
+            GraphChange::RemovedInport { name, target } => {
+                write!(f, "- inport {} -> {}", name, target)
+            }
+            GraphChange::ChangedInport { name, from, to } => {
+                write!(f, "~ inport {}: {} -> {}", name, from, to)
+            }
+            GraphChange::AddedOutport { name, target } => {
+                write!(f, "+ outport {} -> {}", name, target)
+            }
+            GraphChange::RemovedOutport { name, target } => {
+                write!(f, "- outport {} -> {}", name, target)
+            }
+            GraphChange::ChangedOutport { name, from, to } => {
+                write!(f, "~ outport {}: {} -> {}", name, from, to)
+            }
+            GraphChange::AddedGroup { name } => write!(f, "+ group {}", name),
+            GraphChange::RemovedGroup { name } => write!(f, "- group {}", name),
+            GraphChange::ChangedGroup {
+                name,
+                added,
+                removed,
+            } => {
+                write!(f, "~ group {}:", name)?;
+                for node in added {
+                    write!(f, " +{}", node)?;
+                }
+                for node in removed {
+                    write!(f, " -{}", node)?;
+                }
+                Ok(())
+            }
+            GraphChange::ChangedProperties { keys } => {
+                write!(f, "~ properties {}", keys.join(", "))
+            }
+        }
+    }
+}
+
+/// Changes turning one graph into another, listed nodes first, then
+/// edges, IIPs, exported ports, groups and properties
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GraphDiff {
+    pub changes: Vec<GraphChange>,
+}
+
+impl GraphDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// One change per line, prefixed with `+`, `-` or `~`
+impl fmt::Display for GraphDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.changes.iter() {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+fn endpoint(leaf: &GraphLeaf) -> String {
+    match leaf.index {
+        Some(index) => format!("{}.{}[{}]", leaf.node_id, leaf.port, index),
+        None => format!("{}.{}", leaf.node_id, leaf.port),
+    }
+}
+
+/// Keys whose values differ between two maps, sorted
+fn changed_keys<'m>(
+    old: impl IntoIterator<Item = (&'m String, &'m Value)>,
+    new: impl IntoIterator<Item = (&'m String, &'m Value)>,
+) -> Vec<String> {
+    let old = old.into_iter().collect::<HashMap<&String, &Value>>();
+    let new = new.into_iter().collect::<HashMap<&String, &Value>>();
+    old.keys()
+        .chain(new.keys())
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| key.to_string())
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect()
+}
+
+fn diff_exports(
+    old: &HashMap<String, GraphExportedPort>,
+    new: &HashMap<String, GraphExportedPort>,
+    changes: &mut Vec<GraphChange>,
+    added: fn(String, String) -> GraphChange,
+    removed: fn(String, String) -> GraphChange,
+    changed: fn(String, String, String) -> GraphChange,
+) {
+    let target = |port: &GraphExportedPort| format!("{}.{}", port.process, port.port);
+    let names = old.keys().chain(new.keys()).collect::<BTreeSet<&String>>();
+    for name in names {
+        match (old.get(name), new.get(name)) {
+            (Some(before), None) => changes.push(removed(name.clone(), target(before))),
+            (None, Some(after)) => changes.push(added(name.clone(), target(after))),
+            (Some(before), Some(after)) if target(before) != target(after) => {
+                changes.push(changed(name.clone(), target(before), target(after)))
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Structural differences between this graph and a newer version
+    ///
+    /// Layout-only changes still show up as node metadata changes.
+    /// ```no_run
+    /// let diff = before.diff(&after);
+    /// print!("{}", diff);
+    /// ```
+    pub fn diff(&self, other: &Graph) -> GraphDiff {
+        let mut changes = Vec::new();
+
+        for node in self.nodes.iter() {
+            match other.get_node(&node.id) {
+                None => changes.push(GraphChange::RemovedNode {
+                    id: node.id.clone(),
+                    component: node.component.clone(),
+                }),
+                Some(after) => {
+                    if after.component != node.component {
+                        changes.push(GraphChange::ChangedComponent {
+                            id: node.id.clone(),
+                            from: node.component.clone(),
+                            to: after.component.clone(),
+                        });
+                    }
+                    let keys = changed_keys(
+                        node.metadata.iter().flatten(),
+                        after.metadata.iter().flatten(),
+                    );
+                    if !keys.is_empty() {
+                        changes.push(GraphChange::ChangedNodeMetadata {
+                            id: node.id.clone(),
+                            keys,
+                        });
+                    }
+                }
+            }
+        }
+        for node in other.nodes.iter() {
+            if self.get_node(&node.id).is_none() {
+                changes.push(GraphChange::AddedNode {
+                    id: node.id.clone(),
+                    component: node.component.clone(),
+                });
+            }
+        }
+
+        let edges = |graph: &Graph| {
+            graph
+                .edges
+                .iter()
+                .map(|edge| (endpoint(&edge.from), endpoint(&edge.to)))
+                .collect::<Vec<(String, String)>>()
+        };
+        let (old_edges, new_edges) = (edges(self), edges(other));
+        for (from, to) in old_edges.iter().filter(|e| !new_edges.contains(e)) {
+            changes.push(GraphChange::RemovedEdge {
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+        for (from, to) in new_edges.iter().filter(|e| !old_edges.contains(e)) {
+            changes.push(GraphChange::AddedEdge {
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+
+        let initials = |graph: &Graph| {
+            graph
+                .initializers
+                .iter()
+                .filter_map(|iip| {
+                    Some((endpoint(iip.to.as_ref()?), iip.from.as_ref()?.data.clone()))
+                })
+                .collect::<Vec<(String, Value)>>()
+        };
+        let (old_initials, new_initials) = (initials(self), initials(other));
+        for (to, data) in old_initials.iter().filter(|i| !new_initials.contains(i)) {
+            changes.push(GraphChange::RemovedInitial {
+                to: to.clone(),
+                data: data.clone(),
+            });
+        }
+        for (to, data) in new_initials.iter().filter(|i| !old_initials.contains(i)) {
+            changes.push(GraphChange::AddedInitial {
+                to: to.clone(),
+                data: data.clone(),
+            });
+        }
+
+        diff_exports(
+            &self.inports,
+            &other.inports,
+            &mut changes,
+            |name, target| GraphChange::AddedInport { name, target },
+            |name, target| GraphChange::RemovedInport { name, target },
+            |name, from, to| GraphChange::ChangedInport { name, from, to },
+        );
+        diff_exports(
+            &self.outports,
+            &other.outports,
+            &mut changes,
+            |name, target| GraphChange::AddedOutport { name, target },
+            |name, target| GraphChange::RemovedOutport { name, target },
+            |name, from, to| GraphChange::ChangedOutport { name, from, to },
+        );
+
+        for group in self.groups.iter() {
+            match other.groups.iter().find(|g| g.name == group.name) {
+                None => changes.push(GraphChange::RemovedGroup {
+                    name: group.name.clone(),
+                }),
+                Some(after) => {
+                    let added = after
+                        .nodes
+                        .iter()
+                        .filter(|node| !group.nodes.contains(node))
+                        .cloned()
+                        .collect::<Vec<String>>();
+                    let removed = group
+                        .nodes
+                        .iter()
+                        .filter(|node| !after.nodes.contains(node))
+                        .cloned()
+                        .collect::<Vec<String>>();
+                    if !added.is_empty() || !removed.is_empty() {
+                        changes.push(GraphChange::ChangedGroup {
+                            name: group.name.clone(),
+                            added,
+                            removed,
+                        });
+                    }
+                }
+            }
+        }
+        for group in other.groups.iter() {
+            if !self.groups.iter().any(|g| g.name == group.name) {
+                changes.push(GraphChange::AddedGroup {
+                    name: group.name.clone(),
+                });
+            }
+        }
+
+        let keys = changed_keys(self.properties.iter(), other.properties.iter());
+        if !keys.is_empty() {
+            changes.push(GraphChange::ChangedProperties { keys });
+        }
+
+        GraphDiff { changes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::diff::GraphChange;
+    use crate::graph::graph::Graph;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_diff() {
+        'given_two_versions_of_a_graph: {
+            let mut before = Graph::new("flow", true);
+            before
+                .add_node("Read", "ReadFile", None)
+                .add_node("Parse", "ParseJson", None)
+                .add_node("Log", "Output", None)
+                .add_edge("Read", "out", "Parse", "in", None)
+                .add_edge("Parse", "out", "Log", "in", None)
+                .add_initial(json!("a.txt"), "Read", "source", None)
+                .add_inport("file", "Read", "source", None)
+                .add_group("io", vec!["Read".to_owned()], None);
+            let mut after = Graph::new("flow", true);
+            after
+                .add_node("Read", "ReadFile", json!({"x": 10}).as_object().cloned())
+                .add_node("Parse", "ParseYaml", None)
+                .add_node("Store", "WriteFile", None)
+                .add_edge("Parse", "out", "Store", "in", None)
+                .add_edge("Read", "out", "Parse", "in", None)
+                .add_initial(json!("b.txt"), "Read", "source", None)
+                .add_inport("file", "Read", "path", None)
+                .add_group("io", vec!["Read".to_owned(), "Store".to_owned()], None);
+            'when_comparing_them: {
+                let diff = before.diff(&after);
+                'then_each_change_should_be_listed: {
+                    assert_eq!(
+                        diff.changes,
+                        vec![
+                            GraphChange::ChangedNodeMetadata {
+                                id: "Read".to_owned(),
+                                keys: vec!["x".to_owned()]
+                            },
+                            GraphChange::ChangedComponent {
+                                id: "Parse".to_owned(),
+                                from: "ParseJson".to_owned(),
+                                to: "ParseYaml".to_owned()
+                            },
+                            GraphChange::RemovedNode {
+                                id: "Log".to_owned(),
+                                component: "Output".to_owned()
+                            },
+                            GraphChange::AddedNode {
+                                id: "Store".to_owned(),
+                                component: "WriteFile".to_owned()
+                            },
+                            GraphChange::RemovedEdge {
+                                from: "Parse.out".to_owned(),
+                                to: "Log.in".to_owned()
+                            },
+                            GraphChange::AddedEdge {
+                                from: "Parse.out".to_owned(),
+                                to: "Store.in".to_owned()
+                            },
+                            GraphChange::RemovedInitial {
+                                to: "Read.source".to_owned(),
+                                data: json!("a.txt")
+                            },
+                            GraphChange::AddedInitial {
+                                to: "Read.source".to_owned(),
+                                data: json!("b.txt")
+                            },
+                            GraphChange::ChangedInport {
+                                name: "file".to_owned(),
+                                from: "Read.source".to_owned(),
+                                to: "Read.path".to_owned()
+                            },
+                            GraphChange::ChangedGroup {
+                                name: "io".to_owned(),
+                                added: vec!["Store".to_owned()],
+                                removed: vec![]
+                            },
+                        ]
+                    );
+                }
+                'then_it_should_read_as_a_report: {
+                    let report = diff.to_string();
+                    assert!(report.contains("~ node Parse: component ParseJson -> ParseYaml\n"));
+                    assert!(report.contains("+ edge Parse.out -> Store.in\n"));
+                    assert!(report.contains("- iip \"a.txt\" -> Read.source\n"));
+                }
+                'then_it_should_serialize_for_tools: {
+                    assert_eq!(
+                        serde_json::to_value(&diff.changes[3]).unwrap(),
+                        json!({"change": "addedNode", "id": "Store", "component": "WriteFile"})
+                    );
+                }
+            }
+            'when_comparing_a_graph_with_itself: {
+                'then_there_should_be_no_changes: {
+                    assert!(before.diff(&before).is_empty());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::graph::Graph;
+use super::types::{GraphExportedPort, GraphNode};
+
+/// Compact textual summary of a graph, one item per line:
+/// ```text
+/// Graph "example": 2 nodes, 1 edges, 1 IIPs, 0 groups
+///   Read(ReadFile)
+///   Log(Output)
+///   Read.out -> Log.in
+///   "a.txt" -> Read.in
+/// ```
+impl<'a> fmt::Display for Graph<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Graph {:?}: {} nodes, {} edges, {} IIPs, {} groups",
+            self.name,
+            self.nodes.len(),
+            self.edges.len(),
+            self.initializers.len(),
+            self.groups.len()
+        )?;
+        for node in self.nodes.iter() {
+            writeln!(f, "  {}({})", node.id, node.component)?;
+        }
+        for edge in self.edges.iter() {
+            writeln!(f, "  {} -> {}", edge.source(), edge.target())?;
+        }
+        for iip in self.initializers.iter() {
+            if let (Some(from), Some(to)) = (iip.from.as_ref(), iip.target()) {
+                writeln!(f, "  {} -> {}", from.data, to)?;
+            }
+        }
+        for (name, port) in sorted_ports(&self.inports) {
+            writeln!(f, "  inport {} -> {}.{}", name, port.process, port.port)?;
+        }
+        for (name, port) in sorted_ports(&self.outports) {
+            writeln!(f, "  outport {} <- {}.{}", name, port.process, port.port)?;
+        }
+        for group in self.groups.iter() {
+            writeln!(f, "  group {}: {}", group.name, group.nodes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Debug for Graph<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Graph")
+            .field("name", &self.name)
+            .field("case_sensitive", &self.case_sensitive)
+            .field("properties", &self.properties)
+            .field("nodes", &self.nodes)
+            .field("edges", &self.edges)
+            .field("initializers", &self.initializers)
+            .field("groups", &self.groups)
+            .field("inports", &self.inports)
+            .field("outports", &self.outports)
+            .field("current_revision", &self.current_revision)
+            .finish_non_exhaustive()
+    }
+}
+
+fn sorted_ports(ports: &HashMap<String, GraphExportedPort>) -> Vec<(&String, &GraphExportedPort)> {
+    let mut ports: Vec<_> = ports.iter().collect();
+    ports.sort_by_key(|(name, _)| *name);
+    ports
+}
+
+fn boxed(node: &GraphNode) -> [String; 4] {
+    let width = node.id.len().max(node.component.len());
+    let border = format!("+{}+", "-".repeat(width + 2));
+    [
+        border.clone(),
+        format!("| {:<width$} |", node.id, width = width),
+        format!("| {:<width$} |", node.component, width = width),
+        border,
+    ]
+}
+
+impl<'a> Graph<'a> {
+    /// Render the graph as rows of boxes, one row per layer, with the
+    /// connections leaving each row listed underneath it:
+    /// ```text
+    /// "a.txt" -> Read.in
+    /// +----------+
+    /// | Read     |
+    /// | ReadFile |
+    /// +----------+
+    ///   | Read.out -> Log.in
+    ///   v
+    /// +--------+
+    /// | Log    |
+    /// | Output |
+    /// +--------+
+    /// ```
+    /// Connections going back to an earlier row, as in feedback loops,
+    /// are marked with `^`. Meant for small graphs in test output and
+    /// REPL sessions.
+    pub fn to_ascii_art(&self) -> String {
+        let layer_of = self.layers();
+        let layer_count = layer_of.iter().map(|layer| layer + 1).max().unwrap_or(0);
+        let index: HashMap<&str, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.as_str(), i))
+            .collect();
+
+        let mut out = String::new();
+        for iip in self.initializers.iter() {
+            if let (Some(from), Some(to)) = (iip.from.as_ref(), iip.target()) {
+                out.push_str(&format!("{} -> {}\n", from.data, to));
+            }
+        }
+        for layer in 0..layer_count {
+            let boxes: Vec<[String; 4]> = self
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| layer_of[*i] == layer)
+                .map(|(_, node)| boxed(node))
+                .collect();
+            for row in 0..4 {
+                let line = boxes
+                    .iter()
+                    .map(|b| b[row].as_str())
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                out.push_str(&line);
+                out.push('\n');
+            }
+
+            let mut forward = false;
+            for edge in self.edges.iter() {
+                let (Some(from), Some(to)) = (
+                    index.get(edge.from.node_id.as_str()),
+                    index.get(edge.to.node_id.as_str()),
+                ) else {
+                    continue;
+                };
+                if layer_of[*from] != layer {
+                    continue;
+                }
+                let marker = if layer_of[*to] > layer {
+                    forward = true;
+                    '|'
+                } else {
+                    '^'
+                };
+                out.push_str(&format!(
+                    "  {} {} -> {}\n",
+                    marker,
+                    edge.source(),
+                    edge.target()
+                ));
+            }
+            if forward {
+                out.push_str("  v\n");
+            }
+        }
+        out
+    }
+
+    /// Longest-path layer of each node, ignoring edges that close a cycle
+    fn layers(&self) -> Vec<usize> {
+        let index: HashMap<&str, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.as_str(), i))
+            .collect();
+        let mut outgoing = vec![Vec::new(); self.nodes.len()];
+        for edge in self.edges.iter() {
+            if let (Some(from), Some(to)) = (
+                index.get(edge.from.node_id.as_str()),
+                index.get(edge.to.node_id.as_str()),
+            ) {
+                outgoing[*from].push(*to);
+            }
+        }
+
+        // Depth-first order without back edges gives a topological order
+        let mut state = vec![0u8; self.nodes.len()];
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut dag = vec![Vec::new(); self.nodes.len()];
+        for root in 0..self.nodes.len() {
+            if state[root] != 0 {
+                continue;
+            }
+            let mut stack = vec![(root, 0)];
+            state[root] = 1;
+            while let Some((node, next)) = stack.pop() {
+                if let Some(&child) = outgoing[node].get(next) {
+                    stack.push((node, next + 1));
+                    match state[child] {
+                        0 => {
+                            dag[node].push(child);
+                            state[child] = 1;
+                            stack.push((child, 0));
+                        }
+                        2 => dag[node].push(child),
+                        _ => {}
+                    }
+                } else {
+                    state[node] = 2;
+                    order.push(node);
+                }
+            }
+        }
+
+        let mut layer = vec![0; self.nodes.len()];
+        for &node in order.iter().rev() {
+            for &child in dag[node].iter() {
+                layer[child] = layer[child].max(layer[node] + 1);
+            }
+        }
+        layer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_display() {
+        'given_a_small_graph: {
+            let mut g = Graph::new("example", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Log", "Output", None)
+                .add_edge("Read", "out", "Log", "in", None)
+                .add_initial(json!("a.txt"), "Read", "in", None)
+                .add_inport("file", "Read", "in", None);
+            'when_formatting_it: {
+                'then_it_should_list_every_item: {
+                    assert_eq!(
+                        g.to_string(),
+                        "Graph \"example\": 2 nodes, 1 edges, 1 IIPs, 0 groups\n  Read(ReadFile)\n  Log(Output)\n  Read.out -> Log.in\n  \"a.txt\" -> Read.in\n  inport file -> Read.in\n"
+                    );
+                    assert!(format!("{:?}", g).starts_with("Graph { name: \"example\""));
+                }
+            }
+            'when_rendering_ascii_art: {
+                'then_it_should_draw_boxes_in_layers: {
+                    assert_eq!(
+                        g.to_ascii_art(),
+                        [
+                            "\"a.txt\" -> Read.in",
+                            "+----------+",
+                            "| Read     |",
+                            "| ReadFile |",
+                            "+----------+",
+                            "  | Read.out -> Log.in",
+                            "  v",
+                            "+--------+",
+                            "| Log    |",
+                            "| Output |",
+                            "+--------+",
+                            "",
+                        ]
+                        .join("\n")
+                    );
+                }
+            }
+        }
+        'given_a_graph_with_a_feedback_loop: {
+            let mut g = Graph::new("", true);
+            g.add_node("A", "a", None)
+                .add_node("B", "b", None)
+                .add_node("C", "c", None)
+                .add_edge("A", "out", "B", "in", None)
+                .add_edge("A", "out", "C", "in", None)
+                .add_edge("B", "out", "A", "in", None);
+            'then_it_should_mark_the_back_edge: {
+                let art = g.to_ascii_art();
+                assert!(art.contains("+---+  +---+\n| B |  | C |"));
+                assert!(art.contains("  ^ B.out -> A.in"));
+            }
+        }
+    }
+}
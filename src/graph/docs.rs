@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::registry::{ComponentRegistry, ComponentSpec, PortSpec};
+use super::types::{GraphExportedPort, GraphNode};
+
+/// Graph property, node metadata and exported port metadata key holding
+/// a one-line description
+pub const DESCRIPTION: &str = "description";
+/// Graph property holding longer Markdown documentation
+pub const README: &str = "readme";
+
+fn description_of(metadata: &Option<Map<String, Value>>) -> Option<&str> {
+    metadata
+        .as_ref()
+        .and_then(|meta| meta.get(DESCRIPTION))
+        .and_then(|d| d.as_str())
+}
+
+fn description_patch(description: &str) -> Map<String, Value> {
+    let mut patch = Map::new();
+    patch.insert(DESCRIPTION.to_owned(), Value::from(description));
+    patch
+}
+
+impl GraphNode {
+    pub fn description(&self) -> Option<&str> {
+        description_of(&self.metadata)
+    }
+}
+
+impl GraphExportedPort {
+    pub fn description(&self) -> Option<&str> {
+        description_of(&self.metadata)
+    }
+}
+
+/// Escape text for use inside a Markdown table cell
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+impl<'a> Graph<'a> {
+    pub fn description(&self) -> Option<&str> {
+        self.properties.get(DESCRIPTION).and_then(|d| d.as_str())
+    }
+
+    pub fn readme(&self) -> Option<&str> {
+        self.properties.get(README).and_then(|r| r.as_str())
+    }
+
+    pub fn set_description(&mut self, description: &str) -> &mut Self {
+        self.set_properties(description_patch(description))
+    }
+
+    pub fn set_readme(&mut self, readme: &str) -> &mut Self {
+        let mut properties = Map::new();
+        properties.insert(README.to_owned(), Value::from(readme));
+        self.set_properties(properties)
+    }
+
+    pub fn set_node_description(&mut self, id: &str, description: &str) -> &mut Self {
+        self.set_node_metadata(id, description_patch(description))
+    }
+
+    pub fn set_inport_description(&mut self, public_port: &str, description: &str) -> &mut Self {
+        self.set_inports_metadata(public_port, description_patch(description))
+    }
+
+    pub fn set_outport_description(&mut self, public_port: &str, description: &str) -> &mut Self {
+        self.set_outports_metadata(public_port, description_patch(description))
+    }
+
+    /// Describe the graph as a component, for use as a subgraph
+    ///
+    /// Exported ports become the component's ports, typed after the
+    /// component ports they expose. This is the graph's port signature,
+    /// which `check_composition` holds the graphs using it to.
+    pub fn component_spec(&self, registry: Option<&ComponentRegistry>) -> ComponentSpec {
+        let ports = |exported: &HashMap<String, GraphExportedPort>, inbound| {
+            let mut names = exported.keys().collect::<Vec<&String>>();
+            names.sort();
+            names
+                .into_iter()
+                .map(|name| {
+                    let (datatype, description, schema) =
+                        self.exported_port_contract(&exported[name], inbound, registry);
+                    PortSpec {
+                        datatype: Some(datatype),
+                        description: if description.is_empty() {
+                            None
+                        } else {
+                            Some(description)
+                        },
+                        schema,
+                        ..PortSpec::new(name)
+                    }
+                })
+                .collect()
+        };
+        ComponentSpec {
+            name: self.name.clone(),
+            description: self.description().map(|d| d.to_owned()),
+            in_ports: ports(&self.inports, true),
+            out_ports: ports(&self.outports, false),
+        }
+    }
+
+    /// Render Markdown documentation for the graph
+    ///
+    /// Covers the graph's description property, its nodes, exported ports
+    /// and IIPs, and embeds a Mermaid diagram of the connections.
+    /// Component and port descriptions are taken from the registry when
+    /// one is given.
+    /// ```no_run
+    /// std::fs::write("README.md", my_graph.generate_docs(Some(&registry)))?;
+    /// ```
+    pub fn generate_docs(&self, registry: Option<&ComponentRegistry>) -> String {
+        let mut doc = String::new();
+        let title = if self.name.is_empty() {
+            "Graph"
+        } else {
+            &self.name
+        };
+        doc.push_str(&format!("# {}\n\n", title));
+        if let Some(description) = self.description() {
+            doc.push_str(&format!("{}\n\n", description));
+        }
+        if let Some(readme) = self.readme() {
+            doc.push_str(&format!("{}\n\n", readme.trim_end()));
+        }
+
+        doc.push_str("## Nodes\n\n");
+        doc.push_str("| Node | Component | Description |\n|---|---|---|\n");
+        for node in self.nodes.iter() {
+            let description = node
+                .description()
+                .map(|d| d.to_owned())
+                .or_else(|| {
+                    registry
+                        .and_then(|r| r.get(&node.component))
+                        .and_then(|spec| spec.description.clone())
+                })
+                .unwrap_or_default();
+            doc.push_str(&format!(
+                "| {} | `{}` | {} |\n",
+                cell(&node.id),
+                cell(&node.component),
+                cell(&description)
+            ));
+        }
+        doc.push('\n');
+
+        for (heading, ports, inbound) in [
+            ("Inports", &self.inports, true),
+            ("Outports", &self.outports, false),
+        ] {
+            if ports.is_empty() {
+                continue;
+            }
+            doc.push_str(&format!("## {}\n\n", heading));
+            doc.push_str("| Port | Node | Type | Description |\n|---|---|---|---|\n");
+            let mut names = ports.keys().collect::<Vec<&String>>();
+            names.sort();
+            for name in names {
+                let exported = &ports[name];
+                let (datatype, description, _) =
+                    self.exported_port_contract(exported, inbound, registry);
+                doc.push_str(&format!(
+                    "| {} | {}.{} | {} | {} |\n",
+                    cell(name),
+                    cell(&exported.process),
+                    cell(&exported.port),
+                    cell(&datatype),
+                    cell(&description)
+                ));
+            }
+            doc.push('\n');
+        }
+
+        if !self.initializers.is_empty() {
+            doc.push_str("## Configuration\n\n");
+            doc.push_str("| Node | Port | Value |\n|---|---|---|\n");
+            for iip in self.initializers.iter() {
+                if let (Some(to), Some(from)) = (iip.to.as_ref(), iip.from.as_ref()) {
+                    doc.push_str(&format!(
+                        "| {} | {} | `{}` |\n",
+                        cell(&to.node_id),
+                        cell(&to.port),
+                        cell(&from.data.to_string())
+                    ));
+                }
+            }
+            doc.push('\n');
+        }
+
+        doc.push_str("## Diagram\n\n```mermaid\ngraph LR\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            doc.push_str(&format!(
+                "    n{}[\"{}<br/>{}\"]\n",
+                i, node.id, node.component
+            ));
+        }
+        let index = |id: &str| self.nodes.iter().position(|node| node.id == id);
+        for edge in self.edges.iter() {
+            if let (Some(from), Some(to)) = (index(&edge.from.node_id), index(&edge.to.node_id)) {
+                doc.push_str(&format!(
+                    "    n{} -- \"{} → {}\" --> n{}\n",
+                    from, edge.from.port, edge.to.port, to
+                ));
+            }
+        }
+        doc.push_str("```\n");
+        doc
+    }
+
+    /// Datatype, description and schema of an exported port
+    ///
+    /// A description or schema in the export's metadata takes precedence
+    /// over the one declared by the component.
+    fn exported_port_contract(
+        &self,
+        exported: &GraphExportedPort,
+        inbound: bool,
+        registry: Option<&ComponentRegistry>,
+    ) -> (String, String, Option<Value>) {
+        let spec = self
+            .get_node(&exported.process)
+            .and_then(|node| registry.and_then(|r| r.get(&node.component)))
+            .and_then(|spec| {
+                if inbound {
+                    spec.get_inport(&exported.port)
+                } else {
+                    spec.get_outport(&exported.port)
+                }
+            });
+        (
+            spec.and_then(|p| p.datatype.clone())
+                .unwrap_or_else(|| "all".to_owned()),
+            exported
+                .description()
+                .map(|d| d.to_owned())
+                .or_else(|| spec.and_then(|p| p.description.clone()))
+                .unwrap_or_default(),
+            exported
+                .metadata
+                .as_ref()
+                .and_then(|m| m.get("schema"))
+                .cloned()
+                .or_else(|| spec.and_then(|p| p.schema.clone())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::registry::ComponentRegistry;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_docs() {
+        'given_a_graph_and_a_registry: {
+            let registry = ComponentRegistry::from_json_string(
+                r#"[
+                    {"name": "ReadFile", "description": "Read a file from disk", "inPorts": [{"id": "source", "type": "string", "description": "File path"}], "outPorts": [{"id": "out"}]},
+                    {"name": "Output", "inPorts": [{"id": "in"}], "outPorts": []}
+                ]"#,
+            )
+            .unwrap();
+            let mut g = Graph::new("reader", true);
+            g.set_properties(
+                json!({"description": "Prints a file"})
+                    .as_object()
+                    .cloned()
+                    .unwrap(),
+            )
+            .add_node("Read", "ReadFile", None)
+            .add_node("Log", "Output", None)
+            .add_edge("Read", "out", "Log", "in", None)
+            .add_inport("path", "Read", "source", None)
+            .add_initial(json!("README.md"), "Read", "source", None);
+            'when_generating_docs: {
+                let doc = g.generate_docs(Some(&registry));
+                'then_it_should_document_the_graph: {
+                    assert!(doc.starts_with("# reader\n\nPrints a file\n"));
+                    assert!(doc.contains("| Read | `ReadFile` | Read a file from disk |"));
+                    assert!(doc.contains("| path | Read.source | string | File path |"));
+                    assert!(doc.contains("| Read | source | `\"README.md\"` |"));
+                    assert!(doc.contains("n0 -- \"out → in\" --> n1"));
+                }
+            }
+            'when_documenting_inline: {
+                g.set_readme("Usage notes")
+                    .set_node_description("Log", "Prints to stdout")
+                    .set_inport_description("path", "File to print");
+                let doc = g.generate_docs(Some(&registry));
+                'then_inline_docs_should_be_used: {
+                    assert_eq!(
+                        g.get_node("Log").unwrap().description(),
+                        Some("Prints to stdout")
+                    );
+                    assert!(doc.contains("Prints a file\n\nUsage notes\n"));
+                    assert!(doc.contains("| Log | `Output` | Prints to stdout |"));
+                    assert!(doc.contains("| path | Read.source | string | File to print |"));
+                }
+                'then_the_component_info_should_carry_them: {
+                    let spec = g.component_spec(Some(&registry));
+                    assert_eq!(spec.name, "reader");
+                    assert_eq!(spec.description.as_deref(), Some("Prints a file"));
+                    assert_eq!(spec.in_ports[0].id, "path");
+                    assert_eq!(spec.in_ports[0].datatype.as_deref(), Some("string"));
+                    assert_eq!(
+                        spec.in_ports[0].description.as_deref(),
+                        Some("File to print")
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::graph::Graph;
+
+/// Header written before an encoded command stream
+pub const MAGIC: &[u8; 8] = b"uC/Flo01";
+
+/// Component available in a device's firmware
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddedComponent {
+    /// Identifier the firmware knows the component by
+    pub id: u8,
+    #[serde(default)]
+    pub in_ports: Vec<String>,
+    #[serde(default)]
+    pub out_ports: Vec<String>,
+}
+
+/// Components a device can instantiate
+#[derive(Clone, Debug, Default)]
+pub struct EmbeddedTarget {
+    components: HashMap<String, EmbeddedComponent>,
+}
+
+impl EmbeddedTarget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, name: &str, component: EmbeddedComponent) -> &mut Self {
+        self.components.insert(name.to_owned(), component);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&EmbeddedComponent> {
+        self.components.get(name)
+    }
+}
+
+/// Packet value a device can receive as an IIP
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DevicePacket {
+    Void,
+    Boolean(bool),
+    Integer(i32),
+}
+
+/// Command setting up a network on a device
+///
+/// Nodes are numbered from 1 in the order they were created.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DeviceCommand {
+    Reset,
+    CreateComponent { component: u8 },
+    ConnectNodes { src: u8, src_port: u8, tgt: u8, tgt_port: u8 },
+    SendPacket { node: u8, port: u8, packet: DevicePacket },
+    StartNetwork,
+}
+
+impl DeviceCommand {
+    /// Fixed size encoding: the command type followed by its arguments
+    pub fn encode(&self) -> [u8; 8] {
+        let mut out = [0u8; 8];
+        match self {
+            DeviceCommand::Reset => out[0] = 0,
+            DeviceCommand::CreateComponent { component } => {
+                out[0] = 1;
+                out[1] = *component;
+            }
+            DeviceCommand::ConnectNodes {
+                src,
+                src_port,
+                tgt,
+                tgt_port,
+            } => {
+                out[..5].copy_from_slice(&[2, *src, *tgt, *src_port, *tgt_port]);
+            }
+            DeviceCommand::SendPacket { node, port, packet } => {
+                out[..3].copy_from_slice(&[3, *node, *port]);
+                match packet {
+                    DevicePacket::Void => out[3] = 0,
+                    DevicePacket::Boolean(b) => {
+                        out[3] = 1;
+                        out[4] = *b as u8;
+                    }
+                    DevicePacket::Integer(i) => {
+                        out[3] = 2;
+                        out[4..].copy_from_slice(&i.to_le_bytes());
+                    }
+                }
+            }
+            DeviceCommand::StartNetwork => out[0] = 4,
+        }
+        out
+    }
+}
+
+/// Encode commands into a stream a device can load, prefixed with `MAGIC`
+pub fn encode_commands(commands: &[DeviceCommand]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    for command in commands {
+        out.extend_from_slice(&command.encode());
+    }
+    out
+}
+
+/// Part of a graph that cannot run on the device
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EmbedIssue {
+    /// Component not available in the firmware
+    UnsupportedComponent { node: String, component: String },
+    /// Port not declared by the embedded component
+    UnknownPort { node: String, port: String },
+    /// Addressable port connection; devices only have plain ports
+    IndexedPort { node: String, port: String },
+    /// IIP that is not null, a boolean or a 32-bit integer
+    UnsupportedPacket { node: String, port: String },
+    /// Device networks address at most 255 nodes
+    TooManyNodes(usize),
+}
+
+fn packet(value: &Value) -> Option<DevicePacket> {
+    match value {
+        Value::Null => Some(DevicePacket::Void),
+        Value::Bool(b) => Some(DevicePacket::Boolean(*b)),
+        Value::Number(n) => n
+            .as_i64()
+            .and_then(|i| i32::try_from(i).ok())
+            .map(DevicePacket::Integer),
+        _ => None,
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Lower the graph to commands for a microcontroller runtime
+    ///
+    /// Every node must use a component the target firmware provides, edges
+    /// and IIPs must use plain declared ports, and IIPs must be null,
+    /// boolean or 32-bit integer values. Exported ports are ignored, as the
+    /// device network is not embedded in another graph. All problems are
+    /// returned together.
+    /// ```no_run
+    /// let stream = encode_commands(&my_graph.to_device_commands(&target)?);
+    /// ```
+    pub fn to_device_commands(
+        &self,
+        target: &EmbeddedTarget,
+    ) -> Result<Vec<DeviceCommand>, Vec<EmbedIssue>> {
+        let mut issues = Vec::new();
+        if self.nodes.len() > u8::MAX as usize {
+            return Err(vec![EmbedIssue::TooManyNodes(self.nodes.len())]);
+        }
+
+        let mut commands = vec![DeviceCommand::Reset];
+        let mut numbers: HashMap<&str, (u8, &EmbeddedComponent)> = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            match target.get(&node.component) {
+                Some(component) => {
+                    numbers.insert(&node.id, (i as u8 + 1, component));
+                    commands.push(DeviceCommand::CreateComponent {
+                        component: component.id,
+                    });
+                }
+                None => issues.push(EmbedIssue::UnsupportedComponent {
+                    node: node.id.clone(),
+                    component: node.component.clone(),
+                }),
+            }
+        }
+
+        let mut port = |node: &str, port: &str, index: Option<usize>, outport: bool| {
+            let (number, component) = numbers.get(node)?;
+            let ports = if outport {
+                &component.out_ports
+            } else {
+                &component.in_ports
+            };
+            if index.is_some() {
+                issues.push(EmbedIssue::IndexedPort {
+                    node: node.to_owned(),
+                    port: port.to_owned(),
+                });
+                return None;
+            }
+            match ports.iter().position(|p| self.get_port_name(p) == port) {
+                Some(position) => Some((*number, position as u8)),
+                None => {
+                    issues.push(EmbedIssue::UnknownPort {
+                        node: node.to_owned(),
+                        port: port.to_owned(),
+                    });
+                    None
+                }
+            }
+        };
+
+        let mut connections = Vec::new();
+        for edge in self.edges.iter() {
+            let src = port(&edge.from.node_id, &edge.from.port, edge.from.index, true);
+            let tgt = port(&edge.to.node_id, &edge.to.port, edge.to.index, false);
+            if let (Some((src, src_port)), Some((tgt, tgt_port))) = (src, tgt) {
+                connections.push(DeviceCommand::ConnectNodes {
+                    src,
+                    src_port,
+                    tgt,
+                    tgt_port,
+                });
+            }
+        }
+        let mut packets = Vec::new();
+        let mut unsupported = Vec::new();
+        for iip in self.initializers.iter() {
+            let (to, from) = match (iip.to.as_ref(), iip.from.as_ref()) {
+                (Some(to), Some(from)) => (to, from),
+                _ => continue,
+            };
+            if let Some((node, port)) = port(&to.node_id, &to.port, to.index, false) {
+                match packet(&from.data) {
+                    Some(packet) => packets.push(DeviceCommand::SendPacket { node, port, packet }),
+                    None => unsupported.push(EmbedIssue::UnsupportedPacket {
+                        node: to.node_id.clone(),
+                        port: to.port.clone(),
+                    }),
+                }
+            }
+        }
+
+        issues.extend(unsupported);
+        if !issues.is_empty() {
+            return Err(issues);
+        }
+        commands.extend(connections);
+        commands.extend(packets);
+        commands.push(DeviceCommand::StartNetwork);
+        Ok(commands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::embedded::{
+        encode_commands, DeviceCommand, DevicePacket, EmbedIssue, EmbeddedComponent,
+        EmbeddedTarget, MAGIC,
+    };
+    use crate::graph::graph::Graph;
+    use beady::scenario;
+    use serde_json::json;
+
+    fn target() -> EmbeddedTarget {
+        let mut target = EmbeddedTarget::new();
+        target
+            .register(
+                "Timer",
+                EmbeddedComponent {
+                    id: 10,
+                    in_ports: vec!["interval".to_owned()],
+                    out_ports: vec!["out".to_owned()],
+                },
+            )
+            .register(
+                "Led",
+                EmbeddedComponent {
+                    id: 11,
+                    in_ports: vec!["in".to_owned(), "pin".to_owned()],
+                    out_ports: vec![],
+                },
+            );
+        target
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_embedded_export() {
+        'given_a_blink_graph: {
+            let mut g = Graph::new("blink", false);
+            g.add_node("Timer", "Timer", None)
+                .add_node("Led", "Led", None)
+                .add_edge("Timer", "out", "Led", "in", None)
+                .add_initial(json!(500), "Timer", "interval", None)
+                .add_initial(json!(13), "Led", "pin", None);
+            'when_lowering_it_for_a_device: {
+                let commands = g.to_device_commands(&target()).unwrap();
+                'then_it_should_create_connect_and_start_the_network: {
+                    assert_eq!(
+                        commands,
+                        vec![
+                            DeviceCommand::Reset,
+                            DeviceCommand::CreateComponent { component: 10 },
+                            DeviceCommand::CreateComponent { component: 11 },
+                            DeviceCommand::ConnectNodes {
+                                src: 1,
+                                src_port: 0,
+                                tgt: 2,
+                                tgt_port: 0
+                            },
+                            DeviceCommand::SendPacket {
+                                node: 1,
+                                port: 0,
+                                packet: DevicePacket::Integer(500)
+                            },
+                            DeviceCommand::SendPacket {
+                                node: 2,
+                                port: 1,
+                                packet: DevicePacket::Integer(13)
+                            },
+                            DeviceCommand::StartNetwork,
+                        ]
+                    );
+                }
+                'then_it_should_encode_to_fixed_size_commands: {
+                    let stream = encode_commands(&commands);
+                    assert_eq!(&stream[..8], MAGIC);
+                    assert_eq!(stream.len(), 8 + commands.len() * 8);
+                    assert_eq!(&stream[40..48], &[3, 1, 0, 2, 0xf4, 0x01, 0, 0]);
+                }
+            }
+            'when_it_uses_unsupported_parts: {
+                g.add_node("Http", "HttpRequest", None)
+                    .add_initial(json!("fast"), "Timer", "interval", None)
+                    .add_edge("Timer", "tick", "Led", "in", None);
+                let issues = g.to_device_commands(&target()).err().unwrap();
+                'then_every_problem_should_be_reported: {
+                    assert_eq!(
+                        issues,
+                        vec![
+                            EmbedIssue::UnsupportedComponent {
+                                node: "Http".to_owned(),
+                                component: "HttpRequest".to_owned()
+                            },
+                            EmbedIssue::UnknownPort {
+                                node: "Timer".to_owned(),
+                                port: "tick".to_owned()
+                            },
+                            EmbedIssue::UnsupportedPacket {
+                                node: "Timer".to_owned(),
+                                port: "interval".to_owned()
+                            },
+                        ]
+                    );
+                }
+            }
+        }
+    }
+}
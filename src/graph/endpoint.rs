@@ -0,0 +1,321 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+
+use serde::{Deserialize, Serialize};
+
+use super::types::{GraphEdge, GraphIIP, GraphLeaf};
+
+macro_rules! string_id {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn new(id: &str) -> Self {
+                Self(id.to_owned())
+            }
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            pub fn into_string(self) -> String {
+                self.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Borrow<str> for $name {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(id: &str) -> Self {
+                Self(id.to_owned())
+            }
+        }
+
+        impl From<&String> for $name {
+            fn from(id: &String) -> Self {
+                Self(id.clone())
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(id: String) -> Self {
+                Self(id)
+            }
+        }
+
+        impl From<&$name> for $name {
+            fn from(id: &$name) -> Self {
+                id.clone()
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+    };
+}
+
+string_id!(
+    /// ID of a node in a graph
+    NodeId
+);
+
+string_id!(
+    /// Name of a port on a node, or of an exported graph port
+    PortName
+);
+
+/// A port on a node, optionally addressing one slot of an array port
+///
+/// Keeps node and port apart so they can't be swapped by accident the
+/// way positional `&str` arguments can:
+/// ```no_run
+/// let from = Endpoint::new("Read", "out");
+/// let to = Endpoint::new("Display", "in").with_index(2);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub node: NodeId,
+    pub port: PortName,
+    pub index: Option<usize>,
+}
+
+impl Endpoint {
+    pub fn new(node: impl Into<NodeId>, port: impl Into<PortName>) -> Self {
+        Self {
+            node: node.into(),
+            port: port.into(),
+            index: None,
+        }
+    }
+
+    pub fn with_index(mut self, index: usize) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Whether the endpoint refers to the given leaf, ignoring the
+    /// index when the endpoint has none
+    pub fn matches(&self, leaf: &GraphLeaf) -> bool {
+        self.node == leaf.node_id.as_str()
+            && self.port == leaf.port.as_str()
+            && (self.index.is_none() || self.index == leaf.index)
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{}.{}[{}]", self.node, self.port, index),
+            None => write!(f, "{}.{}", self.node, self.port),
+        }
+    }
+}
+
+impl<N: Into<NodeId>, P: Into<PortName>> From<(N, P)> for Endpoint {
+    fn from((node, port): (N, P)) -> Self {
+        Endpoint::new(node, port)
+    }
+}
+
+impl From<&GraphLeaf> for Endpoint {
+    fn from(leaf: &GraphLeaf) -> Self {
+        Self {
+            node: NodeId::new(&leaf.node_id),
+            port: PortName::new(&leaf.port),
+            index: leaf.index,
+        }
+    }
+}
+
+impl From<Endpoint> for GraphLeaf {
+    fn from(endpoint: Endpoint) -> Self {
+        Self {
+            node_id: endpoint.node.into_string(),
+            port: endpoint.port.into_string(),
+            index: endpoint.index,
+        }
+    }
+}
+
+impl GraphLeaf {
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::from(self)
+    }
+}
+
+impl GraphEdge {
+    pub fn source(&self) -> Endpoint {
+        self.from.endpoint()
+    }
+
+    pub fn target(&self) -> Endpoint {
+        self.to.endpoint()
+    }
+}
+
+impl GraphIIP {
+    pub fn target(&self) -> Option<Endpoint> {
+        self.to.as_ref().map(GraphLeaf::endpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::graph::endpoint::{Endpoint, NodeId, PortName};
+    use crate::graph::graph::Graph;
+    use crate::graph::types::GraphLeaf;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_typed_identifiers() {
+        'given_typed_identifiers: {
+            let node = NodeId::from("Read");
+            let port: PortName = "out".into();
+            'then_they_should_behave_like_strings: {
+                assert_eq!(node, "Read");
+                assert_eq!(port.len(), 3);
+                assert_eq!(node.to_string(), "Read");
+                assert_eq!(serde_json::to_string(&node).unwrap(), "\"Read\"");
+                let mut ports = HashMap::new();
+                ports.insert(port.clone(), 1);
+                assert_eq!(ports.get("out"), Some(&1));
+            }
+        }
+        'given_an_endpoint: {
+            let endpoint = Endpoint::new("Display", "in").with_index(2);
+            'then_it_should_convert_to_and_from_leaves: {
+                assert_eq!(endpoint.to_string(), "Display.in[2]");
+                let leaf = GraphLeaf::from(endpoint.clone());
+                assert_eq!(leaf.node_id, "Display");
+                assert_eq!(leaf.index, Some(2));
+                assert_eq!(leaf.endpoint(), endpoint);
+                assert!(Endpoint::new("Display", "in").matches(&leaf));
+                assert!(!Endpoint::new("Display", "in").with_index(1).matches(&leaf));
+                assert_eq!(
+                    Endpoint::from(("Read", "out")),
+                    Endpoint::new("Read", "out")
+                );
+            }
+        }
+        'given_a_graph_with_an_edge: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Display", "Output", None)
+                .add_edge("Read", "out", "Display", "in", None);
+            'then_the_edge_should_expose_its_endpoints: {
+                assert_eq!(g.edges[0].source(), Endpoint::new("Read", "out"));
+                assert_eq!(g.edges[0].target(), Endpoint::new("Display", "in"));
+            }
+        }
+        'given_typed_identifiers_for_a_graph: {
+            let (read, display) = (NodeId::new("Read"), NodeId::new("Display"));
+            let (out, input) = (PortName::new("out"), PortName::new("in"));
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Display", "Output", None)
+                .add_edge("Read", "out", "Display", "in", None)
+                .add_initial(json!("file.txt"), read.clone(), "source", None)
+                .add_inport("file", read.clone(), PortName::new("source"), None)
+                .add_outport(PortName::new("shown"), display.clone(), "out", None);
+            'then_they_should_be_accepted_like_strings: {
+                assert!(g.get_edge(&read, &out, &display, &input).is_some());
+                assert_eq!(
+                    g.get_edges(read.clone(), "out", "Display", input.clone())
+                        .len(),
+                    1
+                );
+                assert_eq!(g.inports["file"].process, "Read");
+                g.remove_initial(read.clone(), "source")
+                    .remove_inport(PortName::new("file"))
+                    .remove_outport("shown")
+                    .remove_edge(read, out, Some("Display"), Some("in"));
+                assert!(g.initializers.is_empty());
+                assert!(g.inports.is_empty() && g.outports.is_empty());
+                assert!(g.edges.is_empty());
+            }
+        }
+        'given_a_graph_wired_through_endpoints: {
+            let mut g = Graph::new("", false);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Display", "Output", None)
+                .connect_endpoints(
+                    Endpoint::new("Read", "out"),
+                    Endpoint::new("Display", "in").with_index(0),
+                    None,
+                )
+                .connect_endpoints(
+                    ("Read", "out"),
+                    Endpoint::new("Display", "IN").with_index(1),
+                    None,
+                );
+            'then_it_should_add_indexed_edges: {
+                assert_eq!(g.edges.len(), 2);
+                assert_eq!(g.edges[1].to.index, Some(1));
+                assert_eq!(g.edges[1].to.port, "in");
+            }
+            'when_disconnecting_one_index: {
+                g.disconnect_endpoints(
+                    ("Read", "out"),
+                    Endpoint::new("Display", "in").with_index(0),
+                );
+                'then_only_that_edge_should_be_removed: {
+                    assert_eq!(g.edges.len(), 1);
+                    assert_eq!(g.edges[0].to.index, Some(1));
+                }
+                'and_then_disconnecting_without_an_index: {
+                    g.disconnect_endpoints(("Read", "out"), ("Display", "in"));
+                    'then_every_matching_edge_should_be_removed: {
+                        assert_eq!(g.edges.len(), 0);
+                    }
+                }
+            }
+        }
+    }
+}
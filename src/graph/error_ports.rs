@@ -0,0 +1,112 @@
+use super::endpoint::Endpoint;
+use super::graph::Graph;
+use super::policy::{MutationKind, MutationTarget};
+
+/// Outport components send failures to, by convention
+pub const ERROR_PORT: &str = "error";
+
+/// Match a name against a pattern where `*` stands for any text
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => match name.strip_prefix(prefix) {
+            Some(tail) => (0..=tail.len())
+                .filter(|i| tail.is_char_boundary(*i))
+                .any(|i| glob_matches(rest, &tail[i..])),
+            None => false,
+        },
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Connect the `error` outport of matching nodes to a handler
+    ///
+    /// `pattern` is matched against node IDs and component names, with
+    /// `*` standing for any text, e.g. `"Fetch*"` or `"core/*"`. The
+    /// handler node itself and nodes whose error port already leads to
+    /// the handler are skipped. All edges are added in one transaction.
+    /// ```no_run
+    /// my_graph.wire_errors_to("net/*", ("Errors", "in"));
+    /// ```
+    pub fn wire_errors_to(&mut self, pattern: &str, handler: impl Into<Endpoint>) -> &mut Self {
+        let handler = handler.into();
+        let sources = self
+            .nodes
+            .iter()
+            .filter(|node| node.id != handler.node.as_str())
+            .filter(|node| {
+                glob_matches(pattern, &node.id) || glob_matches(pattern, &node.component)
+            })
+            .filter(|node| {
+                !self.edges.iter().any(|edge| {
+                    edge.from.node_id == node.id
+                        && edge.from.port == ERROR_PORT
+                        && handler.matches(&edge.to)
+                })
+            })
+            .map(|node| node.id.clone())
+            .collect::<Vec<String>>();
+        if sources.is_empty() {
+            return self;
+        }
+        let mutations = sources
+            .iter()
+            .map(|node| {
+                let target = MutationTarget::Edge {
+                    from: node.clone(),
+                    to: handler.node.to_string(),
+                };
+                (MutationKind::AddEdge, target)
+            })
+            .collect();
+        self.within_transaction("wire_errors", mutations, |graph| {
+            for node in sources {
+                graph.connect_endpoints(Endpoint::new(node, ERROR_PORT), handler.clone(), None);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use beady::scenario;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_error_wiring() {
+        'given_a_graph_with_fallible_nodes: {
+            let mut g = Graph::new("", true);
+            g.add_node("FetchUsers", "net/HttpRequest", None)
+                .add_node("FetchOrders", "net/HttpRequest", None)
+                .add_node("Parse", "core/ParseJson", None)
+                .add_node("Errors", "core/Output", None)
+                .add_edge("FetchOrders", "error", "Errors", "in", None);
+            g.init_journal(None);
+            'when_wiring_errors_by_component: {
+                g.wire_errors_to("net/*", ("Errors", "in"));
+                'then_each_matching_node_should_be_connected_once: {
+                    assert!(g.get_edge("FetchUsers", "error", "Errors", "in").is_some());
+                    assert_eq!(
+                        g.edges
+                            .iter()
+                            .filter(|e| e.from.node_id == "FetchOrders")
+                            .count(),
+                        1
+                    );
+                    assert!(g.get_edge("Parse", "error", "Errors", "in").is_none());
+                    assert_eq!(g.current_revision, 1);
+                }
+            }
+            'when_wiring_everything: {
+                g.wire_errors_to("*", ("Errors", "in"));
+                'then_the_handler_should_not_feed_itself: {
+                    assert!(g.get_edge("Parse", "error", "Errors", "in").is_some());
+                    assert!(g.get_edge("Errors", "error", "Errors", "in").is_none());
+                    assert_eq!(g.edges.len(), 3);
+                }
+            }
+        }
+    }
+}
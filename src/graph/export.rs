@@ -0,0 +1,182 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::types::{GraphEdge, GraphExportedPort, GraphGroup, GraphIIP, GraphLeaf, GraphNode};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// Borrowing mirrors of the `GraphJson` layout, so that exports can be
+// streamed without cloning the graph's metadata
+
+#[derive(Serialize)]
+struct LeafRef<'g> {
+    port: &'g str,
+    process: &'g str,
+    index: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ConnectionRef<'g> {
+    src: Option<LeafRef<'g>>,
+    tgt: Option<LeafRef<'g>>,
+    data: Option<&'g Value>,
+    metadata: Option<&'g Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'g str>,
+}
+
+#[derive(Serialize)]
+struct ProcessRef<'g> {
+    component: &'g str,
+    metadata: Cow<'g, Map<String, Value>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphRef<'g> {
+    case_sensitive: bool,
+    properties: Map<String, Value>,
+    inports: BTreeMap<&'g str, &'g GraphExportedPort>,
+    outports: BTreeMap<&'g str, &'g GraphExportedPort>,
+    groups: &'g [GraphGroup],
+    processes: BTreeMap<&'g str, ProcessRef<'g>>,
+    connections: Vec<ConnectionRef<'g>>,
+}
+
+fn leaf(leaf: &GraphLeaf) -> LeafRef<'_> {
+    LeafRef {
+        port: &leaf.port,
+        process: &leaf.node_id,
+        index: leaf.index,
+    }
+}
+
+fn non_empty(metadata: &Option<Map<String, Value>>) -> Option<&Map<String, Value>> {
+    metadata.as_ref().filter(|m| !m.is_empty())
+}
+
+fn process(node: &GraphNode) -> (&str, ProcessRef<'_>) {
+    (
+        node.id.as_str(),
+        ProcessRef {
+            component: &node.component,
+            metadata: match node.metadata.as_ref() {
+                Some(metadata) => Cow::Borrowed(metadata),
+                None => Cow::Owned(Map::new()),
+            },
+        },
+    )
+}
+
+fn edge_connection(edge: &GraphEdge) -> ConnectionRef<'_> {
+    ConnectionRef {
+        src: Some(leaf(&edge.from)),
+        tgt: Some(leaf(&edge.to)),
+        data: None,
+        metadata: non_empty(&edge.metadata),
+        key: edge.key.as_deref(),
+    }
+}
+
+fn iip_connection(iip: &GraphIIP) -> ConnectionRef<'_> {
+    ConnectionRef {
+        src: None,
+        tgt: iip.to.as_ref().map(leaf),
+        data: iip.from.as_ref().map(|from| &from.data),
+        metadata: non_empty(&iip.metadata),
+        key: None,
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Serialize the graph to JSON, streaming it into a writer
+    ///
+    /// Produces the same document as `to_json`, without copying the
+    /// graph first. Processes and exported ports are written in key order,
+    /// so the output is deterministic.
+    /// ```no_run
+    /// my_graph.to_writer(std::io::BufWriter::new(File::create("graph.json")?))?;
+    /// ```
+    pub fn to_writer<W: Write>(&self, writer: W) -> Result<(), io::Error> {
+        serde_json::to_writer(writer, &self.json_ref())?;
+        Ok(())
+    }
+
+    fn json_ref(&self) -> GraphRef<'_> {
+        let mut properties = self.properties.clone();
+        properties.insert("name".to_owned(), Value::from(self.name.to_owned()));
+        properties.remove("baseDir");
+        properties.remove("componentLoader");
+
+        #[cfg(feature = "parallel")]
+        let (processes, connections) = (
+            self.nodes.par_iter().map(process).collect::<Vec<_>>(),
+            self.edges
+                .par_iter()
+                .map(edge_connection)
+                .chain(self.initializers.par_iter().map(iip_connection))
+                .collect::<Vec<_>>(),
+        );
+        #[cfg(not(feature = "parallel"))]
+        let (processes, connections) = (
+            self.nodes.iter().map(process).collect::<Vec<_>>(),
+            self.edges
+                .iter()
+                .map(edge_connection)
+                .chain(self.initializers.iter().map(iip_connection))
+                .collect::<Vec<_>>(),
+        );
+
+        GraphRef {
+            case_sensitive: self.case_sensitive,
+            properties,
+            inports: self.inports.iter().map(|(k, v)| (k.as_str(), v)).collect(),
+            outports: self.outports.iter().map(|(k, v)| (k.as_str(), v)).collect(),
+            groups: &self.groups,
+            processes: processes.into_iter().collect(),
+            connections,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use assert_json_diff::assert_json_eq;
+    use beady::scenario;
+    use futures::executor::block_on;
+    use serde_json::{json, Value};
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_streaming_export() {
+        'given_a_graph: {
+            let mut g = Graph::new("export", true);
+            g.add_node("Read", "ReadFile", json!({"x": 1}).as_object().cloned())
+                .add_node("Log", "Output", None)
+                .add_edge_index("Read", "out", Some(0), "Log", "in", None, None)
+                .add_initial(json!("a.txt"), "Read", "source", None)
+                .add_inport("file", "Read", "source", None)
+                .add_group("all", vec!["Read".to_owned()], None);
+            'when_streaming_it_to_a_writer: {
+                let mut out = Vec::new();
+                g.to_writer(&mut out).unwrap();
+                'then_it_should_match_to_json: {
+                    let streamed: Value = serde_json::from_slice(&out).unwrap();
+                    assert_json_eq!(streamed, json!(block_on(g.to_json())));
+                }
+                'then_it_should_be_deterministic: {
+                    let mut again = Vec::new();
+                    g.to_writer(&mut again).unwrap();
+                    assert_eq!(out, again);
+                }
+            }
+        }
+    }
+}
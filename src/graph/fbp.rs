@@ -0,0 +1,677 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::types::GraphLeaf;
+
+/// Syntax or reference error in a `.fbp` source
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FbpError {
+    /// 1-based line number
+    pub line: usize,
+    pub message: String,
+}
+
+impl FbpError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FbpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for FbpError {}
+
+/// Byte offsets of `pattern` outside of quotes and brackets
+fn top_level_matches(text: &str, pattern: &str) -> Vec<usize> {
+    let mut matches = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut depth = 0usize;
+    let mut skip_until = 0;
+    for (i, c) in text.char_indices() {
+        if i < skip_until {
+            continue;
+        }
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' => quote = Some(c),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && text[i..].starts_with(pattern) => {
+                matches.push(i);
+                skip_until = i + pattern.len();
+            }
+            _ => {}
+        }
+    }
+    matches
+}
+
+fn split_top_level<'t>(text: &'t str, separator: &str) -> Vec<&'t str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for i in top_level_matches(text, separator) {
+        pieces.push(&text[start..i]);
+        start = i + separator.len();
+    }
+    pieces.push(&text[start..]);
+    pieces
+}
+
+fn strip_comment(line: &str) -> &str {
+    match top_level_matches(line, "#").first() {
+        Some(i) => &line[..*i],
+        None => line,
+    }
+}
+
+/// JSON if the text is valid JSON, the text itself otherwise
+fn literal_value(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_owned()))
+}
+
+/// Inverse of `literal_value`
+///
+/// Strings are written as they are where that reads back the same,
+/// and as JSON strings otherwise.
+fn literal_text(value: &Value) -> String {
+    match value {
+        Value::String(s) if serde_json::from_str::<Value>(s).is_err() && !s.contains('\n') => {
+            s.clone()
+        }
+        other => other.to_string(),
+    }
+}
+
+/// `literal_text` for a metadata value, which can't contain FBP syntax
+fn metadata_text(value: &Value) -> String {
+    match value {
+        Value::String(s)
+            if s.contains(|c| ",()'\"#=[]{}\\".contains(c))
+                || s.contains("->")
+                || s.trim() != s =>
+        {
+            value.to_string()
+        }
+        other => literal_text(other),
+    }
+}
+
+/// `literal_text` for the inside of a quoted IIP, escaping `\` and `'`
+fn iip_text(value: &Value) -> String {
+    literal_text(value)
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+}
+
+fn is_name(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '/' || c == '.')
+}
+
+#[derive(Clone, Debug)]
+struct PortRef {
+    port: String,
+    index: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct NodeRef {
+    id: String,
+    component: Option<String>,
+    metadata: Map<String, Value>,
+}
+
+#[derive(Clone, Debug)]
+enum Source {
+    Data(Value),
+    Port(String, PortRef),
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+    Node(NodeRef),
+    Connection(Source, String, PortRef),
+    Export(bool, String, String, String),
+}
+
+fn parse_port(token: &str, line: usize) -> Result<PortRef, FbpError> {
+    let (port, index) = match token.split_once('[') {
+        Some((port, index)) => {
+            let index = index
+                .strip_suffix(']')
+                .and_then(|index| index.parse::<usize>().ok())
+                .ok_or_else(|| FbpError::new(line, format!("invalid port index in {}", token)))?;
+            (port, Some(index))
+        }
+        None => (token, None),
+    };
+    if !is_name(port) || port.contains('/') {
+        return Err(FbpError::new(
+            line,
+            format!("invalid port name {:?}", token),
+        ));
+    }
+    Ok(PortRef {
+        port: port.to_owned(),
+        index,
+    })
+}
+
+fn parse_node(spec: &str, line: usize) -> Result<NodeRef, FbpError> {
+    let spec = spec.trim();
+    let (id, inner) = match spec.split_once('(') {
+        Some((id, rest)) => match rest.strip_suffix(')') {
+            Some(inner) => (id.trim(), Some(inner)),
+            None => {
+                return Err(FbpError::new(
+                    line,
+                    format!("unclosed component in {}", spec),
+                ))
+            }
+        },
+        None => (spec, None),
+    };
+    if !is_name(id) || id.contains('/') {
+        return Err(FbpError::new(line, format!("invalid node name {:?}", id)));
+    }
+    let mut node = NodeRef {
+        id: id.to_owned(),
+        component: None,
+        metadata: Map::new(),
+    };
+    let Some(inner) = inner else {
+        return Ok(node);
+    };
+    let (component, metadata) = match inner.split_once(':') {
+        Some((component, metadata)) => (component.trim(), Some(metadata)),
+        None => (inner.trim(), None),
+    };
+    if !is_name(component) {
+        return Err(FbpError::new(
+            line,
+            format!("invalid component name {:?}", component),
+        ));
+    }
+    node.component = Some(component.to_owned());
+    for pair in metadata
+        .map(|m| split_top_level(m, ","))
+        .unwrap_or_default()
+    {
+        match pair.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                node.metadata
+                    .insert(key.trim().to_owned(), literal_value(value.trim()));
+            }
+            _ => {
+                return Err(FbpError::new(
+                    line,
+                    format!("invalid metadata {:?}", pair.trim()),
+                ))
+            }
+        }
+    }
+    Ok(node)
+}
+
+fn parse_iip(text: &str, line: usize) -> Result<Value, FbpError> {
+    let unterminated = || FbpError::new(line, format!("unterminated IIP {}", text));
+    let content = text
+        .strip_prefix('\'')
+        .and_then(|rest| rest.strip_suffix('\''))
+        .ok_or_else(unterminated)?;
+    let mut unescaped = String::new();
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(next @ ('\\' | '\''))) => {
+                unescaped.push(next);
+                chars.next();
+            }
+            // The closing quote is escaped
+            ('\\', None) => return Err(unterminated()),
+            _ => unescaped.push(c),
+        }
+    }
+    Ok(literal_value(&unescaped))
+}
+
+fn parse_export(text: &str, line: usize) -> Result<(String, String, String), FbpError> {
+    text.split_once(':')
+        .and_then(|(inner, name)| {
+            let (node, port) = inner.trim().split_once('.')?;
+            Some((node.trim(), port.trim(), name.trim()))
+        })
+        .filter(|(node, port, name)| is_name(node) && is_name(port) && is_name(name))
+        .map(|(node, port, name)| (node.to_owned(), port.to_owned(), name.to_owned()))
+        .ok_or_else(|| FbpError::new(line, format!("expected NODE.PORT:NAME, got {:?}", text)))
+}
+
+/// Split a token off the start or end of a connection segment
+fn token(segment: &str, from_end: bool, line: usize) -> Result<(&str, &str), FbpError> {
+    let split = if from_end {
+        segment
+            .trim()
+            .rsplit_once(char::is_whitespace)
+            .map(|(rest, token)| (token, rest))
+    } else {
+        segment.trim().split_once(char::is_whitespace)
+    };
+    split.ok_or_else(|| {
+        FbpError::new(
+            line,
+            format!("expected a node and a port in {:?}", segment.trim()),
+        )
+    })
+}
+
+fn parse_statement(
+    text: &str,
+    line: usize,
+    statements: &mut Vec<(usize, Statement)>,
+) -> Result<(), FbpError> {
+    for (prefix, inport) in [("INPORT=", true), ("OUTPORT=", false)] {
+        if let Some(export) = text.strip_prefix(prefix) {
+            let (node, port, name) = parse_export(export, line)?;
+            statements.push((line, Statement::Export(inport, name, node, port)));
+            return Ok(());
+        }
+    }
+    let segments = split_top_level(text, "->");
+    if segments.len() == 1 {
+        statements.push((line, Statement::Node(parse_node(text, line)?)));
+        return Ok(());
+    }
+    let last = segments.len() - 1;
+    let mut source = None;
+    for (i, segment) in segments.iter().enumerate() {
+        if i == 0 && segment.trim().starts_with('\'') {
+            source = Some(Source::Data(parse_iip(segment.trim(), line)?));
+            continue;
+        }
+        let (inport, rest) = match i {
+            0 => (None, *segment),
+            _ => {
+                let (inport, rest) = token(segment, false, line)?;
+                (Some(parse_port(inport, line)?), rest)
+            }
+        };
+        let (outport, spec) = match i {
+            _ if i == last => (None, rest),
+            _ => {
+                let (outport, spec) = token(rest, true, line)?;
+                (Some(parse_port(outport, line)?), spec)
+            }
+        };
+        let node = parse_node(spec, line)?;
+        let id = node.id.clone();
+        statements.push((line, Statement::Node(node)));
+        if let (Some(source), Some(inport)) = (source.take(), inport) {
+            statements.push((line, Statement::Connection(source, id.clone(), inport)));
+        }
+        source = outport.map(|outport| Source::Port(id, outport));
+    }
+    Ok(())
+}
+
+impl<'a> Graph<'a> {
+    /// Load a graph from the textual FBP language
+    ///
+    /// Supports node declarations (`Read(ReadFile:x=1)`), connection
+    /// chains with port indexes (`Read OUT[1] -> IN Show(Output)`), IIPs
+    /// (`'data' -> IN Read`), exported ports (`INPORT=Read.IN:FILE`),
+    /// comments, and `# @name value` annotations, which set the graph name
+    /// and string properties. Statements are separated by newlines or
+    /// commas. IIP data and metadata values are read as JSON where they
+    /// parse as JSON, and as strings otherwise.
+    ///
+    /// As in NoFlo, the graph is case insensitive, so port names are
+    /// lowercased. A node can be used before the statement giving its
+    /// component, but every node needs a component somewhere.
+    /// ```no_run
+    /// let graph = Graph::from_fbp_string("'file.txt' -> IN Read(ReadFile) OUT -> IN Show(Output)")?;
+    /// ```
+    pub fn from_fbp_string(source: &str) -> Result<Graph<'a>, FbpError> {
+        let mut statements = Vec::new();
+        let mut properties = Map::new();
+        let mut name = String::new();
+        for (i, text) in source.lines().enumerate() {
+            let line = i + 1;
+            let annotation = text
+                .trim()
+                .strip_prefix('#')
+                .and_then(|comment| comment.trim_start().strip_prefix('@'));
+            if let Some(annotation) = annotation {
+                let (key, value) = annotation
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((annotation, ""));
+                match key {
+                    "name" => name = value.trim().to_owned(),
+                    _ => {
+                        properties.insert(key.to_owned(), Value::String(value.trim().to_owned()));
+                    }
+                }
+                continue;
+            }
+            for statement in split_top_level(strip_comment(text), ",") {
+                if !statement.trim().is_empty() {
+                    parse_statement(statement.trim(), line, &mut statements)?;
+                }
+            }
+        }
+
+        // Nodes in order of first use, with the component given anywhere
+        let mut nodes: Vec<(usize, NodeRef)> = Vec::new();
+        for (line, statement) in statements.iter() {
+            let Statement::Node(node) = statement else {
+                continue;
+            };
+            match nodes.iter_mut().find(|(_, known)| known.id == node.id) {
+                None => nodes.push((*line, node.clone())),
+                Some((_, known)) => {
+                    match (&known.component, &node.component) {
+                        (Some(a), Some(b)) if a != b => {
+                            return Err(FbpError::new(
+                                *line,
+                                format!("node {} is already a {}", node.id, a),
+                            ))
+                        }
+                        (None, Some(_)) => known.component = node.component.clone(),
+                        _ => {}
+                    }
+                    known.metadata.extend(node.metadata.clone());
+                }
+            }
+        }
+
+        let mut graph = Graph::new(&name, false);
+        graph.start_transaction("load_fbp", None);
+        if !properties.is_empty() {
+            graph.set_properties(properties);
+        }
+        for (line, node) in nodes.iter() {
+            let Some(component) = node.component.as_ref() else {
+                return Err(FbpError::new(
+                    *line,
+                    format!("node {} has no component", node.id),
+                ));
+            };
+            let metadata = Some(node.metadata.clone()).filter(|m| !m.is_empty());
+            graph.add_node(&node.id, component, metadata);
+        }
+        for (line, statement) in statements {
+            match statement {
+                Statement::Node(_) => {}
+                Statement::Connection(Source::Data(data), node, to) => {
+                    let port = graph.get_port_name(&to.port);
+                    graph.add_initial_index(data, &node, &port, to.index, None);
+                }
+                Statement::Connection(Source::Port(out_node, from), node, to) => {
+                    let out_port = graph.get_port_name(&from.port);
+                    let in_port = graph.get_port_name(&to.port);
+                    graph.add_edge_index(
+                        &out_node, &out_port, from.index, &node, &in_port, to.index, None,
+                    );
+                }
+                Statement::Export(inport, name, node, port) => {
+                    if graph.get_node(&node).is_none() {
+                        return Err(FbpError::new(line, format!("no node {} to export", node)));
+                    }
+                    if inport {
+                        graph.add_inport(&name, &node, &port, None);
+                    } else {
+                        graph.add_outport(&name, &node, &port, None);
+                    }
+                }
+            }
+        }
+        graph.end_transaction("load_fbp", None);
+        Ok(graph)
+    }
+
+    /// Write the graph in the textual FBP language
+    ///
+    /// The output reads back with `from_fbp_string`. Port names are
+    /// uppercased unless the graph is case sensitive. Groups, edge and
+    /// IIP metadata, and properties that aren't strings have no FBP
+    /// syntax and are left out.
+    pub fn to_fbp_string(&self) -> String {
+        let port = |name: &str| match self.case_sensitive {
+            true => name.to_owned(),
+            false => name.to_uppercase(),
+        };
+        let leaf = |leaf: &GraphLeaf| match leaf.index {
+            Some(index) => format!("{}[{}]", port(&leaf.port), index),
+            None => port(&leaf.port),
+        };
+        let mut lines = Vec::new();
+        if !self.name.is_empty() {
+            lines.push(format!("# @name {}", self.name));
+        }
+        for (key, value) in self.properties.iter() {
+            match value {
+                Value::String(value) if key != "name" && !value.contains('\n') => {
+                    lines.push(format!("# @{} {}", key, value))
+                }
+                _ => {}
+            }
+        }
+        for (keyword, ports) in [("INPORT", &self.inports), ("OUTPORT", &self.outports)] {
+            for (name, exported) in ports.iter().collect::<BTreeMap<_, _>>() {
+                lines.push(format!(
+                    "{}={}.{}:{}",
+                    keyword,
+                    exported.process,
+                    port(&exported.port),
+                    port(name)
+                ));
+            }
+        }
+        for node in self.nodes.iter() {
+            let metadata = node
+                .metadata
+                .iter()
+                .flatten()
+                .map(|(key, value)| format!("{}={}", key, metadata_text(value)))
+                .collect::<Vec<String>>();
+            match metadata.is_empty() {
+                true => lines.push(format!("{}({})", node.id, node.component)),
+                false => lines.push(format!(
+                    "{}({}:{})",
+                    node.id,
+                    node.component,
+                    metadata.join(",")
+                )),
+            }
+        }
+        for iip in self.initializers.iter() {
+            if let (Some(from), Some(to)) = (iip.from.as_ref(), iip.to.as_ref()) {
+                lines.push(format!(
+                    "'{}' -> {} {}",
+                    iip_text(&from.data),
+                    leaf(to),
+                    to.node_id
+                ));
+            }
+        }
+        for edge in self.edges.iter() {
+            lines.push(format!(
+                "{} {} -> {} {}",
+                edge.from.node_id,
+                leaf(&edge.from),
+                leaf(&edge.to),
+                edge.to.node_id
+            ));
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::blocking::to_json;
+    use crate::graph::graph::Graph;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_fbp_dsl() {
+        'given_an_fbp_source: {
+            let source = r#"
+                # @name Counter
+                # @runtime zflow
+                INPORT=Read.IN:FILENAME
+                OUTPORT=Show.OUT:OUT
+                'package.json' -> IN Read(ReadFile:label=Reader,x=5) # trailing comment
+                '{"lines": true}' -> OPTIONS Read, '-> a, b' -> PREFIX Show
+                Read OUT -> IN Split(SplitStr) OUT[1] -> IN Show
+                Show(Output)
+            "#;
+            'when_parsing_it: {
+                let g = Graph::from_fbp_string(source).unwrap();
+                'then_nodes_should_be_declared_with_components: {
+                    let components = g
+                        .nodes
+                        .iter()
+                        .map(|node| (node.id.as_str(), node.component.as_str()))
+                        .collect::<Vec<_>>();
+                    assert_eq!(
+                        components,
+                        vec![
+                            ("Read", "ReadFile"),
+                            ("Show", "Output"),
+                            ("Split", "SplitStr")
+                        ]
+                    );
+                    let meta = g.get_node("Read").unwrap().metadata.clone().unwrap();
+                    assert_eq!(json!(meta), json!({"label": "Reader", "x": 5}));
+                }
+                'then_connections_should_use_lowercase_ports: {
+                    assert!(g.get_edge("Read", "out", "Split", "in").is_some());
+                    let edge = g.get_edge("Split", "out", "Show", "in").unwrap();
+                    assert_eq!(edge.from.index, Some(1));
+                }
+                'then_iips_should_be_read_as_json_or_strings: {
+                    let data = g
+                        .initializers
+                        .iter()
+                        .map(|iip| iip.from.clone().unwrap().data)
+                        .collect::<Vec<_>>();
+                    assert_eq!(
+                        data,
+                        vec![
+                            json!("package.json"),
+                            json!({"lines": true}),
+                            json!("-> a, b")
+                        ]
+                    );
+                }
+                'then_ports_and_annotations_should_be_set: {
+                    assert_eq!(g.inports.get("filename").unwrap().process, "Read");
+                    assert_eq!(g.outports.get("out").unwrap().port, "out");
+                    assert_eq!(g.name, "Counter");
+                    assert_eq!(g.properties["runtime"], json!("zflow"));
+                }
+                'then_it_should_round_trip: {
+                    let text = g.to_fbp_string();
+                    let copy = Graph::from_fbp_string(&text).unwrap();
+                    assert_eq!(
+                        serde_json::to_value(to_json(&copy)).unwrap(),
+                        serde_json::to_value(to_json(&g)).unwrap()
+                    );
+                    assert!(text.contains("Read OUT -> IN Split"));
+                }
+            }
+        }
+        'given_a_graph_with_tricky_values: {
+            let mut g = Graph::new("", false);
+            let metadata = json!({
+                "tags": ["a", "b"],
+                "n": "5",
+                "label": "a, b",
+                "call": "f(x)",
+                "quote": "it's \"here\"",
+                "hash": "#1",
+                "pair": "a=b",
+                "arrow": "a -> b",
+                "open": "[1",
+                "padded": " x ",
+                "path": "C:\\dir\\"
+            });
+            g.add_node("A", "core/Kick", metadata.as_object().cloned())
+                .add_initial(json!("it's"), "A", "in", None)
+                .add_initial(json!("5"), "A", "count", None)
+                .add_initial(json!("a\\"), "A", "path", None)
+                .add_initial(json!("it's \\' odd"), "A", "odd", None);
+            'when_round_tripping: {
+                let copy = Graph::from_fbp_string(&g.to_fbp_string()).unwrap();
+                'then_values_should_keep_their_types: {
+                    assert_eq!(
+                        copy.get_node("A").unwrap().metadata,
+                        g.get_node("A").unwrap().metadata
+                    );
+                    assert_eq!(
+                        copy.initializers[0].from.clone().unwrap().data,
+                        json!("it's")
+                    );
+                    assert_eq!(copy.initializers[1].from.clone().unwrap().data, json!("5"));
+                    assert_eq!(
+                        copy.initializers[2].from.clone().unwrap().data,
+                        json!("a\\")
+                    );
+                    assert_eq!(
+                        copy.initializers[3].from.clone().unwrap().data,
+                        json!("it's \\' odd")
+                    );
+                }
+            }
+        }
+        'given_invalid_sources: {
+            'when_a_node_has_no_component: {
+                let err = Graph::from_fbp_string("A OUT -> IN B(Output)")
+                    .err()
+                    .unwrap();
+                'then_the_error_should_name_it: {
+                    assert_eq!(err.to_string(), "line 1: node A has no component");
+                }
+            }
+            'when_an_iip_ends_in_an_escaped_quote: {
+                let err = Graph::from_fbp_string("'a\\' -> IN A(Kick)").err();
+                'then_it_should_be_rejected: {
+                    assert!(err.is_some());
+                }
+            }
+            'when_a_statement_is_malformed: {
+                let err = Graph::from_fbp_string("A(Kick)\nA OUT -> B(Output)")
+                    .err()
+                    .unwrap();
+                'then_the_error_should_point_at_the_line: {
+                    assert_eq!(err.line, 2);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use futures::executor::block_on;
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::types::{GraphEdgeJson, GraphLeafJson};
+
+/// Feature flags on nodes and edges
+///
+/// Nodes and connections can be switched off through their metadata,
+/// either unconditionally with `"enabled": false` or through a
+/// `"when": "<expr>"` condition evaluated against a set of parameters:
+/// ```no_run
+/// let mut params = Map::new();
+/// params.insert("env".to_string(), json!("prod"));
+/// let effective = my_graph.resolve_feature_flags(&params);
+/// ```
+/// Conditions support parameter lookups (`env`, `deploy.region`),
+/// string, number and boolean literals, `==`, `!=`, `!`, `&&`, `||`
+/// and parentheses.
+impl<'a> Graph<'a> {
+    /// Produce a copy of the graph with all disabled nodes and edges removed.
+    ///
+    /// A disabled node that only passes data through (exactly one incoming
+    /// and one outgoing edge) is routed around, connecting its upstream
+    /// directly to its downstream.
+    pub fn resolve_feature_flags(&self, params: &Map<String, Value>) -> Graph<'a> {
+        let mut json = block_on(self.to_json());
+
+        json.connections
+            .retain(|conn| is_enabled(conn.metadata.as_ref(), params));
+
+        let mut disabled = json
+            .processes
+            .iter()
+            .filter(|(_, process)| !is_enabled(process.metadata.as_ref(), params))
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<String>>();
+        disabled.sort();
+
+        for id in disabled.iter() {
+            json.processes.remove(id);
+
+            let incoming = json
+                .connections
+                .iter()
+                .filter(|conn| conn.src.is_some() && leaf_is(&conn.tgt, id))
+                .cloned()
+                .collect::<Vec<GraphEdgeJson>>();
+            let outgoing = json
+                .connections
+                .iter()
+                .filter(|conn| leaf_is(&conn.src, id))
+                .cloned()
+                .collect::<Vec<GraphEdgeJson>>();
+
+            json.connections
+                .retain(|conn| !leaf_is(&conn.src, id) && !leaf_is(&conn.tgt, id));
+
+            if incoming.len() == 1 && outgoing.len() == 1 {
+                json.connections.push(GraphEdgeJson {
+                    src: incoming[0].src.clone(),
+                    tgt: outgoing[0].tgt.clone(),
+                    data: None,
+                    metadata: outgoing[0].metadata.clone(),
+                    key: None,
+                });
+            }
+        }
+
+        json.inports
+            .retain(|_, port| !disabled.contains(&port.process));
+        json.outports
+            .retain(|_, port| !disabled.contains(&port.process));
+        json.groups.iter_mut().for_each(|group| {
+            group.nodes.retain(|node| !disabled.contains(node));
+        });
+
+        block_on(Graph::from_json(json, None))
+    }
+}
+
+fn leaf_is(leaf: &Option<GraphLeafJson>, process: &str) -> bool {
+    leaf.as_ref().map(|l| l.process == process).unwrap_or(false)
+}
+
+/// Check the `enabled` and `when` metadata keys against the given parameters
+pub fn is_enabled(metadata: Option<&Map<String, Value>>, params: &Map<String, Value>) -> bool {
+    let metadata = match metadata {
+        Some(metadata) => metadata,
+        None => return true,
+    };
+    if let Some(Value::Bool(false)) = metadata.get("enabled") {
+        return false;
+    }
+    if let Some(Value::String(expr)) = metadata.get("when") {
+        return match evaluate_condition(expr, params) {
+            Ok(result) => result,
+            Err(err) => {
+                log::error!("Invalid feature flag condition `{}`: {}", expr, err);
+                false
+            }
+        };
+    }
+    true
+}
+
+/// Evaluate a `when` condition against the given parameters
+pub fn evaluate_condition(expr: &str, params: &Map<String, Value>) -> Result<bool, String> {
+    let tokens = tokenize(expr)?;
+    let mut parser = ConditionParser {
+        tokens,
+        pos: 0,
+        params,
+    };
+    let value = parser.parse_or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(format!("unexpected token {:?}", parser.tokens[parser.pos]));
+    }
+    Ok(truthy(&value))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Value),
+    Eq,
+    NotEq,
+    Not,
+    And,
+    Or,
+    LParen,
+    RParen,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let chars = expr.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' | '\n' => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '=' if chars.get(i + 1) == Some(&'=') => {
+                tokens.push(Token::Eq);
+                i += 2;
+            }
+            '!' if chars.get(i + 1) == Some(&'=') => {
+                tokens.push(Token::NotEq);
+                i += 2;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            '&' if chars.get(i + 1) == Some(&'&') => {
+                tokens.push(Token::And);
+                i += 2;
+            }
+            '|' if chars.get(i + 1) == Some(&'|') => {
+                tokens.push(Token::Or);
+                i += 2;
+            }
+            '"' | '\'' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|ch| *ch == c)
+                    .ok_or_else(|| "unterminated string".to_string())?;
+                let s = chars[i + 1..i + 1 + end].iter().collect::<String>();
+                tokens.push(Token::Literal(Value::String(s)));
+                i += end + 2;
+            }
+            _ if c.is_ascii_digit() || c == '-' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let s = chars[start..i].iter().collect::<String>();
+                let n = s
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid number `{}`", s))?;
+                tokens.push(Token::Literal(Value::from(n)));
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+                {
+                    i += 1;
+                }
+                let s = chars[start..i].iter().collect::<String>();
+                tokens.push(match s.as_str() {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ => Token::Ident(s),
+                });
+            }
+            _ => return Err(format!("unexpected character `{}`", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct ConditionParser<'p> {
+    tokens: Vec<Token>,
+    pos: usize,
+    params: &'p Map<String, Value>,
+}
+
+impl<'p> ConditionParser<'p> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<Value, String> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = Value::Bool(truthy(&left) || truthy(&right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Value, String> {
+        let mut left = self.parse_comparison()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            let right = self.parse_comparison()?;
+            left = Value::Bool(truthy(&left) && truthy(&right));
+        }
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Value, String> {
+        let left = self.parse_unary()?;
+        match self.peek() {
+            Some(Token::Eq) => {
+                self.pos += 1;
+                let right = self.parse_unary()?;
+                Ok(Value::Bool(values_equal(&left, &right)))
+            }
+            Some(Token::NotEq) => {
+                self.pos += 1;
+                let right = self.parse_unary()?;
+                Ok(Value::Bool(!values_equal(&left, &right)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Value, String> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                let value = self.parse_unary()?;
+                Ok(Value::Bool(!truthy(&value)))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let value = self.parse_or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err("missing closing parenthesis".to_string());
+                }
+                self.pos += 1;
+                Ok(value)
+            }
+            Some(Token::Literal(value)) => {
+                self.pos += 1;
+                Ok(value)
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                Ok(lookup(self.params, &name))
+            }
+            Some(token) => Err(format!("unexpected token {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn lookup(params: &Map<String, Value>, path: &str) -> Value {
+    let mut parts = path.split('.');
+    let mut current = parts
+        .next()
+        .and_then(|first| params.get(first))
+        .cloned()
+        .unwrap_or(Value::Null);
+    for part in parts {
+        current = current.get(part).cloned().unwrap_or(Value::Null);
+    }
+    current
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().map(|n| n != 0.0).unwrap_or(false),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use beady::scenario;
+    use serde_json::{json, Map, Value};
+
+    use super::evaluate_condition;
+
+    fn meta(value: Value) -> Option<Map<String, Value>> {
+        value.as_object().cloned()
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_feature_flags() {
+        'given_a_set_of_parameters: {
+            let params = json!({"env": "prod", "deploy": {"region": "eu"}, "debug": false})
+                .as_object()
+                .unwrap()
+                .clone();
+            'then_it_should_evaluate_conditions: {
+                assert_eq!(evaluate_condition("env == 'prod'", &params), Ok(true));
+                assert_eq!(evaluate_condition("env != \"prod\"", &params), Ok(false));
+                assert_eq!(
+                    evaluate_condition("deploy.region == 'eu' && !debug", &params),
+                    Ok(true)
+                );
+                assert_eq!(
+                    evaluate_condition("(debug || missing) || env == 'dev'", &params),
+                    Ok(false)
+                );
+                assert!(evaluate_condition("env ==", &params).is_err());
+            }
+            'when_a_graph_has_disabled_nodes_and_edges: {
+                let mut g = Graph::new("", true);
+                g.add_node("Read", "ReadFile", None)
+                    .add_node("Debug", "Log", meta(json!({"when": "env == 'dev'"})))
+                    .add_node("Write", "WriteFile", None)
+                    .add_node("Trace", "Log", meta(json!({"enabled": false})))
+                    .add_edge("Read", "out", "Debug", "in", None)
+                    .add_edge("Debug", "out", "Write", "in", None)
+                    .add_edge("Read", "out", "Trace", "in", None)
+                    .add_edge(
+                        "Read",
+                        "err",
+                        "Write",
+                        "err",
+                        meta(json!({"when": "debug"})),
+                    )
+                    .add_initial(json!("a.txt"), "Read", "source", None);
+                let resolved = g.resolve_feature_flags(&params);
+                'then_it_should_remove_disabled_nodes: {
+                    assert_eq!(resolved.nodes.len(), 2);
+                    assert!(resolved.get_node("Debug").is_none());
+                    assert!(resolved.get_node("Trace").is_none());
+                }
+                'then_it_should_route_around_pass_through_nodes: {
+                    assert!(resolved.get_edge("Read", "out", "Write", "in").is_some());
+                    assert_eq!(resolved.edges.len(), 1);
+                    assert_eq!(resolved.initializers.len(), 1);
+                }
+                'then_it_should_leave_the_original_graph_untouched: {
+                    assert_eq!(g.nodes.len(), 4);
+                    assert_eq!(g.edges.len(), 4);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,779 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Map, Value};
+
+use super::endpoint::Endpoint;
+use super::graph::Graph;
+use super::policy::{MutationKind, MutationTarget};
+use super::transform::{
+    MapReduceOptions, Partitioning, HASH_PARTITION_COMPONENT, MERGE_COMPONENT,
+    ORDERED_MERGE_COMPONENT, ROUND_ROBIN_COMPONENT,
+};
+use super::types::{GraphEdge, GraphError, GraphGroup, GraphIIP, GraphLeaf};
+
+/// An end of the edges crossing into or out of a replicated set of nodes,
+/// with the node that spreads packets over the copies or merges their
+/// output
+struct Junction {
+    leaf: GraphLeaf,
+    name: String,
+    edges: Vec<GraphEdge>,
+    exports: Vec<(String, Option<Map<String, Value>>)>,
+}
+
+impl Junction {
+    /// The junction for the given end, added if there's none yet
+    fn find<'j>(junctions: &'j mut Vec<Junction>, leaf: &GraphLeaf, suffix: &str) -> &'j mut Self {
+        let found = junctions.iter().position(|junction| {
+            junction.leaf.node_id == leaf.node_id
+                && junction.leaf.port == leaf.port
+                && junction.leaf.index == leaf.index
+        });
+        let position = found.unwrap_or_else(|| {
+            let name = match leaf.index {
+                Some(index) => format!("{}_{}_{}_{}", leaf.node_id, leaf.port, index, suffix),
+                None => format!("{}_{}_{}", leaf.node_id, leaf.port, suffix),
+            };
+            junctions.push(Junction {
+                leaf: leaf.clone(),
+                name,
+                edges: Vec::new(),
+                exports: Vec::new(),
+            });
+            junctions.len() - 1
+        });
+        &mut junctions[position]
+    }
+
+    fn add_node(&self, graph: &mut Graph, component: &str) {
+        let mut metadata = Map::new();
+        metadata.insert(
+            "replicate".to_owned(),
+            Value::from(self.leaf.node_id.clone()),
+        );
+        graph.add_node(&self.name, component, Some(metadata));
+    }
+
+    /// Add the distributor, and route the edges and exported ports into
+    /// the original port through it to every instance
+    fn distribute(&self, graph: &mut Graph, instances: &[String], partitioning: &Partitioning) {
+        match partitioning {
+            Partitioning::RoundRobin => self.add_node(graph, ROUND_ROBIN_COMPONENT),
+            Partitioning::HashByKey(key) => {
+                self.add_node(graph, HASH_PARTITION_COMPONENT);
+                graph.add_initial(json!(key), &self.name, "key", None);
+            }
+        }
+        for edge in self.edges.iter() {
+            graph
+                .remove_edge(
+                    &edge.from.node_id,
+                    &edge.from.port,
+                    Some(&edge.to.node_id),
+                    Some(&edge.to.port),
+                )
+                .add_edge_index(
+                    &edge.from.node_id,
+                    &edge.from.port,
+                    edge.from.index,
+                    &self.name,
+                    "in",
+                    None,
+                    edge.metadata.clone(),
+                );
+        }
+        for (i, instance) in instances.iter().enumerate() {
+            let leaf = &self.leaf;
+            graph.add_edge_index(
+                &self.name,
+                "out",
+                Some(i),
+                instance,
+                &leaf.port,
+                leaf.index,
+                None,
+            );
+        }
+        for (name, metadata) in self.exports.iter() {
+            graph
+                .remove_inport(name)
+                .add_inport(name, &self.name, "in", metadata.clone());
+        }
+    }
+
+    /// Add the merger, and route the edges and exported ports out of the
+    /// original port through it from every instance
+    fn merge(&self, graph: &mut Graph, instances: &[String], preserve_order: bool) {
+        if preserve_order {
+            self.add_node(graph, ORDERED_MERGE_COMPONENT);
+        } else {
+            self.add_node(graph, MERGE_COMPONENT);
+        }
+        for edge in self.edges.iter() {
+            graph
+                .remove_edge(
+                    &edge.from.node_id,
+                    &edge.from.port,
+                    Some(&edge.to.node_id),
+                    Some(&edge.to.port),
+                )
+                .add_edge_index(
+                    &self.name,
+                    "out",
+                    None,
+                    &edge.to.node_id,
+                    &edge.to.port,
+                    edge.to.index,
+                    edge.metadata.clone(),
+                );
+        }
+        for (i, instance) in instances.iter().enumerate() {
+            let leaf = &self.leaf;
+            graph.add_edge_index(
+                instance,
+                &leaf.port,
+                leaf.index,
+                &self.name,
+                "in",
+                Some(i),
+                None,
+            );
+        }
+        for (name, metadata) in self.exports.iter() {
+            graph
+                .remove_outport(name)
+                .add_outport(name, &self.name, "out", metadata.clone());
+        }
+    }
+}
+
+/// Everything replicating a set of nodes changes, worked out before the
+/// graph is touched
+struct Replication {
+    members: Vec<String>,
+    /// Node names of each copy, by original node
+    copies: Vec<HashMap<String, String>>,
+    /// Edges between members, repeated within each copy
+    internal: Vec<GraphEdge>,
+    inbound: Vec<Junction>,
+    outbound: Vec<Junction>,
+    initials: Vec<GraphIIP>,
+    /// Groups holding members, which the copies and junctions join
+    enclosing: Vec<GraphGroup>,
+    /// The replicated group, with the names of its copies
+    group: Option<(GraphGroup, Vec<String>)>,
+}
+
+impl Replication {
+    /// Plan `n` copies of `members`, failing if a new name is taken
+    fn plan(
+        graph: &Graph,
+        members: Vec<String>,
+        group: Option<GraphGroup>,
+        n: usize,
+        rename: impl Fn(&str, usize) -> String,
+    ) -> Result<Self, GraphError> {
+        let is_member = |node: &str| members.iter().any(|member| member == node);
+        let copies = (1..=n)
+            .map(|i| {
+                members
+                    .iter()
+                    .map(|member| (member.clone(), rename(member, i)))
+                    .collect::<HashMap<_, _>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut internal = Vec::new();
+        let mut inbound: Vec<Junction> = Vec::new();
+        let mut outbound: Vec<Junction> = Vec::new();
+        for edge in graph.edges.iter() {
+            match (is_member(&edge.from.node_id), is_member(&edge.to.node_id)) {
+                (true, true) => internal.push(edge.clone()),
+                (false, true) => Junction::find(&mut inbound, &edge.to, "split")
+                    .edges
+                    .push(edge.clone()),
+                (true, false) => Junction::find(&mut outbound, &edge.from, "merge")
+                    .edges
+                    .push(edge.clone()),
+                (false, false) => {}
+            }
+        }
+        for (ports, junctions, suffix) in [
+            (&graph.inports, &mut inbound, "split"),
+            (&graph.outports, &mut outbound, "merge"),
+        ] {
+            let mut exported = ports
+                .iter()
+                .filter(|(_, port)| is_member(&port.process))
+                .collect::<Vec<_>>();
+            exported.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, port) in exported {
+                let leaf = GraphLeaf {
+                    node_id: port.process.clone(),
+                    port: port.port.clone(),
+                    index: None,
+                };
+                Junction::find(junctions, &leaf, suffix)
+                    .exports
+                    .push((name.clone(), port.metadata.clone()));
+            }
+        }
+        let initials = graph
+            .initializers
+            .iter()
+            .filter(|iip| {
+                iip.to
+                    .as_ref()
+                    .map(|to| is_member(&to.node_id))
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let enclosing = match group {
+            Some(_) => Vec::new(),
+            None => graph
+                .groups
+                .iter()
+                .filter(|parent| parent.nodes.iter().any(|node| is_member(node)))
+                .cloned()
+                .collect::<Vec<_>>(),
+        };
+        let group = group.map(|group| {
+            let names = (1..=n).map(|i| rename(&group.name, i)).collect::<Vec<_>>();
+            (group, names)
+        });
+
+        let replication = Replication {
+            members,
+            copies,
+            internal,
+            inbound,
+            outbound,
+            initials,
+            enclosing,
+            group,
+        };
+        let names = replication.new_nodes();
+        let taken = names
+            .iter()
+            .enumerate()
+            .find(|(i, name)| graph.get_node(name).is_some() || names[..*i].contains(name));
+        if let Some((_, name)) = taken {
+            return Err(GraphError::NodeExists(name.clone()));
+        }
+        let groups = replication
+            .group
+            .as_ref()
+            .map(|(_, names)| names.as_slice())
+            .unwrap_or_default();
+        let taken = groups
+            .iter()
+            .enumerate()
+            .find(|(i, name)| graph.get_group(name).is_some() || groups[..*i].contains(name));
+        if let Some((_, name)) = taken {
+            return Err(GraphError::GroupExists(name.clone()));
+        }
+        Ok(replication)
+    }
+
+    /// The original node followed by its copies
+    fn instances(&self, node: &str) -> Vec<String> {
+        let mut names = vec![node.to_owned()];
+        names.extend(self.copies.iter().map(|copy| copy[node].clone()));
+        names
+    }
+
+    /// Names of the copies, then of the distributors and mergers
+    fn new_nodes(&self) -> Vec<String> {
+        self.copies
+            .iter()
+            .flat_map(|copy| self.members.iter().map(|member| copy[member].clone()))
+            .chain(self.junctions().map(|junction| junction.name.clone()))
+            .collect()
+    }
+
+    fn junctions(&self) -> impl Iterator<Item = &Junction> {
+        self.inbound.iter().chain(self.outbound.iter())
+    }
+
+    /// Every change `apply` makes, as checked by the mutation policy
+    fn mutations(
+        &self,
+        graph: &Graph,
+        partitioning: &Partitioning,
+    ) -> Vec<(MutationKind, MutationTarget)> {
+        let mut mutations = Vec::new();
+        let mut new_edges = Vec::new();
+        for junction in self.inbound.iter() {
+            for edge in junction.edges.iter() {
+                new_edges.push((edge.from.node_id.clone(), junction.name.clone()));
+            }
+            for instance in self.instances(&junction.leaf.node_id) {
+                new_edges.push((junction.name.clone(), instance));
+            }
+        }
+        for junction in self.outbound.iter() {
+            for edge in junction.edges.iter() {
+                new_edges.push((junction.name.clone(), edge.to.node_id.clone()));
+            }
+            for instance in self.instances(&junction.leaf.node_id) {
+                new_edges.push((instance, junction.name.clone()));
+            }
+        }
+        for junction in self.junctions() {
+            for edge in junction.edges.iter() {
+                let target = MutationTarget::Edge {
+                    from: edge.from.node_id.clone(),
+                    to: edge.to.node_id.clone(),
+                };
+                mutations.push((MutationKind::RemoveEdge, target));
+            }
+        }
+        for copy in self.copies.iter() {
+            for edge in self.internal.iter() {
+                new_edges.push((
+                    copy[&edge.from.node_id].clone(),
+                    copy[&edge.to.node_id].clone(),
+                ));
+            }
+            for iip in self.initials.iter() {
+                let to = &iip.to.as_ref().unwrap().node_id;
+                let target = MutationTarget::Initial(copy[to].clone());
+                mutations.push((MutationKind::AddInitial, target));
+            }
+        }
+        for name in self.new_nodes() {
+            mutations.push((MutationKind::AddNode, MutationTarget::Node(name)));
+        }
+        if let Partitioning::HashByKey(_) = partitioning {
+            for junction in self.inbound.iter() {
+                let target = MutationTarget::Initial(junction.name.clone());
+                mutations.push((MutationKind::AddInitial, target));
+            }
+        }
+        for (from, to) in new_edges {
+            mutations.push((MutationKind::AddEdge, MutationTarget::Edge { from, to }));
+        }
+        for junction in self.inbound.iter() {
+            for (name, _) in junction.exports.iter() {
+                let target = MutationTarget::Inport(name.clone());
+                mutations.push((MutationKind::RemoveInport, target.clone()));
+                mutations.push((MutationKind::AddInport, target));
+            }
+        }
+        for junction in self.outbound.iter() {
+            for (name, _) in junction.exports.iter() {
+                let target = MutationTarget::Outport(name.clone());
+                mutations.push((MutationKind::RemoveOutport, target.clone()));
+                mutations.push((MutationKind::AddOutport, target));
+            }
+        }
+        for parent in self.enclosing.iter() {
+            mutations.extend(graph.set_group_nodes_mutations(&parent.name));
+        }
+        if let Some((_, names)) = self.group.as_ref() {
+            for name in names.iter() {
+                mutations.push((MutationKind::AddGroup, MutationTarget::Group(name.clone())));
+            }
+        }
+        mutations
+    }
+
+    fn apply(&self, graph: &mut Graph, options: &MapReduceOptions) {
+        for copy in self.copies.iter() {
+            self.add_copy(graph, copy);
+        }
+        for junction in self.inbound.iter() {
+            let instances = self.instances(&junction.leaf.node_id);
+            junction.distribute(graph, &instances, &options.partitioning);
+        }
+        for junction in self.outbound.iter() {
+            let instances = self.instances(&junction.leaf.node_id);
+            junction.merge(graph, &instances, options.preserve_order);
+        }
+        for parent in self.enclosing.iter() {
+            let mut nodes = parent.nodes.clone();
+            for member in self.members.iter().filter(|m| parent.nodes.contains(m)) {
+                nodes.extend(self.copies.iter().map(|copy| copy[member].clone()));
+                nodes.extend(
+                    self.junctions()
+                        .filter(|j| &j.leaf.node_id == member)
+                        .map(|j| j.name.clone()),
+                );
+            }
+            graph.set_group_nodes(&parent.name, nodes);
+        }
+        if let Some((group, names)) = self.group.as_ref() {
+            for (name, copy) in names.iter().zip(self.copies.iter()) {
+                let nodes = self.members.iter().map(|m| copy[m].clone()).collect();
+                graph.add_group(name, nodes, group.metadata.clone());
+            }
+        }
+    }
+
+    /// Add one copy of the members, with their IIPs and the edges
+    /// between them
+    fn add_copy(&self, graph: &mut Graph, copy: &HashMap<String, String>) {
+        for member in self.members.iter() {
+            let node = graph.get_node(member).cloned().unwrap();
+            graph.add_node(&copy[member], &node.component, node.metadata);
+        }
+        for iip in self.initials.iter() {
+            if let (Some(to), Some(from)) = (iip.to.as_ref(), iip.from.as_ref()) {
+                graph.add_initial_index(
+                    from.data.clone(),
+                    &copy[&to.node_id],
+                    &to.port,
+                    to.index,
+                    iip.metadata.clone(),
+                );
+            }
+        }
+        for edge in self.internal.iter() {
+            graph.add_edge_index(
+                &copy[&edge.from.node_id],
+                &edge.from.port,
+                edge.from.index,
+                &copy[&edge.to.node_id],
+                &edge.to.port,
+                edge.to.index,
+                edge.metadata.clone(),
+            );
+        }
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Run mutations in a transaction with the given id, unless one is
+    /// already open, in which case they become part of it
+    ///
+    /// The listed mutations are checked with `permit_all` first, and
+    /// nothing is run if one of them is denied. Otherwise `mutate` runs
+    /// inside `vetted`, so it must make no other changes.
+    pub(crate) fn within_transaction(
+        &mut self,
+        id: &str,
+        mutations: Vec<(MutationKind, MutationTarget)>,
+        mutate: impl FnOnce(&mut Self),
+    ) -> &mut Self {
+        let _ = self.try_within_transaction(id, mutations, mutate);
+        self
+    }
+
+    /// `within_transaction`, returning the denial if there is one
+    pub(crate) fn try_within_transaction(
+        &mut self,
+        id: &str,
+        mutations: Vec<(MutationKind, MutationTarget)>,
+        mutate: impl FnOnce(&mut Self),
+    ) -> Result<(), GraphError> {
+        self.permit_all(mutations)?;
+        let own = self.transaction.id.is_none();
+        if own {
+            self.start_transaction(id, None);
+        }
+        self.vetted(mutate);
+        if own {
+            self.end_transaction(id, None);
+        }
+        Ok(())
+    }
+
+    /// Scale a node out into copies
+    ///
+    /// Adds `n` copies of the node, named `rename(id, i)` for `i` in
+    /// `1..=n`, with its component, metadata and IIPs. Every inport fed
+    /// from elsewhere in the graph gets a distributor (`<id>_<port>_split`)
+    /// that hands packets to the node and its copies in turn, and every
+    /// outport read elsewhere gets a merger (`<id>_<port>_merge`) that
+    /// collects their output. Exported ports move to the distributors and
+    /// mergers, and the new nodes join every group the node is in. All
+    /// changes are made in one transaction. Fails without changing
+    /// anything if the node doesn't exist, one of the names is taken or
+    /// the mutation policy denies a change.
+    /// ```no_run
+    /// my_graph.replicate_node("Fetch", 3, |id, i| format!("{}{}", id, i))?;
+    /// ```
+    pub fn replicate_node(
+        &mut self,
+        id: &str,
+        n: usize,
+        rename: impl Fn(&str, usize) -> String,
+    ) -> Result<&mut Self, GraphError> {
+        self.replicate_node_with(id, n, rename, &MapReduceOptions::default())
+    }
+
+    /// `replicate_node` with the given kinds of distributors and mergers
+    pub(crate) fn replicate_node_with(
+        &mut self,
+        id: &str,
+        n: usize,
+        rename: impl Fn(&str, usize) -> String,
+        options: &MapReduceOptions,
+    ) -> Result<&mut Self, GraphError> {
+        if self.get_node(id).is_none() {
+            return Err(GraphError::NodeNotFound(id.to_owned()));
+        }
+        let replication = Replication::plan(self, vec![id.to_owned()], None, n, rename)?;
+        self.replicate(replication, options)
+    }
+
+    /// Scale a group out into copies
+    ///
+    /// Like `replicate_node`, for all nodes of a group at once: each copy
+    /// of the group gets copies of its nodes, named `rename(node, i)`, with
+    /// the edges between them, and is added as group `rename(name, i)`.
+    /// Edges into and out of the group go through distributors and mergers,
+    /// which are left out of the group and its copies since they belong to
+    /// neither. Groups nested in the group aren't copied. Fails if the
+    /// group doesn't exist, and does nothing if it has no nodes.
+    /// ```no_run
+    /// my_graph.replicate_group("fetching", 3, |id, i| format!("{}{}", id, i))?;
+    /// ```
+    pub fn replicate_group(
+        &mut self,
+        name: &str,
+        n: usize,
+        rename: impl Fn(&str, usize) -> String,
+    ) -> Result<&mut Self, GraphError> {
+        self.replicate_group_with(name, n, rename, &MapReduceOptions::default())
+    }
+
+    /// `replicate_group` with the given kinds of distributors and mergers
+    pub(crate) fn replicate_group_with(
+        &mut self,
+        name: &str,
+        n: usize,
+        rename: impl Fn(&str, usize) -> String,
+        options: &MapReduceOptions,
+    ) -> Result<&mut Self, GraphError> {
+        let Some(group) = self.get_group(name).cloned() else {
+            return Err(GraphError::GroupNotFound(name.to_owned()));
+        };
+        let members = group
+            .nodes
+            .iter()
+            .filter(|node| self.get_node(node).is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+        if members.is_empty() {
+            return Ok(self);
+        }
+        let replication = Replication::plan(self, members, Some(group), n, rename)?;
+        self.replicate(replication, options)
+    }
+
+    /// Check and make a planned replication in one transaction
+    fn replicate(
+        &mut self,
+        replication: Replication,
+        options: &MapReduceOptions,
+    ) -> Result<&mut Self, GraphError> {
+        let mutations = replication.mutations(self, &options.partitioning);
+        self.try_within_transaction("replicate", mutations, |graph| {
+            replication.apply(graph, options);
+        })?;
+        Ok(self)
+    }
+
+    /// Connect one outport to several inports in one transaction
+    /// ```no_run
+    /// my_graph.fan_out(("Split", "out"), [("Left", "in"), ("Right", "in")]);
+    /// ```
+    pub fn fan_out<T: Into<Endpoint>>(
+        &mut self,
+        from: impl Into<Endpoint>,
+        targets: impl IntoIterator<Item = T>,
+    ) -> &mut Self {
+        let from = from.into();
+        let targets = targets
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<Endpoint>>();
+        let mutations = targets
+            .iter()
+            .map(|to| {
+                let target = MutationTarget::Edge {
+                    from: from.node.to_string(),
+                    to: to.node.to_string(),
+                };
+                (MutationKind::AddEdge, target)
+            })
+            .collect();
+        self.within_transaction("fan_out", mutations, |graph| {
+            for to in targets {
+                graph.connect_endpoints(from.clone(), to, None);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::endpoint::Endpoint;
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use crate::graph::transform::{MERGE_COMPONENT, ROUND_ROBIN_COMPONENT};
+    use crate::graph::types::GraphError;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_group_replication() {
+        'given_a_grouped_stage_with_an_exported_port: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Parse", "ParseJson", None)
+                .add_node("Check", "Validate", None)
+                .add_node("Store", "WriteFile", None)
+                .add_edge("Read", "out", "Parse", "in", None)
+                .add_edge("Parse", "out", "Check", "in", None)
+                .add_edge("Check", "out", "Store", "in", None)
+                .add_outport("errors", "Check", "error", None)
+                .add_group("stage", vec!["Parse".to_owned(), "Check".to_owned()], None);
+            g.init_journal(None);
+            'when_replicating_the_group: {
+                g.replicate_group("stage", 1, |id, i| format!("{}{}", id, i))
+                    .unwrap();
+                'then_the_copy_should_be_wired_internally_and_grouped: {
+                    assert!(g.get_edge("Parse1", "out", "Check1", "in").is_some());
+                    assert_eq!(
+                        g.get_group("stage1").unwrap().nodes,
+                        vec!["Parse1".to_owned(), "Check1".to_owned()]
+                    );
+                    assert_eq!(g.get_group("stage").unwrap().nodes.len(), 2);
+                }
+                'then_only_boundary_edges_should_be_rewired: {
+                    assert!(g.get_edge("Read", "out", "Parse_in_split", "in").is_some());
+                    assert!(g
+                        .get_edge("Parse_in_split", "out", "Parse1", "in")
+                        .is_some());
+                    assert!(g
+                        .get_edge("Check1", "out", "Check_out_merge", "in")
+                        .is_some());
+                    assert!(g
+                        .get_edge("Check_out_merge", "out", "Store", "in")
+                        .is_some());
+                    assert!(g.get_edge("Parse", "out", "Check", "in").is_some());
+                }
+                'then_the_exported_port_should_move_to_a_merger: {
+                    assert_eq!(g.outports["errors"].process, "Check_error_merge");
+                    assert!(g
+                        .get_edge("Check1", "error", "Check_error_merge", "in")
+                        .is_some());
+                }
+                'then_it_should_be_one_revision: {
+                    assert_eq!(g.current_revision, 1);
+                    g.undo();
+                    assert_eq!(g.nodes.len(), 4);
+                    assert!(g.get_group("stage1").is_none());
+                    assert_eq!(g.outports["errors"].process, "Check");
+                }
+            }
+        }
+        'given_a_grouped_node: {
+            let mut g = Graph::new("", true);
+            g.add_node("Fetch", "HttpRequest", None)
+                .add_node("Store", "WriteFile", None)
+                .add_edge("Fetch", "out", "Store", "in", None)
+                .add_group("io", vec!["Fetch".to_owned(), "Store".to_owned()], None);
+            'when_replicating_the_node: {
+                g.replicate_node("Fetch", 1, |id, i| format!("{}{}", id, i))
+                    .unwrap();
+                'then_the_copy_and_merger_should_join_its_group: {
+                    let nodes = &g.get_group("io").unwrap().nodes;
+                    assert!(nodes.contains(&"Fetch1".to_owned()));
+                    assert!(nodes.contains(&"Fetch_out_merge".to_owned()));
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_replication() {
+        'given_a_pipeline_with_a_slow_stage: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Fetch", "HttpRequest", json!({"x": 5}).as_object().cloned())
+                .add_node("Store", "WriteFile", None)
+                .add_edge("Read", "out", "Fetch", "url", None)
+                .add_edge("Fetch", "out", "Store", "in", None)
+                .add_initial(json!({"timeout": 10}), "Fetch", "options", None);
+            g.init_journal(None);
+            'when_replicating_it: {
+                g.replicate_node("Fetch", 2, |id, i| format!("{}{}", id, i))
+                    .unwrap();
+                'then_each_copy_should_be_configured_like_the_original: {
+                    for copy in ["Fetch1", "Fetch2"] {
+                        let node = g.get_node(copy).unwrap();
+                        assert_eq!(node.component, "HttpRequest");
+                        assert_eq!(node.metadata.clone().unwrap()["x"], json!(5));
+                    }
+                    assert_eq!(g.initializers.len(), 3);
+                }
+                'then_upstream_should_go_through_a_distributor: {
+                    let split = g.get_node("Fetch_url_split").unwrap();
+                    assert_eq!(split.component, ROUND_ROBIN_COMPONENT);
+                    assert!(g.get_edge("Read", "out", "Fetch_url_split", "in").is_some());
+                    for (i, copy) in ["Fetch", "Fetch1", "Fetch2"].iter().enumerate() {
+                        let edge = g.get_edge("Fetch_url_split", "out", *copy, "url").unwrap();
+                        assert_eq!(edge.from.index, Some(i));
+                        assert!(g.get_edge("Read", "out", *copy, "url").is_none());
+                    }
+                }
+                'then_downstream_should_go_through_a_merger: {
+                    let merge = g.get_node("Fetch_out_merge").unwrap();
+                    assert_eq!(merge.component, MERGE_COMPONENT);
+                    assert!(g
+                        .get_edge("Fetch_out_merge", "out", "Store", "in")
+                        .is_some());
+                    for (i, copy) in ["Fetch", "Fetch1", "Fetch2"].iter().enumerate() {
+                        let edge = g.get_edge(*copy, "out", "Fetch_out_merge", "in").unwrap();
+                        assert_eq!(edge.to.index, Some(i));
+                        assert!(g.get_edge(*copy, "out", "Store", "in").is_none());
+                    }
+                    assert_eq!(g.edges.len(), 8);
+                }
+                'then_it_should_be_one_revision: {
+                    assert_eq!(g.current_revision, 1);
+                    g.undo();
+                    assert_eq!(g.nodes.len(), 3);
+                    assert!(g.get_edge("Read", "out", "Fetch", "url").is_some());
+                }
+            }
+            'when_a_name_is_taken: {
+                let result = g
+                    .replicate_node("Fetch", 2, |_, i| ["Copy", "Store"][i - 1].to_owned())
+                    .map(|_| ());
+                'then_nothing_should_be_added: {
+                    assert_eq!(result, Err(GraphError::NodeExists("Store".to_owned())));
+                    assert!(g.get_node("Copy").is_none());
+                    assert_eq!(g.nodes.len(), 3);
+                }
+            }
+        }
+        'given_a_splitter: {
+            let mut g = Graph::new("", true);
+            g.add_node("Split", "Split", None)
+                .add_node("Left", "Output", None)
+                .add_node("Right", "Output", None);
+            g.init_journal(None);
+            'when_fanning_out: {
+                g.fan_out(
+                    ("Split", "out"),
+                    [
+                        Endpoint::new("Left", "in"),
+                        Endpoint::new("Right", "in").with_index(1),
+                    ],
+                );
+                'then_every_target_should_be_connected_at_once: {
+                    assert_eq!(g.edges.len(), 2);
+                    assert_eq!(g.edges[1].to.index, Some(1));
+                    assert_eq!(g.current_revision, 1);
+                }
+            }
+        }
+    }
+}
@@ -6,7 +6,7 @@
 
 use crate::internal;
 use crate::internal::event_manager::EventActor;
-use crate::internal::utils::guid;
+use crate::internal::utils::IdGenerator;
 use foreach::ForEach;
 use futures::{executor::block_on, lock::Mutex};
 use internal::event_manager::EventManager;
@@ -15,13 +15,22 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, Read, Write};
 use std::sync::Arc;
-use std::{any::Any, process::exit};
+use std::any::Any;
 // use z_macros::{event_handler_attributes, EventHandler};
 
-use super::journal::TransactionEntry;
+use super::audit::{AuditRecord, AuditSink};
+use super::endpoint::{Endpoint, NodeId, PortName};
+use super::limits::GraphQuota;
+use super::metadata::MetadataValidator;
+use super::journal::{Journal, TransactionEntry};
+use super::policy::{MutationKind, MutationPolicy, MutationTarget};
+#[cfg(feature = "profiling")]
+use super::profile::{ProfileReport, Profiler};
+use super::tags::TagIndex;
 use super::types::{
-    GraphEdge, GraphEdgeJson, GraphExportedPort, GraphGroup, GraphIIP, GraphJson, GraphLeaf,
-    GraphLeafJson, GraphNode, GraphNodeJson, GraphStub, GraphTransaction,
+    DuplicateEdges, EdgePolicy, GraphEdge, GraphEdgeJson, GraphError, GraphExportedPort, GraphGroup,
+    GraphIIP, GraphJson, GraphLeaf, GraphLeafJson, GraphNode, GraphNodeJson, GraphOptions, GraphStub,
+    GraphTransaction, SelfLoops, ValidationLevel,
 };
 
 /// This class represents an abstract FBP graph containing nodes
@@ -30,29 +39,68 @@ use super::types::{
 /// also are the way to start an FBP network.
 #[derive(Clone)]
 pub struct Graph<'a> {
-    pub name: String,
-    pub nodes: Vec<GraphNode>,
-    pub edges: Vec<GraphEdge>,
-    pub initializers: Vec<GraphIIP>, // vec<GraphIIP>
-    pub groups: Vec<GraphGroup>,
-    pub inports: HashMap<String, GraphExportedPort>,
-    pub outports: HashMap<String, GraphExportedPort>,
-    pub properties: Map<String, Value>,
-    pub transaction: GraphTransaction,
-    pub last_revision: usize,
-    pub current_revision: i32,
-    pub transactions: Vec<Vec<TransactionEntry>>,
-    pub case_sensitive: bool,
-    pub entries: Vec<TransactionEntry>,
-    pub history: Vec<Vec<TransactionEntry>>,
-    pub subscribed: bool,
+    pub(crate) name: String,
+    pub(crate) nodes: Vec<GraphNode>,
+    pub(crate) edges: Vec<GraphEdge>,
+    pub(crate) initializers: Vec<GraphIIP>, // vec<GraphIIP>
+    pub(crate) groups: Vec<GraphGroup>,
+    pub(crate) inports: HashMap<String, GraphExportedPort>,
+    pub(crate) outports: HashMap<String, GraphExportedPort>,
+    pub(crate) properties: Map<String, Value>,
+    pub(crate) transaction: GraphTransaction,
+    pub(crate) last_revision: usize,
+    pub(crate) current_revision: i32,
+    pub(crate) transactions: Vec<Vec<TransactionEntry>>,
+    /// Times revisions that could be redone were replaced by new ones
+    pub(crate) history_rewrites: usize,
+    pub(crate) case_sensitive: bool,
+    pub(crate) entries: Vec<TransactionEntry>,
+    pub(crate) subscribed: bool,
+    pub(crate) tag_index: Option<TagIndex>,
+    pub(crate) metadata_validators: HashMap<String, Arc<dyn MetadataValidator>>,
+    /// Errors reported while inside `try_mutate`
+    captured_errors: Option<Vec<GraphError>>,
     listeners: HashMap<&'a str, Vec<EventActor<'a, Self>>>,
+    audit_sinks: Vec<Arc<dyn AuditSink>>,
+    /// Transactions committed while audit notifications are held
+    held_audit: Option<Vec<(usize, Vec<TransactionEntry>)>>,
+    mutation_policy: Option<Arc<dyn MutationPolicy>>,
+    mutation_depth: usize,
+    /// Depth of `vetted` calls, inside which mutators skip their own check
+    vetted: usize,
+    frozen: bool,
+    id_generator: Arc<dyn IdGenerator>,
+    validation_level: ValidationLevel,
+    edge_policy: EdgePolicy,
+    quota: Option<GraphQuota>,
+    #[cfg(feature = "profiling")]
+    profiler: Profiler,
+}
+
+/// Mutable borrows of a graph's collections, see `Graph::raw_parts`
+pub struct RawParts<'g> {
+    pub nodes: &'g mut Vec<GraphNode>,
+    pub edges: &'g mut Vec<GraphEdge>,
+    pub initializers: &'g mut Vec<GraphIIP>,
+    pub groups: &'g mut Vec<GraphGroup>,
+    pub inports: &'g mut HashMap<String, GraphExportedPort>,
+    pub outports: &'g mut HashMap<String, GraphExportedPort>,
+    pub properties: &'g mut Map<String, Value>,
 }
 
 impl<'a> EventManager<'a> for Graph<'a> {
     /// Send event
     fn emit(&mut self, name: &'a str, data: &dyn Any) {
+        self.index_tags(name, data);
+        if let (Some(errors), Some(err)) = (
+            self.captured_errors.as_mut(),
+            data.downcast_ref::<GraphError>(),
+        ) {
+            errors.push(err.clone());
+        }
         if let Some(v) = self.listeners.clone().get_mut(&name) {
+            #[cfg(feature = "profiling")]
+            let started = std::time::Instant::now();
             for i in 0..v.len() {
                 block_on(v[i].callback.lock())(self, data);
                 if (&v[i]).once {
@@ -60,6 +108,8 @@ impl<'a> EventManager<'a> for Graph<'a> {
                 }
             }
             self.listeners.insert(name, v.to_vec());
+            #[cfg(feature = "profiling")]
+            self.profiler.record_event(name, started.elapsed());
         }
     }
     /// Attach listener to an event
@@ -91,7 +141,36 @@ impl<'a> EventManager<'a> for Graph<'a> {
 
 impl<'a> Graph<'a> {
     pub fn new(name: &str, case_sensitive: bool) -> Self {
-        Self {
+        Self::with_options(
+            name,
+            GraphOptions {
+                case_sensitive,
+                ..GraphOptions::default()
+            },
+        )
+    }
+
+    /// Create a graph that takes node `uid`s from the given generator
+    /// ```no_run
+    /// let mut my_graph = Graph::new_with_options("test", true, SequentialIdGenerator::new("node-"));
+    /// ```
+    pub fn new_with_options(
+        name: &str,
+        case_sensitive: bool,
+        id_generator: impl IdGenerator + 'static,
+    ) -> Self {
+        Self::with_options(
+            name,
+            GraphOptions {
+                case_sensitive,
+                id_generator: Arc::new(id_generator),
+                ..GraphOptions::default()
+            },
+        )
+    }
+
+    pub fn with_options(name: &str, options: GraphOptions) -> Self {
+        let mut graph = Self {
             name: name.to_owned(),
             nodes: Vec::new(),
             edges: Vec::new(),
@@ -101,14 +180,380 @@ impl<'a> Graph<'a> {
             outports: HashMap::new(),
             properties: Map::new(),
             transaction: GraphTransaction { id: None, depth: 0 },
-            case_sensitive,
+            case_sensitive: options.case_sensitive,
             listeners: HashMap::new(),
             last_revision: 0,
             current_revision: -1,
             transactions: Vec::new(),
+            history_rewrites: 0,
             entries: Vec::new(),
-            history: Vec::new(),
             subscribed: false,
+            tag_index: Some(TagIndex::new()),
+            metadata_validators: HashMap::new(),
+            captured_errors: None,
+            audit_sinks: Vec::new(),
+            held_audit: None,
+            mutation_policy: None,
+            mutation_depth: 0,
+            vetted: 0,
+            frozen: false,
+            id_generator: options.id_generator,
+            validation_level: options.validation_level,
+            edge_policy: options.edge_policy,
+            quota: options.quota,
+            #[cfg(feature = "profiling")]
+            profiler: Profiler::default(),
+        };
+        if options.journal {
+            graph.init_journal(None);
+        }
+        graph
+    }
+
+    pub fn validation_level(&self) -> ValidationLevel {
+        self.validation_level
+    }
+
+    pub fn set_edge_policy(&mut self, policy: EdgePolicy) -> &mut Self {
+        self.edge_policy = policy;
+        self
+    }
+
+    pub fn edge_policy(&self) -> EdgePolicy {
+        self.edge_policy
+    }
+
+    /// Apply the edge policy to a new connection between existing
+    /// nodes, returning whether it should be added
+    fn admit_edge(
+        &mut self,
+        duplicate: bool,
+        (out_node, out_port): (&str, &str),
+        (in_node, in_port): (&str, &str),
+        metadata: &Option<Map<String, Value>>,
+    ) -> bool {
+        let rejected = if out_node == in_node && self.edge_policy.self_loops == SelfLoops::Reject {
+            GraphError::SelfLoop(out_node.to_owned())
+        } else if !duplicate {
+            return true;
+        } else {
+            match self.edge_policy.duplicates {
+                DuplicateEdges::Ignore => return false,
+                DuplicateEdges::Allow => return true,
+                DuplicateEdges::MergeMetadata => {
+                    if let Some(metadata) = metadata.clone().filter(|m| !m.is_empty()) {
+                        self.set_edge_metadata(out_node, out_port, in_node, in_port, metadata);
+                    }
+                    return false;
+                }
+                DuplicateEdges::Reject => GraphError::DuplicateEdge {
+                    from: format!("{}.{}", out_node, out_port),
+                    to: format!("{}.{}", in_node, in_port),
+                },
+            }
+        };
+        self.reject("edge_rejected", rejected);
+        false
+    }
+
+    /// Log a change that can't be made and report it through `event`
+    fn reject(&mut self, event: &'a str, err: GraphError) {
+        log::error!("{}", err);
+        self.emit(event, &err);
+    }
+
+    /// Report the first of the given nodes missing from the graph
+    fn reject_missing_node(&mut self, event: &'a str, ids: &[&str]) -> bool {
+        match ids.iter().find(|id| self.get_node(id).is_none()) {
+            Some(id) => {
+                self.reject(event, GraphError::NodeNotFound((*id).to_owned()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_case_sensitive(&self) -> bool {
+        self.case_sensitive
+    }
+
+    pub fn properties(&self) -> &Map<String, Value> {
+        &self.properties
+    }
+
+    pub fn groups(&self) -> std::slice::Iter<'_, GraphGroup> {
+        self.groups.iter()
+    }
+
+    pub fn inports(&self) -> &HashMap<String, GraphExportedPort> {
+        &self.inports
+    }
+
+    pub fn outports(&self) -> &HashMap<String, GraphExportedPort> {
+        &self.outports
+    }
+
+    /// Revision the journal is currently at, or -1 without a journal
+    pub fn current_revision(&self) -> i32 {
+        self.current_revision
+    }
+
+    pub fn last_revision(&self) -> usize {
+        self.last_revision
+    }
+
+    /// Whether changes are being recorded into a journal
+    pub fn is_subscribed(&self) -> bool {
+        self.subscribed
+    }
+
+    /// Direct mutable access to the graph's collections
+    ///
+    /// Changes made through the returned parts bypass events, the
+    /// mutation policy and the journal, and suspend the tag index until
+    /// the next node event. Meant for bulk loading and other advanced uses
+    /// where the caller keeps the graph consistent itself.
+    /// ```no_run
+    /// let parts = my_graph.raw_parts();
+    /// parts.edges.reserve(10_000);
+    /// ```
+    pub fn raw_parts(&mut self) -> RawParts<'_> {
+        self.invalidate_tags();
+        RawParts {
+            nodes: &mut self.nodes,
+            edges: &mut self.edges,
+            initializers: &mut self.initializers,
+            groups: &mut self.groups,
+            inports: &mut self.inports,
+            outports: &mut self.outports,
+            properties: &mut self.properties,
+        }
+    }
+
+    /// Consult the given policy before every mutation of the graph
+    pub fn set_mutation_policy(&mut self, policy: impl MutationPolicy + 'static) -> &mut Self {
+        self.mutation_policy = Some(Arc::new(policy));
+        self
+    }
+
+    pub fn clear_mutation_policy(&mut self) -> &mut Self {
+        self.mutation_policy = None;
+        self
+    }
+
+    /// Switch the graph to read-only mode
+    ///
+    /// While frozen, every mutation is denied with `GraphError::Frozen`
+    /// and the journal cannot move to other revisions. Used while a
+    /// network runs the graph in strict mode, or while inspecting a
+    /// historical revision.
+    pub fn freeze(&mut self) -> &mut Self {
+        self.frozen = true;
+        self
+    }
+
+    /// Resume editing a frozen graph
+    pub fn unfreeze(&mut self) -> &mut Self {
+        self.frozen = false;
+        self
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Deny mutations that would grow the graph beyond the node and
+    /// edge counts of the quota with `GraphError::QuotaExceeded`
+    pub fn set_quota(&mut self, quota: GraphQuota) -> &mut Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    pub fn clear_quota(&mut self) -> &mut Self {
+        self.quota = None;
+        self
+    }
+
+    /// Check whether the mutation policy allows a change, without applying it
+    pub fn check_mutation(
+        &self,
+        kind: MutationKind,
+        target: &MutationTarget,
+    ) -> Result<(), GraphError> {
+        self.check_mutation_with_counts(kind, target, self.nodes.len(), self.edges.len())
+    }
+
+    /// `check_mutation` as if the graph had the given node and edge counts
+    fn check_mutation_with_counts(
+        &self,
+        kind: MutationKind,
+        target: &MutationTarget,
+        nodes: usize,
+        edges: usize,
+    ) -> Result<(), GraphError> {
+        if self.frozen {
+            return Err(GraphError::Frozen);
+        }
+        if let Some(quota) = self.quota.as_ref() {
+            let exceeded = match kind {
+                MutationKind::AddNode if nodes >= quota.max_nodes => {
+                    Some(("nodes", quota.max_nodes))
+                }
+                MutationKind::AddEdge if edges >= quota.max_edges => {
+                    Some(("edges", quota.max_edges))
+                }
+                _ => None,
+            };
+            if let Some((resource, limit)) = exceeded {
+                return Err(GraphError::QuotaExceeded {
+                    resource: resource.to_owned(),
+                    limit,
+                });
+            }
+        }
+        if let Some(policy) = self.mutation_policy.as_ref() {
+            policy
+                .check(kind, target)
+                .map_err(|reason| GraphError::PermissionDenied {
+                    kind,
+                    target: target.clone(),
+                    reason,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Check every change a helper is about to make, before it opens a
+    /// transaction
+    ///
+    /// Helpers that make several changes must vet all their targets with
+    /// this first, give up on a denial, and then make the changes inside
+    /// `vetted`. Nodes and edges added or removed earlier in the batch
+    /// count towards the quota of later ones. The first denial is logged,
+    /// emitted as `mutation_denied` and returned. Inside `vetted`, the
+    /// outer helper has already checked everything and this allows.
+    pub(crate) fn permit_all(
+        &mut self,
+        mutations: impl IntoIterator<Item = (MutationKind, MutationTarget)>,
+    ) -> Result<(), GraphError> {
+        if self.vetted > 0 {
+            return Ok(());
+        }
+        let checked = self.check_mutations(mutations);
+        if let Err(err) = checked.as_ref() {
+            log::error!("{}", err);
+            self.emit("mutation_denied", err);
+        }
+        checked
+    }
+
+    /// `check_mutation` for a sequence of changes, without reporting denials
+    pub(crate) fn check_mutations(
+        &self,
+        mutations: impl IntoIterator<Item = (MutationKind, MutationTarget)>,
+    ) -> Result<(), GraphError> {
+        let (mut nodes, mut edges) = (self.nodes.len(), self.edges.len());
+        for (kind, target) in mutations {
+            self.check_mutation_with_counts(kind, &target, nodes, edges)?;
+            match kind {
+                MutationKind::AddNode => nodes += 1,
+                MutationKind::RemoveNode => nodes = nodes.saturating_sub(1),
+                MutationKind::AddEdge => edges += 1,
+                MutationKind::RemoveEdge => edges = edges.saturating_sub(1),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the mutation policy for a mutator call, reporting denials
+    ///
+    /// Inside `vetted` this always allows: the helper that opened it has
+    /// checked the whole change with `permit_all`. Transactions don't
+    /// matter, so changes made after `check_transaction_start` are still
+    /// checked.
+    pub(crate) fn permit(&mut self, kind: MutationKind, target: MutationTarget) -> bool {
+        if self.vetted > 0 {
+            return true;
+        }
+        if let Err(err) = self.check_mutation(kind, &target) {
+            log::error!("{}", err);
+            self.emit("mutation_denied", &err);
+            return false;
+        }
+        #[cfg(feature = "profiling")]
+        if self.mutation_depth == 0 {
+            self.profiler.start_mutation(kind);
+        }
+        true
+    }
+
+    /// Make changes that were checked up front with `permit_all`
+    ///
+    /// Mutators called by `mutate` skip their own policy check. This is
+    /// the only way to skip it, and it's only for callers that vetted
+    /// every change `mutate` makes.
+    pub(crate) fn vetted<R>(&mut self, mutate: impl FnOnce(&mut Self) -> R) -> R {
+        self.vetted += 1;
+        let result = mutate(self);
+        self.vetted -= 1;
+        result
+    }
+
+    /// Timings of mutators and event dispatch recorded so far
+    #[cfg(feature = "profiling")]
+    pub fn profile_report(&self) -> ProfileReport {
+        self.profiler.report()
+    }
+
+    #[cfg(feature = "profiling")]
+    pub fn reset_profile(&mut self) -> &mut Self {
+        self.profiler.reset();
+        self
+    }
+
+    /// Stream committed journal transactions to an external audit sink
+    /// ```no_run
+    /// my_graph.add_audit_sink(FileSink::new("audit.jsonl")).init_journal(None);
+    /// ```
+    pub fn add_audit_sink(&mut self, sink: impl AuditSink + 'static) -> &mut Self {
+        self.audit_sinks.push(Arc::new(sink));
+        self
+    }
+
+    pub(crate) fn notify_audit_sinks(&mut self, rev_id: usize, entries: &[TransactionEntry]) {
+        if let Some(held) = self.held_audit.as_mut() {
+            held.push((rev_id, entries.to_vec()));
+            return;
+        }
+        if self.audit_sinks.is_empty() {
+            return;
+        }
+        let record = AuditRecord::new(&self.name, rev_id, entries.to_vec());
+        for sink in self.audit_sinks.iter() {
+            if let Err(err) = sink.record(&record) {
+                log::error!("Failed to record transaction {}: {}", rev_id, err);
+            }
+        }
+    }
+
+    /// Keep committed transactions from audit sinks until `release_audit`
+    pub(crate) fn hold_audit(&mut self) {
+        self.held_audit.get_or_insert_with(Vec::new);
+    }
+
+    /// Stop holding audit notifications, sending the held ones to the
+    /// sinks if `deliver`, or dropping them if they were rolled back
+    pub(crate) fn release_audit(&mut self, deliver: bool) {
+        let held = self.held_audit.take().unwrap_or_default();
+        if deliver {
+            for (rev_id, entries) in held {
+                self.notify_audit_sinks(rev_id, &entries);
+            }
         }
     }
 
@@ -119,14 +564,35 @@ impl<'a> Graph<'a> {
         port.to_lowercase()
     }
 
+    /// Open a transaction, grouping the following mutations into one
+    /// revision
+    ///
+    /// Transactions can't be nested: if one is already open, nothing
+    /// happens and a `transaction_error` event is emitted with
+    /// `GraphError::NestedTransaction`. Use `try_start_transaction` to get
+    /// the error back instead.
     pub fn start_transaction(
         &mut self,
         id: &str,
         metadata: Option<Map<String, Value>>,
     ) -> &mut Self {
-        if self.transaction.id.is_some() {
-            log::error!("Nested transactions not supported");
-            exit(1)
+        if let Err(err) = self.try_start_transaction(id, metadata) {
+            log::error!("{}", err);
+            self.emit("transaction_error", &err);
+        }
+        self
+    }
+
+    pub fn try_start_transaction(
+        &mut self,
+        id: &str,
+        metadata: Option<Map<String, Value>>,
+    ) -> Result<&mut Self, GraphError> {
+        if let Some(open) = self.transaction.id.clone() {
+            return Err(GraphError::NestedTransaction {
+                open,
+                requested: id.to_owned(),
+            });
         }
 
         self.transaction.id = Some(id.to_string());
@@ -136,23 +602,132 @@ impl<'a> Graph<'a> {
             "start_transaction",
             &(self.transaction.id.clone().unwrap(), metadata),
         );
-        self
+        Ok(self)
     }
 
+    /// Close the open transaction
+    ///
+    /// Without an open transaction, nothing happens and a
+    /// `transaction_error` event is emitted with `GraphError::NoTransaction`.
     pub fn end_transaction(&mut self, id: &str, metadata: Option<Map<String, Value>>) -> &mut Self {
+        if let Err(err) = self.try_end_transaction(id, metadata) {
+            log::error!("{}", err);
+            self.emit("transaction_error", &err);
+        }
+        self
+    }
+
+    pub fn try_end_transaction(
+        &mut self,
+        id: &str,
+        metadata: Option<Map<String, Value>>,
+    ) -> Result<&mut Self, GraphError> {
         if self.transaction.id.is_none() {
-            log::error!("Attempted to end non-existing transaction");
-            exit(1)
+            return Err(GraphError::NoTransaction(id.to_owned()));
         }
 
         self.transaction.id = None;
         self.transaction.depth = 0;
 
         self.emit("end_transaction", &((id.to_string(), metadata)));
-        self
+        Ok(self)
+    }
+
+    /// Run mutations, failing with the first error they report
+    ///
+    /// Mutators keep chaining by returning `&mut Self`, and report
+    /// rejected changes through events instead (`mutation_denied`,
+    /// `node_rejected`, `edge_rejected`, `port_rejected`,
+    /// `metadata_rejected`, `transaction_error`, ...).
+    /// This collects those errors, so embedders can handle them as a
+    /// `Result`. Mutations that did go through are kept.
+    /// ```no_run
+    /// my_graph.try_mutate(|g| {
+    ///     g.add_node("Read", "ReadFile", None)
+    ///         .add_edge("Read", "out", "Log", "in", None);
+    /// })?;
+    /// ```
+    pub fn try_mutate(&mut self, mutate: impl FnOnce(&mut Self)) -> Result<&mut Self, GraphError> {
+        let outer = self.captured_errors.replace(Vec::new());
+        mutate(self);
+        let errors = self.captured_errors.take().unwrap_or_default();
+        self.captured_errors = outer.map(|mut outer| {
+            outer.extend(errors.iter().cloned());
+            outer
+        });
+        match errors.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(self),
+        }
+    }
+
+    /// `add_node`, failing if the node is rejected
+    pub fn try_add_node(
+        &mut self,
+        id: &str,
+        component: &str,
+        metadata: Option<Map<String, Value>>,
+    ) -> Result<&mut Self, GraphError> {
+        self.try_mutate(|g| {
+            g.add_node(id, component, metadata);
+        })
+    }
+
+    /// `add_edge`, failing if the edge is rejected, e.g. for a missing node
+    pub fn try_add_edge(
+        &mut self,
+        out_node: &str,
+        out_port: &str,
+        in_node: &str,
+        in_port: &str,
+        metadata: Option<Map<String, Value>>,
+    ) -> Result<&mut Self, GraphError> {
+        self.try_mutate(|g| {
+            g.add_edge(out_node, out_port, in_node, in_port, metadata);
+        })
+    }
+
+    /// `add_keyed_edge`, failing if the edge is rejected, e.g. for a taken key
+    pub fn try_add_keyed_edge(
+        &mut self,
+        from: impl Into<Endpoint>,
+        to: impl Into<Endpoint>,
+        key: &str,
+        metadata: Option<Map<String, Value>>,
+    ) -> Result<&mut Self, GraphError> {
+        self.try_mutate(|g| {
+            g.add_keyed_edge(from, to, key, metadata);
+        })
+    }
+
+    /// `add_inport`, failing if the port is rejected, e.g. for a missing node
+    pub fn try_add_inport(
+        &mut self,
+        public_port: &str,
+        node_key: &str,
+        port_key: &str,
+        metadata: Option<Map<String, Value>>,
+    ) -> Result<&mut Self, GraphError> {
+        self.try_mutate(|g| {
+            g.add_inport(public_port, node_key, port_key, metadata);
+        })
+    }
+
+    /// `add_outport`, failing if the port is rejected, e.g. for a missing node
+    pub fn try_add_outport(
+        &mut self,
+        public_port: &str,
+        node_key: &str,
+        port_key: &str,
+        metadata: Option<Map<String, Value>>,
+    ) -> Result<&mut Self, GraphError> {
+        self.try_mutate(|g| {
+            g.add_outport(public_port, node_key, port_key, metadata);
+        })
     }
 
     pub fn check_transaction_start(&mut self) -> &mut Self {
+        self.mutation_depth += 1;
         if self.transaction.id.is_none() {
             self.start_transaction("implicit", None);
         } else if self.transaction.id.as_ref().unwrap() == "implicit" {
@@ -162,6 +737,7 @@ impl<'a> Graph<'a> {
         self
     }
     pub fn check_transaction_end(&mut self) -> &mut Self {
+        self.mutation_depth = self.mutation_depth.saturating_sub(1);
         if let Some(transaction_id) = self.transaction.id.clone() {
             if transaction_id == "implicit" {
                 self.transaction.depth -= 1;
@@ -171,19 +747,28 @@ impl<'a> Graph<'a> {
                 self.end_transaction("implicit", None);
             }
         }
+        #[cfg(feature = "profiling")]
+        if self.mutation_depth == 0 {
+            self.profiler.end_mutation();
+        }
 
         self
     }
 
     /// This method allows changing properties of the graph.
     pub fn set_properties(&mut self, properties: Map<String, Value>) -> &mut Self {
+        if !self.permit(MutationKind::ChangeProperties, MutationTarget::Graph) {
+            return self;
+        }
         self.check_transaction_start();
         let before = self.properties.clone();
 
         for item in properties.keys() {
             let val = properties.get(item);
-            if let Some(val) = val {
+            if let Some(val) = val.filter(|val| !val.is_null()) {
                 self.properties.insert(item.to_string(), val.clone());
+            } else {
+                self.properties.remove(item);
             }
         }
 
@@ -204,23 +789,29 @@ impl<'a> Graph<'a> {
 
     pub fn add_inport(
         &mut self,
-        public_port: &str,
-        node_key: &str,
-        port_key: &str,
+        public_port: impl Into<PortName>,
+        node_key: impl Into<NodeId>,
+        port_key: impl Into<PortName>,
         metadata: Option<Map<String, Value>>,
     ) -> &mut Self {
-        // Check that node exists
-        if self.get_node(node_key).is_none() {
+        let node_key = node_key.into();
+        if self.reject_missing_node("port_rejected", &[node_key.as_str()]) {
             return self;
         }
 
-        let port_name = self.get_port_name(public_port);
+        let port_name = self.get_port_name(&public_port.into());
+        if !self.permit(
+            MutationKind::AddInport,
+            MutationTarget::Inport(port_name.clone()),
+        ) {
+            return self;
+        }
 
         self.check_transaction_start();
 
         let val = GraphExportedPort {
-            process: node_key.to_owned(),
-            port: self.get_port_name(port_key),
+            process: node_key.into_string(),
+            port: self.get_port_name(&port_key.into()),
             metadata,
         };
         self.inports.insert(port_name.to_owned(), val.clone());
@@ -232,17 +823,25 @@ impl<'a> Graph<'a> {
         self
     }
 
-    pub fn remove_inport(&mut self, public_port: &str) -> &mut Self {
-        let port_name = self.get_port_name(public_port);
+    pub fn remove_inport(&mut self, public_port: impl Into<PortName>) -> &mut Self {
+        let port_name = self.get_port_name(&public_port.into());
 
         if !self.inports.contains_key(&(port_name.clone())) {
             return self;
         }
+        if !self.permit(
+            MutationKind::RemoveInport,
+            MutationTarget::Inport(port_name.clone()),
+        ) {
+            return self;
+        }
         self.check_transaction_start();
 
         let inp = self.inports.clone();
 
-        self.set_inports_metadata(port_name.as_str(), Map::new());
+        self.vetted(|graph| {
+            graph.set_inports_metadata(port_name.as_str(), Map::new());
+        });
 
         self.inports.remove(&(port_name.clone()));
 
@@ -270,6 +869,12 @@ impl<'a> Graph<'a> {
         if new_port_name == old_port_name {
             return self;
         }
+        if !self.permit(
+            MutationKind::RenameInport,
+            MutationTarget::Inport(old_port_name.clone()),
+        ) {
+            return self;
+        }
 
         self.check_transaction_start();
 
@@ -289,23 +894,29 @@ impl<'a> Graph<'a> {
 
     pub fn add_outport(
         &mut self,
-        public_port: &str,
-        node_key: &str,
-        port_key: &str,
+        public_port: impl Into<PortName>,
+        node_key: impl Into<NodeId>,
+        port_key: impl Into<PortName>,
         metadata: Option<Map<String, Value>>,
     ) -> &mut Self {
-        // Check that node exists
-        if self.get_node(node_key).is_none() {
+        let node_key = node_key.into();
+        if self.reject_missing_node("port_rejected", &[node_key.as_str()]) {
             return self;
         }
 
-        let port_name = self.get_port_name(public_port);
+        let port_name = self.get_port_name(&public_port.into());
+        if !self.permit(
+            MutationKind::AddOutport,
+            MutationTarget::Outport(port_name.clone()),
+        ) {
+            return self;
+        }
 
         self.check_transaction_start();
 
         let val = GraphExportedPort {
-            process: node_key.to_owned(),
-            port: self.get_port_name(port_key),
+            process: node_key.into_string(),
+            port: self.get_port_name(&port_key.into()),
             metadata,
         };
         self.outports.insert(port_name.to_owned(), val.clone());
@@ -316,17 +927,25 @@ impl<'a> Graph<'a> {
         self
     }
 
-    pub fn remove_outport(&mut self, public_port: &str) -> &mut Self {
-        let port_name = self.get_port_name(public_port);
+    pub fn remove_outport(&mut self, public_port: impl Into<PortName>) -> &mut Self {
+        let port_name = self.get_port_name(&public_port.into());
 
         if !self.outports.contains_key(&(port_name.clone())) {
             return self;
         }
+        if !self.permit(
+            MutationKind::RemoveOutport,
+            MutationTarget::Outport(port_name.clone()),
+        ) {
+            return self;
+        }
         self.check_transaction_start();
 
         let oup = self.outports.clone();
 
-        self.set_outports_metadata(port_name.as_str(), Map::new());
+        self.vetted(|graph| {
+            graph.set_outports_metadata(port_name.as_str(), Map::new());
+        });
 
         self.outports.remove(&(port_name.clone()));
 
@@ -354,6 +973,12 @@ impl<'a> Graph<'a> {
         if new_port_name == old_port_name {
             return self;
         }
+        if !self.permit(
+            MutationKind::RenameOutport,
+            MutationTarget::Outport(old_port_name.clone()),
+        ) {
+            return self;
+        }
 
         self.check_transaction_start();
 
@@ -377,10 +1002,19 @@ impl<'a> Graph<'a> {
         public_port: &str,
         metadata: Map<String, Value>,
     ) -> &mut Self {
+        let Some(metadata) = self.validate_metadata(metadata) else {
+            return self;
+        };
         let port_name = self.get_port_name(public_port);
         if !self.inports.contains_key(&(port_name.clone())) {
             return self;
         }
+        if !self.permit(
+            MutationKind::ChangeInport,
+            MutationTarget::Inport(port_name.clone()),
+        ) {
+            return self;
+        }
 
         self.check_transaction_start();
 
@@ -397,7 +1031,7 @@ impl<'a> Graph<'a> {
                 let val = meta.get(item);
                 let mut existing_meta = p.metadata.clone();
                 if let Some(existing_meta) = existing_meta.as_mut() {
-                    if let Some(val) = val {
+                    if let Some(val) = val.filter(|val| !val.is_null()) {
                         existing_meta.insert(item.clone(), val.clone());
                     } else {
                         existing_meta.remove(item);
@@ -426,10 +1060,19 @@ impl<'a> Graph<'a> {
         public_port: &str,
         metadata: Map<String, Value>,
     ) -> &mut Self {
+        let Some(metadata) = self.validate_metadata(metadata) else {
+            return self;
+        };
         let port_name = self.get_port_name(public_port);
         if !self.outports.contains_key(&(port_name.clone())) {
             return self;
         }
+        if !self.permit(
+            MutationKind::ChangeOutport,
+            MutationTarget::Outport(port_name.clone()),
+        ) {
+            return self;
+        }
 
         self.check_transaction_start();
 
@@ -446,7 +1089,7 @@ impl<'a> Graph<'a> {
                 let val = meta.get(item);
                 let mut existing_meta = p.metadata.clone();
                 if let Some(existing_meta) = existing_meta.as_mut() {
-                    if let Some(val) = val {
+                    if let Some(val) = val.filter(|val| !val.is_null()) {
                         existing_meta.insert(item.clone(), val.clone());
                     } else {
                         existing_meta.remove(item);
@@ -477,6 +1120,9 @@ impl<'a> Graph<'a> {
         nodes: Vec<String>,
         metadata: Option<Map<String, Value>>,
     ) -> &mut Self {
+        if !self.permit(MutationKind::AddGroup, MutationTarget::Group(group.to_owned())) {
+            return self;
+        }
         self.check_transaction_start();
         let g = &GraphGroup {
             name: group.to_owned(),
@@ -490,6 +1136,12 @@ impl<'a> Graph<'a> {
     }
 
     pub fn rename_group(&mut self, old_name: &str, new_name: &str) -> &mut Self {
+        if !self.permit(
+            MutationKind::RenameGroup,
+            MutationTarget::Group(old_name.to_owned()),
+        ) {
+            return self;
+        }
         self.check_transaction_start();
         for i in 0..self.groups.len() {
             let mut group = &mut self.groups[i];
@@ -498,28 +1150,49 @@ impl<'a> Graph<'a> {
                 self.emit("rename_group", &(old_name.to_owned(), new_name.to_owned()));
             }
         }
+        self.reparent_child_groups(old_name, Some(new_name));
         self.check_transaction_end();
         self
     }
 
     pub fn remove_group(&mut self, group_name: &str) -> &mut Self {
-        self.check_transaction_start();
+        // Nested groups are handed to the parent of the removed one
+        let mut mutations = vec![(
+            MutationKind::RemoveGroup,
+            MutationTarget::Group(group_name.to_owned()),
+        )];
+        for child in self.child_groups(group_name) {
+            let target = MutationTarget::Group(child.name.clone());
+            mutations.push((MutationKind::ChangeGroup, target));
+        }
+        if self.permit_all(mutations).is_err() {
+            return self;
+        }
+        self.vetted(|graph| {
+            graph.check_transaction_start();
 
-        self.groups = self
-            .groups
-            .clone()
-            .iter()
-            .filter(|v| {
-                if v.name == group_name.to_owned() {
-                    self.set_group_metadata(group_name, Map::new());
-                    self.emit("remove_group", v.clone());
-                    return false;
-                }
-                return true;
-            })
-            .map(|v| v.clone())
-            .collect();
-        self.check_transaction_end();
+            let parent = graph
+                .get_group(group_name)
+                .and_then(|group| group.parent())
+                .map(|parent| parent.to_owned());
+            graph.reparent_child_groups(group_name, parent.as_deref());
+
+            graph.groups = graph
+                .groups
+                .clone()
+                .iter()
+                .filter(|v| {
+                    if v.name == group_name.to_owned() {
+                        graph.set_group_metadata(group_name, Map::new());
+                        graph.emit("remove_group", v.clone());
+                        return false;
+                    }
+                    return true;
+                })
+                .map(|v| v.clone())
+                .collect();
+            graph.check_transaction_end();
+        });
         self
     }
     pub fn set_group_metadata(
@@ -527,18 +1200,33 @@ impl<'a> Graph<'a> {
         group_name: &str,
         metadata: Map<String, Value>,
     ) -> &mut Self {
+        let Some(metadata) = self.validate_metadata(metadata) else {
+            return self;
+        };
+        if !self.permit(
+            MutationKind::ChangeGroup,
+            MutationTarget::Group(group_name.to_owned()),
+        ) {
+            return self;
+        }
         self.check_transaction_start();
         for (i, group) in self.groups.clone().iter_mut().enumerate() {
             if group.name != group_name.to_owned() {
                 continue;
             }
             let before = group.metadata.clone();
+            if group.metadata.is_none() {
+                group.metadata = Some(Map::new());
+            }
             for item in metadata.clone().keys() {
                 if let Some(meta) = group.metadata.as_mut() {
-                    if let Some(val) = metadata.get(item) {
-                        meta.insert(item.to_owned(), val.clone());
-                    } else {
-                        meta.remove(item);
+                    match metadata.get(item) {
+                        Some(Value::Null) | None => {
+                            meta.remove(item);
+                        }
+                        Some(val) => {
+                            meta.insert(item.to_owned(), val.clone());
+                        }
                     }
                 }
             }
@@ -566,10 +1254,17 @@ impl<'a> Graph<'a> {
         component: &str,
         metadata: Option<Map<String, Value>>,
     ) -> &mut Self {
+        if self.validation_level == ValidationLevel::Strict && self.get_node(id).is_some() {
+            self.reject("node_rejected", GraphError::NodeExists(id.to_owned()));
+            return self;
+        }
+        if !self.permit(MutationKind::AddNode, MutationTarget::Node(id.to_owned())) {
+            return self;
+        }
         self.check_transaction_start();
         let node = &GraphNode {
             id: id.to_owned(),
-            uid: guid(),
+            uid: self.id_generator.next_id(),
             component: component.to_owned(),
             metadata,
         };
@@ -587,11 +1282,17 @@ impl<'a> Graph<'a> {
     /// ```
     /// Once the node has been removed, the `remove_node` event will be
     pub fn remove_node(&mut self, id: &str) -> &mut Self {
-        if let Some(node) = self.get_node(id).cloned() {
-            self.check_transaction_start();
-            self.edges.clone().iter().foreach(|edge, _iter| {
+        let Some(node) = self.get_node(id).cloned() else {
+            return self;
+        };
+        if self.permit_all(self.remove_node_mutations(id)).is_err() {
+            return self;
+        }
+        self.vetted(|graph| {
+            graph.check_transaction_start();
+            graph.edges.clone().iter().foreach(|edge, _iter| {
                 if (edge.from.node_id == node.id) || (edge.to.node_id == node.id) {
-                    self.remove_edge(
+                    graph.remove_edge(
                         edge.from.node_id.as_str(),
                         edge.from.port.as_str(),
                         Some(edge.to.node_id.as_str()),
@@ -599,38 +1300,38 @@ impl<'a> Graph<'a> {
                     );
                 }
             });
-            self.initializers.clone().iter().foreach(|iip, _iter| {
+            graph.initializers.clone().iter().foreach(|iip, _iter| {
                 if let Some(to) = iip.to.clone() {
                     if to.node_id == node.id {
-                        self.remove_initial(to.node_id.as_str(), to.port.as_str());
+                        graph.remove_initial(to.node_id.as_str(), to.port.as_str());
                     }
                 }
             });
-            self.inports.clone().keys().foreach(|port, _iter| {
-                if let Some(private) = self.inports.clone().get(port) {
+            graph.inports.clone().keys().foreach(|port, _iter| {
+                if let Some(private) = graph.inports.clone().get(port) {
                     if private.process == id {
-                        self.remove_inport(port);
+                        graph.remove_inport(port);
                     }
                 }
             });
-            self.outports.clone().keys().foreach(|port, _iter| {
-                if let Some(private) = self.outports.clone().get(port) {
+            graph.outports.clone().keys().foreach(|port, _iter| {
+                if let Some(private) = graph.outports.clone().get(port) {
                     if private.process == id {
-                        self.remove_outport(port);
+                        graph.remove_outport(port);
                     }
                 }
             });
 
-            self.groups = self
+            graph.groups = graph
                 .groups
                 .clone()
                 .iter()
                 .filter(|group| {
                     if group.nodes.is_empty() {
-                        self.check_transaction_start();
-                        self.set_group_metadata(group.name.as_str(), Map::new());
-                        self.emit("remove_group", group.clone());
-                        self.check_transaction_end();
+                        graph.check_transaction_start();
+                        graph.set_group_metadata(group.name.as_str(), Map::new());
+                        graph.emit("remove_group", group.clone());
+                        graph.check_transaction_end();
                     }
                     return true;
                 })
@@ -638,26 +1339,83 @@ impl<'a> Graph<'a> {
                 .map(|g| g.clone())
                 .collect();
 
-            self.set_node_metadata(id, Map::new());
-            self.nodes = self
+            graph.set_node_metadata(id, Map::new());
+            graph.nodes = graph
                 .nodes
                 .clone()
                 .iter()
                 .filter(|n| n.id != node.id)
                 .map(|n| n.clone())
                 .collect::<Vec<GraphNode>>();
-            self.emit("remove_node", &node);
-            self.check_transaction_end();
-        }
+            graph.emit("remove_node", &node);
+            graph.check_transaction_end();
+        });
 
         self
     }
 
+    /// Changes made by `remove_node`: the node itself, then the edges,
+    /// IIPs, exported ports and groups removed along with it
+    pub(crate) fn remove_node_mutations(&self, id: &str) -> Vec<(MutationKind, MutationTarget)> {
+        let mut mutations = vec![(
+            MutationKind::RemoveNode,
+            MutationTarget::Node(id.to_owned()),
+        )];
+        for edge in self
+            .edges
+            .iter()
+            .filter(|edge| edge.from.node_id == id || edge.to.node_id == id)
+        {
+            let target = MutationTarget::Edge {
+                from: edge.from.node_id.clone(),
+                to: edge.to.node_id.clone(),
+            };
+            mutations.push((MutationKind::RemoveEdge, target));
+        }
+        if self
+            .initializers
+            .iter()
+            .any(|iip| iip.to.as_ref().map(|to| to.node_id == id).unwrap_or(false))
+        {
+            mutations.push((
+                MutationKind::RemoveInitial,
+                MutationTarget::Initial(id.to_owned()),
+            ));
+        }
+        for (name, _) in self.inports.iter().filter(|(_, port)| port.process == id) {
+            mutations.push((
+                MutationKind::RemoveInport,
+                MutationTarget::Inport(name.clone()),
+            ));
+        }
+        for (name, _) in self.outports.iter().filter(|(_, port)| port.process == id) {
+            mutations.push((
+                MutationKind::RemoveOutport,
+                MutationTarget::Outport(name.clone()),
+            ));
+        }
+        for group in self.groups.iter() {
+            let target = MutationTarget::Group(group.name.clone());
+            if group.nodes.iter().any(|node| node == id) {
+                mutations.push((MutationKind::RemoveGroup, target));
+            } else if group.nodes.is_empty() {
+                mutations.push((MutationKind::ChangeGroup, target));
+            }
+        }
+        mutations
+    }
+
     /// Renaming a node
     ///
     /// Nodes IDs can be changed by calling this method.
     pub fn rename_node(&mut self, old_id: &str, new_id: &str) -> &mut Self {
         if let Some(node) = self.get_node(old_id).cloned().as_mut() {
+            if !self.permit(
+                MutationKind::RenameNode,
+                MutationTarget::Node(old_id.to_owned()),
+            ) {
+                return self;
+            }
             self.check_transaction_start();
             node.id = new_id.to_owned();
 
@@ -716,8 +1474,33 @@ impl<'a> Graph<'a> {
         self
     }
 
+    /// Change the component a node instantiates, keeping its ID,
+    /// connections and metadata
+    pub fn set_node_component(&mut self, id: &str, component: &str) -> &mut Self {
+        if let Some(index) = self.nodes.iter().position(|node| node.id == id) {
+            if !self.permit(MutationKind::ChangeNode, MutationTarget::Node(id.to_owned())) {
+                return self;
+            }
+            self.check_transaction_start();
+            let before = self.nodes[index].component.clone();
+            self.nodes[index].component = component.to_owned();
+            self.emit(
+                "change_component",
+                &(id.to_owned(), before, component.to_owned()),
+            );
+            self.check_transaction_end();
+        }
+        self
+    }
+
     pub fn set_node_metadata(&mut self, id: &str, metadata: Map<String, Value>) -> &mut Self {
+        let Some(metadata) = self.validate_metadata(metadata) else {
+            return self;
+        };
         if let Some(node) = self.get_node(id).cloned().as_mut() {
+            if !self.permit(MutationKind::ChangeNode, MutationTarget::Node(id.to_owned())) {
+                return self;
+            }
             self.check_transaction_start();
 
             let before = node.metadata.clone();
@@ -735,7 +1518,7 @@ impl<'a> Graph<'a> {
                 let val = meta.get(item);
                 
                 if let Some(existing_meta) = node.metadata.as_mut() {
-                    if let Some(val) = val {
+                    if let Some(val) = val.filter(|val| !val.is_null()) {
                         (*existing_meta).insert(item.clone(), val.clone());
                     } else {
                         (*existing_meta).remove(item);
@@ -750,8 +1533,8 @@ impl<'a> Graph<'a> {
                 .position(|n| n.id == id.to_owned())
                 .unwrap();
             self.nodes[node_index] = node.clone();
+            self.check_transaction_end();
         }
-        self.check_transaction_end();
         self
     }
 
@@ -777,26 +1560,37 @@ impl<'a> Graph<'a> {
         }
         let out_port_name = self.get_port_name(out_port);
         let in_port_name = self.get_port_name(in_port);
-        let some = self
-            .edges
-            .iter()
-            .filter(|edge| {
-                (edge.from.node_id == out_node.to_owned())
-                    && (edge.from.port == out_port_name.to_owned())
-                    && (edge.to.node_id == in_node.to_owned())
-                    && (edge.to.port == in_port_name.to_owned())
-            })
-            .collect::<Vec<&GraphEdge>>();
-        if !some.is_empty() {
+        let duplicate = self.edges.iter().any(|edge| {
+            (edge.from.node_id == out_node.to_owned())
+                && (edge.from.port == out_port_name.to_owned())
+                && (edge.to.node_id == in_node.to_owned())
+                && (edge.to.port == in_port_name.to_owned())
+                && edge.key.is_none()
+        });
+        if self.reject_missing_node("edge_rejected", &[out_node, in_node]) {
             return self;
         }
-        if self.get_node(out_node).is_none() {
+        if !self.admit_edge(
+            duplicate,
+            (out_node, &out_port_name),
+            (in_node, &in_port_name),
+            &metadata,
+        ) {
             return self;
         }
-        if self.get_node(in_node).is_none() {
+        if !self.permit(
+            MutationKind::AddEdge,
+            MutationTarget::Edge {
+                from: out_node.to_owned(),
+                to: in_node.to_owned(),
+            },
+        ) {
             return self;
         }
         self.check_transaction_start();
+        // An admitted duplicate gets a key of its own, so that it can be
+        // removed without its parallel edges, e.g. when undone
+        let key = duplicate.then(|| self.id_generator.next_id());
         let edge = &GraphEdge {
             from: GraphLeaf {
                 port: out_port_name.to_owned(),
@@ -809,6 +1603,7 @@ impl<'a> Graph<'a> {
                 index: None,
             },
             metadata,
+            key,
         };
         self.edges.push(edge.clone());
         self.emit("add_edge", edge);
@@ -831,29 +1626,38 @@ impl<'a> Graph<'a> {
         }
         let out_port_name = self.get_port_name(out_port);
         let in_port_name = self.get_port_name(in_port);
-        if let Some(_) = self.edges.clone().iter().find(|edge| {
-            // don't add a duplicate edge
-            if (edge.from.node_id == out_node.to_owned())
+        let duplicate = self.edges.iter().any(|edge| {
+            (edge.from.node_id == out_node.to_owned())
                 && (edge.from.port == out_port_name.to_owned())
                 && (edge.to.node_id == in_node.to_owned())
                 && (edge.to.port == in_port_name.to_owned())
-            {
-                if index_1 == edge.from.index && index_2 == edge.to.index {
-                    return true;
-                }
-            }
-            return false;
-        }) {
+                && index_1 == edge.from.index
+                && index_2 == edge.to.index
+                && edge.key.is_none()
+        });
+
+        if self.reject_missing_node("edge_rejected", &[out_node, in_node]) {
             return self;
         }
-
-        if self.get_node(out_node).is_none() {
+        if !self.admit_edge(
+            duplicate,
+            (out_node, &out_port_name),
+            (in_node, &in_port_name),
+            &metadata,
+        ) {
             return self;
         }
-        if self.get_node(in_node).is_none() {
+        if !self.permit(
+            MutationKind::AddEdge,
+            MutationTarget::Edge {
+                from: out_node.to_owned(),
+                to: in_node.to_owned(),
+            },
+        ) {
             return self;
         }
         self.check_transaction_start();
+        let key = duplicate.then(|| self.id_generator.next_id());
         let edge = &GraphEdge {
             from: GraphLeaf {
                 port: out_port_name.to_owned(),
@@ -866,6 +1670,7 @@ impl<'a> Graph<'a> {
                 index: index_2,
             },
             metadata,
+            key,
         };
         self.edges.push(edge.clone());
         self.emit("add_edge", edge);
@@ -884,11 +1689,13 @@ impl<'a> Graph<'a> {
     /// Removing a connection will emit the `removeEdge` event.
     pub fn remove_edge(
         &mut self,
-        node: &str,
-        port: &str,
+        node: impl Into<NodeId>,
+        port: impl Into<PortName>,
         node2: Option<&str>,
         port2: Option<&str>,
     ) -> &mut Self {
+        let (node, port) = (node.into(), port.into());
+        let (node, port) = (node.as_str(), port.as_str());
         if self
             .get_edge(
                 node,
@@ -900,6 +1707,15 @@ impl<'a> Graph<'a> {
         {
             return self;
         }
+        if !self.permit(
+            MutationKind::RemoveEdge,
+            MutationTarget::Edge {
+                from: node.to_owned(),
+                to: node2.unwrap_or("").to_owned(),
+            },
+        ) {
+            return self;
+        }
 
         self.check_transaction_start();
         let out_port = self.get_port_name(port);
@@ -919,26 +1735,30 @@ impl<'a> Graph<'a> {
                         && edge.to.node_id.as_str() == node2.unwrap()
                         && edge.to.port == in_port.clone().unwrap()
                     {
-                        self.set_edge_metadata(
-                            edge.from.node_id.as_str(),
-                            edge.from.port.as_str(),
-                            edge.to.node_id.as_str(),
-                            edge.to.port.as_str(),
-                            Map::new(),
-                        );
+                        self.vetted(|graph| {
+                            graph.set_edge_metadata(
+                                edge.from.node_id.as_str(),
+                                edge.from.port.as_str(),
+                                edge.to.node_id.as_str(),
+                                edge.to.port.as_str(),
+                                Map::new(),
+                            );
+                        });
                         self.emit("remove_edge", edge.clone());
                         return false;
                     }
                 } else if (edge.from.node_id.as_str() == node && edge.from.port == out_port)
                     || (edge.to.node_id.as_str() == node && edge.to.port == out_port)
                 {
-                    self.set_edge_metadata(
-                        edge.from.node_id.as_str(),
-                        edge.from.port.as_str(),
-                        edge.to.node_id.as_str(),
-                        edge.to.port.as_str(),
-                        Map::new(),
-                    );
+                    self.vetted(|graph| {
+                        graph.set_edge_metadata(
+                            edge.from.node_id.as_str(),
+                            edge.from.port.as_str(),
+                            edge.to.node_id.as_str(),
+                            edge.to.port.as_str(),
+                            Map::new(),
+                        );
+                    });
                     self.emit("remove_edge", edge.clone());
                     return false;
                 }
@@ -952,20 +1772,97 @@ impl<'a> Graph<'a> {
         self
     }
 
+    /// Connecting endpoints
+    ///
+    /// Same as `add_edge_index`, with each side given as an `Endpoint`:
+    /// ```no_run
+    /// my_graph.connect_endpoints(Endpoint::new("Read", "out"), Endpoint::new("Display", "in").with_index(2), None);
+    /// my_graph.connect_endpoints(("Read", "out"), ("Log", "in"), None);
+    /// ```
+    pub fn connect_endpoints(
+        &mut self,
+        from: impl Into<Endpoint>,
+        to: impl Into<Endpoint>,
+        metadata: Option<Map<String, Value>>,
+    ) -> &mut Self {
+        let (from, to) = (from.into(), to.into());
+        self.add_edge_index(
+            &from.node,
+            &from.port,
+            from.index,
+            &to.node,
+            &to.port,
+            to.index,
+            metadata,
+        )
+    }
+
+    /// Disconnecting endpoints
+    ///
+    /// Removes the edges going from one endpoint to the other. Unlike
+    /// `remove_edge`, an endpoint with an index only matches edges on
+    /// that index of an array port.
+    /// ```no_run
+    /// my_graph.disconnect_endpoints(("Read", "out"), Endpoint::new("Display", "in").with_index(2));
+    /// ```
+    pub fn disconnect_endpoints(
+        &mut self,
+        from: impl Into<Endpoint>,
+        to: impl Into<Endpoint>,
+    ) -> &mut Self {
+        let (mut from, mut to) = (from.into(), to.into());
+        from.port = self.get_port_name(&from.port).into();
+        to.port = self.get_port_name(&to.port).into();
+        let removed = self
+            .edges
+            .iter()
+            .filter(|edge| from.matches(&edge.from) && to.matches(&edge.to))
+            .cloned()
+            .collect::<Vec<GraphEdge>>();
+        if removed.is_empty() {
+            return self;
+        }
+        if !self.permit(
+            MutationKind::RemoveEdge,
+            MutationTarget::Edge {
+                from: from.node.to_string(),
+                to: to.node.to_string(),
+            },
+        ) {
+            return self;
+        }
+
+        self.check_transaction_start();
+        self.edges
+            .retain(|edge| !(from.matches(&edge.from) && to.matches(&edge.to)));
+        for edge in removed.iter() {
+            self.emit("remove_edge", edge);
+        }
+        self.check_transaction_end();
+        self
+    }
+
     /// Getting an edge
     ///
     /// Edge objects can be retrieved from the graph by the node and port IDs:
     /// ```no_run
     /// my_edge = my_graph.get_edge("Read", "out", "Write", "in");
     /// ```
-    pub fn get_edge(&self, node: &str, port: &str, node2: &str, port2: &str) -> Option<&GraphEdge> {
-        let out_port = self.get_port_name(port);
-        let in_port = self.get_port_name(port2);
+    pub fn get_edge(
+        &self,
+        node: impl Into<NodeId>,
+        port: impl Into<PortName>,
+        node2: impl Into<NodeId>,
+        port2: impl Into<PortName>,
+    ) -> Option<&GraphEdge> {
+        let (node, node2) = (node.into(), node2.into());
+        let out_port = self.get_port_name(&port.into());
+        let in_port = self.get_port_name(&port2.into());
 
         self.edges.iter().find(|edge| {
-            edge.from.node_id.as_str() == node
+            edge.from.node_id == node.as_str()
                 && edge.from.port == out_port
-                && edge.to.node_id.as_str() == node2
+                && edge.to.node_id == node2.as_str()
                 && edge.to.port == in_port
         })
     }
@@ -981,7 +1878,19 @@ impl<'a> Graph<'a> {
         port2: &str,
         metadata: Map<String, Value>,
     ) -> &mut Self {
+        let Some(metadata) = self.validate_metadata(metadata) else {
+            return self;
+        };
         if let Some(edge) = self.get_edge(node, port, node2, port2).cloned().as_mut() {
+            if !self.permit(
+                MutationKind::ChangeEdge,
+                MutationTarget::Edge {
+                    from: node.to_owned(),
+                    to: node2.to_owned(),
+                },
+            ) {
+                return self;
+            }
             self.check_transaction_start();
             if edge.metadata.is_none() {
                 edge.metadata = Some(Map::new());
@@ -990,7 +1899,7 @@ impl<'a> Graph<'a> {
             for item in metadata.clone().keys() {
                 let val = metadata.get(item);
                 if let Some(edge_metadata) = edge.metadata.as_mut() {
-                    if let Some(val) = val {
+                    if let Some(val) = val.filter(|val| !val.is_null()) {
                         (*edge_metadata).insert(item.clone(), val.clone());
                     } else {
                         (*edge_metadata).remove(item);
@@ -1015,6 +1924,157 @@ impl<'a> Graph<'a> {
         self
     }
 
+    /// Parallel edges
+    ///
+    /// Several edges can connect the same pair of ports when each of
+    /// them has its own key, e.g. for routing topologies that need to
+    /// tell the connections apart. Keyed edges are exempt from the
+    /// duplicate edge policy and are looked up, changed and removed by
+    /// their key:
+    /// ```no_run
+    /// my_graph.add_keyed_edge(("Router", "out"), ("Sink", "in"), "primary", None);
+    /// my_graph.add_keyed_edge(("Router", "out"), ("Sink", "in"), "backup", None);
+    /// my_graph.remove_edge_by_key("backup");
+    /// ```
+    pub fn add_keyed_edge(
+        &mut self,
+        from: impl Into<Endpoint>,
+        to: impl Into<Endpoint>,
+        key: &str,
+        metadata: Option<Map<String, Value>>,
+    ) -> &mut Self {
+        let (from, to) = (from.into(), to.into());
+        if self.get_edge_by_key(key).is_some() {
+            self.reject("edge_rejected", GraphError::EdgeKeyExists(key.to_owned()));
+            return self;
+        }
+        if self.reject_missing_node("edge_rejected", &[&from.node, &to.node]) {
+            return self;
+        }
+        let out_port = self.get_port_name(&from.port);
+        let in_port = self.get_port_name(&to.port);
+        if !self.admit_edge(false, (&from.node, &out_port), (&to.node, &in_port), &metadata) {
+            return self;
+        }
+        if !self.permit(
+            MutationKind::AddEdge,
+            MutationTarget::Edge {
+                from: from.node.to_string(),
+                to: to.node.to_string(),
+            },
+        ) {
+            return self;
+        }
+        self.check_transaction_start();
+        let edge = &GraphEdge {
+            from: GraphLeaf {
+                port: out_port,
+                node_id: from.node.into_string(),
+                index: from.index,
+            },
+            to: GraphLeaf {
+                port: in_port,
+                node_id: to.node.into_string(),
+                index: to.index,
+            },
+            metadata: Some(metadata.unwrap_or_default()),
+            key: Some(key.to_owned()),
+        };
+        self.edges.push(edge.clone());
+        self.emit("add_edge", edge);
+        self.check_transaction_end();
+        self
+    }
+
+    pub fn get_edge_by_key(&self, key: &str) -> Option<&GraphEdge> {
+        self.edges
+            .iter()
+            .find(|edge| edge.key.as_deref() == Some(key))
+    }
+
+    /// All edges between two ports, keyed or not
+    pub fn get_edges(
+        &self,
+        node: impl Into<NodeId>,
+        port: impl Into<PortName>,
+        node2: impl Into<NodeId>,
+        port2: impl Into<PortName>,
+    ) -> Vec<&GraphEdge> {
+        let (node, node2) = (node.into(), node2.into());
+        let out_port = self.get_port_name(&port.into());
+        let in_port = self.get_port_name(&port2.into());
+        self.edges
+            .iter()
+            .filter(|edge| {
+                edge.from.node_id == node.as_str()
+                    && edge.from.port == out_port
+                    && edge.to.node_id == node2.as_str()
+                    && edge.to.port == in_port
+            })
+            .collect()
+    }
+
+    pub fn remove_edge_by_key(&mut self, key: &str) -> &mut Self {
+        let Some(index) = self
+            .edges
+            .iter()
+            .position(|edge| edge.key.as_deref() == Some(key))
+        else {
+            return self;
+        };
+        let edge = self.edges[index].clone();
+        if !self.permit(
+            MutationKind::RemoveEdge,
+            MutationTarget::Edge {
+                from: edge.from.node_id.clone(),
+                to: edge.to.node_id.clone(),
+            },
+        ) {
+            return self;
+        }
+        self.check_transaction_start();
+        self.edges.remove(index);
+        self.emit("remove_edge", &edge);
+        self.check_transaction_end();
+        self
+    }
+
+    pub fn set_edge_metadata_by_key(&mut self, key: &str, metadata: Map<String, Value>) -> &mut Self {
+        let Some(metadata) = self.validate_metadata(metadata) else {
+            return self;
+        };
+        let Some(index) = self
+            .edges
+            .iter()
+            .position(|edge| edge.key.as_deref() == Some(key))
+        else {
+            return self;
+        };
+        if !self.permit(
+            MutationKind::ChangeEdge,
+            MutationTarget::Edge {
+                from: self.edges[index].from.node_id.clone(),
+                to: self.edges[index].to.node_id.clone(),
+            },
+        ) {
+            return self;
+        }
+        self.check_transaction_start();
+        let before = self.edges[index].metadata.clone();
+        let edge_metadata = self.edges[index].metadata.get_or_insert_with(Map::new);
+        for (item, val) in metadata.iter() {
+            if val.is_null() {
+                edge_metadata.remove(item);
+            } else {
+                edge_metadata.insert(item.clone(), val.clone());
+            }
+        }
+        let edge = self.edges[index].clone();
+        self.emit("change_edge", &(edge, before, metadata));
+        self.check_transaction_end();
+        self
+    }
+
     /// Adding Initial Information Packets
     ///
     /// Initial Information Packets (IIPs) can be used for sending data
@@ -1037,15 +2097,23 @@ impl<'a> Graph<'a> {
     pub fn add_initial(
         &mut self,
         data: Value,
-        node: &str,
-        port: &str,
+        node: impl Into<NodeId>,
+        port: impl Into<PortName>,
         mut metadata: Option<Map<String, Value>>,
     ) -> &mut Self {
+        let (node, port) = (node.into(), port.into());
+        let (node, port) = (node.as_str(), port.as_str());
         if metadata.is_none() {
             metadata = Some(Map::new());
         }
         if let Some(_node) = self.get_node(node) {
             let port_name = self.get_port_name(port);
+            if !self.permit(
+                MutationKind::AddInitial,
+                MutationTarget::Initial(node.to_owned()),
+            ) {
+                return self;
+            }
             self.check_transaction_start();
             let stub = GraphStub { data };
             let initializer = GraphIIP {
@@ -1067,16 +2135,24 @@ impl<'a> Graph<'a> {
     pub fn add_initial_index(
         &mut self,
         data: Value,
-        node: &str,
-        port: &str,
+        node: impl Into<NodeId>,
+        port: impl Into<PortName>,
         index: Option<usize>,
         mut metadata: Option<Map<String, Value>>,
     ) -> &mut Self {
+        let (node, port) = (node.into(), port.into());
+        let (node, port) = (node.as_str(), port.as_str());
         if metadata.is_none() {
             metadata = Some(Map::new());
         }
         if let Some(_) = self.get_node(node) {
             let port_name = self.get_port_name(port);
+            if !self.permit(
+                MutationKind::AddInitial,
+                MutationTarget::Initial(node.to_owned()),
+            ) {
+                return self;
+            }
             self.check_transaction_start();
             let stub = GraphStub { data };
             let initializer = GraphIIP {
@@ -1098,13 +2174,13 @@ impl<'a> Graph<'a> {
     pub fn add_graph_initial(
         &mut self,
         data: Value,
-        node: &str,
+        node: impl Into<PortName>,
         mut metadata: Option<Map<String, Value>>,
     ) -> &mut Self {
         if metadata.is_none() {
             metadata = Some(Map::new());
         }
-        if let Some(inport) = self.inports.clone().get(node) {
+        if let Some(inport) = self.inports.clone().get(node.into().as_str()) {
             self.add_initial(data, &inport.process, &inport.port, metadata);
         }
         self
@@ -1113,14 +2189,14 @@ impl<'a> Graph<'a> {
     pub fn add_graph_initial_index(
         &mut self,
         data: Value,
-        node: &str,
+        node: impl Into<PortName>,
         index: Option<usize>,
         mut metadata: Option<Map<String, Value>>,
     ) -> &mut Self {
         if metadata.is_none() {
             metadata = Some(Map::new());
         }
-        if let Some(inport) = self.inports.clone().get(node) {
+        if let Some(inport) = self.inports.clone().get(node.into().as_str()) {
             self.add_initial_index(data, &inport.process, &inport.port, index, metadata);
         }
         self
@@ -1139,8 +2215,20 @@ impl<'a> Graph<'a> {
     /// my_graph.remove_graph_initial("file");
     /// ```
     /// Remove an IIP will emit a `remove_initial` event.
-    pub fn remove_initial(&mut self, id: &str, port: &str) -> &mut Self {
-        let port_name = self.get_port_name(port);
+    pub fn remove_initial(
+        &mut self,
+        id: impl Into<NodeId>,
+        port: impl Into<PortName>,
+    ) -> &mut Self {
+        let id = id.into();
+        let id = id.as_str();
+        let port_name = self.get_port_name(&port.into());
+        if !self.permit(
+            MutationKind::RemoveInitial,
+            MutationTarget::Initial(id.to_owned()),
+        ) {
+            return self;
+        }
         self.check_transaction_start();
         let inits = self.initializers.clone();
         let mut _initializers = Vec::new();
@@ -1148,6 +2236,8 @@ impl<'a> Graph<'a> {
             if let Some(to) = iip.to.clone() {
                 if to.node_id.as_str() == id && to.port == port_name {
                     self.emit("remove_initial", &iip);
+                } else {
+                    _initializers.push(iip);
                 }
             } else {
                 _initializers.push(iip);
@@ -1158,8 +2248,8 @@ impl<'a> Graph<'a> {
         self
     }
 
-    pub fn remove_graph_initial(&mut self, id: &str) -> &mut Self {
-        if let Some(inport) = self.inports.clone().get(id) {
+    pub fn remove_graph_initial(&mut self, id: impl Into<PortName>) -> &mut Self {
+        if let Some(inport) = self.inports.clone().get(id.into().as_str()) {
             self.remove_initial(&inport.process, &inport.port);
         }
         self
@@ -1216,6 +2306,7 @@ impl<'a> Graph<'a> {
                 }),
                 metadata: None,
                 data: None,
+                key: edge.key.clone(),
             };
             if let Some(metadata) = edge.metadata.clone() {
                 if !metadata.is_empty() {
@@ -1231,6 +2322,7 @@ impl<'a> Graph<'a> {
                 tgt: None,
                 data: None,
                 metadata: None,
+                key: None,
             };
             if let Some(to) = initializer.to.clone() {
                 iip.tgt = Some(GraphLeafJson {
@@ -1294,7 +2386,7 @@ impl<'a> Graph<'a> {
                         graph.add_initial_index(
                             data,
                             &tgt.process,
-                            &graph.get_port_name(&tgt.port),
+                            graph.get_port_name(&tgt.port),
                             tgt.index,
                             conn.metadata,
                         );
@@ -1302,7 +2394,7 @@ impl<'a> Graph<'a> {
                         graph.add_initial(
                             data,
                             &tgt.process,
-                            &graph.get_port_name(&tgt.port),
+                            graph.get_port_name(&tgt.port),
                             conn.metadata,
                         );
                     }
@@ -1310,6 +2402,23 @@ impl<'a> Graph<'a> {
                     return;
                 }
             }
+            if let (Some(src), Some(tgt), Some(key)) = (&conn.src, &conn.tgt, &conn.key) {
+                graph.add_keyed_edge(
+                    Endpoint {
+                        node: src.process.as_str().into(),
+                        port: src.port.as_str().into(),
+                        index: src.index,
+                    },
+                    Endpoint {
+                        node: tgt.process.as_str().into(),
+                        port: tgt.port.as_str().into(),
+                        index: tgt.index,
+                    },
+                    key,
+                    conn.metadata,
+                );
+                return;
+            }
             if conn.src.clone().is_some() || conn.tgt.clone().is_some() {
                 if conn.src.clone().unwrap().index.is_some()
                     || conn.tgt.clone().unwrap().index.is_some()
@@ -1341,7 +2450,7 @@ impl<'a> Graph<'a> {
                 graph.add_inport(
                     inport,
                     &pri.clone().process,
-                    &graph.get_port_name(&pri.port),
+                    graph.get_port_name(&pri.port),
                     pri.metadata.clone(),
                 );
             }
@@ -1351,7 +2460,7 @@ impl<'a> Graph<'a> {
                 graph.add_outport(
                     outport,
                     &pri.clone().process,
-                    &graph.get_port_name(&pri.port),
+                    graph.get_port_name(&pri.port),
                     pri.metadata.clone(),
                 );
             }
@@ -5,9 +5,14 @@ mod tests {
     use serde_json::Map;
     use crate::graph::{
         graph::Graph,
-        types::{GraphEdge, GraphGroup, GraphIIP, GraphJson, GraphNode},
+        types::{
+            DuplicateEdges, EdgePolicy, GraphEdge, GraphError, GraphGroup, GraphIIP, GraphJson, GraphNode,
+            GraphOptions, SelfLoops, ValidationLevel,
+        },
     };
     use crate::internal::event_manager::EventManager;
+    use crate::graph::journal::Journal;
+    use crate::internal::utils::{SeededIdGenerator, SequentialIdGenerator};
     use assert_json_diff::assert_json_eq;
     use beady::scenario;
     use futures::executor::block_on;
@@ -910,5 +915,273 @@ mod tests {
                 }
             }
         }
+        'given_a_custom_id_generator:{
+            'when_nodes_are_added_with_a_sequential_generator:{
+                let mut g = Graph::new_with_options("", true, SequentialIdGenerator::new("node-"));
+                g.add_node("Foo", "foo", None).add_node("Bar", "bar", None);
+                'then_their_uids_should_be_predictable:{
+                    assert_eq!(g.nodes[0].uid, "node-0");
+                    assert_eq!(g.nodes[1].uid, "node-1");
+                }
+            }
+            'when_two_graphs_share_a_seed:{
+                let mut a = Graph::new_with_options("", true, SeededIdGenerator::new(42));
+                let mut b = Graph::new_with_options("", true, SeededIdGenerator::new(42));
+                a.add_node("Foo", "foo", None).add_node("Bar", "bar", None);
+                b.add_node("Foo", "foo", None).add_node("Bar", "bar", None);
+                'then_they_should_generate_the_same_uids:{
+                    assert_eq!(a.nodes[0].uid, b.nodes[0].uid);
+                    assert_eq!(a.nodes[1].uid, b.nodes[1].uid);
+                    assert_ne!(a.nodes[0].uid, a.nodes[1].uid);
+                }
+            }
+        }
+        'given_graph_options:{
+            'when_the_defaults_are_used:{
+                let g = Graph::with_options("", GraphOptions::default());
+                'then_it_should_behave_like_a_new_graph:{
+                    assert!(!g.case_sensitive);
+                    assert_eq!(g.validation_level(), ValidationLevel::Lenient);
+                    assert!(!g.subscribed);
+                }
+            }
+            'when_journal_and_strict_validation_are_requested:{
+                let mut g = Graph::with_options("", GraphOptions {
+                    case_sensitive: true,
+                    validation_level: ValidationLevel::Strict,
+                    journal: true,
+                    ..GraphOptions::default()
+                });
+                g.add_node("Foo", "foo", None).add_node("Foo", "bar", None);
+                'then_it_should_start_journaled_and_reject_duplicate_nodes:{
+                    assert!(g.case_sensitive);
+                    assert!(g.subscribed);
+                    assert_eq!(g.nodes.len(), 1);
+                    assert_eq!(g.nodes[0].component, "foo");
+                    assert_eq!(g.last_revision, 1);
+                }
+            }
+        }
+        'given_an_edge_policy:{
+            'when_duplicates_are_ignored_by_default:{
+                let mut g = Graph::new("", true);
+                g.add_node("A", "a", None).add_node("B", "b", None)
+                    .add_edge("A", "out", "B", "in", None)
+                    .add_edge("A", "out", "B", "in", None)
+                    .add_edge("A", "out", "A", "in", None);
+                'then_only_the_first_edge_and_the_self_loop_should_be_kept:{
+                    assert_eq!(g.edge_policy(), EdgePolicy::default());
+                    assert_eq!(g.edges.len(), 2);
+                    assert_eq!(g.edges[1].to.node_id, "A");
+                }
+            }
+            'when_duplicates_and_self_loops_are_rejected:{
+                let mut g = Graph::new("", true);
+                let rejected = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+                let seen = rejected.clone();
+                g.set_edge_policy(EdgePolicy { duplicates: DuplicateEdges::Reject, self_loops: SelfLoops::Reject })
+                    .connect("edge_rejected", move |_, data| {
+                        seen.lock().unwrap().push(data.downcast_ref::<GraphError>().unwrap().clone());
+                    }, false);
+                g.add_node("A", "a", None).add_node("B", "b", None)
+                    .add_edge("A", "out", "B", "in", None)
+                    .add_edge("A", "out", "B", "in", None)
+                    .add_edge_index("A", "out", None, "A", "in", Some(0), None);
+                'then_each_rejection_should_be_reported:{
+                    assert_eq!(g.edges.len(), 1);
+                    assert_eq!(rejected.lock().unwrap().clone(), vec![
+                        GraphError::DuplicateEdge { from: "A.out".to_owned(), to: "B.in".to_owned() },
+                        GraphError::SelfLoop("A".to_owned()),
+                    ]);
+                }
+            }
+            'when_duplicates_are_allowed_or_merged:{
+                let mut allowed = Graph::with_options("", GraphOptions {
+                    edge_policy: EdgePolicy { duplicates: DuplicateEdges::Allow, ..EdgePolicy::default() },
+                    ..GraphOptions::default()
+                });
+                allowed.add_node("A", "a", None).add_node("B", "b", None)
+                    .add_edge("A", "out", "B", "in", None)
+                    .add_edge("A", "out", "B", "in", None);
+                let mut merged = Graph::new("", true);
+                merged.set_edge_policy(EdgePolicy { duplicates: DuplicateEdges::MergeMetadata, ..EdgePolicy::default() })
+                    .add_node("A", "a", None).add_node("B", "b", None)
+                    .add_edge("A", "out", "B", "in", json!({"route": 1}).as_object().cloned())
+                    .add_edge("A", "out", "B", "in", json!({"label": "x"}).as_object().cloned());
+                'then_parallel_edges_or_merged_metadata_should_result:{
+                    assert_eq!(allowed.edges.len(), 2);
+                    assert!(allowed.edges[0].key.is_none());
+                    assert!(allowed.edges[1].key.is_some());
+                    assert_eq!(merged.edges.len(), 1);
+                    assert_eq!(
+                        merged.edges[0].metadata.clone().unwrap(),
+                        json!({"route": 1, "label": "x"}).as_object().cloned().unwrap()
+                    );
+                }
+            }
+        }
+        'given_a_journaled_graph_allowing_duplicate_edges:{
+            let mut g = Graph::with_options("", GraphOptions {
+                edge_policy: EdgePolicy { duplicates: DuplicateEdges::Allow, ..EdgePolicy::default() },
+                ..GraphOptions::default()
+            });
+            g.add_node("A", "a", None).add_node("B", "b", None);
+            g.init_journal(None);
+            g.add_edge("A", "out", "B", "in", None);
+            g.add_edge("A", "out", "B", "in", None);
+            'when_undoing_the_duplicate:{
+                g.undo();
+                'then_only_the_duplicate_should_be_removed:{
+                    assert_eq!(g.edges.len(), 1);
+                    assert!(g.edges[0].key.is_none());
+                }
+                'and_then_redoing_it:{
+                    g.redo();
+                    'then_both_edges_should_be_back:{
+                        assert_eq!(g.edges.len(), 2);
+                    }
+                    'and_then_undoing_both:{
+                        g.undo().undo();
+                        'then_no_edge_should_be_left:{
+                            assert!(g.edges.is_empty());
+                        }
+                        'and_then_redoing_both:{
+                            g.redo().redo();
+                            'then_both_edges_should_be_back_again:{
+                                assert_eq!(g.edges.len(), 2);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        'given_parallel_keyed_edges:{
+            let mut g = Graph::new("", true);
+            g.add_node("Router", "router", None).add_node("Sink", "sink", None);
+            g.init_journal(None);
+            g.add_keyed_edge(("Router", "out"), ("Sink", "in"), "primary", None)
+                .add_keyed_edge(("Router", "out"), ("Sink", "in"), "backup", None)
+                .add_keyed_edge(("Router", "out"), ("Sink", "in"), "backup", None)
+                .add_edge("Router", "out", "Sink", "in", None);
+            'then_each_key_should_make_a_distinct_edge:{
+                assert_eq!(g.get_edges("Router", "out", "Sink", "in").len(), 3);
+                assert_eq!(g.get_edge_by_key("backup").unwrap().key.as_deref(), Some("backup"));
+            }
+            'when_changing_and_removing_by_key:{
+                g.set_edge_metadata_by_key("backup", json!({"weight": 2}).as_object().cloned().unwrap())
+                    .remove_edge_by_key("primary");
+                'then_only_that_edge_should_be_affected:{
+                    assert_eq!(g.edges.len(), 2);
+                    assert!(g.get_edge_by_key("primary").is_none());
+                    assert_eq!(g.get_edge_by_key("backup").unwrap().metadata.clone().unwrap().get("weight"), Some(&json!(2)));
+                }
+                'and_then_undoing_the_removal:{
+                    g.undo();
+                    'then_the_keyed_edge_should_come_back:{
+                        assert!(g.get_edge_by_key("primary").is_some());
+                        assert_eq!(g.edges.len(), 3);
+                    }
+                }
+            }
+            'when_saving_and_loading:{
+                let loaded = block_on(Graph::from_json_string(&g.to_json_string().unwrap(), None)).unwrap();
+                'then_the_keys_should_be_kept:{
+                    assert_eq!(loaded.get_edges("Router", "out", "Sink", "in").len(), g.edges.len());
+                    assert!(loaded.get_edge_by_key("backup").is_some());
+                }
+            }
+        }
+        'given_a_graph_behind_accessors:{
+            let mut g = Graph::new("Accessors", true);
+            g.add_node("Foo", "foo", None).add_inport("in", "Foo", "in", None);
+            'then_it_should_expose_read_only_views:{
+                assert_eq!(g.name(), "Accessors");
+                assert!(g.is_case_sensitive());
+                assert_eq!(g.nodes().len(), 1);
+                assert_eq!(g.inports().len(), 1);
+                assert_eq!(g.groups().len(), 0);
+                assert_eq!(g.current_revision(), -1);
+                assert!(!g.is_subscribed());
+            }
+            'when_using_raw_parts:{
+                g.connect("add_node", |_, _| panic!("raw parts must not emit events"), false);
+                g.raw_parts().nodes.push(GraphNode {
+                    id: "Bar".to_owned(),
+                    uid: "bar".to_owned(),
+                    component: "bar".to_owned(),
+                    metadata: None,
+                });
+                'then_changes_should_bypass_events:{
+                    assert!(g.get_node("Bar").is_some());
+                }
+            }
+        }
+        'given_misused_transactions:{
+            let mut g = Graph::new("", true);
+            'when_nesting_a_transaction:{
+                g.start_transaction("outer", None);
+                let nested = g.try_start_transaction("inner", None).err();
+                'then_an_error_should_be_returned_and_the_open_one_kept:{
+                    assert_eq!(nested, Some(GraphError::NestedTransaction {
+                        open: "outer".to_owned(),
+                        requested: "inner".to_owned()
+                    }));
+                    assert_eq!(g.transaction.id.as_deref(), Some("outer"));
+                }
+            }
+            'when_ending_without_a_transaction:{
+                g.end_transaction("outer", None);
+                let result = g.try_mutate(|g| {
+                    g.add_node("A", "a", None).end_transaction("outer", None);
+                }).err();
+                'then_the_error_should_be_reported_without_exiting:{
+                    assert_eq!(result, Some(GraphError::NoTransaction("outer".to_owned())));
+                    assert!(g.get_node("A").is_some());
+                }
+            }
+            'when_mutations_succeed:{
+                'then_try_mutate_should_keep_chaining:{
+                    assert!(g.try_mutate(|g| { g.add_node("B", "b", None); }).is_ok());
+                    assert!(g.get_node("B").is_some());
+                }
+            }
+        }
+        'given_changes_that_would_be_skipped:{
+            let mut g = Graph::with_options("", GraphOptions {
+                validation_level: ValidationLevel::Strict,
+                ..GraphOptions::default()
+            });
+            g.add_node("A", "a", None).add_node("B", "b", None)
+                .add_keyed_edge(("A", "out"), ("B", "in"), "main", None);
+            'when_using_the_fallible_variants:{
+                'then_each_should_report_why:{
+                    assert_eq!(g.try_add_node("A", "a", None).err(), Some(GraphError::NodeExists("A".to_owned())));
+                    assert_eq!(
+                        g.try_add_edge("A", "out", "Nope", "in", None).err(),
+                        Some(GraphError::NodeNotFound("Nope".to_owned()))
+                    );
+                    assert_eq!(
+                        g.try_add_keyed_edge(("A", "out"), ("B", "in"), "main", None).err(),
+                        Some(GraphError::EdgeKeyExists("main".to_owned()))
+                    );
+                    assert_eq!(
+                        g.try_add_inport("IN", "Nope", "in", None).err(),
+                        Some(GraphError::NodeNotFound("Nope".to_owned()))
+                    );
+                    assert_eq!(
+                        g.try_add_outport("OUT", "Nope", "out", None).err(),
+                        Some(GraphError::NodeNotFound("Nope".to_owned()))
+                    );
+                    assert_eq!(g.nodes.len(), 2);
+                    assert_eq!(g.edges.len(), 1);
+                    assert!(g.inports.is_empty() && g.outports.is_empty());
+                }
+                'then_accepted_changes_should_succeed:{
+                    assert!(g.try_add_edge("A", "out", "B", "in", None).is_ok());
+                    assert!(g.try_add_inport("IN", "A", "in", None).is_ok());
+                    assert_eq!(g.edges.len(), 2);
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,592 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::policy::{MutationKind, MutationTarget};
+use super::types::GraphGroup;
+use crate::internal::event_manager::EventManager;
+
+/// Metadata key holding the group's display color
+pub const GROUP_COLOR: &str = "color";
+/// Metadata key holding the group's description
+pub const GROUP_DESCRIPTION: &str = "description";
+/// Metadata key telling editors to render the group collapsed
+pub const GROUP_COLLAPSED: &str = "collapsed";
+/// Metadata key naming the group this group is nested in
+pub const GROUP_PARENT: &str = "parent";
+
+/// A group with the groups nested in it, as returned by `Graph::group_tree`
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupTree {
+    pub name: String,
+    pub children: Vec<GroupTree>,
+}
+
+/// How `Graph::auto_group` partitions the nodes of a graph
+#[derive(Clone, Debug, PartialEq)]
+pub enum AutoGroupStrategy {
+    /// One group per component namespace, e.g. `core` for `core/Repeat`
+    Namespace,
+    /// One group per set of nodes connected to each other
+    ConnectedComponents,
+    /// Densely connected clusters found by label propagation
+    Communities,
+}
+
+impl GraphGroup {
+    pub fn color(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|meta| meta.get(GROUP_COLOR))
+            .and_then(|color| color.as_str())
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|meta| meta.get(GROUP_DESCRIPTION))
+            .and_then(|description| description.as_str())
+    }
+
+    pub fn is_collapsed(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .and_then(|meta| meta.get(GROUP_COLLAPSED))
+            .and_then(|collapsed| collapsed.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Name of the group this group is nested in
+    pub fn parent(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|meta| meta.get(GROUP_PARENT))
+            .and_then(|parent| parent.as_str())
+    }
+}
+
+impl<'a> Graph<'a> {
+    pub fn get_group(&self, name: &str) -> Option<&GraphGroup> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    /// Set the display color of a group, or clear it with `None`
+    pub fn set_group_color(&mut self, name: &str, color: Option<&str>) -> &mut Self {
+        self.set_group_attribute(name, GROUP_COLOR, color.map(Value::from))
+    }
+
+    /// Set the description of a group, or clear it with `None`
+    pub fn set_group_description(&mut self, name: &str, description: Option<&str>) -> &mut Self {
+        self.set_group_attribute(name, GROUP_DESCRIPTION, description.map(Value::from))
+    }
+
+    /// Mark a group as collapsed in editors
+    ///
+    /// Expanded groups don't store the key at all, so that expanding a
+    /// group serializes the same as never having collapsed it.
+    pub fn set_group_collapsed(&mut self, name: &str, collapsed: bool) -> &mut Self {
+        self.set_group_attribute(
+            name,
+            GROUP_COLLAPSED,
+            collapsed.then_some(Value::Bool(true)),
+        )
+    }
+
+    /// Nest a group inside another one, or move it back to the top level with `None`
+    ///
+    /// Parents that don't exist or that would make the hierarchy cyclic
+    /// are rejected and leave the group where it was.
+    pub fn set_group_parent(&mut self, name: &str, parent: Option<&str>) -> &mut Self {
+        if self.get_group(name).is_none() {
+            log::error!("No group {} found", name);
+            return self;
+        }
+        if let Some(parent) = parent {
+            if self.get_group(parent).is_none() {
+                log::error!("No group {} found", parent);
+                return self;
+            }
+            if parent == name || self.group_ancestors(parent).iter().any(|a| a == name) {
+                log::error!("Nesting group {} in {} would create a cycle", name, parent);
+                return self;
+            }
+        }
+        self.set_group_attribute(name, GROUP_PARENT, parent.map(Value::from))
+    }
+
+    /// Groups directly nested in the given group
+    pub fn child_groups(&self, name: &str) -> Vec<&GraphGroup> {
+        self.groups
+            .iter()
+            .filter(|group| group.parent() == Some(name))
+            .collect()
+    }
+
+    /// Names of the groups enclosing the given group, innermost first
+    pub fn group_ancestors(&self, name: &str) -> Vec<String> {
+        let mut ancestors: Vec<String> = Vec::new();
+        let mut current = self.get_group(name).and_then(|group| group.parent());
+        while let Some(parent) = current {
+            if parent == name || ancestors.iter().any(|a| a == parent) {
+                break;
+            }
+            ancestors.push(parent.to_owned());
+            current = self.get_group(parent).and_then(|group| group.parent());
+        }
+        ancestors
+    }
+
+    /// Group hierarchy, starting from the top-level groups
+    ///
+    /// Groups whose parent doesn't exist are treated as top-level.
+    pub fn group_tree(&self) -> Vec<GroupTree> {
+        self.groups
+            .iter()
+            .filter(|group| match group.parent() {
+                Some(parent) => self.get_group(parent).is_none(),
+                None => true,
+            })
+            .map(|group| self.group_subtree(&group.name, &mut vec![]))
+            .collect()
+    }
+
+    fn group_subtree(&self, name: &str, visited: &mut Vec<String>) -> GroupTree {
+        visited.push(name.to_owned());
+        let children = self
+            .child_groups(name)
+            .iter()
+            .filter(|child| !visited.contains(&child.name))
+            .map(|child| child.name.clone())
+            .collect::<Vec<_>>()
+            .iter()
+            .map(|child| self.group_subtree(child, visited))
+            .collect();
+        GroupTree {
+            name: name.to_owned(),
+            children,
+        }
+    }
+
+    /// Check that every parent exists and that no group is nested in itself
+    pub fn validate_group_hierarchy(&self) -> Result<(), String> {
+        for group in self.groups.iter() {
+            if let Some(parent) = group.parent() {
+                if self.get_group(parent).is_none() {
+                    return Err(format!(
+                        "Group {} is nested in missing group {}",
+                        group.name, parent
+                    ));
+                }
+            }
+            let mut current = group.parent();
+            let mut steps = 0;
+            while let Some(parent) = current {
+                if parent == group.name || steps > self.groups.len() {
+                    return Err(format!("Group {} is nested in itself", group.name));
+                }
+                steps += 1;
+                current = self.get_group(parent).and_then(|g| g.parent());
+            }
+        }
+        Ok(())
+    }
+
+    /// Move a node out of the groups it belongs to and into the given group
+    pub fn move_node_to_group(&mut self, node: &str, group: &str) -> &mut Self {
+        if self.get_node(node).is_none() {
+            log::error!("No node {} found", node);
+            return self;
+        }
+        if self.get_group(group).is_none() {
+            log::error!("No group {} found", group);
+            return self;
+        }
+        let changed = self
+            .groups
+            .iter()
+            .filter(|current| (current.name == group) != current.nodes.iter().any(|n| n == node))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mutations = changed
+            .iter()
+            .flat_map(|current| self.set_group_nodes_mutations(&current.name))
+            .collect::<Vec<_>>();
+        if self.permit_all(mutations).is_err() {
+            return self;
+        }
+        self.vetted(|graph| {
+            graph.check_transaction_start();
+            for current in changed {
+                let mut nodes = current.nodes.clone();
+                if current.name == group {
+                    nodes.push(node.to_owned());
+                } else {
+                    nodes.retain(|n| n != node);
+                }
+                graph.set_group_nodes(&current.name, nodes);
+            }
+            graph.check_transaction_end();
+        });
+        self
+    }
+
+    /// Changes made by `set_group_nodes`, to check before calling it
+    pub(crate) fn set_group_nodes_mutations(
+        &self,
+        name: &str,
+    ) -> Vec<(MutationKind, MutationTarget)> {
+        vec![(
+            MutationKind::ChangeGroup,
+            MutationTarget::Group(name.to_owned()),
+        )]
+    }
+
+    /// Replace the members of a group in place
+    ///
+    /// The group keeps its position, metadata and children. Emits
+    /// `change_group_nodes` with the name and the old and new members,
+    /// journaled as a single change.
+    pub(crate) fn set_group_nodes(&mut self, name: &str, nodes: Vec<String>) {
+        if !self.permit(
+            MutationKind::ChangeGroup,
+            MutationTarget::Group(name.to_owned()),
+        ) {
+            return;
+        }
+        let Some(position) = self.groups.iter().position(|group| group.name == name) else {
+            return;
+        };
+        self.check_transaction_start();
+        let old = std::mem::replace(&mut self.groups[position].nodes, nodes.clone());
+        self.emit("change_group_nodes", &(name.to_owned(), old, nodes));
+        self.check_transaction_end();
+    }
+
+    /// Create groups from the structure of the graph
+    ///
+    /// Groups are added through the regular `add_group` path, in a single
+    /// transaction, replacing any existing group of the same name. Single
+    /// node clusters are left ungrouped.
+    /// ```no_run
+    /// my_graph.auto_group(AutoGroupStrategy::Namespace);
+    /// ```
+    pub fn auto_group(&mut self, strategy: AutoGroupStrategy) -> &mut Self {
+        let clusters: Vec<(String, Vec<String>)> = match strategy {
+            AutoGroupStrategy::Namespace => {
+                let mut namespaces: Vec<(String, Vec<String>)> = Vec::new();
+                for node in self.nodes.iter() {
+                    let namespace = match node.component.rsplit_once('/') {
+                        Some((namespace, _)) => namespace,
+                        None => continue,
+                    };
+                    match namespaces.iter_mut().find(|(name, _)| name == namespace) {
+                        Some((_, nodes)) => nodes.push(node.id.clone()),
+                        None => namespaces.push((namespace.to_owned(), vec![node.id.clone()])),
+                    }
+                }
+                namespaces
+            }
+            AutoGroupStrategy::ConnectedComponents => self
+                .connected_components()
+                .into_iter()
+                .filter(|nodes| nodes.len() > 1)
+                .enumerate()
+                .map(|(i, nodes)| (format!("component_{}", i + 1), nodes))
+                .collect(),
+            AutoGroupStrategy::Communities => self
+                .communities()
+                .into_iter()
+                .filter(|nodes| nodes.len() > 1)
+                .enumerate()
+                .map(|(i, nodes)| (format!("community_{}", i + 1), nodes))
+                .collect(),
+        };
+
+        let mut mutations = vec![(MutationKind::AddGroup, MutationTarget::Graph)];
+        for (name, _) in clusters.iter() {
+            let target = MutationTarget::Group(name.clone());
+            if self.get_group(name).is_some() {
+                mutations.push((MutationKind::RemoveGroup, target.clone()));
+            }
+            mutations.push((MutationKind::AddGroup, target));
+        }
+        if self.permit_all(mutations).is_err() {
+            return self;
+        }
+        self.vetted(|graph| {
+            graph.check_transaction_start();
+            for (name, nodes) in clusters {
+                if graph.get_group(&name).is_some() {
+                    graph.remove_group(&name);
+                }
+                graph.add_group(&name, nodes, None);
+            }
+            graph.check_transaction_end();
+        });
+        self
+    }
+
+    /// Clusters of nodes found by label propagation over undirected edges
+    fn communities(&self) -> Vec<Vec<String>> {
+        let mut neighbours: HashMap<&str, Vec<&str>> = HashMap::new();
+        for edge in self.edges.iter() {
+            neighbours
+                .entry(edge.from.node_id.as_str())
+                .or_default()
+                .push(edge.to.node_id.as_str());
+            neighbours
+                .entry(edge.to.node_id.as_str())
+                .or_default()
+                .push(edge.from.node_id.as_str());
+        }
+        let mut labels: HashMap<&str, &str> = self
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node.id.as_str()))
+            .collect();
+
+        // Bounded so that oscillating labels can't loop forever
+        for _ in 0..self.nodes.len().max(1) * 2 {
+            let mut changed = false;
+            for node in self.nodes.iter() {
+                let mut counts: HashMap<&str, usize> = HashMap::new();
+                for n in neighbours.get(node.id.as_str()).into_iter().flatten() {
+                    if let Some(label) = labels.get(n) {
+                        *counts.entry(label).or_default() += 1;
+                    }
+                }
+                // Ties go to the smallest label to keep results stable
+                let best = counts
+                    .into_iter()
+                    .max_by(|(a, x), (b, y)| x.cmp(y).then(b.cmp(a)))
+                    .map(|(label, _)| label);
+                if let Some(best) = best {
+                    if labels.get(node.id.as_str()) != Some(&best) {
+                        labels.insert(node.id.as_str(), best);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let mut communities: Vec<(&str, Vec<String>)> = Vec::new();
+        for node in self.nodes.iter() {
+            let label = labels[node.id.as_str()];
+            match communities.iter_mut().find(|(l, _)| *l == label) {
+                Some((_, nodes)) => nodes.push(node.id.clone()),
+                None => communities.push((label, vec![node.id.clone()])),
+            }
+        }
+        communities.into_iter().map(|(_, nodes)| nodes).collect()
+    }
+
+    /// Point the children of a group at another parent
+    pub(crate) fn reparent_child_groups(&mut self, from: &str, to: Option<&str>) {
+        let children = self
+            .child_groups(from)
+            .iter()
+            .map(|child| child.name.clone())
+            .collect::<Vec<_>>();
+        for child in children {
+            self.set_group_attribute(&child, GROUP_PARENT, to.map(Value::from));
+        }
+    }
+
+    fn set_group_attribute(&mut self, name: &str, key: &str, value: Option<Value>) -> &mut Self {
+        let mut metadata = Map::new();
+        metadata.insert(key.to_owned(), value.unwrap_or(Value::Null));
+        self.set_group_metadata(name, metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::groups::{AutoGroupStrategy, GroupTree};
+    use crate::graph::journal::Journal;
+    use beady::scenario;
+    use futures::executor::block_on;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_group_attributes() {
+        'given_a_graph_with_a_group: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_group("io", vec!["Read".to_owned()], None);
+            'when_setting_typed_attributes: {
+                g.set_group_color("io", Some("#ff0000"))
+                    .set_group_description("io", Some("File access"))
+                    .set_group_collapsed("io", true);
+                'then_they_should_be_readable_back: {
+                    let group = g.get_group("io").unwrap();
+                    assert_eq!(group.color(), Some("#ff0000"));
+                    assert_eq!(group.description(), Some("File access"));
+                    assert!(group.is_collapsed());
+                }
+                'then_they_should_survive_a_json_round_trip: {
+                    let source = g.to_json_string().unwrap();
+                    let loaded = block_on(Graph::from_json_string(&source, None)).unwrap();
+                    let group = loaded.get_group("io").unwrap();
+                    assert_eq!(group.color(), Some("#ff0000"));
+                    assert_eq!(group.description(), Some("File access"));
+                    assert!(group.is_collapsed());
+                }
+            }
+            'when_clearing_attributes: {
+                g.set_group_color("io", Some("#ff0000"))
+                    .set_group_collapsed("io", true)
+                    .set_group_color("io", None)
+                    .set_group_collapsed("io", false);
+                'then_they_should_be_removed_from_the_metadata: {
+                    let group = g.get_group("io").unwrap();
+                    assert_eq!(group.color(), None);
+                    assert!(!group.is_collapsed());
+                    assert!(group.metadata.as_ref().unwrap().is_empty());
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_nested_groups() {
+        'given_a_graph_with_nested_groups: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Parse", "ParseJson", None)
+                .add_group("app", vec![], None)
+                .add_group("io", vec!["Read".to_owned()], None)
+                .add_group("parsing", vec!["Parse".to_owned()], None)
+                .set_group_parent("io", Some("app"))
+                .set_group_parent("parsing", Some("io"));
+            'then_it_should_expose_the_hierarchy: {
+                assert_eq!(g.group_ancestors("parsing"), vec!["io", "app"]);
+                assert_eq!(g.child_groups("app").len(), 1);
+                assert_eq!(
+                    g.group_tree(),
+                    vec![GroupTree {
+                        name: "app".to_owned(),
+                        children: vec![GroupTree {
+                            name: "io".to_owned(),
+                            children: vec![GroupTree {
+                                name: "parsing".to_owned(),
+                                children: vec![],
+                            }],
+                        }],
+                    }]
+                );
+                assert!(g.validate_group_hierarchy().is_ok());
+            }
+            'when_nesting_a_group_in_its_descendant: {
+                g.set_group_parent("app", Some("parsing"));
+                'then_it_should_be_rejected: {
+                    assert_eq!(g.get_group("app").unwrap().parent(), None);
+                    assert!(g.validate_group_hierarchy().is_ok());
+                }
+            }
+            'when_moving_a_node_between_groups: {
+                g.move_node_to_group("Parse", "io");
+                'then_it_should_only_belong_to_the_target: {
+                    assert_eq!(g.get_group("io").unwrap().nodes, vec!["Read", "Parse"]);
+                    assert!(g.get_group("parsing").unwrap().nodes.is_empty());
+                    assert_eq!(g.get_group("parsing").unwrap().parent(), Some("io"));
+                }
+            }
+            'when_renaming_a_parent: {
+                g.rename_group("io", "files");
+                'then_children_should_follow: {
+                    assert_eq!(g.get_group("parsing").unwrap().parent(), Some("files"));
+                }
+            }
+            'when_removing_a_parent: {
+                g.remove_group("io");
+                'then_children_should_move_up: {
+                    assert_eq!(g.get_group("parsing").unwrap().parent(), Some("app"));
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_group_membership() {
+        'given_a_journaled_graph_with_two_groups: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Parse", "ParseJson", None)
+                .add_group("io", vec!["Read".to_owned()], None)
+                .add_group("parsing", vec!["Parse".to_owned()], None);
+            g.init_journal(None);
+            'when_moving_a_node_between_groups: {
+                g.move_node_to_group("Parse", "io");
+                'then_the_groups_should_keep_their_order: {
+                    let names = g.groups.iter().map(|group| group.name.as_str());
+                    assert_eq!(names.collect::<Vec<_>>(), vec!["io", "parsing"]);
+                    assert_eq!(g.get_group("io").unwrap().nodes, vec!["Read", "Parse"]);
+                }
+                'then_undo_should_restore_both_groups_at_once: {
+                    g.undo();
+                    assert_eq!(g.get_group("io").unwrap().nodes, vec!["Read"]);
+                    assert_eq!(g.get_group("parsing").unwrap().nodes, vec!["Parse"]);
+                    let names = g.groups.iter().map(|group| group.name.as_str());
+                    assert_eq!(names.collect::<Vec<_>>(), vec!["io", "parsing"]);
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_auto_group() {
+        'given_a_graph_with_two_clusters: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "fs/ReadFile", None)
+                .add_node("Parse", "json/Parse", None)
+                .add_node("Write", "fs/WriteFile", None)
+                .add_node("Tick", "core/Timer", None)
+                .add_node("Log", "Output", None)
+                .add_edge("Read", "out", "Parse", "in", None)
+                .add_edge("Parse", "out", "Write", "in", None)
+                .add_edge("Tick", "out", "Log", "in", None);
+            'when_grouping_by_namespace: {
+                g.auto_group(AutoGroupStrategy::Namespace);
+                'then_each_namespace_should_get_a_group: {
+                    assert_eq!(g.get_group("fs").unwrap().nodes, vec!["Read", "Write"]);
+                    assert_eq!(g.get_group("json").unwrap().nodes, vec!["Parse"]);
+                    assert_eq!(g.get_group("core").unwrap().nodes, vec!["Tick"]);
+                    assert_eq!(g.groups.len(), 3);
+                }
+            }
+            'when_grouping_by_connectivity: {
+                g.auto_group(AutoGroupStrategy::ConnectedComponents);
+                'then_each_connected_set_should_get_a_group: {
+                    assert_eq!(g.groups.len(), 2);
+                    assert_eq!(g.get_group("component_1").unwrap().nodes.len(), 3);
+                    assert_eq!(g.get_group("component_2").unwrap().nodes.len(), 2);
+                }
+            }
+            'when_grouping_by_community: {
+                g.auto_group(AutoGroupStrategy::Communities);
+                'then_clusters_should_not_be_mixed: {
+                    assert!(!g.groups.is_empty());
+                    for group in g.groups.iter() {
+                        let chain = group
+                            .nodes
+                            .iter()
+                            .all(|n| ["Read", "Parse", "Write"].contains(&n.as_str()));
+                        let timer = group
+                            .nodes
+                            .iter()
+                            .all(|n| ["Tick", "Log"].contains(&n.as_str()));
+                        assert!(chain || timer);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use super::graph::Graph;
+use super::policy::{MutationKind, MutationTarget};
+use super::types::GraphIIP;
+
+/// Key of an IIP data object referring to an external file
+pub const FILE_REFERENCE: &str = "$file";
+/// Graph property naming the directory file references are relative to
+///
+/// Only a hint for tools: `resolve_iip_files` takes the directory from
+/// its caller, never from the graph.
+pub const BASE_DIR: &str = "baseDir";
+
+impl GraphIIP {
+    /// Path of the file holding the IIP data, for `{"$file": "..."}` IIPs
+    pub fn file_reference(&self) -> Option<&str> {
+        let data = self.from.as_ref()?.data.as_object()?;
+        if data.len() != 1 {
+            return None;
+        }
+        data.get(FILE_REFERENCE)?.as_str()
+    }
+}
+
+/// Resolve a file reference against `base`, refusing to leave it
+///
+/// References must be relative, and `..` may not climb above `base`.
+fn join_reference(base: &Path, reference: &str) -> Result<PathBuf, io::Error> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(reference).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if relative.pop() => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("IIP file {} is outside of {}", reference, base.display()),
+                ))
+            }
+        }
+    }
+    Ok(base.join(relative))
+}
+
+/// Resolve symlinks in `path`, refusing it if it then lies outside `base`
+fn canonical_within(base: &Path, path: &Path) -> Result<PathBuf, io::Error> {
+    let canonical = fs::canonicalize(path)?;
+    if !canonical.starts_with(fs::canonicalize(base)?) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "IIP file {} links outside of {}",
+                path.display(),
+                base.display()
+            ),
+        ));
+    }
+    Ok(canonical)
+}
+
+/// File name for an externalized IIP, keeping only characters safe in paths
+fn file_name(node: &str, port: &str) -> String {
+    format!("{}.{}", node, port)
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_owned()
+}
+
+impl<'a> Graph<'a> {
+    /// Replace `{"$file": "..."}` IIPs with the contents of the referenced files
+    ///
+    /// References are resolved against `base_dir`; the graph's `baseDir`
+    /// property is ignored, so a graph can't pick which directory it reads
+    /// from. Absolute references, references escaping that directory and
+    /// symlinks pointing out of it are refused. Files ending in `.json` are
+    /// parsed as JSON, anything else is delivered as a string.
+    pub fn resolve_iip_files(&mut self, base_dir: &str) -> Result<&mut Self, io::Error> {
+        let base = Path::new(base_dir);
+        let mut resolved = Vec::new();
+        for iip in self.initializers.iter() {
+            let reference = match iip.file_reference() {
+                Some(reference) => reference,
+                None => {
+                    resolved.push(None);
+                    continue;
+                }
+            };
+            let path = join_reference(base, reference)?;
+            let contents = canonical_within(base, &path).and_then(fs::read_to_string);
+            let contents = contents.map_err(|e| {
+                if e.kind() == io::ErrorKind::InvalidInput {
+                    return e;
+                }
+                io::Error::new(
+                    e.kind(),
+                    format!("Can't read IIP file {}: {}", path.display(), e),
+                )
+            })?;
+            let data = if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+                serde_json::from_str(&contents)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?
+            } else {
+                Value::String(contents)
+            };
+            resolved.push(Some(data));
+        }
+        let targets = self.initial_data_targets(&resolved);
+        self.permit_initial_data(&targets)?;
+        self.replace_initial_data(resolved, targets);
+        Ok(self)
+    }
+
+    /// Move IIP data larger than `threshold` bytes of JSON out to files in `dir`
+    ///
+    /// Each externalized IIP is replaced with a `{"$file": "..."}` reference
+    /// to `<node>.<port>.json` relative to `dir`, so `dir` has to be passed
+    /// to `resolve_iip_files` to read them back. Characters other than
+    /// letters, digits, `-`, `_` and `.` are replaced in file names.
+    /// Returns the written files.
+    pub fn externalize_large_iips(
+        &mut self,
+        dir: &str,
+        threshold: usize,
+    ) -> Result<Vec<PathBuf>, io::Error> {
+        let mut files: Vec<(PathBuf, String)> = Vec::new();
+        let mut replaced = Vec::new();
+        for (i, iip) in self.initializers.iter().enumerate() {
+            let (from, to) = match (iip.from.as_ref(), iip.to.as_ref()) {
+                (Some(from), Some(to)) if iip.file_reference().is_none() => (from, to),
+                _ => {
+                    replaced.push(None);
+                    continue;
+                }
+            };
+            let data = serde_json::to_string_pretty(&from.data)?;
+            if data.len() <= threshold {
+                replaced.push(None);
+                continue;
+            }
+            let mut name = file_name(&to.node_id, &to.port);
+            if let Some(index) = to.index {
+                name.push_str(&format!(".{}", index));
+            }
+            if files
+                .iter()
+                .any(|(p, _)| p.ends_with(format!("{}.json", name)))
+            {
+                name.push_str(&format!(".{}", i));
+            }
+            let name = format!("{}.json", name);
+            replaced.push(Some(json!({ FILE_REFERENCE: name })));
+            files.push((Path::new(dir).join(name), data));
+        }
+        let targets = self.initial_data_targets(&replaced);
+        self.permit_initial_data(&targets)?;
+        let mut written = Vec::new();
+        for (path, data) in files {
+            fs::create_dir_all(dir)?;
+            fs::write(&path, data)?;
+            written.push(path);
+        }
+        self.replace_initial_data(replaced, targets);
+        Ok(written)
+    }
+
+    /// Ports whose IIPs get replaced, given new data for each IIP in order
+    fn initial_data_targets(&self, data: &[Option<Value>]) -> Vec<(String, String)> {
+        let mut targets: Vec<(String, String)> = Vec::new();
+        for (iip, new) in self.initializers.iter().zip(data.iter()) {
+            if let (Some(to), Some(_)) = (iip.to.as_ref(), new) {
+                let target = (to.node_id.clone(), to.port.clone());
+                if !targets.contains(&target) {
+                    targets.push(target);
+                }
+            }
+        }
+        targets
+    }
+
+    /// Check that the IIPs going into the given ports may be replaced
+    fn permit_initial_data(&mut self, targets: &[(String, String)]) -> Result<(), io::Error> {
+        let mutations = targets
+            .iter()
+            .flat_map(|(node, _)| {
+                [
+                    (
+                        MutationKind::RemoveInitial,
+                        MutationTarget::Initial(node.clone()),
+                    ),
+                    (
+                        MutationKind::AddInitial,
+                        MutationTarget::Initial(node.clone()),
+                    ),
+                ]
+            })
+            .collect::<Vec<_>>();
+        self.permit_all(mutations)
+            .map_err(|err| io::Error::new(io::ErrorKind::PermissionDenied, err.to_string()))
+    }
+
+    /// Swap the data of IIPs, given new data for each IIP in order
+    ///
+    /// IIPs can only be removed per target port, so every IIP sharing a
+    /// port with a changed one is re-added, keeping their order.
+    fn replace_initial_data(&mut self, data: Vec<Option<Value>>, targets: Vec<(String, String)>) {
+        if targets.is_empty() {
+            return;
+        }
+        let initializers = self.initializers.clone();
+        self.vetted(|graph| {
+            graph.check_transaction_start();
+            for (node, port) in targets.iter() {
+                graph.remove_initial(node, port);
+            }
+            for (iip, new) in initializers.into_iter().zip(data) {
+                let to = match iip.to {
+                    Some(to) if targets.contains(&(to.node_id.clone(), to.port.clone())) => to,
+                    _ => continue,
+                };
+                let value = match (new, iip.from) {
+                    (Some(value), _) => value,
+                    (None, Some(from)) => from.data,
+                    (None, None) => continue,
+                };
+                graph.add_initial_index(value, &to.node_id, &to.port, to.index, iip.metadata);
+            }
+            graph.check_transaction_end();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::policy::ProtectedNodesPolicy;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_file_iips() {
+        'given_a_graph_with_a_bulky_iip: {
+            let dir = std::env::temp_dir().join(format!(
+                "zflow-iips-{}-{:?}",
+                std::process::id(),
+                std::thread::current().id()
+            ));
+            let dir = dir.to_str().unwrap().to_owned();
+            let payload = json!({"rows": (0..50).collect::<Vec<i32>>()});
+            let mut g = Graph::new("", true);
+            g.add_node("Load", "LoadRows", None)
+                .add_initial(payload.clone(), "Load", "rows", None)
+                .add_initial(json!(10), "Load", "limit", None);
+            'when_externalizing_large_iips: {
+                let written = g.externalize_large_iips(&dir, 64).unwrap();
+                'then_only_the_bulky_one_should_be_moved_out: {
+                    assert_eq!(written.len(), 1);
+                    assert_eq!(g.initializers.len(), 2);
+                    let iip = g
+                        .initializers
+                        .iter()
+                        .find(|iip| iip.to.as_ref().unwrap().port == "rows")
+                        .unwrap();
+                    assert_eq!(iip.file_reference(), Some("Load.rows.json"));
+                    assert_eq!(
+                        written[0],
+                        std::path::Path::new(&dir).join("Load.rows.json")
+                    );
+                    let _ = std::fs::remove_dir_all(&dir);
+                }
+                'and_then_resolving_file_references: {
+                    g.resolve_iip_files(&dir).unwrap();
+                    'then_the_data_should_be_inlined_again: {
+                        let iip = g
+                            .initializers
+                            .iter()
+                            .find(|iip| iip.to.as_ref().unwrap().port == "rows")
+                            .unwrap();
+                        assert_eq!(iip.from.as_ref().unwrap().data, payload);
+                        let _ = std::fs::remove_dir_all(&dir);
+                    }
+                }
+            }
+            'when_externalizing_for_a_node_with_a_path_in_its_id: {
+                g.add_node("../../etc/x", "LoadRows", None).add_initial(
+                    payload.clone(),
+                    "../../etc/x",
+                    "rows",
+                    None,
+                );
+                g.remove_initial("Load", "rows");
+                let written = g.externalize_large_iips(&dir, 64).unwrap();
+                'then_the_file_should_stay_in_the_directory: {
+                    assert_eq!(written.len(), 1);
+                    assert_eq!(written[0].parent().unwrap(), std::path::Path::new(&dir));
+                    assert_eq!(written[0].file_name().unwrap(), "_.._etc_x.rows.json");
+                    let _ = std::fs::remove_dir_all(&dir);
+                }
+            }
+            'when_a_reference_escapes_the_base_directory: {
+                'then_it_should_be_refused: {
+                    for reference in ["/etc/passwd", "../secret.json", "a/../../secret.json"] {
+                        let mut g = g.clone();
+                        g.add_initial(json!({"$file": reference}), "Load", "schema", None);
+                        let err = g.resolve_iip_files(&dir).unwrap_err();
+                        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+                    }
+                }
+            }
+            'when_a_reference_is_a_symlink_out_of_the_base_directory: {
+                let outside = format!("{}-outside.json", dir);
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(&outside, "{}").unwrap();
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&outside, std::path::Path::new(&dir).join("link.json"))
+                    .unwrap();
+                g.add_initial(json!({"$file": "link.json"}), "Load", "schema", None);
+                'then_it_should_be_refused: {
+                    let err = g.resolve_iip_files(&dir).unwrap_err();
+                    #[cfg(unix)]
+                    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+                    assert!(err.to_string().contains("outside"));
+                    let _ = std::fs::remove_dir_all(&dir);
+                    let _ = std::fs::remove_file(&outside);
+                }
+            }
+            'when_the_base_directory_is_only_set_on_the_graph: {
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(std::path::Path::new(&dir).join("schema.json"), "{}").unwrap();
+                g.set_properties(json!({"baseDir": dir}).as_object().cloned().unwrap());
+                g.add_initial(json!({"$file": "schema.json"}), "Load", "schema", None);
+                'then_it_should_not_be_used: {
+                    assert!(g.resolve_iip_files(".").is_err());
+                    let _ = std::fs::remove_dir_all(&dir);
+                }
+            }
+            'when_the_target_node_is_protected: {
+                g.set_mutation_policy(ProtectedNodesPolicy {
+                    nodes: vec!["Load".to_owned()],
+                });
+                'then_nothing_should_be_written_or_replaced: {
+                    let err = g.externalize_large_iips(&dir, 64).unwrap_err();
+                    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+                    assert!(!std::path::Path::new(&dir).exists());
+                    let iip = g
+                        .initializers
+                        .iter()
+                        .find(|iip| iip.to.as_ref().unwrap().port == "rows")
+                        .unwrap();
+                    assert_eq!(iip.from.as_ref().unwrap().data, payload);
+                }
+            }
+            'when_resolving_a_missing_file: {
+                g.add_initial(json!({"$file": "missing.json"}), "Load", "schema", None);
+                'then_it_should_fail: {
+                    assert!(g.resolve_iip_files("/nonexistent").is_err());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::slice;
+
+use serde_json::{Map, Value};
+
+use crate::internal::event_manager::EventManager;
+
+use super::graph::Graph;
+use super::policy::{MutationKind, MutationTarget};
+use super::types::{GraphEdge, GraphIIP, GraphNode};
+
+/// Iterating over the graph
+///
+/// Read-only traversal borrows the graph's collections directly. The
+/// `_mut` variants hand out a guard that lets nodes or edges be edited
+/// in place; when the guard is dropped, the changes go through the
+/// mutation policy and are emitted as `change_node`, `change_component`
+/// and `change_edge` events within a single transaction, so journals
+/// and listeners see them like any other mutation:
+/// ```no_run
+/// for node in my_graph.nodes_mut().iter_mut() {
+///     node.component = node.component.replace("core/", "std/");
+/// }
+/// ```
+/// IDs and endpoints can't be changed this way; use `rename_node` or
+/// remove and re-add the edge instead.
+impl<'a> Graph<'a> {
+    pub fn nodes(&self) -> slice::Iter<'_, GraphNode> {
+        self.nodes.iter()
+    }
+
+    pub fn edges(&self) -> slice::Iter<'_, GraphEdge> {
+        self.edges.iter()
+    }
+
+    pub fn iips(&self) -> slice::Iter<'_, GraphIIP> {
+        self.initializers.iter()
+    }
+
+    pub fn nodes_mut(&mut self) -> NodesMut<'_, 'a> {
+        NodesMut {
+            snapshot: self.nodes.clone(),
+            graph: self,
+        }
+    }
+
+    pub fn edges_mut(&mut self) -> EdgesMut<'_, 'a> {
+        EdgesMut {
+            snapshot: self.edges.clone(),
+            graph: self,
+        }
+    }
+}
+
+/// In-place access to a graph's nodes, committed when dropped
+pub struct NodesMut<'g, 'a> {
+    graph: &'g mut Graph<'a>,
+    snapshot: Vec<GraphNode>,
+}
+
+impl<'g, 'a> NodesMut<'g, 'a> {
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, GraphNode> {
+        self.graph.nodes.iter_mut()
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut GraphNode> {
+        self.graph.nodes.iter_mut().find(|node| node.id == id)
+    }
+}
+
+impl<'s, 'g, 'a> IntoIterator for &'s mut NodesMut<'g, 'a> {
+    type Item = &'s mut GraphNode;
+    type IntoIter = slice::IterMut<'s, GraphNode>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<'g, 'a> Drop for NodesMut<'g, 'a> {
+    fn drop(&mut self) {
+        let graph = &mut *self.graph;
+        let mut changed = Vec::new();
+        for (index, before) in self.snapshot.iter().enumerate() {
+            let node = &mut graph.nodes[index];
+            if node.id != before.id || node.uid != before.uid {
+                log::error!("Node {} can't be renamed through nodes_mut", before.id);
+                node.id = before.id.clone();
+                node.uid = before.uid.clone();
+            }
+            if node.metadata == before.metadata && node.component == before.component {
+                continue;
+            }
+            if !graph.permit(
+                MutationKind::ChangeNode,
+                MutationTarget::Node(before.id.clone()),
+            ) {
+                graph.nodes[index] = before.clone();
+                continue;
+            }
+            changed.push(index);
+        }
+        if changed.is_empty() {
+            return;
+        }
+
+        graph.check_transaction_start();
+        for index in changed {
+            let before = &self.snapshot[index];
+            let node = graph.nodes[index].clone();
+            if node.component != before.component {
+                graph.emit(
+                    "change_component",
+                    &(
+                        node.id.clone(),
+                        before.component.clone(),
+                        node.component.clone(),
+                    ),
+                );
+            }
+            if node.metadata != before.metadata {
+                let metadata = node.metadata.clone().unwrap_or_default();
+                graph.emit("change_node", &(node, before.metadata.clone(), metadata));
+            }
+        }
+        graph.check_transaction_end();
+    }
+}
+
+/// In-place access to a graph's edges, committed when dropped
+pub struct EdgesMut<'g, 'a> {
+    graph: &'g mut Graph<'a>,
+    snapshot: Vec<GraphEdge>,
+}
+
+impl<'g, 'a> EdgesMut<'g, 'a> {
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, GraphEdge> {
+        self.graph.edges.iter_mut()
+    }
+}
+
+impl<'s, 'g, 'a> IntoIterator for &'s mut EdgesMut<'g, 'a> {
+    type Item = &'s mut GraphEdge;
+    type IntoIter = slice::IterMut<'s, GraphEdge>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<'g, 'a> Drop for EdgesMut<'g, 'a> {
+    fn drop(&mut self) {
+        let graph = &mut *self.graph;
+        let mut changed = Vec::new();
+        for (index, before) in self.snapshot.iter().enumerate() {
+            let edge = &mut graph.edges[index];
+            if edge.from.endpoint() != before.from.endpoint()
+                || edge.to.endpoint() != before.to.endpoint()
+            {
+                log::error!(
+                    "Edge {} -> {} can't be rewired through edges_mut",
+                    before.from.endpoint(),
+                    before.to.endpoint()
+                );
+                edge.from = before.from.clone();
+                edge.to = before.to.clone();
+            }
+            if edge.metadata == before.metadata {
+                continue;
+            }
+            if !graph.permit(
+                MutationKind::ChangeEdge,
+                MutationTarget::Edge {
+                    from: before.from.node_id.clone(),
+                    to: before.to.node_id.clone(),
+                },
+            ) {
+                graph.edges[index] = before.clone();
+                continue;
+            }
+            changed.push(index);
+        }
+        if changed.is_empty() {
+            return;
+        }
+
+        graph.check_transaction_start();
+        for index in changed {
+            let edge = graph.edges[index].clone();
+            let metadata: Map<String, Value> = edge.metadata.clone().unwrap_or_default();
+            graph.emit(
+                "change_edge",
+                &(edge, self.snapshot[index].metadata.clone(), metadata),
+            );
+        }
+        graph.check_transaction_end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use crate::graph::policy::ProtectedNodesPolicy;
+    use crate::internal::event_manager::EventManager;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_iterators() {
+        'given_a_journaled_graph: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "core/ReadFile", None)
+                .add_node("Write", "core/WriteFile", None)
+                .add_edge("Read", "out", "Write", "in", None)
+                .add_initial(json!("a.txt"), "Read", "in", None);
+            g.init_journal(None);
+            'when_traversing_it: {
+                'then_it_should_yield_every_item: {
+                    assert_eq!(g.nodes().count(), 2);
+                    assert_eq!(g.edges().count(), 1);
+                    assert_eq!(g.iips().count(), 1);
+                    assert_eq!(
+                        g.nodes().map(|node| node.id.as_str()).collect::<Vec<_>>(),
+                        vec!["Read", "Write"]
+                    );
+                }
+            }
+            'when_editing_nodes_in_place: {
+                let events = Arc::new(Mutex::new(Vec::new()));
+                let seen = events.clone();
+                g.connect(
+                    "change_component",
+                    move |_, _| seen.lock().unwrap().push("change_component"),
+                    false,
+                );
+                for node in &mut g.nodes_mut() {
+                    node.component = node.component.replace("core/", "std/");
+                }
+                g.nodes_mut().get_mut("Read").unwrap().metadata =
+                    json!({"x": 1}).as_object().cloned();
+                'then_the_changes_should_be_journaled_as_events: {
+                    assert_eq!(g.nodes[0].component, "std/ReadFile");
+                    assert_eq!(events.lock().unwrap().len(), 2);
+                    assert_eq!(g.last_revision, 2);
+                    g.undo();
+                    assert!(g.nodes[0]
+                        .metadata
+                        .as_ref()
+                        .and_then(|meta| meta.get("x"))
+                        .is_none());
+                    g.undo();
+                    assert_eq!(g.nodes[0].component, "core/ReadFile");
+                }
+            }
+            'when_renaming_through_the_guard: {
+                g.nodes_mut().get_mut("Read").unwrap().id = "Input".to_owned();
+                'then_the_rename_should_be_reverted: {
+                    assert!(g.get_node("Read").is_some());
+                    assert_eq!(g.last_revision, 0);
+                }
+            }
+            'when_editing_edges_in_place: {
+                for edge in &mut g.edges_mut() {
+                    edge.metadata = json!({"route": 2}).as_object().cloned();
+                }
+                'then_the_change_should_be_journaled: {
+                    assert_eq!(
+                        g.edges[0].metadata.as_ref().unwrap().get("route"),
+                        Some(&json!(2))
+                    );
+                    assert_eq!(g.last_revision, 1);
+                }
+            }
+            'when_the_policy_protects_a_node: {
+                g.set_mutation_policy(ProtectedNodesPolicy {
+                    nodes: vec!["Write".to_owned()],
+                });
+                for node in &mut g.nodes_mut() {
+                    node.component = "Other".to_owned();
+                }
+                'then_only_permitted_changes_should_be_kept: {
+                    assert_eq!(g.nodes[0].component, "Other");
+                    assert_eq!(g.nodes[1].component, "core/WriteFile");
+                }
+            }
+        }
+    }
+}
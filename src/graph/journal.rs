@@ -6,6 +6,8 @@
 ///    (c) 2011-2012 Henri Bergius, Nemein
 ///    FBP Graph may be freely distributed under the MIT license
 
+use std::any::Any;
+
 use crate::internal::event_manager::EventManager;
 use foreach::ForEach;
 use log::error;
@@ -13,7 +15,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 
 use super::{
+    churn::ChurnReport,
     graph::Graph,
+    policy::{MutationKind, MutationTarget},
     types::{GraphEdge, GraphExportedPort, GraphGroup, GraphIIP, GraphLeaf},
 };
 use crate::graph::types::GraphNode;
@@ -27,6 +31,326 @@ pub struct TransactionEntry {
     pub new: Option<Map<String, Value>>,
 }
 
+impl TransactionEntry {
+    /// Nodes the entry applies to
+    ///
+    /// Renames refer to the node by its ID before the rename.
+    pub fn nodes(&self) -> Vec<String> {
+        let args = match self.args.as_ref().and_then(|a| a.as_object()) {
+            Some(args) => args,
+            None => return vec![],
+        };
+        let leaf_node = |leaf: Option<&Value>| {
+            leaf.and_then(|l| l.get("node_id"))
+                .and_then(|id| id.as_str())
+                .map(|id| id.to_owned())
+        };
+        let string_arg = |key: &str| {
+            args.get(key)
+                .and_then(|id| id.as_str())
+                .map(|id| vec![id.to_owned()])
+                .unwrap_or_default()
+        };
+        match self.cmd.as_deref() {
+            Some("add_node") | Some("remove_node") | Some("change_node")
+            | Some("change_component") => string_arg("id"),
+            Some("rename_node") => string_arg("old_id"),
+            Some("add_edge") | Some("remove_edge") | Some("change_edge") => {
+                [leaf_node(args.get("from")), leaf_node(args.get("to"))]
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            }
+            Some("add_initial") | Some("remove_initial") => {
+                leaf_node(args.get("to")).into_iter().collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Group the entry applies to
+    pub fn group(&self) -> Option<String> {
+        let args = self.args.as_ref()?.as_object()?;
+        let key = match self.cmd.as_deref()? {
+            "add_group" | "remove_group" | "change_group" | "change_group_nodes" => "name",
+            "rename_group" => "old_name",
+            _ => return None,
+        };
+        args.get(key)?.as_str().map(|name| name.to_owned())
+    }
+
+    /// Change the entry makes to the graph, or its inverse makes if
+    /// `inversed`, as checked by the mutation policy
+    pub fn mutation(&self, inversed: bool) -> Option<(MutationKind, MutationTarget)> {
+        use MutationKind::*;
+        let args = self.args.as_ref()?.as_object()?;
+        let string_arg = |key: &str| args.get(key)?.as_str().map(|arg| arg.to_owned());
+        let leaf_node = |key: &str| {
+            args.get(key)?
+                .get("node_id")?
+                .as_str()
+                .map(|id| id.to_owned())
+        };
+        // Renames are inverted by renaming the new name back
+        let renamed = if inversed { "new_id" } else { "old_id" };
+        let (kind, target) = match self.cmd.as_deref()? {
+            "add_node" => (AddNode, MutationTarget::Node(string_arg("id")?)),
+            "remove_node" => (RemoveNode, MutationTarget::Node(string_arg("id")?)),
+            "rename_node" => (RenameNode, MutationTarget::Node(string_arg(renamed)?)),
+            "change_component" | "change_node" => {
+                (ChangeNode, MutationTarget::Node(string_arg("id")?))
+            }
+            cmd @ ("add_edge" | "remove_edge" | "change_edge") => {
+                let target = MutationTarget::Edge {
+                    from: leaf_node("from")?,
+                    to: leaf_node("to")?,
+                };
+                let kind = match cmd {
+                    "add_edge" => AddEdge,
+                    "remove_edge" => RemoveEdge,
+                    _ => ChangeEdge,
+                };
+                (kind, target)
+            }
+            "add_initial" => (AddInitial, MutationTarget::Initial(leaf_node("to")?)),
+            "remove_initial" => (RemoveInitial, MutationTarget::Initial(leaf_node("to")?)),
+            "change_properties" => (ChangeProperties, MutationTarget::Graph),
+            "add_group" => (AddGroup, MutationTarget::Group(string_arg("name")?)),
+            "remove_group" => (RemoveGroup, MutationTarget::Group(string_arg("name")?)),
+            "change_group" | "change_group_nodes" => {
+                (ChangeGroup, MutationTarget::Group(string_arg("name")?))
+            }
+            "rename_group" => {
+                let renamed = if inversed { "new_name" } else { "old_name" };
+                (RenameGroup, MutationTarget::Group(string_arg(renamed)?))
+            }
+            "add_inport" => (AddInport, MutationTarget::Inport(string_arg("name")?)),
+            "remove_inport" => (RemoveInport, MutationTarget::Inport(string_arg("name")?)),
+            "change_inport" => (ChangeInport, MutationTarget::Inport(string_arg("name")?)),
+            "rename_inport" => (RenameInport, MutationTarget::Inport(string_arg(renamed)?)),
+            "add_outport" => (AddOutport, MutationTarget::Outport(string_arg("name")?)),
+            "remove_outport" => (RemoveOutport, MutationTarget::Outport(string_arg("name")?)),
+            "change_outport" => (ChangeOutport, MutationTarget::Outport(string_arg("name")?)),
+            "rename_outport" => (RenameOutport, MutationTarget::Outport(string_arg(renamed)?)),
+            _ => return None,
+        };
+        if !inversed {
+            return Some((kind, target));
+        }
+        let kind = match kind {
+            AddNode => RemoveNode,
+            RemoveNode => AddNode,
+            AddEdge => RemoveEdge,
+            RemoveEdge => AddEdge,
+            AddInitial => RemoveInitial,
+            RemoveInitial => AddInitial,
+            AddGroup => RemoveGroup,
+            RemoveGroup => AddGroup,
+            AddInport => RemoveInport,
+            RemoveInport => AddInport,
+            AddOutport => RemoveOutport,
+            RemoveOutport => AddOutport,
+            kind => kind,
+        };
+        Some((kind, target))
+    }
+
+    /// Whether the entry has every argument replaying it needs
+    ///
+    /// Entries from other journals can't be trusted to, and replaying a
+    /// malformed one panics.
+    pub fn is_well_formed(&self) -> bool {
+        let cmd = match self.cmd.as_deref() {
+            Some(cmd) => cmd,
+            None => return false,
+        };
+        if cmd == "start_transaction" || cmd == "end_transaction" {
+            return true;
+        }
+        let args = match self.args.as_ref().and_then(|a| a.as_object()) {
+            Some(args) => args,
+            None => return false,
+        };
+        let strings = |keys: &[&str]| {
+            keys.iter()
+                .all(|key| args.get(*key).is_some_and(Value::is_string))
+        };
+        let parses = |parse: fn(&Value) -> bool| self.args.as_ref().is_some_and(parse);
+        match cmd {
+            "add_node" => strings(&["id", "component"]),
+            "remove_node" | "change_node" => strings(&["id"]),
+            "change_component" => strings(&["id", "new"]),
+            "rename_node" | "rename_inport" | "rename_outport" => strings(&["old_id", "new_id"]),
+            "rename_group" => strings(&["old_name", "new_name"]),
+            "change_group" | "remove_inport" | "change_inport" | "remove_outport"
+            | "change_outport" => strings(&["name"]),
+            "add_inport" | "add_outport" => {
+                strings(&["name"])
+                    && args
+                        .get("port")
+                        .is_some_and(|port| GraphExportedPort::deserialize(port).is_ok())
+            }
+            "add_edge" | "remove_edge" => parses(|a| GraphEdge::deserialize(a).is_ok()),
+            "change_edge" => ["from", "to"].iter().all(|key| {
+                args.get(*key)
+                    .is_some_and(|leaf| GraphLeaf::deserialize(leaf).is_ok())
+            }),
+            "add_initial" => parses(|a| GraphIIP::deserialize(a).is_ok()),
+            "remove_initial" => {
+                parses(|a| GraphIIP::deserialize(a).is_ok_and(|iip| iip.to.is_some()))
+            }
+            "add_group" | "remove_group" => parses(|a| GraphGroup::deserialize(a).is_ok()),
+            "change_group_nodes" => {
+                strings(&["name"])
+                    && ["old", "new"].iter().all(|key| {
+                        args.get(*key)
+                            .is_some_and(|nodes| Vec::<String>::deserialize(nodes).is_ok())
+                    })
+            }
+            "change_properties" => true,
+            _ => false,
+        }
+    }
+}
+
+/// Graph events recorded as journal commands, besides transaction bounds
+pub const JOURNALED_EVENTS: [&str; 24] = [
+    "add_node",
+    "remove_node",
+    "rename_node",
+    "change_component",
+    "change_node",
+    "add_edge",
+    "remove_edge",
+    "change_edge",
+    "add_initial",
+    "remove_initial",
+    "change_properties",
+    "add_group",
+    "rename_group",
+    "remove_group",
+    "change_group",
+    "change_group_nodes",
+    "add_inport",
+    "remove_inport",
+    "rename_inport",
+    "change_inport",
+    "add_outport",
+    "remove_outport",
+    "rename_outport",
+    "change_outport",
+];
+
+/// Arguments of the journal command for a graph event, as understood by
+/// `Journal::execute_entry`
+pub(crate) fn event_args(name: &str, data: &dyn Any) -> Option<Value> {
+    let metadata = |meta: &Option<Map<String, Value>>| match meta {
+        Some(meta) => json!(meta),
+        None => json!(null),
+    };
+    let args = match name {
+        "add_node" | "remove_node" => json!(data.downcast_ref::<GraphNode>()?),
+        "add_edge" | "remove_edge" => json!(data.downcast_ref::<GraphEdge>()?),
+        "add_initial" | "remove_initial" => json!(data.downcast_ref::<GraphIIP>()?),
+        "add_group" | "remove_group" => json!(data.downcast_ref::<GraphGroup>()?),
+        "rename_node" | "rename_inport" | "rename_outport" => {
+            let (old_id, new_id) = data.downcast_ref::<(String, String)>()?;
+            json!({
+                "old_id": *old_id,
+                "new_id": *new_id
+            })
+        }
+        "rename_group" => {
+            let (old_name, new_name) = data.downcast_ref::<(String, String)>()?;
+            json!({
+                "old_name": *old_name,
+                "new_name": *new_name
+            })
+        }
+        "change_component" => {
+            let (id, old, new) = data.downcast_ref::<(String, String, String)>()?;
+            json!({
+                "id": *id,
+                "old": *old,
+                "new": *new
+            })
+        }
+        "change_node" => {
+            let (node, old, _) = data
+                .downcast_ref::<(GraphNode, Option<Map<String, Value>>, Map<String, Value>)>()?;
+            json!({
+                "id": node.id,
+                "new": metadata(&node.metadata),
+                "old": metadata(old)
+            })
+        }
+        "change_edge" => {
+            let (edge, old, _) = data
+                .downcast_ref::<(GraphEdge, Option<Map<String, Value>>, Map<String, Value>)>()?;
+            json!({
+                "from": edge.from,
+                "to": edge.to,
+                "key": edge.key,
+                "new": metadata(&edge.metadata),
+                "old": metadata(old)
+            })
+        }
+        "change_group" => {
+            let (group, old, _) = data
+                .downcast_ref::<(GraphGroup, Option<Map<String, Value>>, Map<String, Value>)>()?;
+            json!({
+                "name": group.name,
+                "new": metadata(&group.metadata),
+                "old": metadata(old)
+            })
+        }
+        "change_group_nodes" => {
+            let (name, old, new) = data.downcast_ref::<(String, Vec<String>, Vec<String>)>()?;
+            json!({
+                "name": *name,
+                "old": *old,
+                "new": *new
+            })
+        }
+        "change_properties" => {
+            let (new, old) = data.downcast_ref::<(Map<String, Value>, Map<String, Value>)>()?;
+            json!({
+                "old": *old,
+                "new": *new
+            })
+        }
+        "add_inport" | "add_outport" => {
+            let (name, port) = data.downcast_ref::<(String, GraphExportedPort)>()?;
+            json!({
+                "name": name,
+                "port": *port
+            })
+        }
+        "remove_inport" | "remove_outport" => {
+            let (name, port) = data.downcast_ref::<(String, Option<GraphExportedPort>)>()?;
+            json!({
+                "name": name,
+                "port": *port
+            })
+        }
+        "change_inport" | "change_outport" => {
+            let (name, port, old, _) = data.downcast_ref::<(
+                String,
+                GraphExportedPort,
+                Option<Map<String, Value>>,
+                Map<String, Value>,
+            )>()?;
+            json!({
+                "name": name,
+                "new": port.metadata,
+                "old": *old
+            })
+        }
+        _ => return None,
+    };
+    Some(args)
+}
+
 pub trait JournalStore<'a>: EventManager<'a> {
     fn count_transactions(&self) -> usize;
     fn put_transaction(&mut self, rev_id: usize, entry: Vec<TransactionEntry>);
@@ -39,11 +363,15 @@ impl<'a> JournalStore<'a> for Graph<'a> {
     }
 
     fn put_transaction(&mut self, rev_id: usize, entries: Vec<TransactionEntry>) {
-        if rev_id > self.last_revision {
-            self.last_revision = rev_id;
-        }
+        // A new revision after an undo replaces the ones that could be redone
+        self.last_revision = rev_id;
         self.emit("transaction", &(rev_id, entries.clone()));
-        self.transactions.insert(rev_id, entries);
+        self.notify_audit_sinks(rev_id, &entries);
+        if rev_id < self.transactions.len() {
+            self.history_rewrites += 1;
+        }
+        self.transactions.truncate(rev_id);
+        self.transactions.push(entries);
     }
 
     fn fetch_transaction(&mut self, rev_id: usize) -> Option<&mut Vec<TransactionEntry>> {
@@ -78,6 +406,43 @@ pub trait Journal<'a>: EventManager<'a> {
     fn can_redo(&self) -> bool;
     /// If there is something to undo
     fn can_undo(&self) -> bool;
+    /// Journal entries matching the predicate, paired with their revision
+    ///
+    /// Reads the stored transactions only, without moving the graph
+    /// to another revision.
+    fn replay_filtered(
+        &mut self,
+        predicate: impl FnMut(usize, &TransactionEntry) -> bool,
+    ) -> Vec<(usize, TransactionEntry)>;
+    /// Summarize change frequency, authors and graph size over the journal
+    fn churn_report(&self) -> ChurnReport;
+}
+
+impl<'a> Graph<'a> {
+    /// Changes made by moving the graph from its current revision to
+    /// `rev_id`, in the order they are replayed
+    pub(crate) fn revision_mutations(&self, rev_id: i32) -> Vec<(MutationKind, MutationTarget)> {
+        let current = self.current_revision;
+        let transactions = |range: std::ops::RangeInclusive<i32>| {
+            range.filter_map(|r| {
+                usize::try_from(r)
+                    .ok()
+                    .and_then(|r| self.transactions.get(r))
+            })
+        };
+        if rev_id > current {
+            transactions((current + 1)..=rev_id)
+                .flatten()
+                .filter_map(|entry| entry.mutation(false))
+                .collect()
+        } else {
+            transactions((rev_id + 1)..=current)
+                .rev()
+                .flat_map(|entries| entries.iter().rev())
+                .filter_map(|entry| entry.mutation(true))
+                .collect()
+        }
+    }
 }
 
 impl<'a> Journal<'a> for Graph<'a> {
@@ -137,373 +502,17 @@ impl<'a> Journal<'a> for Graph<'a> {
         }
 
         // Subscribe to graph changes
-        self.connect(
-            "add_node",
-            |this, data| {
-                this.append_command(
-                    "add_node",
-                    json!(data.downcast_ref::<GraphNode>().unwrap()),
-                    None,
-                );
-            },
-            false,
-        );
-        self.connect(
-            "remove_node",
-            |this, data| {
-                this.append_command(
-                    "remove_node",
-                    json!(data.downcast_ref::<GraphNode>().unwrap()),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "rename_node",
-            |this, data| {
-                let (old_name, new_name) = data.downcast_ref::<(String, String)>().unwrap();
-                this.append_command(
-                    "rename_node",
-                    json!({
-                        "old_id": *old_name,
-                        "new_id": *new_name
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "change_node",
-            |this, data| {
-                let (node, old, new) = data
-                    .downcast_ref::<(GraphNode, Option<Map<String, Value>>, Map<String, Value>)>()
-                    .unwrap();
-                this.append_command(
-                    "change_node",
-                    json!({
-                        "id": node.id,
-                        "new": *new,
-                        "old": *old
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "add_edge",
-            |this, data| {
-                this.append_command(
-                    "add_edge",
-                    json!(data.downcast_ref::<GraphEdge>().unwrap()),
-                    None,
-                );
-            },
-            false,
-        );
-        self.connect(
-            "remove_edge",
-            |this, data| {
-                this.append_command(
-                    "remove_edge",
-                    json!(data.downcast_ref::<GraphEdge>().unwrap()),
-                    None,
-                );
-            },
-            false,
-        );
-        self.connect(
-            "change_edge",
-            |this, data| {
-                let (edge, old_meta, _) = data
-                    .downcast_ref::<(GraphEdge, Option<Map<String, Value>>, Map<String, Value>)>()
-                    .unwrap();
-                let old = if old_meta.is_none() {
-                    json!(null)
-                } else {
-                    json!(old_meta.clone().unwrap())
-                };
-                let new = if edge.metadata.is_none() {
-                    json!(null)
-                } else {
-                    json!(edge.metadata.clone().unwrap())
-                };
-                this.append_command(
-                    "change_edge",
-                    json!({
-                        "from": edge.from,
-                        "to": edge.to,
-                        "new": new,
-                        "old": old
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "add_initial",
-            |this, data| {
-                this.append_command(
-                    "add_initial",
-                    json!(data.downcast_ref::<GraphIIP>().unwrap()),
-                    None,
-                );
-            },
-            false,
-        );
-        self.connect(
-            "remove_initial",
-            |this, data| {
-                this.append_command(
-                    "remove_initial",
-                    json!(data.downcast_ref::<GraphIIP>().unwrap()),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "change_properties",
-            |this, data| {
-                let (new_props, old_props) = data
-                    .downcast_ref::<(Map<String, Value>, Map<String, Value>)>()
-                    .unwrap();
-                this.append_command(
-                    "change_properties",
-                    json!({
-                        "old": *old_props,
-                        "new": *new_props
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "add_group",
-            |this, data| {
-                this.append_command(
-                    "add_group",
-                    json!(data.downcast_ref::<GraphGroup>().unwrap()),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "rename_group",
-            |this, data| {
-                let (old_name, new_name) = data.downcast_ref::<(String, String)>().unwrap();
-                this.append_command(
-                    "rename_group",
-                    json!({
-                        "old_name": *old_name,
-                        "new_name": *new_name
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "remove_group",
-            |this, data| {
-                this.append_command(
-                    "remove_group",
-                    json!(data.downcast_ref::<GraphGroup>().unwrap()),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "change_group",
-            |this, data| {
-                let (group, old, _) = data
-                    .downcast_ref::<(GraphGroup, Option<Map<String, Value>>, Map<String, Value>)>()
-                    .unwrap();
-                let old = if old.is_none() {
-                    json!(null)
-                } else {
-                    json!(old.clone().unwrap())
-                };
-                let new = if group.metadata.is_none() {
-                    json!(null)
-                } else {
-                    json!(group.metadata.clone().unwrap())
-                };
-                this.append_command(
-                    "change_group",
-                    json!({
-                        "name": group.name,
-                        "new": new,
-                        "old": old
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "add_inport",
-            |this, data| {
-                let (name, port) = data.downcast_ref::<(String, GraphExportedPort)>().unwrap();
-                this.append_command(
-                    "add_inport",
-                    json!({
-                        "name": name,
-                        "port": *port
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "remove_inport",
-            |this, data| {
-                let (name, port) = data
-                    .downcast_ref::<(String, Option<GraphExportedPort>)>()
-                    .unwrap();
-                this.append_command(
-                    "remove_inport",
-                    json!({
-                        "name": name,
-                        "port": *port
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "rename_inport",
-            |this, data| {
-                let (old_name, new_name) = data.downcast_ref::<(String, String)>().unwrap();
-                this.append_command(
-                    "rename_inport",
-                    json!({
-                        "old_id": *old_name,
-                        "new_id": *new_name
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "change_inport",
-            |this, data| {
-                let (name, port, old, _) = data
-                    .downcast_ref::<(
-                        String,
-                        GraphExportedPort,
-                        Map<String, Value>,
-                        Map<String, Value>,
-                    )>()
-                    .unwrap();
-                this.append_command(
-                    "change_inport",
-                    json!({
-                        "name": name,
-                        "new": port.metadata,
-                        "old": *old
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "add_outport",
-            |this, data| {
-                let (name, port) = data.downcast_ref::<(String, GraphExportedPort)>().unwrap();
-                this.append_command(
-                    "add_outport",
-                    json!({
-                        "name": name,
-                        "port": *port
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "remove_outport",
-            |this, data| {
-                let (name, port) = data
-                    .downcast_ref::<(String, Option<GraphExportedPort>)>()
-                    .unwrap();
-                this.append_command(
-                    "remove_outport",
-                    json!({
-                        "name": name,
-                        "port": *port
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "rename_outport",
-            |this, data| {
-                let (old_name, new_name) = data.downcast_ref::<(String, String)>().unwrap();
-                this.append_command(
-                    "rename_outport",
-                    json!({
-                        "old_id": *old_name,
-                        "new_id": *new_name
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
-
-        self.connect(
-            "change_outport",
-            |this, data| {
-                let (name, port, old, _) = data
-                    .downcast_ref::<(
-                        String,
-                        GraphExportedPort,
-                        Map<String, Value>,
-                        Map<String, Value>,
-                    )>()
-                    .unwrap();
-                this.append_command(
-                    "change_outport",
-                    json!({
-                        "name": name,
-                        "new": port.metadata,
-                        "old": *old
-                    }),
-                    None,
-                );
-            },
-            false,
-        );
+        for name in JOURNALED_EVENTS {
+            self.connect(
+                name,
+                move |this, data| {
+                    if let Some(args) = event_args(name, data) {
+                        this.append_command(name, args, None);
+                    }
+                },
+                false,
+            );
+        }
 
         self.connect(
             "start_transaction",
@@ -599,7 +608,7 @@ impl<'a> Journal<'a> for Graph<'a> {
                         self.add_node(
                             a.get("id").unwrap().as_str().unwrap(),
                             a.get("component").unwrap().as_str().unwrap(),
-                            None,
+                            a.get("metadata").and_then(|m| m.as_object()).cloned(),
                         );
                     }
                     "remove_node" => {
@@ -613,31 +622,48 @@ impl<'a> Journal<'a> for Graph<'a> {
                             a.get("new_id").unwrap().as_str().unwrap(),
                         );
                     }
+                    "change_component" => {
+                        let a = a.as_object().unwrap();
+                        self.set_node_component(
+                            a.get("id").unwrap().as_str().unwrap(),
+                            a.get("new").unwrap().as_str().unwrap(),
+                        );
+                    }
                     "change_node" => {
                         let a = a.as_object().unwrap();
                         let id = a.get("id").unwrap().as_str().unwrap();
-                        let new = a.get("new").unwrap().as_object().unwrap();
-                        if let Some(old) = a.get("old").unwrap().as_object() {
-                            self.set_node_metadata(id, calculate_meta(old.clone(), new.clone()));
-                        } else {
-                            self.set_node_metadata(id, calculate_meta(Map::new(), new.clone()));
-                        }
+                        self.set_node_metadata(
+                            id,
+                            calculate_meta(meta_arg(a, "old"), meta_arg(a, "new")),
+                        );
                     }
                     "add_edge" => {
                         let edge = GraphEdge::deserialize(&a);
-                        if let Ok(edge) = edge {
-                            self.add_edge(
+                        if let Ok(GraphEdge {
+                            from,
+                            to,
+                            metadata,
+                            key: Some(key),
+                        }) = edge
+                        {
+                            self.add_keyed_edge(&from, &to, &key, metadata);
+                        } else if let Ok(edge) = edge {
+                            self.add_edge_index(
                                 &edge.from.node_id,
                                 &edge.from.port,
+                                edge.from.index,
                                 &edge.to.node_id,
                                 &edge.to.port,
-                                None,
+                                edge.to.index,
+                                edge.metadata,
                             );
                         }
                     }
                     "remove_edge" => {
                         let edge = GraphEdge::deserialize(&a);
-                        if let Ok(edge) = edge {
+                        if let Ok(GraphEdge { key: Some(key), .. }) = edge {
+                            self.remove_edge_by_key(&key);
+                        } else if let Ok(edge) = edge {
                             self.remove_edge(
                                 &edge.from.node_id,
                                 &edge.from.port,
@@ -649,15 +675,19 @@ impl<'a> Journal<'a> for Graph<'a> {
                     "change_edge" => {
                         let from = GraphLeaf::deserialize(a.get("from").unwrap()).unwrap();
                         let to = GraphLeaf::deserialize(a.get("to").unwrap()).unwrap();
-                        let new = a.get("new").unwrap().as_object().unwrap().clone();
-                        let old = a.get("old").unwrap().as_object().unwrap().clone();
-                        self.set_edge_metadata(
-                            &from.node_id,
-                            &from.port,
-                            &to.node_id,
-                            &to.port,
-                            calculate_meta(old, new),
-                        );
+                        let a = a.as_object().unwrap();
+                        let (new, old) = (meta_arg(a, "new"), meta_arg(a, "old"));
+                        if let Some(key) = a.get("key").and_then(|key| key.as_str()) {
+                            self.set_edge_metadata_by_key(key, calculate_meta(old, new));
+                        } else {
+                            self.set_edge_metadata(
+                                &from.node_id,
+                                &from.port,
+                                &to.node_id,
+                                &to.port,
+                                calculate_meta(old, new),
+                            );
+                        }
                     }
                     "add_initial" => {
                         let iip = GraphIIP::deserialize(&a);
@@ -692,7 +722,7 @@ impl<'a> Journal<'a> for Graph<'a> {
                     "end_transaction" => {}
                     "change_properties" => {
                         let a = a.as_object().unwrap();
-                        self.set_properties(a.get("new").unwrap().as_object().unwrap().clone());
+                        self.set_properties(calculate_meta(meta_arg(a, "old"), meta_arg(a, "new")));
                     }
                     "add_group" => {
                         if let Ok(group) = GraphGroup::deserialize(&a) {
@@ -701,7 +731,7 @@ impl<'a> Journal<'a> for Graph<'a> {
                     }
                     "rename_group" => {
                         let a = a.as_object().unwrap();
-                        self.rename_node(
+                        self.rename_group(
                             a.get("old_name").unwrap().as_str().unwrap(),
                             a.get("new_name").unwrap().as_str().unwrap(),
                         );
@@ -714,9 +744,15 @@ impl<'a> Journal<'a> for Graph<'a> {
                     "change_group" => {
                         let a = a.as_object().unwrap();
                         let name = a.get("name").unwrap().as_str().unwrap();
-                        let new = a.get("new").unwrap().as_object().unwrap();
-                        let old = a.get("old").unwrap().as_object().unwrap();
-                        self.set_group_metadata(name, calculate_meta(old.clone(), new.clone()));
+                        let (new, old) = (meta_arg(a, "new"), meta_arg(a, "old"));
+                        self.set_group_metadata(name, calculate_meta(old, new));
+                    }
+                    "change_group_nodes" => {
+                        let a = a.as_object().unwrap();
+                        let name = a.get("name").unwrap().as_str().unwrap();
+                        if let Ok(nodes) = Vec::<String>::deserialize(a.get("new").unwrap()) {
+                            self.set_group_nodes(name, nodes);
+                        }
                     }
                     "add_inport" => {
                         let a = a.as_object().unwrap();
@@ -741,9 +777,8 @@ impl<'a> Journal<'a> for Graph<'a> {
                     "change_inport" => {
                         let a = a.as_object().unwrap();
                         let name = a.get("name").unwrap().as_str().unwrap();
-                        let new = a.get("new").unwrap().as_object().unwrap();
-                        let old = a.get("old").unwrap().as_object().unwrap();
-                        self.set_inports_metadata(name, calculate_meta(old.clone(), new.clone()));
+                        let (new, old) = (meta_arg(a, "new"), meta_arg(a, "old"));
+                        self.set_inports_metadata(name, calculate_meta(old, new));
                     }
                     "add_outport" => {
                         let a = a.as_object().unwrap();
@@ -768,9 +803,8 @@ impl<'a> Journal<'a> for Graph<'a> {
                     "change_outport" => {
                         let a = a.as_object().unwrap();
                         let name = a.get("name").unwrap().as_str().unwrap();
-                        let new = a.get("new").unwrap().as_object().unwrap();
-                        let old = a.get("old").unwrap().as_object().unwrap();
-                        self.set_outports_metadata(name, calculate_meta(old.clone(), new.clone()));
+                        let (new, old) = (meta_arg(a, "new"), meta_arg(a, "old"));
+                        self.set_outports_metadata(name, calculate_meta(old, new));
                     }
                     &_ => {
                         log::error!("`Unknown journal entry: {}", cmd);
@@ -796,7 +830,7 @@ impl<'a> Journal<'a> for Graph<'a> {
                         self.add_node(
                             a.get("id").unwrap().as_str().unwrap(),
                             a.get("component").unwrap().as_str().unwrap(),
-                            None,
+                            a.get("metadata").and_then(|m| m.as_object()).cloned(),
                         );
                     }
                     "rename_node" => {
@@ -806,20 +840,25 @@ impl<'a> Journal<'a> for Graph<'a> {
                             a.get("old_id").unwrap().as_str().unwrap(),
                         );
                     }
+                    "change_component" => {
+                        let a = a.as_object().unwrap();
+                        self.set_node_component(
+                            a.get("id").unwrap().as_str().unwrap(),
+                            a.get("old").unwrap().as_str().unwrap(),
+                        );
+                    }
                     "change_node" => {
                         let a = a.as_object().unwrap();
                         let id = a.get("id").unwrap().as_str().unwrap();
-                        let new = a.get("new").unwrap().as_object().unwrap();
-
-                        if let Some(old) = a.get("old").unwrap().as_object() {
-                            self.set_node_metadata(id, calculate_meta(new.clone(), old.clone()));
-                        } else {
-                            let meta = calculate_meta(new.clone(), Map::new());
-                            self.set_node_metadata(id, meta);
-                        }
+                        self.set_node_metadata(
+                            id,
+                            calculate_meta(meta_arg(a, "new"), meta_arg(a, "old")),
+                        );
                     }
                     "add_edge" => {
-                        if let Ok(edge) = GraphEdge::deserialize(&a) {
+                        if let Ok(GraphEdge { key: Some(key), .. }) = GraphEdge::deserialize(&a) {
+                            self.remove_edge_by_key(&key);
+                        } else if let Ok(edge) = GraphEdge::deserialize(&a) {
                             self.remove_edge(
                                 &edge.from.node_id,
                                 &edge.from.port,
@@ -830,29 +869,43 @@ impl<'a> Journal<'a> for Graph<'a> {
                     }
                     "remove_edge" => {
                         let edge = GraphEdge::deserialize(&a);
-                        if let Ok(edge) = edge {
-                            self.add_edge(
+                        if let Ok(GraphEdge {
+                            from,
+                            to,
+                            metadata,
+                            key: Some(key),
+                        }) = edge
+                        {
+                            self.add_keyed_edge(&from, &to, &key, metadata);
+                        } else if let Ok(edge) = edge {
+                            self.add_edge_index(
                                 &edge.from.node_id,
                                 &edge.from.port,
+                                edge.from.index,
                                 &edge.to.node_id,
                                 &edge.to.port,
-                                None,
+                                edge.to.index,
+                                edge.metadata,
                             );
                         }
                     }
                     "change_edge" => {
                         let from = GraphLeaf::deserialize(a.get("from").unwrap()).unwrap();
                         let to = GraphLeaf::deserialize(a.get("to").unwrap()).unwrap();
-                        let new = a.get("new").unwrap().as_object().unwrap().clone();
-                        let old = a.get("old").unwrap().as_object().unwrap().clone();
-
-                        self.set_edge_metadata(
-                            &from.node_id,
-                            &from.port,
-                            &to.node_id,
-                            &to.port,
-                            calculate_meta(new, old),
-                        );
+                        let a = a.as_object().unwrap();
+                        let (new, old) = (meta_arg(a, "new"), meta_arg(a, "old"));
+
+                        if let Some(key) = a.get("key").and_then(|key| key.as_str()) {
+                            self.set_edge_metadata_by_key(key, calculate_meta(new, old));
+                        } else {
+                            self.set_edge_metadata(
+                                &from.node_id,
+                                &from.port,
+                                &to.node_id,
+                                &to.port,
+                                calculate_meta(new, old),
+                            );
+                        }
                     }
                     "add_initial" => {
                         let iip = GraphIIP::deserialize(&a).unwrap();
@@ -887,7 +940,7 @@ impl<'a> Journal<'a> for Graph<'a> {
                     "end_transaction" => {}
                     "change_properties" => {
                         let a = a.as_object().unwrap();
-                        self.set_properties(a.get("old").unwrap().as_object().unwrap().clone());
+                        self.set_properties(calculate_meta(meta_arg(a, "new"), meta_arg(a, "old")));
                     }
                     "add_group" => {
                         if let Ok(group) = GraphGroup::deserialize(&a) {
@@ -896,7 +949,7 @@ impl<'a> Journal<'a> for Graph<'a> {
                     }
                     "rename_group" => {
                         let a = a.as_object().unwrap();
-                        self.rename_node(
+                        self.rename_group(
                             a.get("new_name").unwrap().as_str().unwrap(),
                             a.get("old_name").unwrap().as_str().unwrap(),
                         );
@@ -909,9 +962,15 @@ impl<'a> Journal<'a> for Graph<'a> {
                     "change_group" => {
                         let a = a.as_object().unwrap();
                         let name = a.get("name").unwrap().as_str().unwrap();
-                        let new = a.get("new").unwrap().as_object().unwrap();
-                        let old = a.get("old").unwrap().as_object().unwrap();
-                        self.set_group_metadata(name, calculate_meta(new.clone(), old.clone()));
+                        let (new, old) = (meta_arg(a, "new"), meta_arg(a, "old"));
+                        self.set_group_metadata(name, calculate_meta(new, old));
+                    }
+                    "change_group_nodes" => {
+                        let a = a.as_object().unwrap();
+                        let name = a.get("name").unwrap().as_str().unwrap();
+                        if let Ok(nodes) = Vec::<String>::deserialize(a.get("old").unwrap()) {
+                            self.set_group_nodes(name, nodes);
+                        }
                     }
                     "add_inport" => {
                         let a = a.as_object().unwrap();
@@ -936,9 +995,8 @@ impl<'a> Journal<'a> for Graph<'a> {
                     "change_inport" => {
                         let a = a.as_object().unwrap();
                         let name = a.get("name").unwrap().as_str().unwrap();
-                        let new = a.get("new").unwrap().as_object().unwrap();
-                        let old = a.get("old").unwrap().as_object().unwrap();
-                        self.set_inports_metadata(name, calculate_meta(new.clone(), old.clone()));
+                        let (new, old) = (meta_arg(a, "new"), meta_arg(a, "old"));
+                        self.set_inports_metadata(name, calculate_meta(new, old));
                     }
                     "add_outport" => {
                         let a = a.as_object().unwrap();
@@ -960,7 +1018,7 @@ impl<'a> Journal<'a> for Graph<'a> {
                     }
                     "rename_outport" => {
                         let a = a.as_object().unwrap();
-                        self.rename_inport(
+                        self.rename_outport(
                             a.get("new_id").unwrap().as_str().unwrap(),
                             a.get("old_id").unwrap().as_str().unwrap(),
                         );
@@ -968,9 +1026,8 @@ impl<'a> Journal<'a> for Graph<'a> {
                     "change_outport" => {
                         let a = a.as_object().unwrap();
                         let name = a.get("name").unwrap().as_str().unwrap();
-                        let new = a.get("new").unwrap().as_object().unwrap();
-                        let old = a.get("old").unwrap().as_object().unwrap();
-                        self.set_inports_metadata(name, calculate_meta(new.clone(), old.clone()));
+                        let (new, old) = (meta_arg(a, "new"), meta_arg(a, "old"));
+                        self.set_outports_metadata(name, calculate_meta(new, old));
                     }
                     &_ => {
                         log::error!("`Unknown journal entry: {}", cmd);
@@ -985,6 +1042,20 @@ impl<'a> Journal<'a> for Graph<'a> {
         if rev_id == self.current_revision {
             return self;
         }
+        if rev_id < 0 || rev_id > self.last_revision as i32 {
+            error!("No revision {} in the journal", rev_id);
+            return self;
+        }
+        if self.is_frozen() {
+            error!("Cannot move frozen graph to revision {}", rev_id);
+            return self;
+        }
+        // Check every replayed change up front, so that a denial leaves the
+        // graph at its current revision instead of half-way
+        if self.permit_all(self.revision_mutations(rev_id)).is_err() {
+            error!("Cannot move graph to revision {}", rev_id);
+            return self;
+        }
 
         self.subscribed = false;
         if rev_id > self.current_revision {
@@ -997,9 +1068,11 @@ impl<'a> Journal<'a> for Graph<'a> {
                 (r, end, asc)
             };
             while if asc { r <= end } else { r >= end } {
-                if let Some(transaction) = self.fetch_transaction(rev_id as usize) {
+                if let Some(transaction) = self.fetch_transaction(r as usize) {
                     transaction.clone().iter().foreach(|entry, _| {
-                        self.execute_entry(entry.clone());
+                        self.vetted(|graph| {
+                            graph.execute_entry(entry.clone());
+                        });
                     });
                 }
                 if asc {
@@ -1021,7 +1094,9 @@ impl<'a> Journal<'a> for Graph<'a> {
                     let mut entries = _entries.clone();
                     entries.reverse();
                     entries.iter().foreach(|entry, _| {
-                        self.execute_entry_inversed(entry.clone());
+                        self.vetted(|graph| {
+                            graph.execute_entry_inversed(entry.clone());
+                        });
                     });
                 }
                 // end = rev_id + 1;
@@ -1042,7 +1117,7 @@ impl<'a> Journal<'a> for Graph<'a> {
     }
 
     fn redo(&mut self) -> &mut Self {
-        if !self.can_undo() {
+        if !self.can_redo() {
             return self;
         }
         self.move_to_revision(self.current_revision + 1);
@@ -1056,14 +1131,91 @@ impl<'a> Journal<'a> for Graph<'a> {
     fn can_undo(&self) -> bool {
         self.current_revision > 0
     }
+
+    fn replay_filtered(
+        &mut self,
+        mut predicate: impl FnMut(usize, &TransactionEntry) -> bool,
+    ) -> Vec<(usize, TransactionEntry)> {
+        let mut entries = Vec::new();
+        for rev in 0..self.count_transactions() {
+            if let Some(transaction) = self.fetch_transaction(rev) {
+                for entry in transaction.iter() {
+                    if predicate(rev, entry) {
+                        entries.push((rev, entry.clone()));
+                    }
+                }
+            }
+        }
+        entries
+    }
+
+    fn churn_report(&self) -> ChurnReport {
+        ChurnReport::from_transactions(&self.transactions)
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Journal entries touching a node, following it through renames
+    /// ```no_run
+    /// for (rev, entry) in my_graph.node_history("Read") { println!("{}: {:?}", rev, entry.cmd); }
+    /// ```
+    pub fn node_history(&mut self, id: &str) -> Vec<(usize, TransactionEntry)> {
+        let mut names = self.node_aliases(id);
+        names.push(id.to_owned());
+        self.replay_filtered(|_, entry| {
+            entry.nodes().iter().any(|node| names.contains(node))
+                || (entry.cmd.as_deref() == Some("rename_node")
+                    && entry
+                        .args
+                        .as_ref()
+                        .and_then(|a| a.get("new_id"))
+                        .and_then(|n| n.as_str())
+                        .map(|n| names.iter().any(|name| name == n))
+                        .unwrap_or(false))
+        })
+    }
+
+    /// Journal entries touching a group
+    pub fn group_history(&mut self, name: &str) -> Vec<(usize, TransactionEntry)> {
+        self.replay_filtered(|_, entry| entry.group().as_deref() == Some(name))
+    }
+
+    /// Earlier IDs of a node, found by walking its renames backwards
+    fn node_aliases(&mut self, id: &str) -> Vec<String> {
+        let renames = self.replay_filtered(|_, entry| entry.cmd.as_deref() == Some("rename_node"));
+        let mut aliases: Vec<String> = Vec::new();
+        let mut current = id.to_owned();
+        for (_, entry) in renames.iter().rev() {
+            let args = match entry.args.as_ref() {
+                Some(args) => args,
+                None => continue,
+            };
+            let old_id = args.get("old_id").and_then(|n| n.as_str());
+            let new_id = args.get("new_id").and_then(|n| n.as_str());
+            if let (Some(old_id), Some(new_id)) = (old_id, new_id) {
+                if new_id == current && !aliases.iter().any(|a| a == old_id) {
+                    aliases.push(old_id.to_owned());
+                    current = old_id.to_owned();
+                }
+            }
+        }
+        aliases
+    }
+}
+
+/// Metadata of a `change_*` journal entry, empty if there was none
+fn meta_arg(args: &Map<String, Value>, key: &str) -> Map<String, Value> {
+    args.get(key)
+        .and_then(|meta| meta.as_object())
+        .cloned()
+        .unwrap_or_default()
 }
 
 /// To set, not just update (append) metadata
 fn calculate_meta(old: Map<String, Value>, new: Map<String, Value>) -> Map<String, Value> {
     let mut set_meta = Map::new();
     old.keys().foreach(|key, _| {
-        //set_meta.insert(key.clone(), Value::Null);
-        set_meta.remove(key);
+        set_meta.insert(key.clone(), Value::Null);
     });
     new.keys().foreach(|key, _| {
         set_meta.insert(key.clone(), new.get(key).unwrap().clone());
@@ -1073,8 +1225,10 @@ fn calculate_meta(old: Map<String, Value>, new: Map<String, Value>) -> Map<Strin
 
 #[cfg(test)]
 mod tests {
+    use crate::graph::blocking::to_json;
     use crate::graph::graph::Graph;
     use crate::graph::journal::Journal;
+    use crate::graph::policy::ProtectedNodesPolicy;
     use assert_json_diff::assert_json_eq;
     use beady::scenario;
     use serde_json::json;
@@ -1254,4 +1408,134 @@ mod tests {
             }
         }
     }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_journal_history() {
+        'given_a_journaled_graph_with_edits: {
+            let mut g = Graph::new("", true);
+            g.init_journal(None);
+            g.add_node("Foo", "Bar", None)
+                .add_node("Baz", "Qux", None)
+                .add_edge("Foo", "out", "Baz", "in", None)
+                .add_group("all", vec!["Foo".to_owned()], None)
+                .rename_node("Foo", "Renamed")
+                .set_node_metadata("Renamed", json!({"x": 1}).as_object().cloned().unwrap());
+            'when_filtering_by_node: {
+                let history = g.node_history("Renamed");
+                'then_it_should_follow_the_node_through_renames: {
+                    let cmds = history
+                        .iter()
+                        .map(|(_, entry)| entry.cmd.clone().unwrap())
+                        .collect::<Vec<_>>();
+                    assert_eq!(
+                        cmds,
+                        vec!["add_node", "add_edge", "rename_node", "change_node"]
+                    );
+                    assert_eq!(history[0].0, 1);
+                }
+            }
+            'when_filtering_by_group: {
+                let history = g.group_history("all");
+                'then_it_should_only_list_group_entries: {
+                    assert_eq!(history.len(), 1);
+                    assert_eq!(history[0].1.cmd.as_deref(), Some("add_group"));
+                }
+            }
+            'when_filtering_by_revision: {
+                let history = g.replay_filtered(|rev, _| rev == 2);
+                'then_it_should_include_the_transaction_markers: {
+                    assert_eq!(history.len(), 3);
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_undo_redo() {
+        'given_a_journaled_graph_with_several_revisions: {
+            let mut g = Graph::new("", true);
+            g.init_journal(None);
+            let snapshot = |g: &Graph| serde_json::to_value(to_json(g)).unwrap();
+            let empty = snapshot(&g);
+            g.start_transaction("build", None);
+            g.add_node("A", "Split", json!({"x": 1}).as_object().cloned())
+                .add_node("B", "Output", None)
+                .add_edge_index(
+                    "A",
+                    "out",
+                    Some(1),
+                    "B",
+                    "in",
+                    None,
+                    json!({"route": 2}).as_object().cloned(),
+                )
+                .add_initial(json!("data"), "A", "in", None)
+                .add_inport("IN", "A", "in", None)
+                .add_outport("OUT", "B", "out", None)
+                .add_group("group", vec!["A".to_owned()], None)
+                .set_properties(json!({"name": "test"}).as_object().cloned().unwrap());
+            g.end_transaction("build", None);
+            let built = snapshot(&g);
+            g.set_node_metadata("A", json!({"y": 2}).as_object().cloned().unwrap());
+            g.rename_group("group", "renamed");
+            g.rename_outport("OUT", "RESULT");
+            g.remove_node("B");
+            let last = snapshot(&g);
+            assert_eq!(g.current_revision, 5);
+            'when_undoing_every_revision: {
+                while g.can_undo() {
+                    g.undo();
+                }
+                'then_the_graph_should_be_empty_again: {
+                    assert_eq!(snapshot(&g), empty);
+                    assert_eq!(g.current_revision, 0);
+                }
+            }
+            'when_replaying_from_the_start: {
+                g.move_to_revision(0);
+                g.move_to_revision(1);
+                'then_every_change_of_the_revision_should_be_restored: {
+                    assert_eq!(snapshot(&g), built);
+                    assert_eq!(g.edges[0].from.index, Some(1));
+                }
+                while g.can_redo() {
+                    g.redo();
+                }
+                'then_redoing_the_rest_should_restore_the_last_revision: {
+                    assert_eq!(snapshot(&g), last);
+                    assert_eq!(g.current_revision, 5);
+                }
+            }
+            'when_undoing_metadata_changes: {
+                g.move_to_revision(1);
+                'then_added_keys_should_be_removed: {
+                    let meta = g.get_node("A").unwrap().metadata.clone().unwrap();
+                    assert_eq!(json!(meta), json!({"x": 1}));
+                }
+            }
+            'when_editing_after_an_undo: {
+                g.move_to_revision(1);
+                g.add_node("C", "Output", None);
+                'then_the_undone_revisions_should_be_dropped: {
+                    assert!(!g.can_redo());
+                    assert_eq!(g.current_revision, 2);
+                    g.undo();
+                    assert_eq!(snapshot(&g), built);
+                }
+            }
+            'when_undoing_changes_to_a_protected_node: {
+                g.set_mutation_policy(ProtectedNodesPolicy {
+                    nodes: vec!["B".to_owned()],
+                });
+                g.undo();
+                g.move_to_revision(0);
+                'then_the_graph_should_stay_at_its_revision: {
+                    assert_eq!(g.current_revision, 5);
+                    assert_eq!(snapshot(&g), last);
+                }
+            }
+        }
+    }
 }
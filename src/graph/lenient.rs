@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::types::{GraphEdgeJson, GraphExportedPort, GraphGroup, GraphJson, GraphNodeJson};
+
+/// Part of a graph file that was skipped or repaired while loading
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadIssue {
+    /// Location in the file, e.g. `connections[3]` or `processes.Read`
+    pub path: String,
+    pub message: String,
+}
+
+impl LoadIssue {
+    fn new(path: String, message: impl Into<String>) -> Self {
+        Self {
+            path,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for LoadIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+fn entry<T: DeserializeOwned>(
+    value: &Value,
+    path: String,
+    issues: &mut Vec<LoadIssue>,
+) -> Option<T> {
+    match serde_json::from_value::<T>(value.clone()) {
+        Ok(entry) => Some(entry),
+        Err(err) => {
+            issues.push(LoadIssue::new(path, err.to_string()));
+            None
+        }
+    }
+}
+
+fn section<'v>(
+    json: &'v Map<String, Value>,
+    key: &str,
+    issues: &mut Vec<LoadIssue>,
+) -> Option<&'v Value> {
+    let value = json.get(key)?;
+    match value {
+        Value::Null => None,
+        Value::Object(_) if key != "connections" && key != "groups" => Some(value),
+        Value::Array(_) if key == "connections" || key == "groups" => Some(value),
+        _ => {
+            issues.push(LoadIssue::new(
+                key.to_owned(),
+                "unexpected type, section skipped",
+            ));
+            None
+        }
+    }
+}
+
+fn ports(
+    json: &Map<String, Value>,
+    key: &str,
+    processes: &HashMap<String, GraphNodeJson>,
+    issues: &mut Vec<LoadIssue>,
+) -> HashMap<String, GraphExportedPort> {
+    let mut ports = HashMap::new();
+    if let Some(Value::Object(section)) = section(json, key, issues) {
+        for (name, value) in section.iter() {
+            let path = format!("{}.{}", key, name);
+            if let Some(port) = entry::<GraphExportedPort>(value, path.clone(), issues) {
+                if processes.contains_key(&port.process) {
+                    ports.insert(name.clone(), port);
+                } else {
+                    issues.push(LoadIssue::new(
+                        path,
+                        format!("unknown process {}", port.process),
+                    ));
+                }
+            }
+        }
+    }
+    ports
+}
+
+/// Check a graph file entry by entry, keeping everything that can be loaded
+pub fn sanitize_graph_json(json: &Map<String, Value>) -> (GraphJson, Vec<LoadIssue>) {
+    let mut issues = Vec::new();
+
+    let case_sensitive = match json.get("caseSensitive") {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(flag)) => *flag,
+        Some(_) => {
+            issues.push(LoadIssue::new(
+                "caseSensitive".to_owned(),
+                "expected a boolean, assuming false",
+            ));
+            false
+        }
+    };
+    let properties = match section(json, "properties", &mut issues) {
+        Some(Value::Object(properties)) => properties.clone(),
+        _ => Map::new(),
+    };
+
+    let mut processes = HashMap::new();
+    if let Some(Value::Object(section)) = section(json, "processes", &mut issues) {
+        for (id, value) in section.iter() {
+            if let Some(process) =
+                entry::<GraphNodeJson>(value, format!("processes.{}", id), &mut issues)
+            {
+                processes.insert(id.clone(), process);
+            }
+        }
+    }
+
+    let mut connections = Vec::new();
+    if let Some(Value::Array(section)) = section(json, "connections", &mut issues) {
+        for (i, value) in section.iter().enumerate() {
+            let path = format!("connections[{}]", i);
+            let Some(conn) = entry::<GraphEdgeJson>(value, path.clone(), &mut issues) else {
+                continue;
+            };
+            let Some(tgt) = conn.tgt.as_ref() else {
+                issues.push(LoadIssue::new(path, "connection has no target"));
+                continue;
+            };
+            if conn.data.is_none() && conn.src.is_none() {
+                issues.push(LoadIssue::new(
+                    path,
+                    "connection has neither source nor data",
+                ));
+                continue;
+            }
+            let unknown = conn
+                .src
+                .iter()
+                .chain(Some(tgt))
+                .find(|leaf| !processes.contains_key(&leaf.process));
+            if let Some(leaf) = unknown {
+                issues.push(LoadIssue::new(
+                    path,
+                    format!("unknown process {}", leaf.process),
+                ));
+                continue;
+            }
+            connections.push(conn);
+        }
+    }
+
+    let inports = ports(json, "inports", &processes, &mut issues);
+    let outports = ports(json, "outports", &processes, &mut issues);
+
+    let mut groups = Vec::new();
+    if let Some(Value::Array(section)) = section(json, "groups", &mut issues) {
+        for (i, value) in section.iter().enumerate() {
+            let path = format!("groups[{}]", i);
+            if let Some(mut group) = entry::<GraphGroup>(value, path.clone(), &mut issues) {
+                let before = group.nodes.len();
+                group.nodes.retain(|node| processes.contains_key(node));
+                if group.nodes.len() != before {
+                    issues.push(LoadIssue::new(path, "unknown nodes removed from group"));
+                }
+                groups.push(group);
+            }
+        }
+    }
+
+    (
+        GraphJson {
+            case_sensitive,
+            properties,
+            inports,
+            outports,
+            groups,
+            processes,
+            connections,
+        },
+        issues,
+    )
+}
+
+/// Loading damaged graph files
+///
+/// Unlike `from_json_string`, which refuses a file as soon as any part
+/// of it doesn't match the format, these skip the broken processes,
+/// connections, ports and groups and report each of them, so that an
+/// editor can open the rest of the graph for repair:
+/// ```no_run
+/// let (my_graph, issues) = Graph::load_lenient("flow.json").await?;
+/// for issue in issues.iter() {
+///     println!("{}", issue);
+/// }
+/// ```
+/// Only files that aren't JSON objects at all fail to load.
+impl<'a> Graph<'a> {
+    pub async fn from_json_string_lenient(
+        source: &str,
+        metadata: Option<Map<String, Value>>,
+    ) -> Result<(Graph<'a>, Vec<LoadIssue>), io::Error> {
+        let json = match serde_json::from_str::<Value>(source) {
+            Ok(Value::Object(json)) => json,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Graph JSON must be an object",
+                ))
+            }
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+        };
+        let (json, issues) = sanitize_graph_json(&json);
+        Ok((Graph::from_json(json, metadata).await, issues))
+    }
+
+    pub async fn load_lenient(path: &str) -> Result<(Graph<'a>, Vec<LoadIssue>), io::Error> {
+        let source = fs::read_to_string(path)?;
+        Graph::from_json_string_lenient(&source, None).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use beady::scenario;
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_lenient_load() {
+        'given_a_partly_corrupted_graph_file: {
+            let source = json!({
+                "caseSensitive": "yes",
+                "properties": {"name": "damaged"},
+                "processes": {
+                    "Read": {"component": "ReadFile"},
+                    "Log": {"component": "Output", "metadata": {"x": 1}},
+                    "Broken": {"metadata": {}}
+                },
+                "connections": [
+                    {"src": {"process": "Read", "port": "out"}, "tgt": {"process": "Log", "port": "in"}},
+                    {"src": {"process": "Read", "port": "out"}, "tgt": {"process": "Broken", "port": "in"}},
+                    {"data": "a.txt", "tgt": {"process": "Read", "port": "in"}},
+                    {"src": {"process": "Read"}, "tgt": {"process": "Log", "port": "in"}},
+                    {"data": 1}
+                ],
+                "inports": {"file": {"process": "Read", "port": "in"}, "gone": {"process": "Nope", "port": "in"}},
+                "outports": 5,
+                "groups": [{"name": "all", "nodes": ["Read", "Log", "Broken"]}]
+            })
+            .to_string();
+            'when_loading_it_leniently: {
+                let (g, issues) = block_on(Graph::from_json_string_lenient(&source, None)).unwrap();
+                'then_it_should_keep_the_valid_parts: {
+                    assert_eq!(g.name, "damaged");
+                    assert_eq!(g.nodes.len(), 2);
+                    assert_eq!(g.edges.len(), 1);
+                    assert_eq!(g.initializers.len(), 1);
+                    assert_eq!(g.inports.len(), 1);
+                    assert_eq!(g.groups[0].nodes, vec!["Read", "Log"]);
+                }
+                'then_it_should_report_each_problem: {
+                    let paths: Vec<&str> = issues.iter().map(|issue| issue.path.as_str()).collect();
+                    assert_eq!(
+                        paths,
+                        vec![
+                            "caseSensitive",
+                            "processes.Broken",
+                            "connections[1]",
+                            "connections[3]",
+                            "connections[4]",
+                            "inports.gone",
+                            "outports",
+                            "groups[0]"
+                        ]
+                    );
+                    assert_eq!(
+                        issues[2].to_string(),
+                        "connections[1]: unknown process Broken"
+                    );
+                }
+                'then_a_strict_load_should_refuse_it: {
+                    assert!(block_on(Graph::from_json_string(&source, None)).is_err());
+                }
+            }
+            'when_the_file_is_not_an_object: {
+                'then_it_should_fail: {
+                    assert!(block_on(Graph::from_json_string_lenient("[1, 2]", None)).is_err());
+                    assert!(block_on(Graph::from_json_string_lenient("{", None)).is_err());
+                }
+            }
+        }
+    }
+}
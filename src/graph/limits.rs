@@ -0,0 +1,444 @@
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::iip::BASE_DIR;
+
+/// Size limits for graphs coming from untrusted sources
+///
+/// Used to check whole graphs with `Graph::sanitize`. To cap a graph
+/// while it's being built, see `GraphQuota`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GraphLimits {
+    pub max_nodes: usize,
+    pub max_edges: usize,
+    /// Serialized size of a single metadata object
+    pub max_metadata_bytes: usize,
+    /// Nesting depth of a metadata object, counting the object itself
+    pub max_metadata_depth: usize,
+    /// Length of identifiers and of string values in metadata
+    pub max_string_length: usize,
+    /// Keep `{"$file": ...}` IIPs, which make `resolve_iip_files` read
+    /// files from the host, and the `baseDir` property naming their
+    /// directory
+    pub allow_file_references: bool,
+}
+
+impl Default for GraphLimits {
+    fn default() -> Self {
+        Self {
+            max_nodes: 10_000,
+            max_edges: 50_000,
+            max_metadata_bytes: 64 * 1024,
+            max_metadata_depth: 8,
+            max_string_length: 4096,
+            allow_file_references: false,
+        }
+    }
+}
+
+/// Node and edge counts a graph may not grow beyond while it's edited
+///
+/// See `Graph::set_quota`. Metadata and identifiers are only limited
+/// when a whole graph is checked with `Graph::sanitize`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GraphQuota {
+    pub max_nodes: usize,
+    pub max_edges: usize,
+}
+
+impl Default for GraphQuota {
+    fn default() -> Self {
+        GraphLimits::default().into()
+    }
+}
+
+impl From<GraphLimits> for GraphQuota {
+    fn from(limits: GraphLimits) -> Self {
+        Self {
+            max_nodes: limits.max_nodes,
+            max_edges: limits.max_edges,
+        }
+    }
+}
+
+/// Metadata keys that pollute object prototypes when a graph is opened
+/// in a JavaScript editor
+const DANGEROUS_KEYS: [&str; 3] = ["__proto__", "constructor", "prototype"];
+
+fn depth(value: &Value) -> usize {
+    match value {
+        Value::Object(map) => 1 + map.values().map(depth).max().unwrap_or(0),
+        Value::Array(items) => 1 + items.iter().map(depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn clean_value(value: &mut Value, limits: &GraphLimits, path: &str, changes: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => clean_map(map, limits, path, changes),
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                clean_value(item, limits, &format!("{}[{}]", path, i), changes);
+            }
+        }
+        Value::String(text) if text.chars().count() > limits.max_string_length => {
+            *text = text.chars().take(limits.max_string_length).collect();
+            changes.push(format!("{}: string truncated", path));
+        }
+        _ => {}
+    }
+}
+
+fn clean_map(
+    map: &mut Map<String, Value>,
+    limits: &GraphLimits,
+    path: &str,
+    changes: &mut Vec<String>,
+) {
+    for key in DANGEROUS_KEYS {
+        if map.remove(key).is_some() {
+            changes.push(format!("{}.{}: key removed", path, key));
+        }
+    }
+    for (key, value) in map.iter_mut() {
+        clean_value(value, limits, &format!("{}.{}", path, key), changes);
+    }
+}
+
+/// Clean a metadata object, dropping it entirely when it's too deep or too large
+fn sanitize_metadata(
+    metadata: &mut Option<Map<String, Value>>,
+    limits: &GraphLimits,
+    path: &str,
+    changes: &mut Vec<String>,
+) {
+    let Some(map) = metadata.as_mut() else {
+        return;
+    };
+    let nested = 1 + map.values().map(depth).max().unwrap_or(0);
+    if nested > limits.max_metadata_depth {
+        *metadata = None;
+        changes.push(format!("{}: metadata nested too deeply, removed", path));
+        return;
+    }
+    clean_map(map, limits, path, changes);
+    let size = serde_json::to_string(map)
+        .map(|s| s.len())
+        .unwrap_or(usize::MAX);
+    if size > limits.max_metadata_bytes {
+        *metadata = None;
+        changes.push(format!("{}: metadata too large, removed", path));
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Enforce limits on a graph received from an untrusted source
+    ///
+    /// Graphs with too many nodes or edges, or with identifiers longer
+    /// than `max_string_length`, are refused with an error and left as
+    /// they are. Otherwise metadata is cleaned in place: prototype
+    /// polluting keys are removed, long strings truncated, and metadata
+    /// that is nested too deeply or too large is dropped. Unless
+    /// `allow_file_references` is set, `{"$file": ...}` IIPs and the
+    /// `baseDir` property are removed as well. Returns a
+    /// description of every change. Like loading, this bypasses events
+    /// and the journal, so run it before attaching either.
+    /// ```no_run
+    /// let mut upload = Graph::from_json_string(&body, None).await?;
+    /// let changes = upload.sanitize(GraphLimits::default())?;
+    /// ```
+    pub fn sanitize(&mut self, limits: GraphLimits) -> Result<Vec<String>, String> {
+        if self.nodes.len() > limits.max_nodes {
+            return Err(format!(
+                "Graph has {} nodes, the limit is {}",
+                self.nodes.len(),
+                limits.max_nodes
+            ));
+        }
+        if self.edges.len() > limits.max_edges {
+            return Err(format!(
+                "Graph has {} edges, the limit is {}",
+                self.edges.len(),
+                limits.max_edges
+            ));
+        }
+        let leaves = self
+            .edges
+            .iter()
+            .flat_map(|edge| [&edge.from, &edge.to])
+            .chain(self.initializers.iter().filter_map(|iip| iip.to.as_ref()));
+        let identifiers = std::iter::once(self.name.as_str())
+            .chain(
+                self.nodes
+                    .iter()
+                    .flat_map(|node| [node.id.as_str(), node.component.as_str()]),
+            )
+            .chain(leaves.map(|leaf| leaf.port.as_str()))
+            .chain(self.groups.iter().map(|group| group.name.as_str()))
+            .chain(
+                self.inports
+                    .keys()
+                    .chain(self.outports.keys())
+                    .map(|name| name.as_str()),
+            );
+        for identifier in identifiers {
+            if identifier.chars().count() > limits.max_string_length {
+                return Err(format!(
+                    "Identifier {}... is longer than {} characters",
+                    identifier.chars().take(32).collect::<String>(),
+                    limits.max_string_length
+                ));
+            }
+        }
+
+        let mut changes = Vec::new();
+        if !limits.allow_file_references {
+            if self.properties.remove(BASE_DIR).is_some() {
+                changes.push(format!("properties.{}: file reference removed", BASE_DIR));
+            }
+            let mut i = 0;
+            self.initializers.retain(|iip| {
+                let keep = iip.file_reference().is_none();
+                if !keep {
+                    changes.push(format!("initializers[{}]: file reference removed", i));
+                }
+                i += 1;
+                keep
+            });
+        }
+        let mut properties = Some(std::mem::take(&mut self.properties));
+        sanitize_metadata(&mut properties, &limits, "properties", &mut changes);
+        self.properties = properties.unwrap_or_default();
+        self.invalidate_tags();
+        for node in self.nodes.iter_mut() {
+            let path = format!("processes.{}", node.id);
+            sanitize_metadata(&mut node.metadata, &limits, &path, &mut changes);
+        }
+        for (i, edge) in self.edges.iter_mut().enumerate() {
+            let path = format!("connections[{}]", i);
+            sanitize_metadata(&mut edge.metadata, &limits, &path, &mut changes);
+        }
+        for (i, iip) in self.initializers.iter_mut().enumerate() {
+            let path = format!("initializers[{}]", i);
+            sanitize_metadata(&mut iip.metadata, &limits, &path, &mut changes);
+            if let Some(from) = iip.from.as_mut() {
+                if depth(&from.data) > limits.max_metadata_depth {
+                    from.data = Value::Null;
+                    changes.push(format!("{}.data: nested too deeply, removed", path));
+                } else {
+                    clean_value(
+                        &mut from.data,
+                        &limits,
+                        &format!("{}.data", path),
+                        &mut changes,
+                    );
+                }
+            }
+        }
+        for group in self.groups.iter_mut() {
+            let path = format!("groups.{}", group.name);
+            sanitize_metadata(&mut group.metadata, &limits, &path, &mut changes);
+        }
+        for (kind, ports) in [
+            ("inports", &mut self.inports),
+            ("outports", &mut self.outports),
+        ] {
+            let mut names: Vec<String> = ports.keys().cloned().collect();
+            names.sort();
+            for name in names {
+                let port = ports.get_mut(&name).unwrap();
+                let path = format!("{}.{}", kind, name);
+                sanitize_metadata(&mut port.metadata, &limits, &path, &mut changes);
+            }
+        }
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::limits::{GraphLimits, GraphQuota};
+    use crate::graph::policy::{MutationKind, MutationTarget};
+    use crate::graph::types::{GraphError, GraphOptions};
+    use crate::internal::event_manager::EventManager;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_sanitize() {
+        'given_an_uploaded_graph: {
+            let mut g = Graph::new("upload", true);
+            g.add_node(
+                "Read",
+                "ReadFile",
+                json!({"x": 1, "__proto__": {"admin": true}, "label": "a".repeat(20)})
+                    .as_object()
+                    .cloned(),
+            )
+            .add_node(
+                "Log",
+                "Output",
+                json!({"a": {"b": {"c": {"d": 1}}}}).as_object().cloned(),
+            )
+            .add_edge(
+                "Read",
+                "out",
+                "Log",
+                "in",
+                json!({"blob": (1..=20).collect::<Vec<u32>>()})
+                    .as_object()
+                    .cloned(),
+            )
+            .add_initial(json!({"path": "b".repeat(20)}), "Read", "in", None);
+            let files = |g: &mut Graph| {
+                g.set_properties(json!({"baseDir": "/etc"}).as_object().cloned().unwrap());
+                g.add_initial(json!({"$file": "passwd"}), "Log", "in", None);
+            };
+            let limits = GraphLimits {
+                max_metadata_bytes: 32,
+                max_metadata_depth: 3,
+                max_string_length: 10,
+                ..GraphLimits::default()
+            };
+            'when_sanitizing_it: {
+                let changes = g.sanitize(limits).unwrap();
+                'then_it_should_clean_the_metadata: {
+                    let read = g.get_node("Read").unwrap().metadata.clone().unwrap();
+                    assert!(read.get("__proto__").is_none());
+                    assert_eq!(read.get("label"), Some(&json!("aaaaaaaaaa")));
+                    assert!(g.get_node("Log").unwrap().metadata.is_none());
+                    assert!(g.edges[0].metadata.is_none());
+                    assert_eq!(
+                        g.initializers[0].from.as_ref().unwrap().data,
+                        json!({"path": "bbbbbbbbbb"})
+                    );
+                    assert_eq!(
+                        changes,
+                        vec![
+                            "processes.Read.__proto__: key removed",
+                            "processes.Read.label: string truncated",
+                            "processes.Log: metadata nested too deeply, removed",
+                            "connections[0]: metadata too large, removed",
+                            "initializers[0].data.path: string truncated",
+                        ]
+                    );
+                }
+            }
+            'when_it_refers_to_files: {
+                files(&mut g);
+                'then_the_references_should_be_removed: {
+                    let changes = g.sanitize(GraphLimits::default()).unwrap();
+                    assert!(g.properties.get("baseDir").is_none());
+                    assert_eq!(g.initializers.len(), 1);
+                    assert_eq!(
+                        changes[..2],
+                        [
+                            "properties.baseDir: file reference removed",
+                            "initializers[1]: file reference removed",
+                        ]
+                    );
+                }
+                'then_they_should_be_kept_when_allowed: {
+                    let allowed = GraphLimits {
+                        allow_file_references: true,
+                        ..GraphLimits::default()
+                    };
+                    let changes = g.sanitize(allowed).unwrap();
+                    assert!(!changes.iter().any(|c| c.contains("file reference")));
+                    assert_eq!(g.properties["baseDir"], json!("/etc"));
+                    assert_eq!(g.initializers.len(), 2);
+                }
+            }
+            'when_it_exceeds_hard_limits: {
+                'then_it_should_be_refused: {
+                    let too_many = GraphLimits {
+                        max_nodes: 1,
+                        ..GraphLimits::default()
+                    };
+                    assert_eq!(
+                        g.sanitize(too_many).unwrap_err(),
+                        "Graph has 2 nodes, the limit is 1"
+                    );
+                    let short = GraphLimits {
+                        max_string_length: 4,
+                        ..GraphLimits::default()
+                    };
+                    assert!(g.sanitize(short).is_err());
+                    assert_eq!(g.nodes.len(), 2);
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_quota() {
+        'given_a_graph_with_a_quota: {
+            let mut g = Graph::with_options(
+                "",
+                GraphOptions {
+                    quota: Some(GraphQuota {
+                        max_nodes: 2,
+                        max_edges: 1,
+                    }),
+                    ..GraphOptions::default()
+                },
+            );
+            let denied = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let seen = denied.clone();
+            g.connect(
+                "mutation_denied",
+                move |_, data| {
+                    seen.lock()
+                        .unwrap()
+                        .push(data.downcast_ref::<GraphError>().unwrap().clone());
+                },
+                false,
+            );
+            'when_growing_it_past_the_limits: {
+                g.add_node("A", "a", None)
+                    .add_node("B", "b", None)
+                    .add_node("C", "c", None)
+                    .add_edge("A", "out", "B", "in", None)
+                    .add_edge("B", "out", "A", "in", None);
+                'then_further_additions_should_be_denied: {
+                    assert_eq!(g.nodes.len(), 2);
+                    assert_eq!(g.edges.len(), 1);
+                    assert_eq!(
+                        denied.lock().unwrap().clone(),
+                        vec![
+                            GraphError::QuotaExceeded {
+                                resource: "nodes".to_owned(),
+                                limit: 2
+                            },
+                            GraphError::QuotaExceeded {
+                                resource: "edges".to_owned(),
+                                limit: 1
+                            },
+                        ]
+                    );
+                    assert!(g
+                        .check_mutation(
+                            MutationKind::AddNode,
+                            &MutationTarget::Node("C".to_owned())
+                        )
+                        .is_err());
+                }
+                'and_then_after_removing_a_node: {
+                    g.remove_node("B").add_node("C", "c", None);
+                    'then_there_should_be_room_again: {
+                        assert!(g.get_node("C").is_some());
+                    }
+                }
+                'and_then_after_clearing_the_quota: {
+                    g.clear_quota().add_node("C", "c", None);
+                    'then_it_should_grow_freely: {
+                        assert_eq!(g.nodes.len(), 3);
+                    }
+                }
+            }
+        }
+    }
+}
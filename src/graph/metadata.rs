@@ -0,0 +1,247 @@
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+
+use crate::internal::event_manager::EventManager;
+
+use super::graph::Graph;
+use super::types::GraphError;
+
+/// Outcome of checking a metadata value
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataCheck {
+    Valid,
+    /// Store the given value instead
+    Coerce(Value),
+    /// Refuse the whole metadata change, for the given reason
+    Reject(String),
+}
+
+/// Checks the values written to one metadata key
+///
+/// Validators run in every `set_*_metadata` method, for nodes, edges,
+/// groups and exported ports alike. Null values, which remove keys, are
+/// not checked. When a value is rejected the metadata is left untouched,
+/// and a `metadata_rejected` event is emitted with a
+/// `GraphError::InvalidMetadata`.
+/// ```no_run
+/// my_graph.set_metadata_validator("x", Number);
+/// my_graph.set_metadata_validator("route", IntegerRange { min: 0, max: 9 });
+/// ```
+pub trait MetadataValidator {
+    fn check(&self, value: &Value) -> MetadataCheck;
+}
+
+impl<F> MetadataValidator for F
+where
+    F: Fn(&Value) -> MetadataCheck,
+{
+    fn check(&self, value: &Value) -> MetadataCheck {
+        self(value)
+    }
+}
+
+/// Accepts numbers, coercing numeric strings
+pub struct Number;
+
+impl MetadataValidator for Number {
+    fn check(&self, value: &Value) -> MetadataCheck {
+        match value {
+            Value::Number(_) => MetadataCheck::Valid,
+            Value::String(s) => match s
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+            {
+                Some(n) => MetadataCheck::Coerce(Value::Number(n)),
+                None => MetadataCheck::Reject(format!("{} is not a number", value)),
+            },
+            _ => MetadataCheck::Reject(format!("{} is not a number", value)),
+        }
+    }
+}
+
+/// Accepts integers within bounds, coercing whole floats and numeric strings
+pub struct IntegerRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl MetadataValidator for IntegerRange {
+    fn check(&self, value: &Value) -> MetadataCheck {
+        let int = match value {
+            Value::Number(n) => n
+                .as_i64()
+                .or_else(|| n.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i64)),
+            Value::String(s) => s.trim().parse::<i64>().ok(),
+            _ => None,
+        };
+        match int {
+            None => MetadataCheck::Reject(format!("{} is not an integer", value)),
+            Some(i) if i < self.min || i > self.max => {
+                MetadataCheck::Reject(format!("{} is outside {}..={}", i, self.min, self.max))
+            }
+            Some(i) if value.as_i64() == Some(i) => MetadataCheck::Valid,
+            Some(i) => MetadataCheck::Coerce(Value::from(i)),
+        }
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Check every value written to a metadata key with the given validator
+    pub fn set_metadata_validator(
+        &mut self,
+        key: &str,
+        validator: impl MetadataValidator + 'static,
+    ) -> &mut Self {
+        self.metadata_validators
+            .insert(key.to_owned(), Arc::new(validator));
+        self
+    }
+
+    pub fn remove_metadata_validator(&mut self, key: &str) -> &mut Self {
+        self.metadata_validators.remove(key);
+        self
+    }
+
+    /// Run the registered validators over a metadata change, returning
+    /// the values to store, or `None` when a value was rejected
+    pub(crate) fn validate_metadata(
+        &mut self,
+        mut metadata: Map<String, Value>,
+    ) -> Option<Map<String, Value>> {
+        if self.metadata_validators.is_empty() {
+            return Some(metadata);
+        }
+        for (key, value) in metadata.iter_mut() {
+            if value.is_null() {
+                continue;
+            }
+            let validator = match self.metadata_validators.get(key) {
+                Some(validator) => validator.clone(),
+                None => continue,
+            };
+            match validator.check(value) {
+                MetadataCheck::Valid => {}
+                MetadataCheck::Coerce(coerced) => *value = coerced,
+                MetadataCheck::Reject(reason) => {
+                    let err = GraphError::InvalidMetadata {
+                        key: key.clone(),
+                        reason,
+                    };
+                    log::error!("{}", err);
+                    self.emit("metadata_rejected", &err);
+                    return None;
+                }
+            }
+        }
+        Some(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::graph::graph::Graph;
+    use crate::graph::metadata::{IntegerRange, MetadataCheck, Number};
+    use crate::graph::types::GraphError;
+    use crate::internal::event_manager::EventManager;
+    use beady::scenario;
+    use serde_json::{json, Value};
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_metadata_validators() {
+        'given_a_graph_with_metadata_validators: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Write", "WriteFile", None)
+                .add_edge("Read", "out", "Write", "in", None);
+            g.set_metadata_validator("x", Number)
+                .set_metadata_validator("route", IntegerRange { min: 0, max: 9 })
+                .set_metadata_validator("label", |value: &Value| match value.as_str() {
+                    Some("") => MetadataCheck::Reject("empty label".to_owned()),
+                    _ => MetadataCheck::Valid,
+                });
+            let rejected = Arc::new(Mutex::new(Vec::new()));
+            let seen = rejected.clone();
+            g.connect(
+                "metadata_rejected",
+                move |_, data| {
+                    if let Some(err) = data.downcast_ref::<GraphError>() {
+                        seen.lock().unwrap().push(err.clone());
+                    }
+                },
+                false,
+            );
+            'when_setting_valid_values: {
+                g.set_node_metadata(
+                    "Read",
+                    json!({"x": 10, "label": "in"})
+                        .as_object()
+                        .cloned()
+                        .unwrap(),
+                );
+                'then_they_should_be_stored: {
+                    let meta = g.get_node("Read").unwrap().metadata.clone().unwrap();
+                    assert_eq!(meta.get("x"), Some(&json!(10)));
+                    assert!(rejected.lock().unwrap().is_empty());
+                }
+            }
+            'when_setting_coercible_values: {
+                g.set_node_metadata("Read", json!({"x": "12.5"}).as_object().cloned().unwrap())
+                    .set_edge_metadata(
+                        "Read",
+                        "out",
+                        "Write",
+                        "in",
+                        json!({"route": 3.0}).as_object().cloned().unwrap(),
+                    );
+                'then_they_should_be_coerced: {
+                    let meta = g.get_node("Read").unwrap().metadata.clone().unwrap();
+                    assert_eq!(meta.get("x"), Some(&json!(12.5)));
+                    let meta = g.edges[0].metadata.clone().unwrap();
+                    assert_eq!(meta.get("route"), Some(&json!(3)));
+                }
+            }
+            'when_setting_an_invalid_value: {
+                g.set_node_metadata("Read", json!({"x": 1}).as_object().cloned().unwrap())
+                    .set_node_metadata(
+                        "Read",
+                        json!({"x": 2, "label": ""}).as_object().cloned().unwrap(),
+                    )
+                    .set_edge_metadata(
+                        "Read",
+                        "out",
+                        "Write",
+                        "in",
+                        json!({"route": 12}).as_object().cloned().unwrap(),
+                    );
+                'then_the_whole_change_should_be_rejected: {
+                    let meta = g.get_node("Read").unwrap().metadata.clone().unwrap();
+                    assert_eq!(meta.get("x"), Some(&json!(1)));
+                    assert!(g.edges[0]
+                        .metadata
+                        .as_ref()
+                        .and_then(|m| m.get("route"))
+                        .is_none());
+                    assert_eq!(
+                        rejected.lock().unwrap().clone(),
+                        vec![
+                            GraphError::InvalidMetadata {
+                                key: "label".to_owned(),
+                                reason: "empty label".to_owned()
+                            },
+                            GraphError::InvalidMetadata {
+                                key: "route".to_owned(),
+                                reason: "12 is outside 0..=9".to_owned()
+                            },
+                        ]
+                    );
+                }
+            }
+        }
+    }
+}
@@ -3,4 +3,44 @@
 pub mod graph;
 pub mod types;
 pub mod graph_test;
-pub mod journal;
\ No newline at end of file
+pub mod journal;
+pub mod flags;
+pub mod transform;
+pub mod analysis;
+pub mod registry;
+pub mod audit;
+pub mod policy;
+pub mod groups;
+pub mod conflict;
+pub mod trace;
+pub mod docs;
+pub mod refactor;
+pub mod select;
+pub mod churn;
+pub mod iip;
+pub mod export;
+pub mod autosave;
+pub mod view;
+pub mod endpoint;
+pub mod iter;
+pub mod display;
+pub mod lenient;
+pub mod limits;
+pub mod rpc;
+pub mod scaffold;
+pub mod embedded;
+pub mod anonymize;
+pub mod tags;
+pub mod metadata;
+pub mod recorder;
+pub mod multi;
+pub mod references;
+pub mod schema;
+pub mod blocking;
+pub mod diff;
+pub mod templates;
+pub mod generate;
+pub mod error_ports;
+pub mod fbp;
+#[cfg(feature = "profiling")]
+pub mod profile;
\ No newline at end of file
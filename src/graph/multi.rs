@@ -0,0 +1,210 @@
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::journal::Journal;
+
+/// Apply an edit spanning several graphs as one atomic change
+///
+/// Opens a transaction named `id` on every graph, then runs `edit`. If it
+/// succeeds, the transactions are committed. If it fails, every graph is
+/// moved back to its revision from before the edit and the failed
+/// revisions are dropped from the journals, so they can't be redone.
+/// Audit sinks are only told about the edit if it is kept: when it fails
+/// and any graph's policy refuses the revert, no graph is rolled back.
+/// All graphs must be journaled and outside of any transaction.
+/// ```no_run
+/// atomic_transaction(&mut [&mut parent, &mut subgraph], "rename-port", None, |graphs| {
+///     graphs[1].rename_inport("in", "input");
+///     graphs[0].remove_edge("Read", "out", Some("Sub"), Some("in"));
+///     graphs[0].add_edge("Read", "out", "Sub", "input", None);
+///     Ok(())
+/// })?;
+/// ```
+pub fn atomic_transaction<'a, F>(
+    graphs: &mut [&mut Graph<'a>],
+    id: &str,
+    metadata: Option<Map<String, Value>>,
+    edit: F,
+) -> Result<(), String>
+where
+    F: FnOnce(&mut [&mut Graph<'a>]) -> Result<(), String>,
+{
+    for graph in graphs.iter() {
+        if !graph.subscribed {
+            return Err(format!("Graph {} is not journaled", graph.name));
+        }
+        if let Some(open) = graph.transaction.id.as_ref() {
+            return Err(format!(
+                "Graph {} is already in transaction {}",
+                graph.name, open
+            ));
+        }
+    }
+
+    let revisions = graphs
+        .iter()
+        .map(|graph| graph.current_revision)
+        .collect::<Vec<i32>>();
+    for graph in graphs.iter_mut() {
+        // Audit sinks only hear about the edit once it can't be rolled back
+        graph.hold_audit();
+        graph.start_transaction(id, metadata.clone());
+    }
+    let result = edit(graphs);
+    for graph in graphs.iter_mut() {
+        if graph.transaction.id.as_deref() == Some(id) {
+            graph.end_transaction(id, metadata.clone());
+        }
+    }
+
+    let err = match result {
+        Ok(()) => {
+            for graph in graphs.iter_mut() {
+                graph.release_audit(true);
+            }
+            return Ok(());
+        }
+        Err(err) => err,
+    };
+    log::error!("Transaction {} rolled back: {}", id, err);
+    // Check every revert before making any, so that a graph refusing
+    // its revert doesn't leave the others rolled back alone
+    let denied = graphs
+        .iter_mut()
+        .zip(revisions.iter())
+        .find_map(|(graph, revision)| {
+            let reverts = graph.revision_mutations(*revision);
+            graph.permit_all(reverts).err()
+        });
+    if let Some(denied) = denied {
+        for graph in graphs.iter_mut() {
+            graph.release_audit(true);
+        }
+        return Err(format!("{}; cannot roll back: {}", err, denied));
+    }
+    for (graph, revision) in graphs.iter_mut().zip(revisions) {
+        graph.move_to_revision(revision);
+        let reverted = graph.current_revision == revision;
+        if reverted {
+            graph.transactions.truncate((revision + 1) as usize);
+            graph.last_revision = revision.max(0) as usize;
+        }
+        graph.release_audit(!reverted);
+    }
+    Err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use crate::graph::audit::{AuditRecord, AuditSink};
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use crate::graph::multi::atomic_transaction;
+    use crate::graph::policy::{MutationKind, MutationTarget};
+    use beady::scenario;
+
+    struct MemorySink {
+        records: Arc<Mutex<Vec<AuditRecord>>>,
+    }
+
+    impl AuditSink for MemorySink {
+        fn record(&self, record: &AuditRecord) -> Result<(), io::Error> {
+            self.records.lock().unwrap().push(record.clone());
+            Ok(())
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_atomic_multi_graph_transaction() {
+        'given_a_subgraph_and_its_parent: {
+            let mut sub = Graph::new("sub", true);
+            sub.add_node("Split", "SplitLines", None)
+                .add_inport("in", "Split", "in", None);
+            sub.init_journal(None);
+            let mut parent = Graph::new("parent", true);
+            parent
+                .add_node("Read", "ReadFile", None)
+                .add_node("Lines", "sub", None)
+                .add_edge("Read", "out", "Lines", "in", None);
+            parent.init_journal(None);
+            'when_the_edit_succeeds: {
+                let result =
+                    atomic_transaction(&mut [&mut sub, &mut parent], "rename", None, |graphs| {
+                        graphs[0].rename_inport("in", "text");
+                        graphs[1]
+                            .remove_edge("Read", "out", Some("Lines"), Some("in"))
+                            .add_edge("Read", "out", "Lines", "text", None);
+                        Ok(())
+                    });
+                'then_both_graphs_should_be_changed_in_one_revision: {
+                    assert!(result.is_ok());
+                    assert!(sub.inports.contains_key("text"));
+                    assert_eq!(parent.edges[0].to.port, "text");
+                    assert_eq!(sub.current_revision, 1);
+                    assert_eq!(parent.current_revision, 1);
+                }
+            }
+            'when_the_edit_fails_halfway: {
+                let records = Arc::new(Mutex::new(Vec::new()));
+                sub.add_audit_sink(MemorySink {
+                    records: records.clone(),
+                });
+                let result =
+                    atomic_transaction(&mut [&mut sub, &mut parent], "rename", None, |graphs| {
+                        graphs[0].rename_inport("in", "text");
+                        Err("parent graph is locked".to_owned())
+                    });
+                'then_every_graph_should_be_rolled_back: {
+                    assert_eq!(result, Err("parent graph is locked".to_owned()));
+                    assert!(sub.inports.contains_key("in"));
+                    assert!(!sub.inports.contains_key("text"));
+                    assert_eq!(sub.current_revision, 0);
+                    assert_eq!(parent.current_revision, 0);
+                    assert!(!sub.can_redo());
+                }
+                'and_then_audit_sinks_should_not_hear_of_it: {
+                    assert!(records.lock().unwrap().is_empty());
+                }
+            }
+            'when_the_edit_fails_and_a_graph_refuses_its_revert: {
+                let records = Arc::new(Mutex::new(Vec::new()));
+                sub.add_audit_sink(MemorySink {
+                    records: records.clone(),
+                });
+                parent.set_mutation_policy(|kind: MutationKind, _: &MutationTarget| {
+                    if kind == MutationKind::RemoveNode {
+                        Err("editors may not delete nodes".to_owned())
+                    } else {
+                        Ok(())
+                    }
+                });
+                let result =
+                    atomic_transaction(&mut [&mut sub, &mut parent], "log", None, |graphs| {
+                        graphs[0].rename_inport("in", "text");
+                        graphs[1].add_node("Log", "Output", None);
+                        Err("log is not wired".to_owned())
+                    });
+                'then_no_graph_should_be_rolled_back: {
+                    assert!(result.unwrap_err().contains("cannot roll back"));
+                    assert!(sub.inports.contains_key("text"));
+                    assert!(parent.get_node("Log").is_some());
+                    assert_eq!(sub.current_revision, 1);
+                    assert_eq!(parent.current_revision, 1);
+                    assert_eq!(records.lock().unwrap().len(), 1);
+                }
+            }
+            'when_a_graph_is_not_journaled: {
+                let mut other = Graph::new("other", true);
+                let result = atomic_transaction(&mut [&mut sub, &mut other], "x", None, |_| Ok(()));
+                'then_nothing_should_start: {
+                    assert_eq!(result, Err("Graph other is not journaled".to_owned()));
+                    assert!(sub.transaction.id.is_none());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use std::fmt;
+
+/// Kind of change a graph mutator is about to make
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MutationKind {
+    ChangeProperties,
+    AddNode,
+    RemoveNode,
+    RenameNode,
+    ChangeNode,
+    AddEdge,
+    RemoveEdge,
+    ChangeEdge,
+    AddInitial,
+    RemoveInitial,
+    AddInport,
+    RemoveInport,
+    RenameInport,
+    ChangeInport,
+    AddOutport,
+    RemoveOutport,
+    RenameOutport,
+    ChangeOutport,
+    AddGroup,
+    RemoveGroup,
+    RenameGroup,
+    ChangeGroup,
+}
+
+impl MutationKind {
+    /// Name of the event emitted when the mutation is applied
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MutationKind::ChangeProperties => "change_properties",
+            MutationKind::AddNode => "add_node",
+            MutationKind::RemoveNode => "remove_node",
+            MutationKind::RenameNode => "rename_node",
+            MutationKind::ChangeNode => "change_node",
+            MutationKind::AddEdge => "add_edge",
+            MutationKind::RemoveEdge => "remove_edge",
+            MutationKind::ChangeEdge => "change_edge",
+            MutationKind::AddInitial => "add_initial",
+            MutationKind::RemoveInitial => "remove_initial",
+            MutationKind::AddInport => "add_inport",
+            MutationKind::RemoveInport => "remove_inport",
+            MutationKind::RenameInport => "rename_inport",
+            MutationKind::ChangeInport => "change_inport",
+            MutationKind::AddOutport => "add_outport",
+            MutationKind::RemoveOutport => "remove_outport",
+            MutationKind::RenameOutport => "rename_outport",
+            MutationKind::ChangeOutport => "change_outport",
+            MutationKind::AddGroup => "add_group",
+            MutationKind::RemoveGroup => "remove_group",
+            MutationKind::RenameGroup => "rename_group",
+            MutationKind::ChangeGroup => "change_group",
+        }
+    }
+}
+
+impl fmt::Display for MutationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// What a mutation applies to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MutationTarget {
+    Graph,
+    Node(String),
+    Edge {
+        from: String,
+        to: String,
+    },
+    /// IIP going into the given node
+    Initial(String),
+    Inport(String),
+    Outport(String),
+    Group(String),
+}
+
+impl MutationTarget {
+    /// Whether the mutation touches the given node
+    pub fn involves_node(&self, id: &str) -> bool {
+        match self {
+            MutationTarget::Node(node) | MutationTarget::Initial(node) => node == id,
+            MutationTarget::Edge { from, to } => from == id || to == id,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for MutationTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MutationTarget::Graph => write!(f, "graph"),
+            MutationTarget::Node(id) => write!(f, "node {}", id),
+            MutationTarget::Edge { from, to } => write!(f, "edge {} -> {}", from, to),
+            MutationTarget::Initial(id) => write!(f, "IIP to {}", id),
+            MutationTarget::Inport(name) => write!(f, "inport {}", name),
+            MutationTarget::Outport(name) => write!(f, "outport {}", name),
+            MutationTarget::Group(name) => write!(f, "group {}", name),
+        }
+    }
+}
+
+/// Consulted before each mutating operation on a graph
+///
+/// Returning an error denies the mutation with the given reason; the
+/// graph is left untouched and a `mutation_denied` event is emitted.
+/// Changes made internally as part of an allowed mutation (e.g. edges
+/// removed along with their node) are not checked again.
+/// ```no_run
+/// my_graph.set_mutation_policy(|kind: MutationKind, _: &MutationTarget| {
+///     if kind == MutationKind::RemoveNode { Err("editors may not delete nodes".to_string()) } else { Ok(()) }
+/// });
+/// ```
+pub trait MutationPolicy {
+    fn check(&self, kind: MutationKind, target: &MutationTarget) -> Result<(), String>;
+}
+
+impl<F> MutationPolicy for F
+where
+    F: Fn(MutationKind, &MutationTarget) -> Result<(), String>,
+{
+    fn check(&self, kind: MutationKind, target: &MutationTarget) -> Result<(), String> {
+        self(kind, target)
+    }
+}
+
+/// Denies every mutation
+pub struct ReadOnlyPolicy;
+
+impl MutationPolicy for ReadOnlyPolicy {
+    fn check(&self, _kind: MutationKind, _target: &MutationTarget) -> Result<(), String> {
+        Err("graph is read-only".to_owned())
+    }
+}
+
+/// Denies mutations touching any of the listed nodes
+pub struct ProtectedNodesPolicy {
+    pub nodes: Vec<String>,
+}
+
+impl MutationPolicy for ProtectedNodesPolicy {
+    fn check(&self, _kind: MutationKind, target: &MutationTarget) -> Result<(), String> {
+        match self.nodes.iter().find(|id| target.involves_node(id)) {
+            Some(id) => Err(format!("node {} is protected", id)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use crate::graph::blocking::to_json;
+    use crate::graph::graph::Graph;
+    use crate::graph::groups::AutoGroupStrategy;
+    use crate::graph::policy::{
+        MutationKind, MutationTarget, ProtectedNodesPolicy, ReadOnlyPolicy,
+    };
+    use crate::graph::select::Selector;
+    use crate::graph::transform::MapReduceOptions;
+    use crate::graph::types::GraphError;
+    use crate::internal::event_manager::EventManager;
+    use beady::scenario;
+    use serde_json::{json, Value};
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_mutation_policy() {
+        'given_a_graph_with_protected_nodes: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Write", "WriteFile", None)
+                .add_edge("Read", "out", "Write", "in", None);
+            g.set_mutation_policy(ProtectedNodesPolicy {
+                nodes: vec!["Write".to_owned()],
+            });
+            'when_removing_a_protected_node: {
+                let denied = Arc::new(Mutex::new(Vec::new()));
+                let seen = denied.clone();
+                g.connect(
+                    "mutation_denied",
+                    move |_, data| {
+                        if let Some(err) = data.downcast_ref::<GraphError>() {
+                            seen.lock().unwrap().push(err.clone());
+                        }
+                    },
+                    false,
+                );
+                g.remove_node("Write");
+                'then_it_should_be_denied_with_a_structured_error: {
+                    assert!(g.get_node("Write").is_some());
+                    assert_eq!(
+                        denied.lock().unwrap().clone(),
+                        vec![GraphError::PermissionDenied {
+                            kind: MutationKind::RemoveNode,
+                            target: MutationTarget::Node("Write".to_owned()),
+                            reason: "node Write is protected".to_owned(),
+                        }]
+                    );
+                }
+            }
+            'when_removing_an_unprotected_neighbour: {
+                g.remove_node("Read");
+                'then_it_should_keep_the_edge_into_the_protected_node: {
+                    assert!(g.get_node("Read").is_some());
+                    assert_eq!(g.edges.len(), 1);
+                }
+            }
+            'when_opening_a_transaction_by_hand: {
+                g.check_transaction_start();
+                g.remove_node("Write");
+                g.check_transaction_end();
+                'then_the_policy_should_still_apply: {
+                    assert!(g.get_node("Write").is_some());
+                }
+            }
+            'when_checking_without_applying: {
+                'then_it_should_report_the_decision: {
+                    assert!(g
+                        .check_mutation(
+                            MutationKind::ChangeNode,
+                            &MutationTarget::Node("Read".to_owned())
+                        )
+                        .is_ok());
+                    assert!(g
+                        .check_mutation(
+                            MutationKind::AddEdge,
+                            &MutationTarget::Edge {
+                                from: "Read".to_owned(),
+                                to: "Write".to_owned()
+                            }
+                        )
+                        .is_err());
+                }
+            }
+        }
+        'given_a_read_only_graph: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None);
+            g.set_mutation_policy(ReadOnlyPolicy);
+            'then_it_should_reject_every_change: {
+                g.add_node("Write", "WriteFile", None)
+                    .rename_node("Read", "Input")
+                    .add_group("all", vec!["Read".to_owned()], None);
+                assert_eq!(g.nodes.len(), 1);
+                assert_eq!(g.nodes[0].id, "Read");
+                assert_eq!(g.groups.len(), 0);
+                'and_then_it_should_accept_changes_once_cleared: {
+                    g.clear_mutation_policy()
+                        .add_node("Write", "WriteFile", None);
+                    assert_eq!(g.nodes.len(), 2);
+                }
+            }
+        }
+        'given_a_role_based_policy: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None);
+            g.set_mutation_policy(|kind: MutationKind, _: &MutationTarget| {
+                if kind == MutationKind::RemoveNode {
+                    Err("viewers may not remove nodes".to_owned())
+                } else {
+                    Ok(())
+                }
+            });
+            'then_it_should_apply_the_closure: {
+                g.remove_node("Read").add_node("Write", "WriteFile", None);
+                assert_eq!(g.nodes.len(), 2);
+            }
+        }
+        'given_a_frozen_graph: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None);
+            g.freeze();
+            'then_it_should_reject_every_change: {
+                assert!(g.is_frozen());
+                assert_eq!(
+                    g.check_mutation(
+                        MutationKind::AddNode,
+                        &MutationTarget::Node("Write".to_owned())
+                    ),
+                    Err(GraphError::Frozen)
+                );
+                g.add_node("Write", "WriteFile", None)
+                    .set_node_metadata("Read", json!({"x": 1}).as_object().cloned().unwrap());
+                assert_eq!(g.nodes.len(), 1);
+                assert!(g.nodes[0]
+                    .metadata
+                    .as_ref()
+                    .and_then(|meta| meta.get("x"))
+                    .is_none());
+                'and_then_it_should_hold_inside_a_transaction: {
+                    g.check_transaction_start();
+                    g.add_node("Write", "WriteFile", None);
+                    g.check_transaction_end();
+                    assert_eq!(g.nodes.len(), 1);
+                }
+                'and_then_it_should_accept_changes_once_unfrozen: {
+                    g.unfreeze().add_node("Write", "WriteFile", None);
+                    assert_eq!(g.nodes.len(), 2);
+                }
+            }
+        }
+    }
+
+    type Helper = (&'static str, fn(&mut Graph));
+
+    /// Helpers that make several changes under one implicit transaction
+    fn helpers() -> Vec<Helper> {
+        vec![
+            ("replace_component", |g| {
+                let mapping = HashMap::from([("in".to_owned(), "input".to_owned())]);
+                let _ = g.replace_component("core/Work", "core/Job", &mapping, None);
+            }),
+            ("move_node_to_group", |g| {
+                g.move_node_to_group("Worker", "sink");
+            }),
+            ("auto_group", |g| {
+                g.auto_group(AutoGroupStrategy::Namespace);
+            }),
+            ("replicate_node", |g| {
+                let _ = g.replicate_node("Worker", 2, |id, i| format!("{}{}", id, i));
+            }),
+            ("replicate_group", |g| {
+                let _ = g.replicate_group("source", 1, |id, i| format!("{}{}", id, i));
+            }),
+            ("fan_out", |g| {
+                g.fan_out(("Worker", "out"), [("Log", "extra")]);
+            }),
+            ("wire_errors_to", |g| {
+                g.wire_errors_to("Worker", ("Log", "in"));
+            }),
+            ("map_reduce", |g| {
+                let _ = g.map_reduce("Worker", 2, MapReduceOptions::default());
+            }),
+            ("update_metadata_where", |g| {
+                let patch = json!({"x": 1}).as_object().cloned().unwrap();
+                g.update_metadata_where(&Selector::Group("source".to_owned()), patch);
+            }),
+        ]
+    }
+
+    /// Helpers that change the `Worker` node or its connections
+    const TOUCHING_WORKER: [&str; 7] = [
+        "replace_component",
+        "replicate_node",
+        "replicate_group",
+        "map_reduce",
+        "fan_out",
+        "wire_errors_to",
+        "update_metadata_where",
+    ];
+
+    fn helper_graph<'a>() -> Graph<'a> {
+        let mut g = Graph::new("", true);
+        g.add_node("Read", "core/Read", None)
+            .add_node("Worker", "core/Work", None)
+            .add_node("Log", "core/Output", None)
+            .add_edge("Read", "out", "Worker", "in", None)
+            .add_edge("Worker", "out", "Log", "in", None)
+            .add_initial(json!(1), "Worker", "in", None)
+            .add_group("source", vec!["Read".to_owned(), "Worker".to_owned()], None)
+            .add_group("sink", vec!["Log".to_owned()], None);
+        g
+    }
+
+    fn snapshot(g: &Graph) -> Value {
+        serde_json::to_value(to_json(g)).unwrap()
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_policy_helpers() {
+        'given_a_frozen_graph: {
+            'when_running_each_helper: {
+                'then_nothing_should_change: {
+                    for (name, helper) in helpers() {
+                        let mut g = helper_graph();
+                        let before = snapshot(&g);
+                        g.freeze();
+                        helper(&mut g);
+                        assert_eq!(snapshot(&g), before, "{} changed a frozen graph", name);
+                    }
+                }
+            }
+        }
+        'given_a_protected_node: {
+            'when_running_helpers_that_touch_it: {
+                'then_they_should_be_denied_as_a_whole: {
+                    for (name, helper) in helpers() {
+                        if !TOUCHING_WORKER.contains(&name) {
+                            continue;
+                        }
+                        let mut g = helper_graph();
+                        let before = snapshot(&g);
+                        g.set_mutation_policy(ProtectedNodesPolicy {
+                            nodes: vec!["Worker".to_owned()],
+                        });
+                        helper(&mut g);
+                        assert_eq!(snapshot(&g), before, "{} changed a protected node", name);
+                    }
+                }
+            }
+        }
+        'given_a_quota: {
+            'when_a_helper_would_exceed_it: {
+                let mut g = helper_graph();
+                g.set_quota(crate::graph::limits::GraphQuota {
+                    max_nodes: 4,
+                    ..Default::default()
+                });
+                let result = g.replicate_node("Worker", 2, |id, i| format!("{}{}", id, i));
+                'then_no_copy_should_be_added: {
+                    assert!(matches!(result, Err(GraphError::QuotaExceeded { .. })));
+                    assert_eq!(g.nodes.len(), 3);
+                }
+            }
+        }
+    }
+}
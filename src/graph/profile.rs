@@ -0,0 +1,183 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::policy::MutationKind;
+
+/// Upper bounds of the histogram buckets, in microseconds; the last
+/// bucket holds everything slower
+pub const BUCKET_BOUNDS_US: [u64; 7] = [1, 10, 100, 1_000, 10_000, 100_000, 1_000_000];
+
+/// Timing distribution of one mutator or event
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    pub count: u64,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    pub buckets: [u64; BUCKET_BOUNDS_US.len() + 1],
+}
+
+impl Histogram {
+    pub fn record(&mut self, elapsed: Duration) {
+        if self.count == 0 || elapsed < self.min {
+            self.min = elapsed;
+        }
+        if elapsed > self.max {
+            self.max = elapsed;
+        }
+        self.count += 1;
+        self.total += elapsed;
+        let micros = elapsed.as_micros();
+        let bucket = BUCKET_BOUNDS_US
+            .iter()
+            .position(|bound| micros < *bound as u128)
+            .unwrap_or(BUCKET_BOUNDS_US.len());
+        self.buckets[bucket] += 1;
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        self.total / self.count as u32
+    }
+}
+
+/// Timings collected by a graph built with the `profiling` feature
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProfileReport {
+    /// Top-level mutator calls, keyed by mutation kind
+    pub mutators: BTreeMap<String, Histogram>,
+    /// Listener dispatch, keyed by event name
+    pub events: BTreeMap<String, Histogram>,
+}
+
+impl ProfileReport {
+    /// Mutators sorted by total time spent in them, slowest first
+    pub fn slowest_mutators(&self) -> Vec<(&str, &Histogram)> {
+        let mut mutators: Vec<_> = self
+            .mutators
+            .iter()
+            .map(|(name, histogram)| (name.as_str(), histogram))
+            .collect();
+        mutators.sort_by_key(|(_, histogram)| Reverse(histogram.total));
+        mutators
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (section, entries) in [("mutators", &self.mutators), ("events", &self.events)] {
+            writeln!(f, "{}:", section)?;
+            for (name, histogram) in entries.iter() {
+                writeln!(
+                    f,
+                    "  {:<20} count={} total={:?} mean={:?} max={:?}",
+                    name,
+                    histogram.count,
+                    histogram.total,
+                    histogram.mean(),
+                    histogram.max
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Profiler {
+    report: ProfileReport,
+    pending: Option<(MutationKind, Instant)>,
+}
+
+impl Profiler {
+    pub(crate) fn start_mutation(&mut self, kind: MutationKind) {
+        self.pending = Some((kind, Instant::now()));
+    }
+
+    pub(crate) fn end_mutation(&mut self) {
+        if let Some((kind, started)) = self.pending.take() {
+            self.report
+                .mutators
+                .entry(kind.as_str().to_owned())
+                .or_default()
+                .record(started.elapsed());
+        }
+    }
+
+    pub(crate) fn record_event(&mut self, name: &str, elapsed: Duration) {
+        self.report
+            .events
+            .entry(name.to_owned())
+            .or_default()
+            .record(elapsed);
+    }
+
+    pub(crate) fn report(&self) -> ProfileReport {
+        self.report.clone()
+    }
+
+    pub(crate) fn reset(&mut self) {
+        *self = Profiler::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::graph::graph::Graph;
+    use crate::graph::profile::Histogram;
+    use crate::internal::event_manager::EventManager;
+    use beady::scenario;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_profiling() {
+        'given_a_histogram: {
+            let mut histogram = Histogram::default();
+            'when_recording_timings: {
+                histogram.record(Duration::from_micros(5));
+                histogram.record(Duration::from_millis(2));
+                'then_it_should_bucket_them: {
+                    assert_eq!(histogram.count, 2);
+                    assert_eq!(histogram.min, Duration::from_micros(5));
+                    assert_eq!(histogram.max, Duration::from_millis(2));
+                    assert_eq!(histogram.buckets[1], 1);
+                    assert_eq!(histogram.buckets[4], 1);
+                }
+            }
+        }
+        'given_a_graph_with_listeners: {
+            let mut g = Graph::new("", true);
+            g.connect("add_node", |_, _| {}, false);
+            'when_mutating_it: {
+                g.add_node("Read", "ReadFile", None)
+                    .add_node("Write", "WriteFile", None)
+                    .add_edge("Read", "out", "Write", "in", None)
+                    .remove_node("Read");
+                let report = g.profile_report();
+                'then_it_should_time_top_level_mutators: {
+                    assert_eq!(report.mutators["add_node"].count, 2);
+                    assert_eq!(report.mutators["add_edge"].count, 1);
+                    assert_eq!(report.mutators["remove_node"].count, 1);
+                    assert!(!report.mutators.contains_key("remove_edge"));
+                    assert_eq!(report.slowest_mutators().len(), 3);
+                }
+                'then_it_should_time_event_dispatch: {
+                    assert_eq!(report.events["add_node"].count, 2);
+                    assert!(!report.events.contains_key("add_edge"));
+                }
+                'and_then_after_a_reset: {
+                    g.reset_profile();
+                    'then_the_report_should_be_empty: {
+                        assert!(g.profile_report().mutators.is_empty());
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use futures::executor::block_on;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::internal::event_manager::EventManager;
+
+use super::graph::Graph;
+use super::journal::{event_args, Journal, TransactionEntry, JOURNALED_EVENTS};
+use super::types::GraphJson;
+
+/// Graph event captured by an `EventRecorder`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub event: String,
+    /// Payload snapshot, in the journal's command format
+    pub args: Value,
+    /// Milliseconds since recording started
+    pub at: u64,
+}
+
+/// Graph as it was when recording started, and what happened to it since
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Recording {
+    pub initial: GraphJson,
+    pub events: Vec<RecordedEvent>,
+}
+
+/// Records the event stream of an editing session
+///
+/// Every mutation event is captured with a snapshot of its payload,
+/// including those caused by undo and redo. The recording can be saved,
+/// and replayed later to rebuild the graph step by step, e.g. to
+/// reproduce an editor bug report or to run a demo:
+/// ```no_run
+/// let recorder = EventRecorder::attach(&mut my_graph);
+/// // edit the graph
+/// recorder.recording().save("session.json")?;
+/// // later
+/// let replayed = Recording::load("session.json")?.replay();
+/// ```
+pub struct EventRecorder {
+    initial: GraphJson,
+    events: Arc<Mutex<Vec<RecordedEvent>>>,
+    active: Arc<AtomicBool>,
+}
+
+impl EventRecorder {
+    pub fn attach(graph: &mut Graph) -> Self {
+        let recorder = Self {
+            initial: block_on(graph.to_json()),
+            events: Arc::new(Mutex::new(Vec::new())),
+            active: Arc::new(AtomicBool::new(true)),
+        };
+        let started = Instant::now();
+        let events = ["start_transaction", "end_transaction"]
+            .into_iter()
+            .chain(JOURNALED_EVENTS);
+        for name in events {
+            let recorded = recorder.events.clone();
+            let active = recorder.active.clone();
+            graph.connect(
+                name,
+                move |_, data| {
+                    if !active.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let args = match name {
+                        "start_transaction" | "end_transaction" => data
+                            .downcast_ref::<(String, Option<Map<String, Value>>)>()
+                            .map(|(id, metadata)| json!({"id": id, "metadata": metadata})),
+                        _ => event_args(name, data),
+                    };
+                    if let Some(args) = args {
+                        recorded.lock().unwrap().push(RecordedEvent {
+                            event: name.to_owned(),
+                            args,
+                            at: started.elapsed().as_millis() as u64,
+                        });
+                    }
+                },
+                false,
+            );
+        }
+        recorder
+    }
+
+    /// Stop capturing events; the listeners stay attached but idle
+    pub fn stop(&self) {
+        self.active.store(false, Ordering::Relaxed);
+    }
+
+    pub fn recording(&self) -> Recording {
+        Recording {
+            initial: self.initial.clone(),
+            events: self.events.lock().unwrap().clone(),
+        }
+    }
+}
+
+impl Recording {
+    pub fn save(&self, path: &str) -> Result<(), io::Error> {
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    pub fn load(path: &str) -> Result<Self, io::Error> {
+        let source = fs::read_to_string(path)?;
+        serde_json::from_str::<Recording>(&source)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Rebuild the graph from its initial state and the recorded events
+    pub fn replay<'a>(&self) -> Graph<'a> {
+        let mut graph = block_on(Graph::from_json(self.initial.clone(), None));
+        graph.play_events(&self.events);
+        graph
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Re-apply recorded events as mutations
+    ///
+    /// Explicit transactions are reopened as recorded; implicit ones are
+    /// left to the mutators.
+    pub fn play_events(&mut self, events: &[RecordedEvent]) -> &mut Self {
+        for recorded in events {
+            let transaction = || {
+                let id = recorded.args.get("id")?.as_str()?;
+                let metadata = recorded.args.get("metadata")?.as_object().cloned();
+                Some((id, metadata)).filter(|(id, _)| *id != "implicit")
+            };
+            match recorded.event.as_str() {
+                "start_transaction" => {
+                    if let Some((id, metadata)) = transaction() {
+                        if self.transaction.id.is_none() {
+                            self.start_transaction(id, metadata);
+                        }
+                    }
+                }
+                "end_transaction" => {
+                    if let Some((id, metadata)) = transaction() {
+                        if self.transaction.id.as_deref() == Some(id) {
+                            self.end_transaction(id, metadata);
+                        }
+                    }
+                }
+                event => {
+                    self.execute_entry(TransactionEntry {
+                        cmd: Some(event.to_owned()),
+                        args: Some(recorded.args.clone()),
+                        rev: None,
+                        old: None,
+                        new: None,
+                    });
+                }
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use crate::graph::recorder::{EventRecorder, Recording};
+    use beady::scenario;
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_event_recording() {
+        'given_a_recorded_editing_session: {
+            let mut g = Graph::new("session", true);
+            g.add_node("Read", "ReadFile", None);
+            g.init_journal(None);
+            let recorder = EventRecorder::attach(&mut g);
+            g.start_transaction("wire", None)
+                .add_node("Log", "Output", json!({"x": 5}).as_object().cloned())
+                .add_edge("Read", "out", "Log", "in", None)
+                .end_transaction("wire", None);
+            g.add_initial(json!("a.txt"), "Read", "source", None)
+                .rename_node("Log", "Print")
+                .add_node("Oops", "Typo", None);
+            g.undo();
+            'when_replaying_it: {
+                let recording = recorder.recording();
+                let replayed = recording.replay();
+                'then_the_graph_should_match: {
+                    assert_eq!(replayed.nodes.len(), 2);
+                    assert!(replayed.get_node("Oops").is_none());
+                    assert_eq!(
+                        json!(block_on(replayed.to_json())),
+                        json!(block_on(g.to_json()))
+                    );
+                }
+                'then_payloads_should_be_captured: {
+                    let events = recording
+                        .events
+                        .iter()
+                        .map(|e| e.event.as_str())
+                        .collect::<Vec<&str>>();
+                    assert_eq!(
+                        events[..4],
+                        [
+                            "start_transaction",
+                            "add_node",
+                            "add_edge",
+                            "end_transaction"
+                        ]
+                    );
+                    assert!(events.contains(&"remove_node"));
+                    assert_eq!(recording.events[1].args["metadata"]["x"], json!(5));
+                }
+            }
+            'when_saving_and_loading_it: {
+                let path = std::env::temp_dir().join(format!(
+                    "zflow-recording-{}-{:?}.json",
+                    std::process::id(),
+                    std::thread::current().id()
+                ));
+                let path = path.to_str().unwrap().to_owned();
+                recorder.recording().save(&path).unwrap();
+                let loaded = Recording::load(&path).unwrap();
+                std::fs::remove_file(&path).unwrap();
+                'then_it_should_replay_the_same_way: {
+                    assert_eq!(loaded.events.len(), recorder.recording().events.len());
+                    assert!(loaded.replay().get_node("Print").is_some());
+                }
+            }
+            'when_stopped: {
+                recorder.stop();
+                let before = recorder.recording().events.len();
+                g.add_node("Late", "Output", None);
+                'then_later_events_should_be_ignored: {
+                    assert_eq!(recorder.recording().events.len(), before);
+                }
+            }
+        }
+    }
+}
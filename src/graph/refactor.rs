@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use super::graph::Graph;
+use super::policy::{MutationKind, MutationTarget};
+use super::registry::ComponentRegistry;
+
+impl<'a> Graph<'a> {
+    /// Retarget every node using a component to another component
+    ///
+    /// Ports named in `port_mapping` are renamed on the edges, IIPs and
+    /// exported ports attached to the nodes; other port names are kept.
+    /// When a registry is given, the new component must be registered and
+    /// declare every port the nodes use after remapping. All changes are
+    /// made in a single transaction. Returns the IDs of the retargeted nodes.
+    /// ```no_run
+    /// let mapping = HashMap::from([("in".to_string(), "input".to_string())]);
+    /// my_graph.replace_component("core/Output", "console/Log", &mapping, Some(&registry))?;
+    /// ```
+    pub fn replace_component(
+        &mut self,
+        old: &str,
+        new: &str,
+        port_mapping: &HashMap<String, String>,
+        registry: Option<&ComponentRegistry>,
+    ) -> Result<Vec<String>, String> {
+        let map = |port: &str| {
+            port_mapping
+                .get(port)
+                .cloned()
+                .unwrap_or_else(|| port.to_owned())
+        };
+        let ids = self
+            .nodes
+            .iter()
+            .filter(|node| node.component == old)
+            .map(|node| node.id.clone())
+            .collect::<Vec<String>>();
+
+        if let Some(registry) = registry {
+            let spec = registry
+                .get(new)
+                .ok_or_else(|| format!("Component {} is not registered", new))?;
+            for id in ids.iter() {
+                let inbound = self
+                    .edges
+                    .iter()
+                    .filter(|edge| &edge.to.node_id == id)
+                    .map(|edge| edge.to.port.clone())
+                    .chain(
+                        self.initializers
+                            .iter()
+                            .filter_map(|iip| iip.to.as_ref())
+                            .filter(|to| &to.node_id == id)
+                            .map(|to| to.port.clone()),
+                    )
+                    .chain(
+                        self.inports
+                            .values()
+                            .filter(|exported| &exported.process == id)
+                            .map(|exported| exported.port.clone()),
+                    );
+                for port in inbound {
+                    if spec.get_inport(&map(&port)).is_none() {
+                        return Err(format!(
+                            "Component {} has no inport {} used by node {}",
+                            new,
+                            map(&port),
+                            id
+                        ));
+                    }
+                }
+                let outbound = self
+                    .edges
+                    .iter()
+                    .filter(|edge| &edge.from.node_id == id)
+                    .map(|edge| edge.from.port.clone())
+                    .chain(
+                        self.outports
+                            .values()
+                            .filter(|exported| &exported.process == id)
+                            .map(|exported| exported.port.clone()),
+                    );
+                for port in outbound {
+                    if spec.get_outport(&map(&port)).is_none() {
+                        return Err(format!(
+                            "Component {} has no outport {} used by node {}",
+                            new,
+                            map(&port),
+                            id
+                        ));
+                    }
+                }
+            }
+        }
+        let mut mutations = Vec::new();
+        for id in ids.iter() {
+            mutations.push((MutationKind::ChangeNode, MutationTarget::Node(id.clone())));
+            for edge in self.edges.iter() {
+                let remapped = (&edge.from.node_id == id && map(&edge.from.port) != edge.from.port)
+                    || (&edge.to.node_id == id && map(&edge.to.port) != edge.to.port);
+                if remapped {
+                    let target = MutationTarget::Edge {
+                        from: edge.from.node_id.clone(),
+                        to: edge.to.node_id.clone(),
+                    };
+                    mutations.push((MutationKind::RemoveEdge, target.clone()));
+                    mutations.push((MutationKind::AddEdge, target));
+                }
+            }
+            if self
+                .initializers
+                .iter()
+                .filter_map(|iip| iip.to.as_ref())
+                .any(|to| &to.node_id == id && map(&to.port) != to.port)
+            {
+                mutations.push((
+                    MutationKind::RemoveInitial,
+                    MutationTarget::Initial(id.clone()),
+                ));
+                mutations.push((
+                    MutationKind::AddInitial,
+                    MutationTarget::Initial(id.clone()),
+                ));
+            }
+            for (name, exported) in self.inports.iter() {
+                if &exported.process == id && map(&exported.port) != exported.port {
+                    mutations.push((
+                        MutationKind::RemoveInport,
+                        MutationTarget::Inport(name.clone()),
+                    ));
+                    mutations.push((
+                        MutationKind::AddInport,
+                        MutationTarget::Inport(name.clone()),
+                    ));
+                }
+            }
+            for (name, exported) in self.outports.iter() {
+                if &exported.process == id && map(&exported.port) != exported.port {
+                    mutations.push((
+                        MutationKind::RemoveOutport,
+                        MutationTarget::Outport(name.clone()),
+                    ));
+                    mutations.push((
+                        MutationKind::AddOutport,
+                        MutationTarget::Outport(name.clone()),
+                    ));
+                }
+            }
+        }
+        self.permit_all(mutations).map_err(|err| err.to_string())?;
+
+        self.vetted(|graph| {
+            graph.check_transaction_start();
+            for id in ids.iter() {
+                graph.set_node_component(id, new);
+
+                for edge in graph.edges.clone() {
+                    let from_port = if &edge.from.node_id == id {
+                        map(&edge.from.port)
+                    } else {
+                        edge.from.port.clone()
+                    };
+                    let to_port = if &edge.to.node_id == id {
+                        map(&edge.to.port)
+                    } else {
+                        edge.to.port.clone()
+                    };
+                    if from_port == edge.from.port && to_port == edge.to.port {
+                        continue;
+                    }
+                    graph
+                        .remove_edge(
+                            &edge.from.node_id,
+                            &edge.from.port,
+                            Some(&edge.to.node_id),
+                            Some(&edge.to.port),
+                        )
+                        .add_edge_index(
+                            &edge.from.node_id,
+                            &from_port,
+                            edge.from.index,
+                            &edge.to.node_id,
+                            &to_port,
+                            edge.to.index,
+                            edge.metadata.clone(),
+                        );
+                }
+
+                let mut remapped_ports: Vec<String> = Vec::new();
+                for iip in graph.initializers.iter() {
+                    if let Some(to) = iip.to.as_ref() {
+                        if &to.node_id == id
+                            && map(&to.port) != to.port
+                            && !remapped_ports.contains(&to.port)
+                        {
+                            remapped_ports.push(to.port.clone());
+                        }
+                    }
+                }
+                for port in remapped_ports {
+                    let iips = graph
+                        .initializers
+                        .iter()
+                        .filter(|iip| {
+                            iip.to
+                                .as_ref()
+                                .map(|to| &to.node_id == id && to.port == port)
+                                .unwrap_or(false)
+                        })
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    graph.remove_initial(id, &port);
+                    for iip in iips {
+                        if let (Some(from), Some(to)) = (iip.from, iip.to) {
+                            graph.add_initial_index(
+                                from.data,
+                                id,
+                                map(&port),
+                                to.index,
+                                iip.metadata,
+                            );
+                        }
+                    }
+                }
+
+                for (name, exported) in graph.inports.clone() {
+                    if &exported.process == id && map(&exported.port) != exported.port {
+                        graph.remove_inport(&name).add_inport(
+                            &name,
+                            id,
+                            map(&exported.port),
+                            exported.metadata,
+                        );
+                    }
+                }
+                for (name, exported) in graph.outports.clone() {
+                    if &exported.process == id && map(&exported.port) != exported.port {
+                        graph.remove_outport(&name).add_outport(
+                            &name,
+                            id,
+                            map(&exported.port),
+                            exported.metadata,
+                        );
+                    }
+                }
+            }
+            graph.check_transaction_end();
+        });
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use crate::graph::registry::ComponentRegistry;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_replace_component() {
+        'given_a_graph_using_an_old_component: {
+            let registry = ComponentRegistry::from_json_string(
+                r#"[{"name": "console/Log", "inPorts": [{"id": "input"}, {"id": "prefix"}], "outPorts": []}]"#,
+            )
+            .unwrap();
+            let mut g = Graph::new("", true);
+            g.init_journal(None);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Log", "core/Output", None)
+                .add_edge("Read", "out", "Log", "in", None)
+                .add_initial(json!("> "), "Log", "prefix", None)
+                .add_inport("log", "Log", "in", None);
+            let mapping = HashMap::from([("in".to_owned(), "input".to_owned())]);
+            'when_replacing_it_with_a_mapping: {
+                let replaced = g
+                    .replace_component("core/Output", "console/Log", &mapping, Some(&registry))
+                    .unwrap();
+                'then_nodes_and_ports_should_be_retargeted: {
+                    assert_eq!(replaced, vec!["Log"]);
+                    assert_eq!(g.get_node("Log").unwrap().component, "console/Log");
+                    assert_eq!(g.edges.len(), 1);
+                    assert_eq!(g.edges[0].to.port, "input");
+                    assert_eq!(g.initializers[0].to.as_ref().unwrap().port, "prefix");
+                    assert_eq!(g.inports.get("log").unwrap().port, "input");
+                }
+                'and_then_undoing_it: {
+                    g.undo();
+                    'then_the_old_component_should_be_restored: {
+                        assert_eq!(g.get_node("Log").unwrap().component, "core/Output");
+                        assert_eq!(g.edges[0].to.port, "in");
+                    }
+                }
+            }
+            'when_the_mapping_misses_a_port: {
+                let result = g.replace_component(
+                    "core/Output",
+                    "console/Log",
+                    &HashMap::new(),
+                    Some(&registry),
+                );
+                'then_it_should_be_rejected_without_changes: {
+                    assert_eq!(
+                        result,
+                        Err("Component console/Log has no inport in used by node Log".to_owned())
+                    );
+                    assert_eq!(g.get_node("Log").unwrap().component, "core/Output");
+                }
+            }
+        }
+    }
+}
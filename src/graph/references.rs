@@ -0,0 +1,445 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::graph::Graph;
+use super::registry::ComponentRegistry;
+use super::scaffold::compatible;
+use super::schema::validate_value;
+
+/// Broken reference from a node to another graph
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReferenceIssue {
+    /// Component that is neither registered nor one of the graphs
+    MissingGraph {
+        graph: String,
+        node: String,
+        component: String,
+    },
+    /// Port used on a subgraph node that the subgraph doesn't export
+    MissingInport {
+        graph: String,
+        node: String,
+        subgraph: String,
+        port: String,
+    },
+    MissingOutport {
+        graph: String,
+        node: String,
+        subgraph: String,
+        port: String,
+    },
+}
+
+impl fmt::Display for ReferenceIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReferenceIssue::MissingGraph {
+                graph,
+                node,
+                component,
+            } => write!(
+                f,
+                "{}: node {} uses unknown component or graph {}",
+                graph, node, component
+            ),
+            ReferenceIssue::MissingInport {
+                graph,
+                node,
+                subgraph,
+                port,
+            } => write!(
+                f,
+                "{}: node {} uses inport {}, which graph {} doesn't export",
+                graph, node, port, subgraph
+            ),
+            ReferenceIssue::MissingOutport {
+                graph,
+                node,
+                subgraph,
+                port,
+            } => write!(
+                f,
+                "{}: node {} uses outport {}, which graph {} doesn't export",
+                graph, node, port, subgraph
+            ),
+        }
+    }
+}
+
+/// Graph a component name refers to, either by name or as `<library>/<name>`
+fn find_graph<'g, 'a>(graphs: &[&'g Graph<'a>], component: &str) -> Option<&'g Graph<'a>> {
+    let name = component.rsplit('/').next().unwrap_or(component);
+    graphs
+        .iter()
+        .find(|graph| graph.name == component)
+        .or_else(|| graphs.iter().find(|graph| graph.name == name))
+        .copied()
+}
+
+/// Check that the subgraphs used by a set of graphs exist and export the
+/// ports they're connected through
+///
+/// Nodes whose component is in the registry are regular components and
+/// aren't checked. Any other component must name one of the graphs, and
+/// every port used on the node by edges, IIPs or graph exports must be
+/// exported by that graph. Issues are listed graph by graph, in node order.
+/// ```no_run
+/// for issue in check_references(&[&main, &parser], &registry) {
+///     eprintln!("{}", issue);
+/// }
+/// ```
+pub fn check_references(graphs: &[&Graph], registry: &ComponentRegistry) -> Vec<ReferenceIssue> {
+    let mut issues = Vec::new();
+    for graph in graphs.iter() {
+        for node in graph.nodes.iter() {
+            if registry.get(&node.component).is_some() {
+                continue;
+            }
+            let subgraph = match find_graph(graphs, &node.component) {
+                Some(subgraph) => subgraph,
+                None => {
+                    issues.push(ReferenceIssue::MissingGraph {
+                        graph: graph.name.clone(),
+                        node: node.id.clone(),
+                        component: node.component.clone(),
+                    });
+                    continue;
+                }
+            };
+
+            let mut inports = graph
+                .edges
+                .iter()
+                .map(|edge| &edge.to)
+                .chain(graph.initializers.iter().filter_map(|iip| iip.to.as_ref()))
+                .filter(|to| to.node_id == node.id)
+                .map(|to| to.port.as_str())
+                .chain(
+                    graph
+                        .inports
+                        .values()
+                        .filter(|exported| exported.process == node.id)
+                        .map(|exported| exported.port.as_str()),
+                )
+                .collect::<Vec<&str>>();
+            let mut outports = graph
+                .edges
+                .iter()
+                .filter(|edge| edge.from.node_id == node.id)
+                .map(|edge| edge.from.port.as_str())
+                .chain(
+                    graph
+                        .outports
+                        .values()
+                        .filter(|exported| exported.process == node.id)
+                        .map(|exported| exported.port.as_str()),
+                )
+                .collect::<Vec<&str>>();
+            inports.sort_unstable();
+            inports.dedup();
+            outports.sort_unstable();
+            outports.dedup();
+
+            for port in inports {
+                if !subgraph.inports.contains_key(&subgraph.get_port_name(port)) {
+                    issues.push(ReferenceIssue::MissingInport {
+                        graph: graph.name.clone(),
+                        node: node.id.clone(),
+                        subgraph: subgraph.name.clone(),
+                        port: port.to_owned(),
+                    });
+                }
+            }
+            for port in outports {
+                if !subgraph
+                    .outports
+                    .contains_key(&subgraph.get_port_name(port))
+                {
+                    issues.push(ReferenceIssue::MissingOutport {
+                        graph: graph.name.clone(),
+                        node: node.id.clone(),
+                        subgraph: subgraph.name.clone(),
+                        port: port.to_owned(),
+                    });
+                }
+            }
+        }
+    }
+    issues
+}
+
+/// Way a parent graph uses a subgraph that its port signature doesn't allow
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CompositionIssue {
+    /// Port used on the subgraph node that the subgraph doesn't export
+    MissingInport {
+        node: String,
+        port: String,
+    },
+    MissingOutport {
+        node: String,
+        port: String,
+    },
+    /// Edge between ports of different datatypes
+    IncompatibleType {
+        node: String,
+        port: String,
+        expected: String,
+        actual: String,
+    },
+    /// IIP that doesn't match the schema of the exported port
+    InvalidIip {
+        node: String,
+        port: String,
+        path: String,
+        message: String,
+    },
+}
+
+/// Check that a parent graph uses a subgraph the way its port signature
+/// allows
+///
+/// The signature is the subgraph's `component_spec`. Every node of the
+/// parent running the subgraph is checked: edges and IIPs must use
+/// exported ports, edges must connect ports of compatible datatypes, and
+/// IIPs must match the schema of their port. Datatypes of the parent's
+/// other nodes come from the registry; unknown ones are compatible with
+/// anything. Run it after changing a shared subgraph to catch the
+/// parents it breaks.
+/// ```no_run
+/// for issue in check_composition(&main, &parser, &registry) {
+///     eprintln!("{:?}", issue);
+/// }
+/// ```
+pub fn check_composition(
+    parent: &Graph,
+    child: &Graph,
+    registry: &ComponentRegistry,
+) -> Vec<CompositionIssue> {
+    let signature = child.component_spec(Some(registry));
+    let port_spec = |node_id: &str, port: &str, inbound: bool| {
+        parent
+            .get_node(node_id)
+            .and_then(|node| registry.get(&node.component))
+            .and_then(|spec| {
+                if inbound {
+                    spec.get_inport(port)
+                } else {
+                    spec.get_outport(port)
+                }
+            })
+    };
+    let type_name =
+        |datatype: &Option<String>| datatype.clone().unwrap_or_else(|| "all".to_owned());
+
+    let mut issues = Vec::new();
+    for node in parent.nodes.iter() {
+        if find_graph(&[child], &node.component).is_none() {
+            continue;
+        }
+        for edge in parent
+            .edges
+            .iter()
+            .filter(|edge| edge.to.node_id == node.id)
+        {
+            let Some(port) = signature.get_inport(&edge.to.port) else {
+                issues.push(CompositionIssue::MissingInport {
+                    node: node.id.clone(),
+                    port: edge.to.port.clone(),
+                });
+                continue;
+            };
+            if let Some(upstream) = port_spec(&edge.from.node_id, &edge.from.port, false) {
+                if !compatible(upstream, port) {
+                    issues.push(CompositionIssue::IncompatibleType {
+                        node: node.id.clone(),
+                        port: port.id.clone(),
+                        expected: type_name(&port.datatype),
+                        actual: type_name(&upstream.datatype),
+                    });
+                }
+            }
+        }
+        for edge in parent
+            .edges
+            .iter()
+            .filter(|edge| edge.from.node_id == node.id)
+        {
+            let Some(port) = signature.get_outport(&edge.from.port) else {
+                issues.push(CompositionIssue::MissingOutport {
+                    node: node.id.clone(),
+                    port: edge.from.port.clone(),
+                });
+                continue;
+            };
+            if let Some(downstream) = port_spec(&edge.to.node_id, &edge.to.port, true) {
+                if !compatible(port, downstream) {
+                    issues.push(CompositionIssue::IncompatibleType {
+                        node: node.id.clone(),
+                        port: port.id.clone(),
+                        expected: type_name(&downstream.datatype),
+                        actual: type_name(&port.datatype),
+                    });
+                }
+            }
+        }
+        for iip in parent.initializers.iter() {
+            let (to, from) = match (iip.to.as_ref(), iip.from.as_ref()) {
+                (Some(to), Some(from)) if to.node_id == node.id => (to, from),
+                _ => continue,
+            };
+            let Some(port) = signature.get_inport(&to.port) else {
+                issues.push(CompositionIssue::MissingInport {
+                    node: node.id.clone(),
+                    port: to.port.clone(),
+                });
+                continue;
+            };
+            if let Some(schema) = port.schema.as_ref() {
+                for (path, message) in validate_value(schema, &from.data) {
+                    issues.push(CompositionIssue::InvalidIip {
+                        node: node.id.clone(),
+                        port: port.id.clone(),
+                        path,
+                        message,
+                    });
+                }
+            }
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::references::{
+        check_composition, check_references, CompositionIssue, ReferenceIssue,
+    };
+    use crate::graph::registry::ComponentRegistry;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_cross_graph_references() {
+        'given_a_main_graph_using_a_subgraph: {
+            let registry = ComponentRegistry::from_json_string(
+                r#"[
+                    {"name": "ReadFile", "inPorts": [{"id": "source"}], "outPorts": [{"id": "out"}]},
+                    {"name": "SplitLines", "inPorts": [{"id": "in"}], "outPorts": [{"id": "out"}]}
+                ]"#,
+            )
+            .unwrap();
+            let mut parser = Graph::new("Parser", true);
+            parser
+                .add_node("Split", "SplitLines", None)
+                .add_inport("text", "Split", "in", None)
+                .add_outport("lines", "Split", "out", None);
+            let mut main = Graph::new("main", true);
+            main.add_node("Read", "ReadFile", None)
+                .add_node("Parse", "project/Parser", None)
+                .add_node("Store", "project/Storage", None)
+                .add_edge("Read", "out", "Parse", "text", None)
+                .add_edge("Parse", "words", "Store", "in", None)
+                .add_initial(json!(","), "Parse", "delimiter", None);
+            'when_checking_references: {
+                let issues = check_references(&[&main, &parser], &registry);
+                'then_broken_references_should_be_reported: {
+                    assert_eq!(
+                        issues,
+                        vec![
+                            ReferenceIssue::MissingInport {
+                                graph: "main".to_owned(),
+                                node: "Parse".to_owned(),
+                                subgraph: "Parser".to_owned(),
+                                port: "delimiter".to_owned()
+                            },
+                            ReferenceIssue::MissingOutport {
+                                graph: "main".to_owned(),
+                                node: "Parse".to_owned(),
+                                subgraph: "Parser".to_owned(),
+                                port: "words".to_owned()
+                            },
+                            ReferenceIssue::MissingGraph {
+                                graph: "main".to_owned(),
+                                node: "Store".to_owned(),
+                                component: "project/Storage".to_owned()
+                            },
+                        ]
+                    );
+                }
+                'then_they_should_read_as_diagnostics: {
+                    assert_eq!(
+                        issues[0].to_string(),
+                        "main: node Parse uses inport delimiter, which graph Parser doesn't export"
+                    );
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_composition_contracts() {
+        'given_a_shared_subgraph_and_a_parent_using_it: {
+            let registry = ComponentRegistry::from_json_string(
+                r#"[
+                    {"name": "ReadFile", "inPorts": [{"id": "source"}], "outPorts": [{"id": "out", "type": "string"}]},
+                    {"name": "Count", "inPorts": [{"id": "in", "type": "int"}], "outPorts": []},
+                    {"name": "SplitLines", "inPorts": [
+                        {"id": "in", "type": "string"},
+                        {"id": "options", "schema": {"type": "object", "required": ["delimiter"]}}
+                    ], "outPorts": [{"id": "out", "type": "array"}]}
+                ]"#,
+            )
+            .unwrap();
+            let mut parser = Graph::new("Parser", true);
+            parser
+                .add_node("Split", "SplitLines", None)
+                .add_inport("text", "Split", "in", None)
+                .add_inport("options", "Split", "options", None)
+                .add_outport("lines", "Split", "out", None);
+            let mut main = Graph::new("main", true);
+            main.add_node("Read", "ReadFile", None)
+                .add_node("Parse", "project/Parser", None)
+                .add_node("Count", "Count", None)
+                .add_edge("Read", "out", "Parse", "text", None)
+                .add_edge("Parse", "lines", "Count", "in", None)
+                .add_initial(json!({"trim": true}), "Parse", "options", None);
+            'when_checking_the_composition: {
+                let issues = check_composition(&main, &parser, &registry);
+                'then_contract_violations_should_be_reported: {
+                    assert_eq!(
+                        issues,
+                        vec![
+                            CompositionIssue::IncompatibleType {
+                                node: "Parse".to_owned(),
+                                port: "lines".to_owned(),
+                                expected: "int".to_owned(),
+                                actual: "array".to_owned()
+                            },
+                            CompositionIssue::InvalidIip {
+                                node: "Parse".to_owned(),
+                                port: "options".to_owned(),
+                                path: "".to_owned(),
+                                message: "missing property delimiter".to_owned()
+                            },
+                        ]
+                    );
+                }
+            }
+            'when_the_subgraph_renames_a_port: {
+                parser.rename_inport("text", "input");
+                let issues = check_composition(&main, &parser, &registry);
+                'then_the_parent_should_be_flagged: {
+                    assert!(issues.contains(&CompositionIssue::MissingInport {
+                        node: "Parse".to_owned(),
+                        port: "text".to_owned()
+                    }));
+                }
+            }
+        }
+    }
+}
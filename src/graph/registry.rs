@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Port declaration of a component, as listed by FBP runtimes
+///
+/// Ids with a `<placeholder>`, like `out_<key>`, declare a dynamic family
+/// of ports that components such as routers create on demand. Any port
+/// name with non-empty text in place of the placeholder belongs to it.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortSpec {
+    pub id: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub datatype: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub addressable: bool,
+    /// Fewest connections the port needs, on top of `required`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_connections: Option<usize>,
+    /// Most connections the port accepts, e.g. `1` to forbid fan-in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<usize>,
+    /// JSON Schema for configuration IIPs sent to the port, which editors
+    /// can use to render a form
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+}
+
+impl PortSpec {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    /// Connection count bounds, treating `required` ports as needing one
+    pub fn connection_bounds(&self) -> (usize, Option<usize>) {
+        let min = self
+            .min_connections
+            .unwrap_or(0)
+            .max(if self.required { 1 } else { 0 });
+        (min, self.max_connections)
+    }
+
+    /// Whether the id is a pattern for a dynamic family of ports
+    pub fn is_dynamic(&self) -> bool {
+        self.id
+            .find('<')
+            .map(|start| self.id[start..].contains('>'))
+            .unwrap_or(false)
+    }
+
+    /// Whether a port name is this port, or belongs to its family
+    pub fn matches(&self, port: &str) -> bool {
+        port_matches(&self.id, port)
+    }
+}
+
+/// Match a port name against a port id, which may contain `<placeholder>`s
+pub fn port_matches(pattern: &str, port: &str) -> bool {
+    let start = match pattern.find('<') {
+        Some(start) => start,
+        None => return pattern == port,
+    };
+    let end = match pattern[start..].find('>') {
+        Some(end) => start + end,
+        None => return pattern == port,
+    };
+    let rest = &pattern[end + 1..];
+    match port.strip_prefix(&pattern[..start]) {
+        Some(tail) => (1..=tail.len())
+            .filter(|i| tail.is_char_boundary(*i))
+            .any(|i| port_matches(rest, &tail[i..])),
+        None => false,
+    }
+}
+
+/// Component declaration, following the FBP protocol `component` message
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComponentSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub in_ports: Vec<PortSpec>,
+    #[serde(default)]
+    pub out_ports: Vec<PortSpec>,
+}
+
+impl ComponentSpec {
+    /// Declaration of an inport, preferring exact ids over dynamic families
+    pub fn get_inport(&self, port: &str) -> Option<&PortSpec> {
+        find_port(&self.in_ports, port)
+    }
+
+    pub fn get_outport(&self, port: &str) -> Option<&PortSpec> {
+        find_port(&self.out_ports, port)
+    }
+}
+
+fn find_port<'p>(ports: &'p [PortSpec], port: &str) -> Option<&'p PortSpec> {
+    ports
+        .iter()
+        .find(|p| p.id == port)
+        .or_else(|| ports.iter().find(|p| p.is_dynamic() && p.matches(port)))
+}
+
+/// Collection of known components, used to check graphs against the
+/// ports their components actually declare.
+#[derive(Clone, Default)]
+pub struct ComponentRegistry {
+    components: HashMap<String, ComponentSpec>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, spec: ComponentSpec) -> &mut Self {
+        self.components.insert(spec.name.clone(), spec);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ComponentSpec> {
+        self.components.get(name)
+    }
+
+    pub fn components(&self) -> impl Iterator<Item = &ComponentSpec> {
+        self.components.values()
+    }
+
+    /// Load a registry from a JSON array of component declarations
+    pub fn from_json_string(source: &str) -> Result<Self, io::Error> {
+        let specs = serde_json::from_str::<Vec<ComponentSpec>>(source)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let mut registry = Self::new();
+        for spec in specs {
+            registry.register(spec);
+        }
+        Ok(registry)
+    }
+}
@@ -0,0 +1,766 @@
+use std::io::{self, BufRead, Write};
+
+use futures::executor::block_on;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::graph::Graph;
+use super::journal::Journal;
+use super::policy::{MutationKind, MutationTarget};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// Mutation denied by the graph's policy, quota or freeze
+pub const MUTATION_DENIED: i64 = -32001;
+/// Method of a capability the graph doesn't offer
+pub const CAPABILITY_NOT_ENABLED: i64 = -32002;
+/// Missing or unknown secret, or a capability the secret isn't granted
+pub const UNAUTHORIZED: i64 = -32003;
+
+/// Version of the FBP network protocol reported by `getruntime`
+pub const PROTOCOL_VERSION: &str = "0.7";
+
+/// Capabilities `getruntime` can report, in FBP protocol terms
+///
+/// `graph:undo` isn't part of the FBP protocol; it covers `undo` and
+/// `redo`. Networks and components aren't run by a graph, so their
+/// capabilities are never offered, but their methods are recognized so
+/// clients get a clear answer.
+pub const ALL_CAPABILITIES: [&str; 5] = [
+    "graph:readonly",
+    "protocol:graph",
+    "graph:undo",
+    "protocol:network",
+    "protocol:component",
+];
+
+/// Secrets clients must present, with the capabilities each one grants
+///
+/// Requests carry their secret in a top-level `secret` member, as FBP
+/// protocol messages do. A read only client can be given just
+/// `graph:readonly`.
+/// ```no_run
+/// let access = RpcAccess::new().grant("viewer", &["graph:readonly"]);
+/// my_graph.serve_rpc(stdin.lock(), stdout, Some(&access))?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RpcAccess {
+    secrets: Vec<(String, Vec<String>)>,
+}
+
+impl RpcAccess {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let clients presenting `secret` use the given capabilities
+    pub fn grant(mut self, secret: &str, capabilities: &[&str]) -> Self {
+        let capabilities = capabilities.iter().map(|c| (*c).to_owned());
+        match self.secrets.iter_mut().find(|(known, _)| known == secret) {
+            Some((_, granted)) => granted.extend(capabilities),
+            None => self
+                .secrets
+                .push((secret.to_owned(), capabilities.collect())),
+        }
+        self
+    }
+
+    pub fn allows(&self, secret: Option<&str>, capability: &str) -> bool {
+        self.granted(secret)
+            .map(|granted| granted.iter().any(|c| c == capability))
+            .unwrap_or(false)
+    }
+
+    fn knows(&self, secret: Option<&str>) -> bool {
+        self.granted(secret).is_some()
+    }
+
+    /// Capabilities granted to `secret`
+    ///
+    /// Every known secret is compared in full, so the time taken doesn't
+    /// tell how much of a guess was right.
+    fn granted(&self, secret: Option<&str>) -> Option<&Vec<String>> {
+        let secret = secret?.as_bytes();
+        let mut found = None;
+        for (known, granted) in self.secrets.iter() {
+            if constant_time_eq(known.as_bytes(), secret) {
+                found = Some(granted);
+            }
+        }
+        found
+    }
+}
+
+/// Compare two byte strings in time depending only on the length of `b`
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let mut diff = a.len() ^ b.len();
+    for (i, byte) in b.iter().enumerate() {
+        diff |= usize::from(a.get(i).copied().unwrap_or(0) ^ byte);
+    }
+    diff == 0
+}
+
+/// Capability needed to call a method, `None` for `getruntime` and
+/// unknown methods
+fn required_capability(method: &str) -> Option<&'static str> {
+    match method {
+        "getgraph" => Some("graph:readonly"),
+        "undo" | "redo" => Some("graph:undo"),
+        "addnode" | "removenode" | "renamenode" | "changenode" | "addedge" | "removeedge"
+        | "changeedge" | "addinitial" | "removeinitial" | "addinport" | "removeinport"
+        | "addoutport" | "removeoutport" | "addgroup" | "removegroup" => Some("protocol:graph"),
+        "start" | "stop" | "getstatus" | "debug" | "edges" | "persist" => Some("protocol:network"),
+        "list" | "getsource" | "source" => Some("protocol:component"),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Leaf {
+    node: String,
+    port: String,
+    #[serde(default)]
+    index: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct NodeParams {
+    id: String,
+    #[serde(default)]
+    component: String,
+    #[serde(default)]
+    metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Deserialize)]
+struct RenameParams {
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+struct EdgeParams {
+    src: Leaf,
+    tgt: Leaf,
+    #[serde(default)]
+    metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Deserialize)]
+struct InitialSource {
+    data: Value,
+}
+
+#[derive(Deserialize)]
+struct InitialParams {
+    #[serde(default)]
+    src: Option<InitialSource>,
+    tgt: Leaf,
+    #[serde(default)]
+    metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Deserialize)]
+struct PortParams {
+    public: String,
+    #[serde(default)]
+    node: String,
+    #[serde(default)]
+    port: String,
+    #[serde(default)]
+    metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Deserialize)]
+struct GroupParams {
+    name: String,
+    #[serde(default)]
+    nodes: Vec<String>,
+    #[serde(default)]
+    metadata: Option<Map<String, Value>>,
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+/// JSON-RPC 2.0 control surface
+///
+/// Lets scripts, editors and agents drive a graph over stdio or a
+/// socket without any web stack. Methods and their params follow the
+/// graph commands of the FBP network protocol:
+/// `addnode`, `removenode`, `renamenode`, `changenode`, `addedge`,
+/// `removeedge`, `changeedge`, `addinitial`, `removeinitial`,
+/// `addinport`, `removeinport`, `addoutport`, `removeoutport`,
+/// `addgroup` and `removegroup`, plus `getgraph`, `undo` and `redo`.
+/// `getruntime` reports the protocol version and the capabilities the
+/// graph offers; methods of other capabilities are answered with a
+/// `CAPABILITY_NOT_ENABLED` error. Given an `RpcAccess`, callers are
+/// authenticated before anything else: without a known secret,
+/// `getruntime` only reports the protocol version and every other method
+/// is answered with an `UNAUTHORIZED` error. A known secret must also
+/// grant the method's capability, and `getruntime` only lists the
+/// capabilities granted to the caller.
+/// ```text
+/// --> {"jsonrpc": "2.0", "id": 1, "method": "addnode", "params": {"id": "Read", "component": "ReadFile"}}
+/// <-- {"jsonrpc":"2.0","id":1,"result":null}
+/// ```
+/// Mutations the graph would deny are answered with a `MUTATION_DENIED`
+/// error instead of being dropped silently. Requests without an `id`
+/// are notifications and get no response. Batches are not supported.
+impl<'a> Graph<'a> {
+    /// Handle one request, returning the response to send back if any
+    ///
+    /// Without `access`, every caller may use every offered capability.
+    pub fn handle_rpc(&mut self, request: &str, access: Option<&RpcAccess>) -> Option<String> {
+        let request = match serde_json::from_str::<Value>(request) {
+            Ok(request) => request,
+            Err(e) => {
+                return Some(response(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, e.to_string())),
+                ))
+            }
+        };
+        let id = request.get("id").cloned();
+        let method = match (request.get("jsonrpc"), request.get("method")) {
+            (Some(Value::String(version)), Some(Value::String(method))) if version == "2.0" => {
+                method
+            }
+            _ => {
+                let error = RpcError::new(INVALID_REQUEST, "Not a JSON-RPC 2.0 request");
+                return Some(response(id.unwrap_or(Value::Null), Err(error)));
+            }
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let secret = request.get("secret").and_then(|secret| secret.as_str());
+        let result = self.call_rpc(method, params, access, secret);
+        id.map(|id| response(id, result))
+    }
+
+    /// Answer requests read line by line until the input ends
+    pub fn serve_rpc(
+        &mut self,
+        input: impl BufRead,
+        mut output: impl Write,
+        access: Option<&RpcAccess>,
+    ) -> Result<(), io::Error> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(reply) = self.handle_rpc(&line, access) {
+                writeln!(output, "{}", reply)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Capabilities the graph offers over RPC
+    ///
+    /// A frozen graph is read only, and `undo`/`redo` need a journal.
+    pub fn rpc_capabilities(&self) -> Vec<&'static str> {
+        ALL_CAPABILITIES
+            .into_iter()
+            .filter(|capability| match *capability {
+                "graph:readonly" => true,
+                "protocol:graph" => !self.is_frozen(),
+                "graph:undo" => !self.is_frozen() && self.current_revision >= 0,
+                _ => false,
+            })
+            .collect()
+    }
+
+    /// Offered capabilities the holder of `secret` may use
+    fn granted_capabilities(
+        &self,
+        access: Option<&RpcAccess>,
+        secret: Option<&str>,
+    ) -> Vec<&'static str> {
+        self.rpc_capabilities()
+            .into_iter()
+            .filter(|capability| match access {
+                Some(access) => access.allows(secret, capability),
+                None => true,
+            })
+            .collect()
+    }
+
+    fn rpc_guard(&self, kind: MutationKind, target: MutationTarget) -> Result<(), RpcError> {
+        self.check_mutation(kind, &target)
+            .map_err(|e| RpcError::new(MUTATION_DENIED, e.to_string()))
+    }
+
+    fn rpc_guard_all(
+        &self,
+        mutations: Vec<(MutationKind, MutationTarget)>,
+    ) -> Result<(), RpcError> {
+        self.check_mutations(mutations)
+            .map_err(|e| RpcError::new(MUTATION_DENIED, e.to_string()))
+    }
+
+    fn rpc_node(&self, id: &str) -> Result<(), RpcError> {
+        match self.get_node(id) {
+            Some(_) => Ok(()),
+            None => Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("Unknown node {}", id),
+            )),
+        }
+    }
+
+    fn call_rpc(
+        &mut self,
+        method: &str,
+        p: Value,
+        access: Option<&RpcAccess>,
+        secret: Option<&str>,
+    ) -> Result<Value, RpcError> {
+        if let Some(access) = access {
+            if !access.knows(secret) {
+                if method == "getruntime" {
+                    return Ok(json!({ "version": PROTOCOL_VERSION }));
+                }
+                return Err(RpcError::new(UNAUTHORIZED, "Invalid secret"));
+            }
+            if let Some(capability) = required_capability(method) {
+                if !access.allows(secret, capability) {
+                    return Err(RpcError::new(
+                        UNAUTHORIZED,
+                        format!("Capability {} not granted", capability),
+                    ));
+                }
+            }
+        }
+        if let Some(capability) = required_capability(method) {
+            if !self.rpc_capabilities().contains(&capability) {
+                return Err(RpcError::new(
+                    CAPABILITY_NOT_ENABLED,
+                    format!("{} needs capability {}", method, capability),
+                ));
+            }
+        }
+        match method {
+            "getruntime" => {
+                return Ok(json!({
+                    "type": "zflow",
+                    "version": PROTOCOL_VERSION,
+                    "capabilities": self.granted_capabilities(access, secret),
+                    "allCapabilities": ALL_CAPABILITIES,
+                    "graph": self.name,
+                }))
+            }
+            "getgraph" => {
+                return serde_json::to_value(block_on(self.to_json()))
+                    .map_err(|e| RpcError::new(INVALID_REQUEST, e.to_string()))
+            }
+            "undo" => {
+                if self.can_undo() {
+                    self.rpc_guard_all(self.revision_mutations(self.current_revision - 1))?;
+                }
+                self.undo();
+            }
+            "redo" => {
+                if self.can_redo() {
+                    self.rpc_guard_all(self.revision_mutations(self.current_revision + 1))?;
+                }
+                self.redo();
+            }
+            "addnode" => {
+                let p: NodeParams = params(p)?;
+                self.rpc_guard(MutationKind::AddNode, MutationTarget::Node(p.id.clone()))?;
+                self.add_node(&p.id, &p.component, p.metadata);
+            }
+            "removenode" => {
+                let p: NodeParams = params(p)?;
+                self.rpc_node(&p.id)?;
+                self.rpc_guard_all(self.remove_node_mutations(&p.id))?;
+                self.remove_node(&p.id);
+            }
+            "renamenode" => {
+                let p: RenameParams = params(p)?;
+                self.rpc_node(&p.from)?;
+                self.rpc_guard(
+                    MutationKind::RenameNode,
+                    MutationTarget::Node(p.from.clone()),
+                )?;
+                self.rename_node(&p.from, &p.to);
+            }
+            "changenode" => {
+                let p: NodeParams = params(p)?;
+                self.rpc_node(&p.id)?;
+                self.rpc_guard(MutationKind::ChangeNode, MutationTarget::Node(p.id.clone()))?;
+                self.set_node_metadata(&p.id, p.metadata.unwrap_or_default());
+            }
+            "addedge" | "removeedge" | "changeedge" => {
+                let p: EdgeParams = params(p)?;
+                self.rpc_node(&p.src.node)?;
+                self.rpc_node(&p.tgt.node)?;
+                let kind = match method {
+                    "addedge" => MutationKind::AddEdge,
+                    "removeedge" => MutationKind::RemoveEdge,
+                    _ => MutationKind::ChangeEdge,
+                };
+                let target = MutationTarget::Edge {
+                    from: p.src.node.clone(),
+                    to: p.tgt.node.clone(),
+                };
+                self.rpc_guard(kind, target)?;
+                match kind {
+                    MutationKind::AddEdge => {
+                        self.add_edge_index(
+                            &p.src.node,
+                            &p.src.port,
+                            p.src.index,
+                            &p.tgt.node,
+                            &p.tgt.port,
+                            p.tgt.index,
+                            p.metadata,
+                        );
+                    }
+                    MutationKind::RemoveEdge => {
+                        self.remove_edge(
+                            &p.src.node,
+                            &p.src.port,
+                            Some(&p.tgt.node),
+                            Some(&p.tgt.port),
+                        );
+                    }
+                    _ => {
+                        self.set_edge_metadata(
+                            &p.src.node,
+                            &p.src.port,
+                            &p.tgt.node,
+                            &p.tgt.port,
+                            p.metadata.unwrap_or_default(),
+                        );
+                    }
+                }
+            }
+            "addinitial" | "removeinitial" => {
+                let p: InitialParams = params(p)?;
+                self.rpc_node(&p.tgt.node)?;
+                if method == "removeinitial" {
+                    self.rpc_guard(
+                        MutationKind::RemoveInitial,
+                        MutationTarget::Initial(p.tgt.node.clone()),
+                    )?;
+                    self.remove_initial(&p.tgt.node, &p.tgt.port);
+                    return Ok(Value::Null);
+                }
+                let data = match p.src {
+                    Some(src) => src.data,
+                    None => return Err(RpcError::new(INVALID_PARAMS, "Missing src.data")),
+                };
+                self.rpc_guard(
+                    MutationKind::AddInitial,
+                    MutationTarget::Initial(p.tgt.node.clone()),
+                )?;
+                self.add_initial_index(data, &p.tgt.node, &p.tgt.port, p.tgt.index, p.metadata);
+            }
+            "addinport" | "addoutport" => {
+                let p: PortParams = params(p)?;
+                self.rpc_node(&p.node)?;
+                if method == "addinport" {
+                    self.rpc_guard(
+                        MutationKind::AddInport,
+                        MutationTarget::Inport(p.public.clone()),
+                    )?;
+                    self.add_inport(&p.public, &p.node, &p.port, p.metadata);
+                } else {
+                    self.rpc_guard(
+                        MutationKind::AddOutport,
+                        MutationTarget::Outport(p.public.clone()),
+                    )?;
+                    self.add_outport(&p.public, &p.node, &p.port, p.metadata);
+                }
+            }
+            "removeinport" => {
+                let p: PortParams = params(p)?;
+                self.rpc_guard(
+                    MutationKind::RemoveInport,
+                    MutationTarget::Inport(p.public.clone()),
+                )?;
+                self.remove_inport(&p.public);
+            }
+            "removeoutport" => {
+                let p: PortParams = params(p)?;
+                self.rpc_guard(
+                    MutationKind::RemoveOutport,
+                    MutationTarget::Outport(p.public.clone()),
+                )?;
+                self.remove_outport(&p.public);
+            }
+            "addgroup" => {
+                let p: GroupParams = params(p)?;
+                self.rpc_guard(
+                    MutationKind::AddGroup,
+                    MutationTarget::Group(p.name.clone()),
+                )?;
+                self.add_group(&p.name, p.nodes, p.metadata);
+            }
+            "removegroup" => {
+                let p: GroupParams = params(p)?;
+                self.rpc_guard(
+                    MutationKind::RemoveGroup,
+                    MutationTarget::Group(p.name.clone()),
+                )?;
+                self.remove_group(&p.name);
+            }
+            _ => {
+                return Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format!("Unknown method {}", method),
+                ))
+            }
+        }
+        Ok(Value::Null)
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> String {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(error) => json!({"jsonrpc": "2.0", "id": id, "error": error}),
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use crate::graph::policy::ProtectedNodesPolicy;
+    use crate::graph::rpc::RpcAccess;
+    use beady::scenario;
+    use serde_json::{json, Value};
+
+    fn reply(g: &mut Graph, request: Value) -> Value {
+        serde_json::from_str(&g.handle_rpc(&request.to_string(), None).unwrap()).unwrap()
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_json_rpc() {
+        'given_a_graph_behind_json_rpc: {
+            let mut g = Graph::new("rpc", true);
+            g.init_journal(None);
+            'when_sending_edit_commands: {
+                let input = [
+                    json!({"jsonrpc": "2.0", "id": 1, "method": "addnode", "params": {"id": "Read", "component": "ReadFile"}}),
+                    json!({"jsonrpc": "2.0", "id": 2, "method": "addnode", "params": {"id": "Log", "component": "Output"}}),
+                    json!({"jsonrpc": "2.0", "method": "addedge", "params": {"src": {"node": "Read", "port": "out"}, "tgt": {"node": "Log", "port": "in"}}}),
+                    json!({"jsonrpc": "2.0", "id": 3, "method": "addinitial", "params": {"src": {"data": "a.txt"}, "tgt": {"node": "Read", "port": "in"}}}),
+                ]
+                .iter()
+                .map(|request| request.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+                let mut output = Vec::new();
+                g.serve_rpc(input.as_bytes(), &mut output, None).unwrap();
+                'then_they_should_be_applied_and_answered: {
+                    let output = String::from_utf8(output).unwrap();
+                    assert_eq!(output.lines().count(), 3);
+                    assert_eq!(
+                        output.lines().next().unwrap(),
+                        "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":null}"
+                    );
+                    assert_eq!(g.nodes.len(), 2);
+                    assert_eq!(g.edges.len(), 1);
+                    assert_eq!(g.initializers.len(), 1);
+                }
+                'and_then_reading_and_undoing: {
+                    let graph = reply(
+                        &mut g,
+                        json!({"jsonrpc": "2.0", "id": 4, "method": "getgraph"}),
+                    );
+                    reply(&mut g, json!({"jsonrpc": "2.0", "id": 5, "method": "undo"}));
+                    'then_the_graph_should_be_returned_and_rolled_back: {
+                        assert_eq!(
+                            graph["result"]["processes"]["Read"]["component"],
+                            json!("ReadFile")
+                        );
+                        assert_eq!(g.initializers.len(), 0);
+                    }
+                }
+            }
+            'when_sending_bad_requests: {
+                g.add_node("Read", "ReadFile", None)
+                    .add_node("Log", "Output", None)
+                    .add_edge("Read", "out", "Log", "in", None);
+                g.set_mutation_policy(ProtectedNodesPolicy {
+                    nodes: vec!["Read".to_owned()],
+                });
+                'then_they_should_get_error_codes: {
+                    let code =
+                        |g: &mut Graph, request: Value| reply(g, request)["error"]["code"].clone();
+                    assert_eq!(g.handle_rpc("{", None), Some("{\"error\":{\"code\":-32700,\"message\":\"EOF while parsing an object at line 1 column 1\"},\"id\":null,\"jsonrpc\":\"2.0\"}".to_owned()));
+                    assert_eq!(
+                        code(&mut g, json!({"id": 1, "method": "addnode"})),
+                        json!(-32600)
+                    );
+                    assert_eq!(
+                        code(
+                            &mut g,
+                            json!({"jsonrpc": "2.0", "id": 1, "method": "explode"})
+                        ),
+                        json!(-32601)
+                    );
+                    assert_eq!(
+                        code(
+                            &mut g,
+                            json!({"jsonrpc": "2.0", "id": 1, "method": "addnode", "params": {}})
+                        ),
+                        json!(-32602)
+                    );
+                    assert_eq!(
+                        code(
+                            &mut g,
+                            json!({"jsonrpc": "2.0", "id": 1, "method": "removenode", "params": {"id": "Nope"}})
+                        ),
+                        json!(-32602)
+                    );
+                    let denied = reply(
+                        &mut g,
+                        json!({"jsonrpc": "2.0", "id": 1, "method": "removenode", "params": {"id": "Read"}}),
+                    );
+                    assert_eq!(denied["error"]["code"], json!(-32001));
+                    assert_eq!(
+                        denied["error"]["message"],
+                        json!("remove_node on node Read denied: node Read is protected")
+                    );
+                    assert!(g.get_node("Read").is_some());
+                    let neighbour = reply(
+                        &mut g,
+                        json!({"jsonrpc": "2.0", "id": 1, "method": "removenode", "params": {"id": "Log"}}),
+                    );
+                    assert_eq!(neighbour["error"]["code"], json!(-32001));
+                    assert_eq!(g.edges.len(), 1);
+                    let undo = reply(&mut g, json!({"jsonrpc": "2.0", "id": 1, "method": "undo"}));
+                    assert_eq!(undo["error"]["code"], json!(-32001));
+                    assert!(g.get_node("Read").is_some());
+                }
+            }
+            'when_asking_for_the_runtime: {
+                let runtime = reply(
+                    &mut g,
+                    json!({"jsonrpc": "2.0", "id": 1, "method": "getruntime"}),
+                );
+                'then_the_offered_capabilities_should_be_reported: {
+                    assert_eq!(runtime["result"]["version"], json!("0.7"));
+                    assert_eq!(
+                        runtime["result"]["capabilities"],
+                        json!(["graph:readonly", "protocol:graph", "graph:undo"])
+                    );
+                    assert_eq!(runtime["result"]["graph"], json!("rpc"));
+                }
+            }
+            'when_access_is_restricted: {
+                let access = RpcAccess::new()
+                    .grant("viewer", &["graph:readonly"])
+                    .grant("editor", &["graph:readonly", "protocol:graph"]);
+                let call = |g: &mut Graph, method: &str, secret: Value| {
+                    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "secret": secret, "params": {"id": "Read", "component": "ReadFile"}});
+                    let reply = g.handle_rpc(&request.to_string(), Some(&access));
+                    serde_json::from_str::<Value>(&reply.unwrap()).unwrap()
+                };
+                'then_each_secret_should_get_its_capabilities: {
+                    assert_eq!(
+                        call(&mut g, "getruntime", json!("viewer"))["result"]["capabilities"],
+                        json!(["graph:readonly"])
+                    );
+                    assert!(call(&mut g, "getgraph", json!("viewer"))["result"].is_object());
+                    assert_eq!(
+                        call(&mut g, "addnode", json!("viewer"))["error"]["message"],
+                        json!("Capability protocol:graph not granted")
+                    );
+                    assert_eq!(g.nodes.len(), 0);
+                    assert!(call(&mut g, "addnode", json!("editor"))["result"].is_null());
+                    assert_eq!(g.nodes.len(), 1);
+                }
+                'then_unknown_secrets_should_be_rejected: {
+                    let denied = call(&mut g, "getgraph", json!("guess"));
+                    assert_eq!(denied["error"]["code"], json!(-32003));
+                    assert_eq!(denied["error"]["message"], json!("Invalid secret"));
+                    assert_eq!(
+                        call(&mut g, "getgraph", Value::Null)["error"]["code"],
+                        json!(-32003)
+                    );
+                    assert_eq!(
+                        call(&mut g, "getruntime", json!("guess"))["result"],
+                        json!({"version": "0.7"})
+                    );
+                    assert_eq!(
+                        call(&mut g, "getruntime", Value::Null)["result"],
+                        json!({"version": "0.7"})
+                    );
+                }
+                'then_authentication_should_come_before_capability_checks: {
+                    g.freeze();
+                    assert_eq!(
+                        call(&mut g, "start", json!("guess"))["error"]["code"],
+                        json!(-32003)
+                    );
+                    assert_eq!(
+                        call(&mut g, "addnode", json!("guess"))["error"]["code"],
+                        json!(-32003)
+                    );
+                    assert_eq!(
+                        call(&mut g, "addnode", json!("editor"))["error"]["code"],
+                        json!(-32002)
+                    );
+                }
+            }
+            'when_the_graph_is_frozen: {
+                g.freeze();
+                let reply = |g: &mut Graph, method: &str| {
+                    reply(
+                        g,
+                        json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": {"id": "Read", "component": "ReadFile"}}),
+                    )
+                };
+                'then_only_reading_should_be_offered: {
+                    assert_eq!(
+                        reply(&mut g, "getruntime")["result"]["capabilities"],
+                        json!(["graph:readonly"])
+                    );
+                    assert!(reply(&mut g, "getgraph")["result"].is_object());
+                    let denied = reply(&mut g, "addnode");
+                    assert_eq!(denied["error"]["code"], json!(-32002));
+                    assert_eq!(
+                        denied["error"]["message"],
+                        json!("addnode needs capability protocol:graph")
+                    );
+                    assert_eq!(reply(&mut g, "undo")["error"]["code"], json!(-32002));
+                    assert_eq!(g.nodes.len(), 0);
+                }
+                'then_network_methods_should_be_rejected: {
+                    assert_eq!(
+                        reply(&mut g, "start")["error"]["message"],
+                        json!("start needs capability protocol:network")
+                    );
+                }
+            }
+        }
+    }
+}
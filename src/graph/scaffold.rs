@@ -0,0 +1,197 @@
+use super::error_ports::ERROR_PORT;
+use super::graph::Graph;
+use super::registry::{ComponentRegistry, PortSpec};
+
+/// Whether data sent from one port can be received by another
+pub(crate) fn compatible(out: &PortSpec, inp: &PortSpec) -> bool {
+    match (out.datatype.as_deref(), inp.datatype.as_deref()) {
+        (None, _) | (_, None) => true,
+        (Some("all"), _) | (_, Some("all")) => true,
+        (Some(a), Some(b)) => a == b,
+    }
+}
+
+/// Node ID for a component, e.g. `ReadFile` for `core/ReadFile`
+fn node_id(component: &str, taken: &[String]) -> String {
+    let base = component.rsplit('/').next().unwrap_or(component);
+    let mut id = base.to_owned();
+    let mut n = 2;
+    while taken.contains(&id) {
+        id = format!("{}{}", base, n);
+        n += 1;
+    }
+    id
+}
+
+impl<'a> Graph<'a> {
+    /// Build a linear pipeline graph out of registered components
+    ///
+    /// Each component becomes a node, connected to the next one through
+    /// the single outport/inport pair whose datatypes match. `error`
+    /// outports and dynamic port families are never used for the chain.
+    /// When several pairs are compatible, only pairs with identical
+    /// declared types are kept; if that still leaves more than one, an
+    /// error listing them is returned. The first node's inports and the
+    /// last node's outports, apart from dynamic ones, are exported, so the
+    /// graph can be run as is.
+    /// ```no_run
+    /// let g = Graph::pipeline("count", &registry, &["core/ReadFile", "core/SplitLines", "core/CountWords"])?;
+    /// ```
+    pub fn pipeline(
+        name: &str,
+        registry: &ComponentRegistry,
+        components: &[&str],
+    ) -> Result<Graph<'a>, String> {
+        let specs = components
+            .iter()
+            .map(|name| {
+                registry
+                    .get(name)
+                    .ok_or_else(|| format!("Component {} is not registered", name))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mut ids: Vec<String> = Vec::new();
+        for spec in specs.iter() {
+            let id = node_id(&spec.name, &ids);
+            ids.push(id);
+        }
+
+        let mut connections = Vec::new();
+        for (i, pair) in specs.windows(2).enumerate() {
+            let (from, to) = (pair[0], pair[1]);
+            let candidates = from
+                .out_ports
+                .iter()
+                .filter(|out| out.id != ERROR_PORT && !out.is_dynamic())
+                .flat_map(|out| to.in_ports.iter().map(move |inp| (out, inp)))
+                .filter(|(_, inp)| !inp.is_dynamic())
+                .filter(|(out, inp)| compatible(out, inp))
+                .collect::<Vec<_>>();
+            let chosen = if candidates.len() > 1 {
+                candidates
+                    .iter()
+                    .filter(|(out, inp)| out.datatype.is_some() && out.datatype == inp.datatype)
+                    .cloned()
+                    .collect::<Vec<_>>()
+            } else {
+                candidates.clone()
+            };
+            match chosen.as_slice() {
+                [(out, inp)] => connections.push((i, out.id.clone(), inp.id.clone())),
+                [] if candidates.is_empty() => {
+                    return Err(format!(
+                        "No compatible ports between {} and {}",
+                        from.name, to.name
+                    ));
+                }
+                _ => {
+                    let listed = if chosen.is_empty() { &candidates } else { &chosen };
+                    return Err(format!(
+                        "Ambiguous connection between {} and {}: {}",
+                        from.name,
+                        to.name,
+                        listed
+                            .iter()
+                            .map(|(out, inp)| format!("{} -> {}", out.id, inp.id))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+            }
+        }
+
+        let mut graph = Graph::new(name, false);
+        graph.start_transaction("pipeline", None);
+        for (id, spec) in ids.iter().zip(specs.iter()) {
+            graph.add_node(id, &spec.name, None);
+        }
+        for (i, out, inp) in connections.iter() {
+            graph.add_edge(&ids[*i], out, &ids[i + 1], inp, None);
+        }
+        if let (Some(first), Some(last)) = (specs.first(), specs.last()) {
+            for port in first.in_ports.iter().filter(|p| !p.is_dynamic()) {
+                graph.add_inport(&port.id, &ids[0], &port.id, None);
+            }
+            for port in last.out_ports.iter().filter(|p| !p.is_dynamic()) {
+                graph.add_outport(&port.id, &ids[ids.len() - 1], &port.id, None);
+            }
+        }
+        graph.end_transaction("pipeline", None);
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::registry::ComponentRegistry;
+    use beady::scenario;
+
+    const COMPONENTS: &str = r#"[
+        {"name": "core/ReadFile",
+         "inPorts": [{"id": "source", "type": "string"}],
+         "outPorts": [{"id": "out", "type": "string"}, {"id": "error", "type": "object"}]},
+        {"name": "core/SplitLines",
+         "inPorts": [{"id": "in", "type": "string"}, {"id": "delimiter", "type": "string"}],
+         "outPorts": [{"id": "out", "type": "string"}]},
+        {"name": "core/CountWords",
+         "inPorts": [{"id": "in", "type": "string"}],
+         "outPorts": [{"id": "count", "type": "int"}]},
+        {"name": "core/Tokenize",
+         "inPorts": [{"id": "in", "type": "string"}],
+         "outPorts": [{"id": "out", "type": "string"}]}
+    ]"#;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_pipeline_scaffolding() {
+        'given_a_registry: {
+            let registry = ComponentRegistry::from_json_string(COMPONENTS).unwrap();
+            'when_chaining_components_with_unique_ports: {
+                let g = Graph::pipeline(
+                    "count",
+                    &registry,
+                    &["core/ReadFile", "core/Tokenize", "core/CountWords"],
+                )
+                .unwrap();
+                'then_they_should_be_connected_in_order: {
+                    assert_eq!(g.nodes.len(), 3);
+                    assert_eq!(g.nodes[0].id, "ReadFile");
+                    assert_eq!(g.nodes[0].component, "core/ReadFile");
+                    assert_eq!(g.edges.len(), 2);
+                    assert_eq!(g.edges[0].from.port, "out");
+                    assert_eq!(g.edges[0].to.node_id, "Tokenize");
+                    assert_eq!(g.edges[1].to.node_id, "CountWords");
+                }
+                'then_the_ends_should_be_exported: {
+                    assert_eq!(g.inports.get("source").unwrap().process, "ReadFile");
+                    assert_eq!(g.outports.get("count").unwrap().process, "CountWords");
+                }
+            }
+            'when_a_component_is_used_twice: {
+                let g = Graph::pipeline("twice", &registry, &["core/Tokenize", "core/Tokenize"])
+                    .unwrap();
+                'then_node_ids_should_be_unique: {
+                    assert_eq!(g.nodes[0].id, "Tokenize");
+                    assert_eq!(g.nodes[1].id, "Tokenize2");
+                }
+            }
+            'when_the_connection_is_ambiguous: {
+                let result =
+                    Graph::pipeline("split", &registry, &["core/ReadFile", "core/SplitLines"]);
+                'then_it_should_list_the_candidates: {
+                    let err = result.err().unwrap();
+                    assert!(err.contains("out -> in"));
+                    assert!(err.contains("out -> delimiter"));
+                }
+            }
+            'when_a_component_is_unknown: {
+                let result = Graph::pipeline("bad", &registry, &["core/ReadFile", "core/Nope"]);
+                'then_it_should_fail: {
+                    assert_eq!(result.err().unwrap(), "Component core/Nope is not registered");
+                }
+            }
+        }
+    }
+}
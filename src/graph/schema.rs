@@ -0,0 +1,388 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::graph::Graph;
+use super::registry::{port_matches, ComponentRegistry};
+
+/// IIP that doesn't match the schema of the port it's sent to
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IipIssue {
+    pub node: String,
+    pub port: String,
+    /// JSON pointer to the offending part of the IIP, empty for the IIP itself
+    pub path: String,
+    pub message: String,
+}
+
+fn type_matches(name: &str, value: &Value) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64()
+                || value.is_u64()
+                || value.as_f64().map(|f| f.fract() == 0.0).unwrap_or(false)
+        }
+        _ => true,
+    }
+}
+
+/// Check a value against a JSON Schema
+///
+/// Supports the keywords editors need to build forms: `type`, `enum`,
+/// `const`, `properties`, `required`, `additionalProperties` (as a
+/// boolean), `items`, `minimum`, `maximum`, `minLength`, `maxLength`,
+/// `minItems` and `maxItems`. Other keywords are ignored. Returns
+/// `(path, message)` pairs, with JSON pointer paths.
+pub fn validate_value(schema: &Value, value: &Value) -> Vec<(String, String)> {
+    let mut errors = Vec::new();
+    check(schema, value, "", &mut errors);
+    errors
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<(String, String)>) {
+    let schema = match schema.as_object() {
+        Some(schema) => schema,
+        None => return,
+    };
+    let mut fail = |message: String| errors.push((path.to_owned(), message));
+
+    if let Some(expected) = schema.get("type") {
+        let names = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(|n| n.as_str()).collect(),
+            _ => vec![],
+        };
+        if !names.is_empty() && !names.iter().any(|name| type_matches(name, value)) {
+            fail(format!("expected {}, got {}", names.join(" or "), value));
+            return;
+        }
+    }
+    if let Some(options) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !options.contains(value) {
+            fail(format!(
+                "{} is not one of {}",
+                value,
+                Value::from(options.clone())
+            ));
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            fail(format!("expected {}, got {}", constant, value));
+        }
+    }
+    let bound = |key: &str| schema.get(key).and_then(|b| b.as_f64());
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = bound("minimum").filter(|min| n < *min) {
+            fail(format!("{} is less than {}", n, min));
+        }
+        if let Some(max) = bound("maximum").filter(|max| n > *max) {
+            fail(format!("{} is greater than {}", n, max));
+        }
+    }
+    if let Some(s) = value.as_str() {
+        let len = s.chars().count() as f64;
+        if let Some(min) = bound("minLength").filter(|min| len < *min) {
+            fail(format!("shorter than {} characters", min));
+        }
+        if let Some(max) = bound("maxLength").filter(|max| len > *max) {
+            fail(format!("longer than {} characters", max));
+        }
+    }
+    if let Some(items) = value.as_array() {
+        let len = items.len() as f64;
+        if let Some(min) = bound("minItems").filter(|min| len < *min) {
+            fail(format!("fewer than {} items", min));
+        }
+        if let Some(max) = bound("maxItems").filter(|max| len > *max) {
+            fail(format!("more than {} items", max));
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                check(item_schema, item, &format!("{}/{}", path, i), errors);
+            }
+        }
+    }
+    if let Some(object) = value.as_object() {
+        let properties = schema.get("properties").and_then(|p| p.as_object());
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for key in required.iter().filter_map(|k| k.as_str()) {
+                if !object.contains_key(key) {
+                    errors.push((path.to_owned(), format!("missing property {}", key)));
+                }
+            }
+        }
+        for (key, item) in object.iter() {
+            let item_path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+            match properties.and_then(|p| p.get(key)) {
+                Some(property) => check(property, item, &item_path, errors),
+                None => {
+                    if schema.get("additionalProperties") == Some(&Value::Bool(false)) {
+                        errors.push((item_path, "unexpected property".to_owned()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Infer a JSON Schema describing every one of the sample values
+///
+/// Produces `type`, plus `properties` and `required` for objects (a key
+/// is required if every sampled object has it) and `items` for arrays.
+/// Integers and floats seen together are merged into `number`. No
+/// samples give the empty schema, which accepts anything.
+pub fn infer_schema(samples: &[Value]) -> Value {
+    let mut types: Vec<&str> = Vec::new();
+    for sample in samples {
+        let name = type_name(sample);
+        if !types.contains(&name) {
+            types.push(name);
+        }
+    }
+    if types.contains(&"number") {
+        types.retain(|name| *name != "integer");
+    }
+
+    let mut schema = Map::new();
+    match types.as_slice() {
+        [] => return Value::Object(schema),
+        [name] => schema.insert("type".to_owned(), json!(name)),
+        names => schema.insert("type".to_owned(), json!(names)),
+    };
+    let objects = samples
+        .iter()
+        .filter_map(|sample| sample.as_object())
+        .collect::<Vec<_>>();
+    if !objects.is_empty() {
+        let keys = objects
+            .iter()
+            .flat_map(|object| object.keys())
+            .collect::<BTreeSet<&String>>();
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for key in keys {
+            let values = objects
+                .iter()
+                .filter_map(|object| object.get(key))
+                .cloned()
+                .collect::<Vec<Value>>();
+            if values.len() == objects.len() {
+                required.push(json!(key));
+            }
+            properties.insert(key.clone(), infer_schema(&values));
+        }
+        schema.insert("properties".to_owned(), Value::Object(properties));
+        schema.insert("required".to_owned(), Value::Array(required));
+    }
+    if types.contains(&"array") {
+        let items = samples
+            .iter()
+            .filter_map(|sample| sample.as_array())
+            .flatten()
+            .cloned()
+            .collect::<Vec<Value>>();
+        schema.insert("items".to_owned(), infer_schema(&items));
+    }
+    Value::Object(schema)
+}
+
+impl<'a> Graph<'a> {
+    /// Document the data flowing through an edge
+    ///
+    /// Infers a schema from packets sampled on the edge, e.g. during a
+    /// test run, and stores it in the edge's `schema` metadata.
+    /// ```no_run
+    /// my_graph.infer_edge_schema("Read", "out", "Parse", "in", &sampled);
+    /// ```
+    pub fn infer_edge_schema(
+        &mut self,
+        node: &str,
+        port: &str,
+        node2: &str,
+        port2: &str,
+        samples: &[Value],
+    ) -> &mut Self {
+        let mut metadata = Map::new();
+        metadata.insert("schema".to_owned(), infer_schema(samples));
+        self.set_edge_metadata(node, port, node2, port2, metadata)
+    }
+
+    /// Check IIPs against the schemas their ports declare
+    ///
+    /// IIPs going to nodes whose component isn't registered, or to ports
+    /// without a schema, are not checked.
+    /// ```no_run
+    /// for issue in my_graph.validate_iips(&registry) {
+    ///     println!("{}.{}{}: {}", issue.node, issue.port, issue.path, issue.message);
+    /// }
+    /// ```
+    pub fn validate_iips(&self, registry: &ComponentRegistry) -> Vec<IipIssue> {
+        let mut issues = Vec::new();
+        for iip in self.initializers.iter() {
+            let (to, from) = match (iip.to.as_ref(), iip.from.as_ref()) {
+                (Some(to), Some(from)) => (to, from),
+                _ => continue,
+            };
+            let schema = self
+                .get_node(&to.node_id)
+                .and_then(|node| registry.get(&node.component))
+                .and_then(|spec| {
+                    spec.in_ports
+                        .iter()
+                        .find(|port| self.get_port_name(&port.id) == to.port)
+                        .or_else(|| {
+                            spec.in_ports
+                                .iter()
+                                .find(|port| port_matches(&self.get_port_name(&port.id), &to.port))
+                        })
+                })
+                .and_then(|port| port.schema.as_ref());
+            if let Some(schema) = schema {
+                for (path, message) in validate_value(schema, &from.data) {
+                    issues.push(IipIssue {
+                        node: to.node_id.clone(),
+                        port: to.port.clone(),
+                        path,
+                        message,
+                    });
+                }
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::registry::ComponentRegistry;
+    use crate::graph::schema::{validate_value, IipIssue};
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_iip_schemas() {
+        'given_a_component_with_a_configuration_schema: {
+            let registry = ComponentRegistry::from_json_string(
+                r#"[{
+                    "name": "HttpRequest",
+                    "inPorts": [
+                        {"id": "url", "type": "string", "schema": {"type": "string", "minLength": 1}},
+                        {"id": "options", "type": "object", "schema": {
+                            "type": "object",
+                            "required": ["method"],
+                            "additionalProperties": false,
+                            "properties": {
+                                "method": {"enum": ["GET", "POST"]},
+                                "retries": {"type": "integer", "minimum": 0, "maximum": 5},
+                                "headers": {"type": "array", "items": {"type": "string"}}
+                            }
+                        }}
+                    ],
+                    "outPorts": [{"id": "out"}]
+                }]"#,
+            )
+            .unwrap();
+            'when_the_iips_match: {
+                let mut g = Graph::new("", true);
+                g.add_node("Fetch", "HttpRequest", None)
+                    .add_initial(json!("http://example.com"), "Fetch", "url", None)
+                    .add_initial(
+                        json!({"method": "GET", "retries": 2}),
+                        "Fetch",
+                        "options",
+                        None,
+                    );
+                'then_there_should_be_no_issues: {
+                    assert!(g.validate_iips(&registry).is_empty());
+                }
+            }
+            'when_the_iips_are_invalid: {
+                let mut g = Graph::new("", true);
+                let options = json!({"retries": 9, "headers": ["a", 1], "proxy": true});
+                g.add_node("Fetch", "HttpRequest", None)
+                    .add_initial(json!(""), "Fetch", "url", None)
+                    .add_initial(options, "Fetch", "options", None);
+                let issues = g.validate_iips(&registry);
+                let issue = |port: &str, path: &str, message: &str| IipIssue {
+                    node: "Fetch".to_owned(),
+                    port: port.to_owned(),
+                    path: path.to_owned(),
+                    message: message.to_owned(),
+                };
+                'then_each_problem_should_be_reported_with_its_path: {
+                    assert_eq!(
+                        issues,
+                        vec![
+                            issue("url", "", "shorter than 1 characters"),
+                            issue("options", "", "missing property method"),
+                            issue("options", "/headers/1", "expected string, got 1"),
+                            issue("options", "/proxy", "unexpected property"),
+                            issue("options", "/retries", "9 is greater than 5"),
+                        ]
+                    );
+                }
+            }
+        }
+    }
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_edge_schema_inference() {
+        'given_packets_sampled_on_an_edge: {
+            let samples = vec![
+                json!({"id": 1, "name": "a", "tags": ["x"]}),
+                json!({"id": 2.5, "name": "b", "tags": [], "extra": null}),
+            ];
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Parse", "ParseJson", None)
+                .add_edge("Read", "out", "Parse", "in", None);
+            'when_inferring_the_schema: {
+                g.infer_edge_schema("Read", "out", "Parse", "in", &samples);
+                let schema = g.edges[0].metadata.clone().unwrap()["schema"].clone();
+                'then_it_should_describe_the_data: {
+                    assert_eq!(
+                        schema,
+                        json!({
+                            "type": "object",
+                            "properties": {
+                                "extra": {"type": "null"},
+                                "id": {"type": "number"},
+                                "name": {"type": "string"},
+                                "tags": {"type": "array", "items": {"type": "string"}}
+                            },
+                            "required": ["id", "name", "tags"]
+                        })
+                    );
+                }
+                'then_every_sample_should_validate: {
+                    for sample in samples.iter() {
+                        assert!(validate_value(&schema, sample).is_empty());
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::internal::event_manager::EventManager;
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::policy::{MutationKind, MutationTarget};
+use super::types::GraphEdge;
+
+/// Nodes or edges picked out for a bulk operation
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selector {
+    /// Nodes listed in the group
+    Group(String),
+    /// Nodes using the component
+    Component(String),
+    /// Nodes with the given IDs
+    Nodes(Vec<String>),
+    /// Nodes whose metadata has the key set to the value
+    Metadata(String, Value),
+    /// Edges connecting two nodes matched by the inner selector
+    EdgesWithin(Box<Selector>),
+}
+
+impl<'a> Graph<'a> {
+    /// IDs of the nodes matching a node selector, in graph order
+    pub fn select_nodes(&self, selector: &Selector) -> Vec<String> {
+        let group = match selector {
+            Selector::Group(name) => self.groups.iter().find(|group| &group.name == name),
+            _ => None,
+        };
+        self.nodes
+            .iter()
+            .filter(|node| match selector {
+                Selector::Group(_) => group.map(|g| g.nodes.contains(&node.id)).unwrap_or(false),
+                Selector::Component(component) => &node.component == component,
+                Selector::Nodes(ids) => ids.contains(&node.id),
+                Selector::Metadata(key, value) => node
+                    .metadata
+                    .as_ref()
+                    .and_then(|meta| meta.get(key))
+                    .map(|v| v == value)
+                    .unwrap_or(false),
+                Selector::EdgesWithin(_) => false,
+            })
+            .map(|node| node.id.clone())
+            .collect()
+    }
+
+    /// Edges matching an edge selector, in graph order
+    pub fn select_edges(&self, selector: &Selector) -> Vec<GraphEdge> {
+        let Selector::EdgesWithin(inner) = selector else {
+            return Vec::new();
+        };
+        let nodes = self.select_nodes(inner);
+        self.edges
+            .iter()
+            .filter(|edge| nodes.contains(&edge.from.node_id) && nodes.contains(&edge.to.node_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Apply a metadata patch to every node or edge matching a selector
+    ///
+    /// All changes are made in a single transaction. Besides the usual
+    /// `change_node` and `change_edge` events, one `update_metadata_where`
+    /// event carries the IDs of the changed nodes, the changed edges and
+    /// the patch. Returns the number of nodes and edges changed.
+    ///
+    /// Every node and edge is checked against the freeze flag and the
+    /// mutation policy first; if any of them is denied nothing is changed
+    /// and 0 is returned.
+    /// ```no_run
+    /// my_graph.update_metadata_where(&Selector::Group("ingest".to_owned()), json!({"region": "eu"}).as_object().cloned().unwrap());
+    /// ```
+    pub fn update_metadata_where(
+        &mut self,
+        selector: &Selector,
+        patch: Map<String, Value>,
+    ) -> usize {
+        let nodes = self.select_nodes(selector);
+        let edges = self.select_edges(selector);
+        if nodes.is_empty() && edges.is_empty() {
+            return 0;
+        }
+        let mutations = nodes
+            .iter()
+            .map(|id| (MutationKind::ChangeNode, MutationTarget::Node(id.clone())))
+            .chain(edges.iter().map(|edge| {
+                let target = MutationTarget::Edge {
+                    from: edge.from.node_id.clone(),
+                    to: edge.to.node_id.clone(),
+                };
+                (MutationKind::ChangeEdge, target)
+            }))
+            .collect::<Vec<_>>();
+        if self.permit_all(mutations).is_err() {
+            return 0;
+        }
+        self.vetted(|graph| {
+            graph.check_transaction_start();
+            for id in nodes.iter() {
+                graph.set_node_metadata(id, patch.clone());
+            }
+            for edge in edges.iter() {
+                graph.set_edge_metadata(
+                    &edge.from.node_id,
+                    &edge.from.port,
+                    &edge.to.node_id,
+                    &edge.to.port,
+                    patch.clone(),
+                );
+            }
+            let count = nodes.len() + edges.len();
+            graph.emit("update_metadata_where", &(nodes, edges, patch));
+            graph.check_transaction_end();
+            count
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use crate::graph::policy::ProtectedNodesPolicy;
+    use crate::graph::select::Selector;
+    use crate::graph::types::GraphEdge;
+    use crate::internal::event_manager::EventManager;
+    use beady::scenario;
+    use serde_json::{json, Map, Value};
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_bulk_metadata() {
+        'given_a_graph_with_an_ingest_group: {
+            let mut g = Graph::new("", true);
+            g.init_journal(None);
+            g.add_node("Read", "ReadFile", None)
+                .add_node(
+                    "Parse",
+                    "ParseJson",
+                    json!({"region": "us"}).as_object().cloned(),
+                )
+                .add_node("Store", "WriteFile", None)
+                .add_edge("Read", "out", "Parse", "in", None)
+                .add_edge("Parse", "out", "Store", "in", None)
+                .add_group("ingest", vec!["Read".to_owned(), "Parse".to_owned()], None);
+            let revision = g.current_revision;
+            let events = Rc::new(RefCell::new(0));
+            let seen = events.clone();
+            g.connect(
+                "update_metadata_where",
+                move |_, data| {
+                    if data
+                        .downcast_ref::<(Vec<String>, Vec<GraphEdge>, Map<String, Value>)>()
+                        .is_some()
+                    {
+                        *seen.borrow_mut() += 1;
+                    }
+                },
+                false,
+            );
+            'when_patching_the_group: {
+                let patch = json!({"region": "eu"}).as_object().cloned().unwrap();
+                let count = g.update_metadata_where(&Selector::Group("ingest".to_owned()), patch);
+                'then_every_member_should_be_changed_at_once: {
+                    assert_eq!(count, 2);
+                    for id in ["Read", "Parse"] {
+                        let meta = g.get_node(id).unwrap().metadata.clone().unwrap();
+                        assert_eq!(meta["region"], json!("eu"));
+                    }
+                    assert!(g.get_node("Store").unwrap().metadata.is_none());
+                    assert_eq!(g.current_revision, revision + 1);
+                    assert_eq!(*events.borrow(), 1);
+                }
+            }
+            'when_patching_edges_within_the_group: {
+                let patch = json!({"route": 1}).as_object().cloned().unwrap();
+                let selector =
+                    Selector::EdgesWithin(Box::new(Selector::Group("ingest".to_owned())));
+                let count = g.update_metadata_where(&selector, patch);
+                'then_only_internal_edges_should_be_changed: {
+                    assert_eq!(count, 1);
+                    let edge = g.get_edge("Read", "out", "Parse", "in").unwrap();
+                    assert_eq!(edge.metadata.clone().unwrap()["route"], json!(1));
+                    let edge = g.get_edge("Parse", "out", "Store", "in").unwrap();
+                    assert!(edge.metadata.clone().unwrap().get("route").is_none());
+                }
+            }
+            'when_a_selected_node_is_protected: {
+                g.set_mutation_policy(ProtectedNodesPolicy {
+                    nodes: vec!["Parse".to_owned()],
+                });
+                let patch = json!({"region": "eu"}).as_object().cloned().unwrap();
+                let count = g.update_metadata_where(&Selector::Group("ingest".to_owned()), patch);
+                'then_no_member_should_be_changed: {
+                    assert_eq!(count, 0);
+                    assert!(g.get_node("Read").unwrap().metadata.is_none());
+                    let meta = g.get_node("Parse").unwrap().metadata.clone().unwrap();
+                    assert_eq!(meta["region"], json!("us"));
+                    assert_eq!(g.current_revision, revision);
+                    assert_eq!(*events.borrow(), 0);
+                }
+            }
+            'when_the_graph_is_frozen: {
+                g.freeze();
+                let patch = json!({"route": 1}).as_object().cloned().unwrap();
+                let selector =
+                    Selector::EdgesWithin(Box::new(Selector::Group("ingest".to_owned())));
+                'then_edges_should_be_left_alone: {
+                    assert_eq!(g.update_metadata_where(&selector, patch), 0);
+                    assert_eq!(g.current_revision, revision);
+                }
+            }
+            'when_nothing_matches: {
+                let patch = json!({"x": 1}).as_object().cloned().unwrap();
+                let selector = Selector::Metadata("region".to_owned(), json!("ap"));
+                'then_the_graph_should_be_left_alone: {
+                    assert_eq!(g.update_metadata_where(&selector, patch), 0);
+                    assert_eq!(g.current_revision, revision);
+                    assert_eq!(*events.borrow(), 0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::any::Any;
+use std::collections::{BTreeSet, HashMap};
+
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::types::GraphNode;
+
+/// Node metadata key holding the node's tags, as an array of strings
+pub const TAGS: &str = "tags";
+
+/// Node IDs by tag
+pub(crate) type TagIndex = HashMap<String, BTreeSet<String>>;
+
+impl GraphNode {
+    pub fn tags(&self) -> Vec<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|meta| meta.get(TAGS))
+            .and_then(|tags| tags.as_array())
+            .map(|tags| tags.iter().filter_map(|tag| tag.as_str()).collect())
+            .unwrap_or_default()
+    }
+}
+
+fn tags_patch(tags: Vec<&str>) -> Map<String, Value> {
+    let mut patch = Map::new();
+    patch.insert(TAGS.to_owned(), Value::from(tags));
+    patch
+}
+
+fn learn(index: &mut TagIndex, node: &GraphNode) {
+    for tag in node.tags() {
+        let ids = index.entry(tag.to_owned()).or_default();
+        ids.insert(node.id.clone());
+    }
+}
+
+fn forget(index: &mut TagIndex, id: &str) {
+    index.retain(|_, ids| {
+        ids.remove(id);
+        !ids.is_empty()
+    })
+}
+
+/// Tagging nodes
+///
+/// Tags live in the `tags` metadata key, so they are saved with the graph
+/// and journaled like any other metadata change. The graph keeps an index
+/// of them, updated from its own node events, so looking up the nodes
+/// carrying a tag doesn't scan the graph:
+/// ```no_run
+/// my_graph.tag_node("Read", "io").tag_node("Write", "io");
+/// let io_nodes = my_graph.nodes_with_tag("io");
+/// ```
+impl<'a> Graph<'a> {
+    pub fn tag_node(&mut self, id: &str, tag: &str) -> &mut Self {
+        let patch = match self.get_node(id) {
+            Some(node) if !node.tags().contains(&tag) => {
+                let mut tags = node.tags();
+                tags.push(tag);
+                tags_patch(tags)
+            }
+            _ => return self,
+        };
+        self.set_node_metadata(id, patch)
+    }
+
+    pub fn untag_node(&mut self, id: &str, tag: &str) -> &mut Self {
+        let patch = match self.get_node(id) {
+            Some(node) if node.tags().contains(&tag) => {
+                tags_patch(node.tags().into_iter().filter(|t| *t != tag).collect())
+            }
+            _ => return self,
+        };
+        self.set_node_metadata(id, patch)
+    }
+
+    pub fn has_tag(&self, id: &str, tag: &str) -> bool {
+        match self.tag_index.as_ref() {
+            Some(index) => index.get(tag).map(|ids| ids.contains(id)).unwrap_or(false),
+            None => self
+                .get_node(id)
+                .map(|node| node.tags().contains(&tag))
+                .unwrap_or(false),
+        }
+    }
+
+    /// IDs of the nodes carrying a tag, in ID order
+    pub fn nodes_with_tag(&self, tag: &str) -> Vec<&str> {
+        match self.tag_index.as_ref() {
+            Some(index) => index
+                .get(tag)
+                .map(|ids| ids.iter().map(|id| id.as_str()).collect())
+                .unwrap_or_default(),
+            None => {
+                let mut ids = self
+                    .nodes
+                    .iter()
+                    .filter(|node| node.tags().contains(&tag))
+                    .map(|node| node.id.as_str())
+                    .collect::<Vec<&str>>();
+                ids.sort_unstable();
+                ids
+            }
+        }
+    }
+
+    /// Drop the tag index after the nodes were changed without events; it
+    /// is rebuilt on the next node event
+    pub(crate) fn invalidate_tags(&mut self) {
+        self.tag_index = None;
+    }
+
+    /// Keep the tag index in step with a node event
+    pub(crate) fn index_tags(&mut self, event: &str, data: &dyn Any) {
+        if !matches!(
+            event,
+            "add_node" | "remove_node" | "rename_node" | "change_node"
+        ) {
+            return;
+        }
+        if self.tag_index.is_none() {
+            let mut index = TagIndex::new();
+            for node in self.nodes.iter() {
+                learn(&mut index, node);
+            }
+            self.tag_index = Some(index);
+        }
+        let index = self.tag_index.as_mut().unwrap();
+
+        match event {
+            "add_node" => {
+                if let Some(node) = data.downcast_ref::<GraphNode>() {
+                    learn(index, node);
+                }
+            }
+            "remove_node" => {
+                if let Some(node) = data.downcast_ref::<GraphNode>() {
+                    forget(index, &node.id);
+                }
+            }
+            "rename_node" => {
+                if let Some((old_id, new_id)) = data.downcast_ref::<(String, String)>() {
+                    for ids in index.values_mut() {
+                        if ids.remove(old_id) {
+                            ids.insert(new_id.clone());
+                        }
+                    }
+                }
+            }
+            _ => {
+                type Change = (GraphNode, Option<Map<String, Value>>, Map<String, Value>);
+                if let Some((node, _, _)) = data.downcast_ref::<Change>() {
+                    forget(index, &node.id);
+                    learn(index, node);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_node_tags() {
+        'given_a_graph_with_tagged_nodes: {
+            let mut g = Graph::new("", true);
+            g.init_journal(None);
+            g.add_node(
+                "Read",
+                "ReadFile",
+                json!({"tags": ["io"]}).as_object().cloned(),
+            )
+            .add_node("Parse", "ParseJson", None)
+            .add_node("Write", "WriteFile", None)
+            .tag_node("Write", "io")
+            .tag_node("Parse", "cpu");
+            'then_nodes_should_be_found_by_tag: {
+                assert_eq!(g.nodes_with_tag("io"), vec!["Read", "Write"]);
+                assert_eq!(g.nodes_with_tag("cpu"), vec!["Parse"]);
+                assert!(g.nodes_with_tag("gpu").is_empty());
+                assert!(g.has_tag("Write", "io"));
+            }
+            'then_tags_should_be_stored_in_metadata: {
+                let meta = g.get_node("Write").unwrap().metadata.clone().unwrap();
+                assert_eq!(meta.get("tags"), Some(&json!(["io"])));
+            }
+            'when_untagging_a_node: {
+                g.untag_node("Parse", "cpu");
+                'then_the_tag_should_be_gone: {
+                    assert!(g.nodes_with_tag("cpu").is_empty());
+                    assert!(g.get_node("Parse").unwrap().tags().is_empty());
+                }
+            }
+            'when_renaming_and_removing_nodes: {
+                g.rename_node("Read", "Load").remove_node("Write");
+                'then_the_index_should_follow: {
+                    assert_eq!(g.nodes_with_tag("io"), vec!["Load"]);
+                }
+                'and_then_undoing: {
+                    g.undo();
+                    g.undo();
+                    'then_the_tags_should_be_restored: {
+                        assert_eq!(g.nodes_with_tag("io"), vec!["Read", "Write"]);
+                    }
+                }
+            }
+            'when_editing_metadata_in_place: {
+                for node in g.nodes_mut().iter_mut() {
+                    node.metadata = json!({"tags": ["all"]}).as_object().cloned();
+                }
+                'then_the_index_should_be_updated: {
+                    assert_eq!(g.nodes_with_tag("all"), vec!["Parse", "Read", "Write"]);
+                    assert!(g.nodes_with_tag("io").is_empty());
+                }
+            }
+            'when_changing_nodes_directly: {
+                g.raw_parts().nodes.clear();
+                'then_queries_should_fall_back_to_a_scan: {
+                    assert!(g.nodes_with_tag("io").is_empty());
+                }
+            }
+        }
+    }
+}
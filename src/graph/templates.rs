@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::env;
+
+use serde_json::{Map, Value};
+
+use super::graph::Graph;
+use super::policy::{MutationKind, MutationTarget};
+
+/// Replace `{{name}}` placeholders in a string
+///
+/// Whitespace around names is ignored. Placeholders `lookup` knows
+/// nothing about are left as they are.
+pub fn render_template(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        out.push_str(&rest[..start]);
+        match lookup(rest[start + 2..end].trim()) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn render_value(value: &Value, lookup: &dyn Fn(&str) -> Option<String>) -> Value {
+    match value {
+        Value::String(s) if s.contains("{{") => Value::String(render_template(s, lookup)),
+        Value::Array(items) => {
+            Value::Array(items.iter().map(|v| render_value(v, lookup)).collect())
+        }
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(k, v)| (k.clone(), render_value(v, lookup)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Resolve `{{...}}` templates in node metadata
+    ///
+    /// Strings anywhere in a node's metadata can refer to:
+    /// - `id` and `component` of the node
+    /// - `index`, the node's position among the nodes using the same
+    ///   component, starting at 1
+    /// - `env.NAME`, the `NAME` environment variable, if it's one of
+    ///   `env_vars`; other variables are left as placeholders so graphs
+    ///   can't read secrets from the environment
+    /// - any of the given `vars`, with strings inserted as is and other
+    ///   values as JSON
+    ///
+    /// Changed keys are written back with `set_node_metadata` in a single
+    /// transaction, so the resolution is journaled and validated like any
+    /// other edit. Every changed node is checked against the freeze flag
+    /// and the mutation policy first; if any of them is denied nothing is
+    /// changed.
+    /// ```no_run
+    /// my_graph.add_node("Worker", "core/Fetch", json!({"label": "{{component}} #{{index}}"}).as_object().cloned());
+    /// my_graph.resolve_metadata_templates(&Map::new(), &["REGION"]);
+    /// ```
+    pub fn resolve_metadata_templates(
+        &mut self,
+        vars: &Map<String, Value>,
+        env_vars: &[&str],
+    ) -> &mut Self {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut resolved = Vec::new();
+        for node in self.nodes.iter() {
+            let index = seen.entry(node.component.clone()).or_insert(0);
+            *index += 1;
+            let metadata = match node.metadata.as_ref() {
+                Some(metadata) => metadata,
+                None => continue,
+            };
+            let index = *index;
+            let lookup = |name: &str| -> Option<String> {
+                match name {
+                    "id" => Some(node.id.clone()),
+                    "component" => Some(node.component.clone()),
+                    "index" => Some(index.to_string()),
+                    _ => match name.strip_prefix("env.") {
+                        Some(var) if env_vars.contains(&var) => env::var(var).ok(),
+                        Some(_) => None,
+                        None => vars.get(name).map(|value| match value {
+                            Value::String(s) => s.clone(),
+                            other => other.to_string(),
+                        }),
+                    },
+                }
+            };
+            let changed = metadata
+                .iter()
+                .map(|(key, value)| (key, value, render_value(value, &lookup)))
+                .filter(|(_, value, rendered)| value != &rendered)
+                .map(|(key, _, rendered)| (key.clone(), rendered))
+                .collect::<Map<String, Value>>();
+            if !changed.is_empty() {
+                resolved.push((node.id.clone(), changed));
+            }
+        }
+        if resolved.is_empty() {
+            return self;
+        }
+        let mutations = resolved
+            .iter()
+            .map(|(id, _)| (MutationKind::ChangeNode, MutationTarget::Node(id.clone())));
+        if self.permit_all(mutations).is_err() {
+            return self;
+        }
+        self.vetted(|graph| {
+            graph.check_transaction_start();
+            for (id, metadata) in resolved {
+                graph.set_node_metadata(&id, metadata);
+            }
+            graph.check_transaction_end();
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::policy::ProtectedNodesPolicy;
+    use crate::graph::templates::render_template;
+    use beady::scenario;
+    use serde_json::{json, Map};
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_metadata_templates() {
+        'given_nodes_with_templated_metadata: {
+            let label = json!({"label": "{{component}} #{{index}}"});
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("A", "Fetch", label.as_object().cloned())
+                .add_node("B", "Fetch", label.as_object().cloned())
+                .add_node(
+                    "Log",
+                    "Output",
+                    json!({"title": ["{{ id }} in {{stage}}", "{{limit}}"], "x": 5, "note": "{{unknown}}", "env": ["{{env.ZFLOW_TEMPLATE_REGION}}", "{{env.ZFLOW_TEMPLATE_TOKEN}}"]})
+                        .as_object()
+                        .cloned(),
+                );
+            'when_resolving_them: {
+                std::env::set_var("ZFLOW_TEMPLATE_REGION", "eu");
+                std::env::set_var("ZFLOW_TEMPLATE_TOKEN", "secret");
+                let vars = json!({"stage": "prod", "limit": 10});
+                g.resolve_metadata_templates(vars.as_object().unwrap(), &["ZFLOW_TEMPLATE_REGION"]);
+                let meta = |id: &str| g.get_node(id).unwrap().metadata.clone().unwrap();
+                'then_placeholders_should_be_filled_per_node: {
+                    assert_eq!(meta("A")["label"], json!("Fetch #1"));
+                    assert_eq!(meta("B")["label"], json!("Fetch #2"));
+                    assert_eq!(meta("Log")["title"], json!(["Log in prod", "10"]));
+                }
+                'then_unknown_placeholders_should_be_kept: {
+                    assert_eq!(meta("Log")["note"], json!("{{unknown}}"));
+                    assert_eq!(meta("Log")["x"], json!(5));
+                }
+                'then_only_allowed_environment_variables_should_be_read: {
+                    assert_eq!(
+                        meta("Log")["env"],
+                        json!(["eu", "{{env.ZFLOW_TEMPLATE_TOKEN}}"])
+                    );
+                }
+            }
+        }
+        'given_a_templated_node_next_to_a_protected_one: {
+            let label = json!({"label": "{{component}}"});
+            let mut g = Graph::new("", true);
+            g.add_node("A", "Fetch", label.as_object().cloned())
+                .add_node("B", "Fetch", label.as_object().cloned());
+            g.set_mutation_policy(ProtectedNodesPolicy {
+                nodes: vec!["B".to_owned()],
+            });
+            'when_resolving_them: {
+                g.resolve_metadata_templates(&Map::new(), &[]);
+                'then_no_node_should_change: {
+                    assert_eq!(
+                        g.get_node("A").unwrap().metadata,
+                        label.as_object().cloned()
+                    );
+                    assert_eq!(
+                        g.get_node("B").unwrap().metadata,
+                        label.as_object().cloned()
+                    );
+                }
+            }
+        }
+        'given_a_template_string: {
+            'when_a_placeholder_is_not_closed: {
+                let rendered = render_template("{{a}} and {{b", |_| Some("x".to_owned()));
+                'then_it_should_be_left_alone: {
+                    assert_eq!(rendered, "x and {{b");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::io;
+
+use futures::executor::block_on;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::graph::Graph;
+use super::journal::{Journal, JournalStore};
+use super::types::GraphJson;
+
+/// A flowtrace paired with the graph revision it was recorded against
+///
+/// Lets debuggers answer "what did the graph look like when this trace
+/// was recorded" by loading both together.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceBundle {
+    pub graph_rev: usize,
+    pub graph: GraphJson,
+    /// Flowtrace as recorded by the runtime
+    pub trace: Value,
+}
+
+impl TraceBundle {
+    pub fn to_json_string(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    pub fn from_json_string(source: &str) -> Result<Self, io::Error> {
+        serde_json::from_str::<TraceBundle>(source)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Graph as it was at the bundled revision
+    pub fn load_graph<'a>(&self) -> Graph<'a> {
+        block_on(Graph::from_json(self.graph.clone(), None))
+    }
+}
+
+impl<'a> Graph<'a> {
+    /// Rebuild the graph as it was at a journal revision
+    ///
+    /// Replays the stored transactions into a fresh graph, leaving this
+    /// graph at its current revision.
+    pub fn graph_at_revision(&mut self, rev: usize) -> Option<Graph<'a>> {
+        if rev >= self.count_transactions() {
+            return None;
+        }
+        let mut graph = Graph::new(&self.name, self.case_sensitive);
+        for r in 0..=rev {
+            if let Some(entries) = self.fetch_transaction(r).cloned() {
+                for entry in entries {
+                    graph.execute_entry(entry);
+                }
+            }
+        }
+        Some(graph)
+    }
+
+    /// Bundle a trace with the revision it was recorded against
+    pub fn bundle_trace(&mut self, rev: usize, trace: Value) -> Option<TraceBundle> {
+        let graph = self.graph_at_revision(rev)?;
+        Some(TraceBundle {
+            graph_rev: rev,
+            graph: block_on(graph.to_json()),
+            trace,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use crate::graph::trace::TraceBundle;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_trace_bundle() {
+        'given_a_journaled_graph_edited_after_a_trace: {
+            let mut g = Graph::new("traced", true);
+            g.init_journal(None);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Write", "WriteFile", None)
+                .add_edge("Read", "out", "Write", "in", None);
+            let trace = json!({"header": {"graphs": {}}, "events": [{"protocol": "network", "command": "data"}]});
+            let rev = g.current_revision as usize;
+            g.remove_node("Write");
+            'when_bundling_the_trace_with_its_revision: {
+                let bundle = g.bundle_trace(rev, trace.clone()).unwrap();
+                let source = bundle.to_json_string().unwrap();
+                let loaded = TraceBundle::from_json_string(&source).unwrap();
+                'then_it_should_load_the_graph_as_traced: {
+                    assert_eq!(loaded.graph_rev, rev);
+                    assert_eq!(loaded.trace, trace);
+                    let graph = loaded.load_graph();
+                    assert!(graph.get_node("Write").is_some());
+                    assert_eq!(graph.edges.len(), 1);
+                    assert!(g.get_node("Write").is_none());
+                }
+            }
+            'when_bundling_against_an_unknown_revision: {
+                'then_it_should_return_nothing: {
+                    assert!(g.bundle_trace(100, json!({})).is_none());
+                }
+            }
+        }
+    }
+}
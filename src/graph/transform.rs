@@ -0,0 +1,206 @@
+use super::graph::Graph;
+use super::types::GraphError;
+
+/// How packets are spread over the parallel instances of a node
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Partitioning {
+    /// Send each packet to the next instance in turn
+    #[default]
+    RoundRobin,
+    /// Send packets with the same value for the given key to the same instance
+    HashByKey(String),
+}
+
+/// Options for [`Graph::map_reduce`]
+#[derive(Clone, Debug, Default)]
+pub struct MapReduceOptions {
+    pub partitioning: Partitioning,
+    /// Merge results back in the order their inputs arrived
+    pub preserve_order: bool,
+}
+
+pub const ROUND_ROBIN_COMPONENT: &str = "flow/RoundRobin";
+pub const HASH_PARTITION_COMPONENT: &str = "flow/HashPartition";
+pub const MERGE_COMPONENT: &str = "flow/Merge";
+pub const ORDERED_MERGE_COMPONENT: &str = "flow/OrderedMerge";
+
+impl<'a> Graph<'a> {
+    /// Map/reduce fan-out
+    ///
+    /// Runs `n` instances of a node, or of a subgraph given as the name of
+    /// a group: the original and `n - 1` copies named `<id>_1`, `<id>_2`,
+    /// ... Like `replicate_node` and `replicate_group`, except that the
+    /// distributors spread packets by `options.partitioning`, and the
+    /// mergers keep the input order if `options.preserve_order` is set.
+    /// ```no_run
+    /// my_graph.map_reduce("Resize", 4, MapReduceOptions::default())?;
+    /// ```
+    /// All changes are made within a single transaction, after checking
+    /// each of them against the mutation policy. Fails without changing
+    /// anything if there's no such node or group, a new name is taken, or
+    /// a change is denied.
+    pub fn map_reduce(
+        &mut self,
+        id: &str,
+        n: usize,
+        options: MapReduceOptions,
+    ) -> Result<&mut Self, GraphError> {
+        let node = self.get_node(id).is_some();
+        if !node && self.get_group(id).is_none() {
+            return Err(GraphError::NodeNotFound(id.to_owned()));
+        }
+        if n < 2 {
+            return Ok(self);
+        }
+        let rename = |name: &str, i: usize| format!("{}_{}", name, i);
+        if node {
+            self.replicate_node_with(id, n - 1, rename, &options)
+        } else {
+            self.replicate_group_with(id, n - 1, rename, &options)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::journal::Journal;
+    use crate::graph::policy::ProtectedNodesPolicy;
+    use crate::graph::transform::{MapReduceOptions, Partitioning};
+    use crate::graph::types::GraphError;
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_transform() {
+        'given_a_pipeline_with_a_slow_stage: {
+            let mut g = Graph::new("", true);
+            g.add_node("Read", "ReadFile", None)
+                .add_node("Resize", "image/Resize", None)
+                .add_node("Write", "WriteFile", None)
+                .add_edge("Read", "out", "Resize", "in", None)
+                .add_edge("Resize", "out", "Write", "in", None)
+                .add_initial(json!(640), "Resize", "width", None)
+                .init_journal(None);
+            'when_mapped_over_three_instances: {
+                g.map_reduce(
+                    "Resize",
+                    3,
+                    MapReduceOptions {
+                        partitioning: Partitioning::HashByKey("id".to_owned()),
+                        preserve_order: true,
+                    },
+                )
+                .unwrap();
+                'then_it_should_add_copies_of_the_node: {
+                    assert_eq!(g.nodes.len(), 7);
+                    assert_eq!(g.get_node("Resize_2").unwrap().component, "image/Resize");
+                    assert_eq!(
+                        g.get_node("Resize_in_split").unwrap().component,
+                        "flow/HashPartition"
+                    );
+                    assert_eq!(
+                        g.get_node("Resize_out_merge").unwrap().component,
+                        "flow/OrderedMerge"
+                    );
+                }
+                'then_it_should_wire_distributor_and_merger: {
+                    assert!(g.get_edge("Read", "out", "Resize_in_split", "in").is_some());
+                    for instance in ["Resize", "Resize_1", "Resize_2"] {
+                        assert!(g
+                            .get_edge("Resize_in_split", "out", instance, "in")
+                            .is_some());
+                        assert!(g
+                            .get_edge(instance, "out", "Resize_out_merge", "in")
+                            .is_some());
+                    }
+                    assert!(g
+                        .get_edge("Resize_out_merge", "out", "Write", "in")
+                        .is_some());
+                    assert_eq!(g.edges.len(), 8);
+                }
+                'then_it_should_copy_iips_and_key_the_distributor: {
+                    assert_eq!(g.initializers.len(), 4);
+                }
+                'then_it_should_be_a_single_revision: {
+                    assert_eq!(g.last_revision, 1);
+                }
+            }
+            'when_the_node_is_exported_and_grouped: {
+                g.add_inport("IMAGES", "Resize", "in", None)
+                    .add_outport("THUMBS", "Resize", "out", None)
+                    .add_group("resize", vec!["Resize".to_owned()], None);
+                g.map_reduce("Resize", 2, MapReduceOptions::default())
+                    .unwrap();
+                'then_the_exports_should_move_to_distributor_and_merger: {
+                    let inport = g.inports.get("IMAGES").unwrap();
+                    assert_eq!(
+                        (inport.process.as_str(), inport.port.as_str()),
+                        ("Resize_in_split", "in")
+                    );
+                    let outport = g.outports.get("THUMBS").unwrap();
+                    assert_eq!(
+                        (outport.process.as_str(), outport.port.as_str()),
+                        ("Resize_out_merge", "out")
+                    );
+                }
+                'then_the_new_nodes_should_join_its_groups: {
+                    let group = g.get_group("resize").unwrap();
+                    assert_eq!(
+                        group.nodes,
+                        vec!["Resize", "Resize_1", "Resize_in_split", "Resize_out_merge"]
+                    );
+                }
+            }
+            'when_mapping_a_subgraph: {
+                g.add_group("stage", vec!["Resize".to_owned(), "Write".to_owned()], None);
+                g.map_reduce("stage", 2, MapReduceOptions::default())
+                    .unwrap();
+                'then_the_whole_group_should_be_copied: {
+                    assert!(g.get_edge("Resize_1", "out", "Write_1", "in").is_some());
+                    assert_eq!(
+                        g.get_group("stage_1").unwrap().nodes,
+                        vec!["Resize_1".to_owned(), "Write_1".to_owned()]
+                    );
+                    assert!(g.get_edge("Read", "out", "Resize_in_split", "in").is_some());
+                }
+            }
+            'when_a_new_node_name_is_taken: {
+                g.add_node("Resize_1", "image/Resize", None);
+                let revision = g.last_revision;
+                let result = g
+                    .map_reduce("Resize", 2, MapReduceOptions::default())
+                    .map(|_| ());
+                'then_it_should_fail_without_changes: {
+                    assert_eq!(result, Err(GraphError::NodeExists("Resize_1".to_owned())));
+                    assert!(g.get_node("Resize_in_split").is_none());
+                    assert_eq!(g.last_revision, revision);
+                }
+            }
+            'when_there_is_no_such_node_or_group: {
+                'then_it_should_fail: {
+                    assert_eq!(
+                        g.map_reduce("Crop", 2, MapReduceOptions::default())
+                            .map(|_| ()),
+                        Err(GraphError::NodeNotFound("Crop".to_owned()))
+                    );
+                }
+            }
+            'when_the_node_is_protected: {
+                g.set_mutation_policy(ProtectedNodesPolicy {
+                    nodes: vec!["Resize".to_owned()],
+                });
+                let revision = g.last_revision;
+                'then_it_should_be_denied_without_changes: {
+                    assert!(matches!(
+                        g.map_reduce("Resize", 2, MapReduceOptions::default()),
+                        Err(GraphError::PermissionDenied { .. })
+                    ));
+                    assert_eq!(g.nodes.len(), 3);
+                    assert_eq!(g.last_revision, revision);
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,13 @@
-use std::{collections::HashMap, path::Path};
+use std::{collections::HashMap, fmt, path::Path, sync::Arc};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, Map};
 
-#[derive(Clone, Serialize, Deserialize)]
+use crate::internal::utils::{IdGenerator, NuidGenerator};
+
+use super::limits::GraphQuota;
+use super::policy::{MutationKind, MutationTarget};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GraphNode {
     pub id:String,
     pub uid:String,
@@ -11,27 +16,27 @@ pub struct GraphNode {
 }
 
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GraphNodeJson {
     pub component:String,
     pub metadata:Option<Map<String, Value>>
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GraphLeaf {
     pub port:String,
     pub node_id:String,
     pub index:Option<usize>
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GraphLeafJson {
     pub port:String,
     pub process:String,
     pub index:Option<usize>
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum StubData {
     Number(f32),
     String(String),
@@ -41,37 +46,42 @@ pub enum StubData {
 
 
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GraphStub {
     pub data:Value
 }
 
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GraphEdge {
     pub from:GraphLeaf,
     pub to: GraphLeaf,
-    pub metadata:Option<Map<String, Value>>
+    pub metadata:Option<Map<String, Value>>,
+    /// Tells apart parallel edges between the same ports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key:Option<String>
 }
 
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GraphEdgeJson {
     pub src:Option<GraphLeafJson>,
     pub tgt: Option<GraphLeafJson>,
     pub data:Option<Value>,
-    pub metadata:Option<Map<String, Value>>
+    pub metadata:Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key:Option<String>
 }
 
 
-#[derive(Clone,Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GraphIIP {
     pub to: Option<GraphLeaf>,
     pub from: Option<GraphStub>,
     pub metadata:Option<Map<String, Value>>
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GraphGroup {
     pub name:String,
     pub nodes: Vec<String>,
@@ -79,7 +89,7 @@ pub struct GraphGroup {
 }
 
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GraphExportedPort {
     pub process:String,
     pub port:String,
@@ -87,13 +97,13 @@ pub struct GraphExportedPort {
 }
 
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct GraphTransaction {
     pub id:Option<String>,
     pub depth: i32
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct GraphJson {
     pub case_sensitive: bool,
@@ -103,4 +113,152 @@ pub struct GraphJson {
     pub groups: Vec<GraphGroup>,
     pub processes: HashMap<String, GraphNodeJson>,
     pub connections: Vec<GraphEdgeJson>
-}
\ No newline at end of file
+}
+
+/// How strictly mutators check their input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValidationLevel {
+    /// Accept anything the graph can represent, e.g. duplicate node IDs
+    #[default]
+    Lenient,
+    /// Reject nodes whose ID is already taken
+    Strict,
+}
+
+/// What adding an edge that already exists does
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateEdges {
+    /// Keep the existing edge and drop the new one silently
+    #[default]
+    Ignore,
+    /// Add the new edge next to the existing one, under a generated key
+    Allow,
+    /// Drop the new edge and report `GraphError::DuplicateEdge`
+    Reject,
+    /// Merge the new edge's metadata into the existing edge
+    MergeMetadata,
+}
+
+/// Whether a node may connect to itself, e.g. for feedback loops
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelfLoops {
+    #[default]
+    Allow,
+    /// Drop the edge and report `GraphError::SelfLoop`
+    Reject,
+}
+
+/// How `add_edge` treats duplicate edges and self-loops
+///
+/// Rejected edges are logged and reported through the `edge_rejected`
+/// event with a `GraphError`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EdgePolicy {
+    pub duplicates: DuplicateEdges,
+    pub self_loops: SelfLoops,
+}
+
+/// Construction-time configuration of a graph
+/// ```no_run
+/// let my_graph = Graph::with_options("test", GraphOptions {
+///     case_sensitive: true,
+///     journal: true,
+///     ..GraphOptions::default()
+/// });
+/// ```
+#[derive(Clone)]
+pub struct GraphOptions {
+    pub case_sensitive: bool,
+    pub id_generator: Arc<dyn IdGenerator>,
+    pub validation_level: ValidationLevel,
+    pub edge_policy: EdgePolicy,
+    /// Node and edge counts the graph may not grow beyond
+    pub quota: Option<GraphQuota>,
+    /// Start recording a journal right away
+    pub journal: bool,
+}
+
+impl Default for GraphOptions {
+    fn default() -> Self {
+        Self {
+            case_sensitive: false,
+            id_generator: Arc::new(NuidGenerator),
+            validation_level: ValidationLevel::default(),
+            edge_policy: EdgePolicy::default(),
+            quota: None,
+            journal: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphError {
+    /// Mutation rejected by the graph's mutation policy
+    PermissionDenied {
+        kind: MutationKind,
+        target: MutationTarget,
+        reason: String,
+    },
+    /// Mutation attempted while the graph is frozen
+    Frozen,
+    /// Edge rejected because the same connection already exists
+    DuplicateEdge { from: String, to: String },
+    /// Edge rejected because it connects a node to itself
+    SelfLoop(String),
+    /// Mutation would grow the graph beyond its quota
+    QuotaExceeded { resource: String, limit: usize },
+    /// Metadata value rejected by the validator registered for its key
+    InvalidMetadata { key: String, reason: String },
+    /// Transaction started while another one is open
+    NestedTransaction { open: String, requested: String },
+    /// Transaction ended while none is open
+    NoTransaction(String),
+    /// Node added under an ID that is already taken, in strict validation
+    NodeExists(String),
+    /// Mutation referring to a node the graph doesn't have
+    NodeNotFound(String),
+    /// Keyed edge added under a key that is already taken
+    EdgeKeyExists(String),
+    /// Group added under a name that is already taken
+    GroupExists(String),
+    /// Mutation referring to a group the graph doesn't have
+    GroupNotFound(String),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::PermissionDenied {
+                kind,
+                target,
+                reason,
+            } => write!(f, "{} on {} denied: {}", kind, target, reason),
+            GraphError::Frozen => write!(f, "Graph is frozen"),
+            GraphError::DuplicateEdge { from, to } => {
+                write!(f, "Edge {} -> {} already exists", from, to)
+            }
+            GraphError::SelfLoop(node) => write!(f, "Node {} can't connect to itself", node),
+            GraphError::QuotaExceeded { resource, limit } => {
+                write!(f, "Quota exceeded: at most {} {} allowed", limit, resource)
+            }
+            GraphError::InvalidMetadata { key, reason } => {
+                write!(f, "Invalid metadata {}: {}", key, reason)
+            }
+            GraphError::NestedTransaction { open, requested } => write!(
+                f,
+                "Can't start transaction {} inside transaction {}",
+                requested, open
+            ),
+            GraphError::NoTransaction(id) => {
+                write!(f, "Can't end transaction {}: no transaction is open", id)
+            }
+            GraphError::NodeExists(id) => write!(f, "Node {} already exists", id),
+            GraphError::NodeNotFound(id) => write!(f, "No node {} found", id),
+            GraphError::EdgeKeyExists(key) => write!(f, "Edge with key {} already exists", key),
+            GraphError::GroupExists(name) => write!(f, "Group {} already exists", name),
+            GraphError::GroupNotFound(name) => write!(f, "No group {} found", name),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
@@ -0,0 +1,232 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+// Read-only mirrors of the `GraphJson` layout. Identifiers borrow from the
+// source buffer wherever the JSON has no escapes in them.
+
+#[derive(Clone, Deserialize)]
+pub struct LeafView<'s> {
+    #[serde(borrow)]
+    pub process: Cow<'s, str>,
+    #[serde(borrow)]
+    pub port: Cow<'s, str>,
+    #[serde(default)]
+    pub index: Option<usize>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ConnectionView<'s> {
+    #[serde(borrow, default)]
+    pub src: Option<LeafView<'s>>,
+    #[serde(borrow, default)]
+    pub tgt: Option<LeafView<'s>>,
+    #[serde(default)]
+    pub data: Option<Value>,
+    #[serde(default)]
+    pub metadata: Option<Map<String, Value>>,
+    #[serde(borrow, default)]
+    pub key: Option<Cow<'s, str>>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ProcessView<'s> {
+    #[serde(borrow)]
+    pub component: Cow<'s, str>,
+    #[serde(default)]
+    pub metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ExportedPortView<'s> {
+    #[serde(borrow)]
+    pub process: Cow<'s, str>,
+    #[serde(borrow)]
+    pub port: Cow<'s, str>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct GroupView<'s> {
+    #[serde(borrow)]
+    pub name: Cow<'s, str>,
+    #[serde(borrow, default)]
+    pub nodes: Vec<Cow<'s, str>>,
+}
+
+/// Read-only graph parsed straight from its JSON source
+///
+/// Unlike `Graph`, a view has no events, journal or policies and can't be
+/// edited. It is meant for services that only query or execute graphs.
+/// ```no_run
+/// let source = GraphSource::open("flow.json")?;
+/// let view = GraphView::parse(&source)?;
+/// for edge in view.edges_from("Read") { ... }
+/// ```
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphView<'s> {
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub properties: Map<String, Value>,
+    #[serde(borrow, default)]
+    pub inports: HashMap<Cow<'s, str>, ExportedPortView<'s>>,
+    #[serde(borrow, default)]
+    pub outports: HashMap<Cow<'s, str>, ExportedPortView<'s>>,
+    #[serde(borrow, default)]
+    pub groups: Vec<GroupView<'s>>,
+    #[serde(borrow, default)]
+    pub processes: HashMap<Cow<'s, str>, ProcessView<'s>>,
+    #[serde(borrow, default)]
+    pub connections: Vec<ConnectionView<'s>>,
+}
+
+impl<'s> GraphView<'s> {
+    pub fn parse(source: &'s [u8]) -> Result<Self, io::Error> {
+        serde_json::from_slice(source)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    pub fn name(&self) -> &str {
+        self.properties
+            .get("name")
+            .and_then(|name| name.as_str())
+            .unwrap_or_default()
+    }
+
+    pub fn node(&self, id: &str) -> Option<&ProcessView<'s>> {
+        self.processes.get(id)
+    }
+
+    pub fn node_ids(&self) -> impl Iterator<Item = &str> {
+        self.processes.keys().map(|id| id.as_ref())
+    }
+
+    /// Connections between two processes, leaving out IIPs
+    pub fn edges(&self) -> impl Iterator<Item = &ConnectionView<'s>> {
+        self.connections.iter().filter(|conn| conn.src.is_some())
+    }
+
+    pub fn edges_from<'v>(&'v self, id: &'v str) -> impl Iterator<Item = &'v ConnectionView<'s>> {
+        self.edges()
+            .filter(move |conn| matches!(&conn.src, Some(src) if src.process == id))
+    }
+
+    pub fn edges_to<'v>(&'v self, id: &'v str) -> impl Iterator<Item = &'v ConnectionView<'s>> {
+        self.edges()
+            .filter(move |conn| matches!(&conn.tgt, Some(tgt) if tgt.process == id))
+    }
+
+    pub fn initializers(&self) -> impl Iterator<Item = &ConnectionView<'s>> {
+        self.connections
+            .iter()
+            .filter(|conn| conn.src.is_none() && conn.data.is_some())
+    }
+}
+
+/// Bytes of a graph file, memory-mapped when the `mmap` feature is enabled
+pub enum GraphSource {
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+    Buffered(Vec<u8>),
+}
+
+impl GraphSource {
+    #[cfg(feature = "mmap")]
+    pub fn open(path: &str) -> Result<Self, io::Error> {
+        let file = File::open(path)?;
+        // Safety: the view only reads the mapping; callers must not
+        // truncate the file while a view of it is alive
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(GraphSource::Mapped(map))
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    pub fn open(path: &str) -> Result<Self, io::Error> {
+        use std::io::Read;
+
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        Ok(GraphSource::Buffered(bytes))
+    }
+}
+
+impl Deref for GraphSource {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(feature = "mmap")]
+            GraphSource::Mapped(map) => map,
+            GraphSource::Buffered(bytes) => bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::graph::Graph;
+    use crate::graph::view::{GraphSource, GraphView};
+    use beady::scenario;
+    use serde_json::json;
+
+    #[scenario]
+    #[test]
+    fn fbp_graph_read_only_view() {
+        'given_a_saved_graph: {
+            let mut g = Graph::new("viewed", true);
+            g.add_node("Read", "ReadFile", json!({"x": 1}).as_object().cloned())
+                .add_node("Split", "SplitStr", None)
+                .add_node("Log", "Output", None)
+                .add_edge("Read", "out", "Split", "in", None)
+                .add_edge("Split", "out", "Log", "in", None)
+                .add_initial(json!("a.txt"), "Read", "in", None)
+                .add_inport("file", "Read", "in", None);
+            let path = std::env::temp_dir().join(format!(
+                "zflow-view-{}-{:?}.json",
+                std::process::id(),
+                std::thread::current().id()
+            ));
+            std::fs::write(&path, g.to_json_string().unwrap()).unwrap();
+            'when_loading_it_as_a_view: {
+                let source = GraphSource::open(path.to_str().unwrap()).unwrap();
+                let view = GraphView::parse(&source).unwrap();
+                'then_it_should_answer_queries: {
+                    assert_eq!(view.name(), "viewed");
+                    assert_eq!(view.node_ids().count(), 3);
+                    assert_eq!(view.node("Split").unwrap().component, "SplitStr");
+                    assert_eq!(
+                        view.node("Read")
+                            .unwrap()
+                            .metadata
+                            .as_ref()
+                            .unwrap()
+                            .get("x"),
+                        Some(&json!(1))
+                    );
+                    assert_eq!(view.edges().count(), 2);
+                    let out: Vec<_> = view
+                        .edges_from("Split")
+                        .map(|conn| conn.tgt.as_ref().unwrap().process.to_string())
+                        .collect();
+                    assert_eq!(out, vec!["Log"]);
+                    assert_eq!(view.edges_to("Split").count(), 1);
+                    assert_eq!(view.initializers().count(), 1);
+                    assert_eq!(view.inports.get("file").unwrap().process, "Read");
+                }
+            }
+            'when_parsing_invalid_json: {
+                'then_it_should_fail_with_invalid_data: {
+                    let err = GraphView::parse(b"{\"processes\": 1}").err().unwrap();
+                    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+                }
+            }
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
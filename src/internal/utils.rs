@@ -1,3 +1,69 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
 pub fn guid() -> String {
     nuid::next()
-}
\ No newline at end of file
+}
+
+/// Source of the `uid` given to each new node
+pub trait IdGenerator: Send + Sync {
+    fn next_id(&self) -> String;
+}
+
+/// Random unique IDs; the default for every graph
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NuidGenerator;
+
+impl IdGenerator for NuidGenerator {
+    fn next_id(&self) -> String {
+        guid()
+    }
+}
+
+/// IDs counting up from zero with an optional prefix, e.g. `node-0`, `node-1`
+#[derive(Debug, Default)]
+pub struct SequentialIdGenerator {
+    pub prefix: String,
+    next: AtomicU64,
+}
+
+impl SequentialIdGenerator {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_owned(),
+            next: AtomicU64::new(0),
+        }
+    }
+}
+
+impl IdGenerator for SequentialIdGenerator {
+    fn next_id(&self) -> String {
+        format!("{}{}", self.prefix, self.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Random-looking IDs that repeat for the same seed
+#[derive(Debug)]
+pub struct SeededIdGenerator {
+    state: AtomicU64,
+}
+
+impl SeededIdGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+}
+
+impl IdGenerator for SeededIdGenerator {
+    fn next_id(&self) -> String {
+        // splitmix64
+        let mut z = self
+            .state
+            .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        format!("{:016x}", z ^ (z >> 31))
+    }
+}
@@ -1,2 +1,4 @@
 pub mod graph;
-pub mod internal;
\ No newline at end of file
+pub mod internal;
+#[cfg(feature = "examples")]
+pub mod examples_graphs;
\ No newline at end of file